@@ -44,9 +44,13 @@ do
     sudo cp ../user/busybox_lua_testsuites/$programname ${FAT32_DIR}/fs/"$programname"
 done
 
-# for programname in $(ls ../user/libc-tests)
-# do 
-#     sudo cp ../user/libc-tests/$programname ${FAT32_DIR}/fs/"$programname"
-# done
+for programname in $(ls ../user/libc-tests)
+do 
+    sudo cp ../user/libc-tests/$programname ${FAT32_DIR}/fs/"$programname"
+done
+
+# musl 动态链接程序的 PT_INTERP 为 /lib/ld-musl-riscv64-sf.so.1，即 libc.so 本身
+sudo mkdir -p ${FAT32_DIR}/fs/lib
+sudo cp ../user/libc-tests/libc.so ${FAT32_DIR}/fs/lib/ld-musl-riscv64-sf.so.1
 
 sudo umount ${FAT32_DIR}/fs
@@ -1,14 +1,18 @@
 #![allow(unused)]
 
-pub const FSIMG_START_PAGENUM : usize = 0x9000_0;
-pub const FSIMG_END_PAGENUM : usize = 0xb000_0;
-
 pub const MEMORY_END: usize = 0xb000_0000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 pub const PAGE_MASK: usize = !0xfff;
 
+/// Sv39 二级页表叶子(megapage)大小 2MiB
+pub const HUGE_PAGE_SIZE: usize = 0x20_0000;
+pub const HUGE_PAGE_PAGES: usize = HUGE_PAGE_SIZE / PAGE_SIZE;
+
 pub const USER_STACK_SIZE: usize = PAGE_SIZE * 35;
+/// execve 的 argv 与 envp 总大小（含指针数组）上限，与 Linux 的 ARG_MAX 相同
+/// 参数映射在主线程的用户栈之上，不占用 USER_STACK_SIZE
+pub const ARG_MAX: usize = 128 * 1024;
 pub const KERNEL_STACK_SIZE: usize = PAGE_SIZE * 2;
 pub const KERNEL_HEAP_SIZE: usize = PAGE_SIZE * 0x4000;
 
@@ -24,9 +28,17 @@ pub const MMAP_BASE: usize = 0x8000_0000;
 
 //1G = 0x0-0x3FFF_FFFF    256G = 0x0-0x3F_0000_0000
 pub const DYNAMIC_LINKER:usize = 0x30_0000_0000;
+/// 位置无关可执行文件 (ET_DYN) 的加载基址，其后为该程序的堆
+pub const PIE_BASE: usize = 0x20_0000_0000;
+/// vDSO 所在的页
+pub const VDSO_BASE: usize = DYNAMIC_LINKER - PAGE_SIZE;
 
 // max fd
 pub const FDMAX: usize = 1023;
+/// pid 与 tid 的上限（不含），耗尽时 fork 与 clone 返回 EAGAIN，与 Linux 的默认 pid_max 相同
+pub const PID_MAX: usize = 32768;
+// 每个进程默认可排队的实时信号数
+pub const SIGPENDING_MAX: usize = 1024;
 
 pub use crate::board::{CLOCK_FREQ, MMIO};
 
@@ -44,3 +56,8 @@ pub fn aligned_down(addr: usize) -> usize {
 pub fn is_aligned(addr: usize) -> bool {
     (addr & 0x0fff) == 0
 }
+
+#[allow(unused)]
+pub fn huge_aligned_up(addr: usize) -> usize {
+    (addr + HUGE_PAGE_SIZE - 1) & !(HUGE_PAGE_SIZE - 1)
+}
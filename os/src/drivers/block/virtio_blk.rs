@@ -1,7 +1,6 @@
 use super::BlockDevice;
 use crate::mm::{
-    frame_alloc, frame_dealloc, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum,
-    StepByOne, VirtAddr,
+    frame_alloc_contiguous, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum, VirtAddr,
 };
 
 use alloc::{sync::Arc, vec::Vec};
@@ -43,13 +42,10 @@ impl VirtIOBlock {
 
 #[no_mangle]
 pub extern "C" fn virtio_dma_alloc(pages: usize) -> PhysAddr {
-    let mut ppn_base = PhysPageNum(0);
-    for i in 0..pages {
-        let frame = frame_alloc().unwrap();
-        if i == 0 {
-            ppn_base = frame.ppn;
-        }
-        assert_eq!(frame.ppn.0, ppn_base.0 + i);
+    let frames = frame_alloc_contiguous(pages).unwrap();
+    let ppn_base = frames[0].ppn;
+    for frame in frames {
+        frame.ppn.clear_page();
         QUEUE_FRAMES.write().push(frame);
     }
     ppn_base.into()
@@ -57,11 +53,11 @@ pub extern "C" fn virtio_dma_alloc(pages: usize) -> PhysAddr {
 
 #[no_mangle]
 pub extern "C" fn virtio_dma_dealloc(pa: PhysAddr, pages: usize) -> i32 {
-    let mut ppn_base: PhysPageNum = pa.into();
-    for _ in 0..pages {
-        frame_dealloc(ppn_base);
-        ppn_base.step();
-    }
+    let ppn_base: PhysPageNum = pa.into();
+    // 由QUEUE_FRAMES中的FrameTracker负责归还页帧
+    QUEUE_FRAMES
+        .write()
+        .retain(|frame| frame.ppn.0 < ppn_base.0 || frame.ppn.0 >= ppn_base.0 + pages);
     0
 }
 
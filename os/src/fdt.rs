@@ -0,0 +1,130 @@
+//! 扁平设备树 (FDT) 的最小解析，只读取物理内存与保留内存区域
+//! 启动时 SBI 在 a1 中传入设备树的物理地址，须在开启分页前解析
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+/// 设备树描述的物理内存布局，区域均为 (起始地址, 长度)
+pub struct MemoryMap {
+    /// memory 节点的 reg
+    pub memory: Vec<(usize, usize)>,
+    /// 内存保留块、reserved-memory 子节点的 reg 以及设备树自身
+    pub reserved: Vec<(usize, usize)>,
+}
+
+fn be32(addr: usize) -> u32 {
+    u32::from_be(unsafe { (addr as *const u32).read_unaligned() })
+}
+
+fn be64(addr: usize) -> u64 {
+    u64::from_be(unsafe { (addr as *const u64).read_unaligned() })
+}
+
+fn align4(addr: usize) -> usize {
+    (addr + 3) & !3
+}
+
+/// addr 处以 0 结尾的字符串
+fn cstr(addr: usize) -> &'static str {
+    let mut len = 0;
+    while unsafe { *((addr + len) as *const u8) } != 0 {
+        len += 1;
+    }
+    let bytes = unsafe { core::slice::from_raw_parts(addr as *const u8, len) };
+    core::str::from_utf8(bytes).unwrap_or("")
+}
+
+/// 读取由 cells 个 32 位大端数组成的数
+fn read_cells(addr: usize, cells: usize) -> usize {
+    (0..cells).fold(0, |acc, i| (acc << 32) | be32(addr + i * 4) as usize)
+}
+
+/// 解析 reg 属性中的 (地址, 长度) 对
+fn read_reg(value: usize, len: usize, address_cells: usize, size_cells: usize) -> Vec<(usize, usize)> {
+    let entry = (address_cells + size_cells) * 4;
+    if entry == 0 {
+        return Vec::new();
+    }
+    (0..len / entry)
+        .map(|i| {
+            let addr = value + i * entry;
+            (
+                read_cells(addr, address_cells),
+                read_cells(addr + address_cells * 4, size_cells),
+            )
+        })
+        .collect()
+}
+
+/// 解析 dtb 处的设备树，地址为 0 或格式不正确时返回 None
+pub fn parse_memory_map(dtb: usize) -> Option<MemoryMap> {
+    if dtb == 0 || be32(dtb) != FDT_MAGIC {
+        return None;
+    }
+    let total_size = be32(dtb + 4) as usize;
+    let structs = dtb + be32(dtb + 8) as usize;
+    let strings = dtb + be32(dtb + 12) as usize;
+    let mut map = MemoryMap {
+        memory: Vec::new(),
+        reserved: vec![(dtb, total_size)],
+    };
+    // 内存保留块由 (地址, 长度) 组成，以两个 0 结束
+    let mut p = dtb + be32(dtb + 16) as usize;
+    loop {
+        let (addr, size) = (be64(p) as usize, be64(p + 8) as usize);
+        p += 16;
+        if addr == 0 && size == 0 {
+            break;
+        }
+        map.reserved.push((addr, size));
+    }
+    // 各层节点的名字及其子节点 reg 使用的 (#address-cells, #size-cells)
+    let mut stack: Vec<(&str, usize, usize)> = Vec::new();
+    p = structs;
+    loop {
+        let token = be32(p);
+        p += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = cstr(p);
+                p = align4(p + name.len() + 1);
+                stack.push((name, 2, 1));
+            }
+            FDT_END_NODE => {
+                stack.pop();
+            }
+            FDT_PROP => {
+                let len = be32(p) as usize;
+                let prop = cstr(strings + be32(p + 4) as usize);
+                let value = p + 8;
+                p = align4(value + len);
+                let depth = stack.len();
+                match prop {
+                    "#address-cells" if depth >= 1 => stack[depth - 1].1 = be32(value) as usize,
+                    "#size-cells" if depth >= 1 => stack[depth - 1].2 = be32(value) as usize,
+                    "reg" if depth >= 2 => {
+                        let (parent, address_cells, size_cells) = stack[depth - 2];
+                        let node = stack[depth - 1].0.split('@').next().unwrap_or("");
+                        let regs = read_reg(value, len, address_cells, size_cells);
+                        if depth == 2 && node == "memory" {
+                            map.memory.extend(regs);
+                        } else if depth == 3 && parent == "reserved-memory" {
+                            map.reserved.extend(regs);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            FDT_NOP => {}
+            // FDT_END 或无法识别的标记
+            _ => break,
+        }
+    }
+    Some(map)
+}
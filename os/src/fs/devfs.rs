@@ -2,7 +2,7 @@ use alloc::sync::Arc;
 
 use crate::mm::UserBuffer;
 
-use super::{File, OpenFlags};
+use super::{open_proc_file, File, OpenFlags};
 
 pub struct DevZero;
 pub struct DevNull;
@@ -66,10 +66,13 @@ impl File for DevNull {
 }
 
 pub fn open_device_file(
-    _cwd: &str,
+    cwd: &str,
     path: &str,
     _flags: OpenFlags,
 ) -> Option<Arc<dyn File + Send + Sync>> {
+    if let Some(procfile) = open_proc_file(cwd, path) {
+        return Some(procfile);
+    }
     // warning: just a fake implementation
     if path.ends_with("zero") {
         Some(Arc::new(DevZero::new()))
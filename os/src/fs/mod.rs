@@ -1,6 +1,10 @@
 mod finfo;
+mod pidfd;
 mod pipe;
+mod poll;
+mod procfs;
 mod stdio;
+mod tty;
 mod vfile;
 mod devfs;
 mod fsidx;
@@ -24,11 +28,27 @@ pub trait File: Send + Sync {
     fn write(&self, buf: UserBuffer) -> usize;
     fn read_blocking(&self) -> bool;
     fn write_blocking(&self) -> bool;
+    /// 设备控制命令，未实现的命令按成功处理
+    fn ioctl(&self, _request: usize, _arg: usize) -> isize {
+        0
+    }
+    /// pidfd 返回自身，其余文件返回 None
+    fn as_pidfd(&self) -> Option<&PidFd> {
+        None
+    }
+    /// 状态变化时唤醒 poll/select 的等待队列，没有时 poll/select 只能定时重新检查
+    fn poll_queue(&self) -> Option<Arc<PollQueue>> {
+        None
+    }
 }
 
 pub use finfo::*;
+pub use pidfd::PidFd;
 pub use pipe::{make_pipe, Pipe,PipeRingBuffer};
+pub use poll::{PollQueue, PollTable};
+pub use procfs::open_proc_file;
 pub use stdio::{Stdin, Stdout};
+pub use tty::{tty_poll, tty_poll_expired, tty_release_session, tty_set_session};
 pub use vfile::*;
 pub use devfs::open_device_file;
 pub use fsidx::*;
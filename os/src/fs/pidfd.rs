@@ -0,0 +1,64 @@
+use alloc::sync::{Arc, Weak};
+
+use super::{File, PollQueue};
+use crate::mm::UserBuffer;
+use crate::syscall::EINVAL;
+use crate::task::ProcessControlBlock;
+
+/// 指向进程的文件描述符，进程退出后可读
+/// 只持有弱引用，进程被回收后 pid 即使被重新分配也不会指向新进程
+pub struct PidFd {
+    process: Weak<ProcessControlBlock>,
+    nonblock: bool,
+}
+
+impl PidFd {
+    pub fn new(process: &Arc<ProcessControlBlock>, nonblock: bool) -> Self {
+        Self {
+            process: Arc::downgrade(process),
+            nonblock,
+        }
+    }
+
+    /// 进程已被回收时返回 None
+    pub fn process(&self) -> Option<Arc<ProcessControlBlock>> {
+        self.process.upgrade()
+    }
+
+    pub fn nonblock(&self) -> bool {
+        self.nonblock
+    }
+
+    /// 进程已退出（包括已被回收）
+    /// poll 时调用者持有自身的进程锁，不能获取目标进程的锁
+    pub fn exited(&self) -> bool {
+        self.process().map_or(true, |process| process.exited())
+    }
+}
+
+impl File for PidFd {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: UserBuffer) -> usize {
+        -EINVAL as usize
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        -EINVAL as usize
+    }
+    fn read_blocking(&self) -> bool {
+        !self.exited()
+    }
+    fn write_blocking(&self) -> bool {
+        true
+    }
+    fn as_pidfd(&self) -> Option<&PidFd> {
+        Some(self)
+    }
+    fn poll_queue(&self) -> Option<Arc<PollQueue>> {
+        self.process().map(|process| process.exit_poll_queue.clone())
+    }
+}
@@ -1,37 +1,56 @@
-use super::{File, OpenFlags};
-use crate::{mm::UserBuffer, syscall::EPIPE};
+use super::{File, OpenFlags, PollQueue};
+use crate::{mm::UserBuffer, syscall::{EPIPE, ERESTARTSYS}};
 
 use alloc::{sync::{Arc, Weak}, vec::Vec};
 use spin::Mutex;
 
-use crate::task::suspend_current_and_run_next;
+use crate::task::{current_has_signal, suspend_current_and_run_next};
 
 pub struct Pipe {
     readable: bool,
     writable: bool,
     nonblock: bool,
     buffer: Arc<Mutex<PipeRingBuffer>>,
+    /// 读端与写端共享，缓冲区内容变化或一端关闭时唤醒
+    poll_queue: Arc<PollQueue>,
 }
 
 impl Pipe {
-    pub fn read_end_with_buffer(buffer: Arc<Mutex<PipeRingBuffer>>, nonblock: bool) -> Self {
+    pub fn read_end_with_buffer(
+        buffer: Arc<Mutex<PipeRingBuffer>>,
+        poll_queue: Arc<PollQueue>,
+        nonblock: bool,
+    ) -> Self {
         Self {
             readable: true,
             writable: false,
             nonblock,
             buffer,
+            poll_queue,
         }
     }
-    pub fn write_end_with_buffer(buffer: Arc<Mutex<PipeRingBuffer>>, nonblock: bool) -> Self {
+    pub fn write_end_with_buffer(
+        buffer: Arc<Mutex<PipeRingBuffer>>,
+        poll_queue: Arc<PollQueue>,
+        nonblock: bool,
+    ) -> Self {
         Self {
             readable: false,
             writable: true,
             nonblock,
             buffer,
+            poll_queue,
         }
     }
 }
 
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        // 另一端变为可读（EOF）或可写（EPIPE）
+        self.poll_queue.wake_all();
+    }
+}
+
 const RING_BUFFER_SIZE: usize = 0x20000;
 
 #[derive(Copy, Clone, PartialEq)]
@@ -83,9 +102,10 @@ impl PipeRingBuffer {
 /// Return (read_end, write_end)
 pub fn make_pipe(flags: OpenFlags) -> (Arc<Pipe>, Arc<Pipe>) {
     let buffer = Arc::new(Mutex::new(PipeRingBuffer::new()));
+    let poll_queue = Arc::new(PollQueue::new());
     let nonblock = flags.contains(OpenFlags::NONBLOCK);
-    let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone(), nonblock));
-    let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone(), nonblock));
+    let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone(), poll_queue.clone(), nonblock));
+    let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone(), poll_queue, nonblock));
     buffer.lock().set_read_end(&read_end);
     buffer.lock().set_write_end(&write_end);
     (read_end, write_end)
@@ -107,6 +127,9 @@ impl File for Pipe {
                     return 0;
                 }
                 drop(ring);
+                if current_has_signal() {
+                    return -ERESTARTSYS as usize;
+                }
                 // debug!("read suspend, buf.len = {}, c = {}", l, read_size);
                 suspend_current_and_run_next();
                 continue;
@@ -123,6 +146,8 @@ impl File for Pipe {
             
             ring.head = (ring.head + read_size) % ring.arr_len();
             ring.sz -= read_size;
+            drop(ring);
+            self.poll_queue.wake_all();
             // for pa in buf.into_iter() {
             //     if ring.sz == 0 {
             //         break;
@@ -156,6 +181,14 @@ impl File for Pipe {
                     return write_size;
                 }
                 drop(ring);
+                // 已写入部分数据时返回写入的字节数
+                if current_has_signal() {
+                    return if write_size > 0 {
+                        write_size
+                    } else {
+                        -ERESTARTSYS as usize
+                    };
+                }
                 // debug!("write suspend, buf.len = {}, c = {}", l, write_size);
                 suspend_current_and_run_next();
                 continue;
@@ -177,6 +210,8 @@ impl File for Pipe {
             ring.tail = (ring.tail + write_sz_this_time) % ring.arr_len();
             ring.sz += write_sz_this_time;
             write_size += write_sz_this_time;
+            drop(ring);
+            self.poll_queue.wake_all();
 
             // 不同于read，在写操作时只有写满了buf才返回
             if write_size == buf.len() {
@@ -189,8 +224,9 @@ impl File for Pipe {
             if self.nonblock {
                 return false;
             } else {
+                // 写端全部关闭后读到 EOF，不会阻塞
                 let ring_buffer = self.buffer.lock();
-                if ring_buffer.sz == 0 {
+                if ring_buffer.sz == 0 && !ring_buffer.all_write_ends_closed() {
                     return true;
                 } else {
                     return false;
@@ -205,7 +241,7 @@ impl File for Pipe {
                 return false;
             } else {
                 let ring_buffer = self.buffer.lock();
-                if ring_buffer.sz == RING_BUFFER_SIZE {
+                if ring_buffer.sz == RING_BUFFER_SIZE && !ring_buffer.all_read_ends_closed() {
                     return true;
                 } else {
                     return false;
@@ -214,4 +250,7 @@ impl File for Pipe {
         }
         false
     }
+    fn poll_queue(&self) -> Option<Arc<PollQueue>> {
+        Some(self.poll_queue.clone())
+    }
 }
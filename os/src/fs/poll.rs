@@ -0,0 +1,111 @@
+//! poll/select 的等待队列
+//! 文件状态可能变化时唤醒队列中的所有任务，任务醒来后重新检查它所等待的全部文件
+
+use super::File;
+use crate::task::{
+    block_current_and_run_next, current_task, prepare_to_block, unblock_task, TaskControlBlock,
+};
+use crate::timer::{get_time_ns, sleep_until};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// 文件没有等待队列时，poll/select 每隔该时间醒来重新检查一次
+const POLL_INTERVAL_NS: usize = 1_000_000;
+
+/// 在同一文件上 poll/select 的任务
+pub struct PollQueue(Mutex<Vec<Arc<TaskControlBlock>>>);
+
+impl PollQueue {
+    pub fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    pub(super) fn register(&self, task: &Arc<TaskControlBlock>) {
+        let mut waiters = self.0.lock();
+        if !waiters.iter().any(|waiter| Arc::ptr_eq(waiter, task)) {
+            waiters.push(Arc::clone(task));
+        }
+    }
+
+    pub(super) fn unregister(&self, task: &Arc<TaskControlBlock>) {
+        self.0.lock().retain(|waiter| !Arc::ptr_eq(waiter, task));
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.0.lock().is_empty()
+    }
+
+    /// 文件状态变化后调用，不能在持有任务锁时调用
+    pub fn wake_all(&self) {
+        let waiters = core::mem::take(&mut *self.0.lock());
+        for task in waiters {
+            unblock_task(task);
+        }
+    }
+}
+
+/// 一次 poll/select 调用在各文件等待队列中的登记，释放时移出所有队列
+pub struct PollTable {
+    task: Arc<TaskControlBlock>,
+    queues: Vec<Arc<PollQueue>>,
+    /// 有文件没有等待队列，只能定时重新检查
+    need_recheck: bool,
+}
+
+impl PollTable {
+    /// 开始第一轮等待，见 `prepare_to_block`
+    pub fn new() -> Self {
+        prepare_to_block();
+        Self {
+            task: current_task().unwrap(),
+            queues: Vec::new(),
+            need_recheck: false,
+        }
+    }
+
+    /// 在检查 file 是否就绪之前调用，此后文件的状态变化都会唤醒当前任务
+    pub fn add(&mut self, file: &Arc<dyn File + Send + Sync>) {
+        match file.poll_queue() {
+            Some(queue) => {
+                if !self.queues.iter().any(|q| Arc::ptr_eq(q, &queue)) {
+                    queue.register(&self.task);
+                    self.queues.push(queue);
+                }
+            }
+            None => self.need_recheck = true,
+        }
+    }
+
+    /// 没有文件就绪时调用，阻塞到登记的文件状态变化、收到信号或到达 deadline
+    /// 返回后需重新登记并检查所有文件
+    pub fn wait(&mut self, deadline: Option<usize>) {
+        let deadline = if self.need_recheck {
+            let next = get_time_ns() + POLL_INTERVAL_NS;
+            Some(deadline.map_or(next, |deadline| deadline.min(next)))
+        } else {
+            deadline
+        };
+        match deadline {
+            Some(deadline) => {
+                sleep_until(deadline);
+            }
+            None => block_current_and_run_next(),
+        }
+        self.clear();
+        prepare_to_block();
+    }
+
+    fn clear(&mut self) {
+        for queue in self.queues.drain(..) {
+            queue.unregister(&self.task);
+        }
+        self.need_recheck = false;
+    }
+}
+
+impl Drop for PollTable {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
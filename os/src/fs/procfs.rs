@@ -0,0 +1,105 @@
+//! /proc/self 下的进程内存信息，格式与 Linux 相同
+//! 打开后第一次读取时生成内容，之后的读取返回同一份快照
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use spin::Mutex;
+
+use super::File;
+use crate::config::PAGE_SIZE;
+use crate::mm::UserBuffer;
+use crate::task::{current_process, ProcessControlBlock};
+
+#[derive(Clone, Copy)]
+enum ProcSelfKind {
+    /// 以页为单位：size resident shared text lib data dt
+    Statm,
+    /// 以 kB 为单位的 VmSize、VmLck、VmHWM、VmRSS、VmData
+    Status,
+}
+
+pub struct ProcSelfFile {
+    process: Weak<ProcessControlBlock>,
+    kind: ProcSelfKind,
+    /// 生成的内容与已读取的位置
+    content: Mutex<Option<(String, usize)>>,
+}
+
+impl ProcSelfFile {
+    fn new(kind: ProcSelfKind) -> Self {
+        Self {
+            process: Arc::downgrade(&current_process()),
+            kind,
+            content: Mutex::new(None),
+        }
+    }
+
+    fn render(&self) -> String {
+        let process = match self.process.upgrade() {
+            Some(process) => process,
+            None => return String::new(),
+        };
+        let inner = process.acquire_inner_lock();
+        let mm = inner.mm.lock();
+        let stat = mm.memory_stat();
+        let data_pages = mm.data_pages();
+        drop(mm);
+        let kb = |pages: usize| pages * PAGE_SIZE / 1024;
+        match self.kind {
+            ProcSelfKind::Statm => format!(
+                "{} {} {} 0 0 {} 0\n",
+                stat.vm_pages, stat.resident_pages, stat.shared_pages, data_pages
+            ),
+            ProcSelfKind::Status => format!(
+                "Pid:\t{}\nVmSize:\t{} kB\nVmLck:\t{} kB\nVmHWM:\t{} kB\nVmRSS:\t{} kB\nVmData:\t{} kB\n",
+                process.getpid(),
+                kb(stat.vm_pages),
+                kb(stat.locked_pages),
+                kb(stat.max_resident_pages),
+                kb(stat.resident_pages),
+                kb(data_pages)
+            ),
+        }
+    }
+}
+
+impl File for ProcSelfFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, mut user_buf: UserBuffer) -> usize {
+        let mut content = self.content.lock();
+        let (text, pos) = content.get_or_insert_with(|| (self.render(), 0));
+        let n = user_buf.copy_to_user(&text.as_bytes()[*pos..]);
+        *pos += n;
+        n
+    }
+    fn write(&self, _user_buf: UserBuffer) -> usize {
+        0
+    }
+    fn read_blocking(&self) -> bool {
+        false
+    }
+    fn write_blocking(&self) -> bool {
+        false
+    }
+}
+
+/// path 为 /proc/self/statm 或 /proc/self/status 时打开对应的文件
+pub fn open_proc_file(cwd: &str, path: &str) -> Option<Arc<dyn File + Send + Sync>> {
+    let abs_path = if path.starts_with('/') {
+        String::from(path)
+    } else {
+        format!("{}/{}", cwd.trim_end_matches('/'), path)
+    };
+    let kind = match abs_path.as_str() {
+        "/proc/self/statm" => ProcSelfKind::Statm,
+        "/proc/self/status" => ProcSelfKind::Status,
+        _ => return None,
+    };
+    Some(Arc::new(ProcSelfFile::new(kind)))
+}
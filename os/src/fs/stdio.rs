@@ -1,9 +1,10 @@
 use alloc::vec::Vec;
 
+use super::tty::{tty_check_background, tty_getchar, tty_ioctl, tty_poll, tty_wait_input};
 use super::File;
 use crate::mm::UserBuffer;
-use crate::sbi::console_getchar;
-use crate::task::suspend_current_and_run_next;
+use crate::syscall::ERESTARTSYS;
+use crate::task::{current_has_signal, SIGTTIN};
 
 pub struct Stdin;
 
@@ -22,32 +23,31 @@ impl File for Stdin {
         false
     }
     fn read(&self, mut user_buf: UserBuffer) -> usize {
-        // assert_eq!(user_buf.len(), 1);
-        // busy loop
-        let mut c: usize;
-        let mut count: usize = 0;
+        // 后台进程组不能读取控制终端
+        if let Err(errno) = tty_check_background(SIGTTIN) {
+            return errno as usize;
+        }
         let mut buf = Vec::new();
-        while count < user_buf.len() {
-            c = console_getchar();
-            match c {
-                // `c > 255`是为了兼容OPENSBI，OPENSBI未获取字符时会返回-1
-                0 | 256.. => {
-                    suspend_current_and_run_next();
-                    continue;
+        while buf.len() < user_buf.len() {
+            tty_poll();
+            match tty_getchar().map(|c| c as usize) {
+                // 收到信号时返回已读取的部分
+                None if current_has_signal() => {
+                    if buf.is_empty() {
+                        return -ERESTARTSYS as usize;
+                    }
+                    break;
                 }
-                LF | CR => {
+                None => tty_wait_input(),
+                Some(LF | CR) => {
                     buf.push(CR as u8);
-                    count += 1;
                     break;
                 }
-                _ => {
-                    buf.push(c as u8);
-                    count += 1;
-                }
+                Some(c) => buf.push(c as u8),
             }
         }
         user_buf.copy_to_user(buf.as_slice());
-        count
+        buf.len()
     }
     fn write(&self, _user_buf: UserBuffer) -> usize {
         panic!("Cannot write to stdin!");
@@ -58,6 +58,9 @@ impl File for Stdin {
     fn write_blocking(&self) -> bool {
         false
     }
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        tty_ioctl(request, arg)
+    }
 }
 
 impl File for Stdout {
@@ -84,4 +87,7 @@ impl File for Stdout {
     fn write_blocking(&self) -> bool {
         false
     }
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        tty_ioctl(request, arg)
+    }
 }
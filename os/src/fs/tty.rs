@@ -0,0 +1,266 @@
+//! 控制终端
+//! 控制台是系统中唯一的终端。会话首进程通过 TIOCSCTTY 获得控制终端，初始进程的会话在启动时即拥有它；
+//! 终端输入的 ^C、^\、^Z 向前台进程组发送 SIGINT、SIGQUIT、SIGTSTP，
+//! 后台进程组读终端或修改前台进程组时收到 SIGTTIN / SIGTTOU
+//! 控制台没有输入中断，存在前台进程组或读终端的任务时由定时器周期性地轮询，NO_HZ 停止时间片时钟后仍能收到 ^C
+
+use super::PollQueue;
+use crate::mm::{translated_ref, translated_refmut};
+use crate::sbi::console_getchar;
+use crate::syscall::{EINVAL, EIO, ENOTTY, EPERM, ERESTARTSYS};
+use crate::task::{
+    block_current_and_run_next, current_has_signal, current_ignores_signal, current_process,
+    current_task, current_user_token, prepare_to_block, process_group, send_signal_pgrp, SigInfo,
+    TidHandle, SIGCONT, SIGHUP, SIGINT, SIGQUIT, SIGTSTP, SIGTTIN, SIGTTOU, SI_KERNEL,
+};
+use crate::timer::{add_timer, get_time_ns, TimerEvent};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::{Lazy, Mutex};
+
+const CTRL_C: u8 = 0x03;
+const CTRL_BACKSLASH: u8 = 0x1c;
+const CTRL_Z: u8 = 0x1a;
+
+pub const TIOCSCTTY: usize = 0x540E;
+pub const TIOCGPGRP: usize = 0x540F;
+pub const TIOCSPGRP: usize = 0x5410;
+pub const TIOCNOTTY: usize = 0x5422;
+pub const TIOCGSID: usize = 0x5429;
+
+/// 轮询控制台的间隔，与时间片时钟相同
+const TTY_POLL_INTERVAL_NS: usize = 10_000_000;
+
+struct Tty {
+    /// 以控制台为控制终端的会话，持有会话号使其在终端释放前不会被重新分配
+    session: Option<Arc<TidHandle>>,
+    /// 前台进程组
+    foreground: Option<Arc<TidHandle>>,
+    /// 已从控制台读入、尚未被进程读取的字符
+    input: VecDeque<u8>,
+}
+
+impl Tty {
+    fn sid(&self) -> Option<usize> {
+        self.session.as_ref().map(|session| session.0)
+    }
+
+    fn pgid(&self) -> Option<usize> {
+        self.foreground.as_ref().map(|pgrp| pgrp.0)
+    }
+}
+
+static TTY: Lazy<Mutex<Tty>> = Lazy::new(|| {
+    Mutex::new(Tty {
+        session: None,
+        foreground: None,
+        input: VecDeque::new(),
+    })
+});
+
+/// 等待终端输入的任务
+static TTY_READERS: Lazy<PollQueue> = Lazy::new(PollQueue::new);
+
+/// 轮询定时器是否已设置
+static TTY_POLL_ARMED: AtomicBool = AtomicBool::new(false);
+
+/// 有前台进程组或读终端的任务时需要继续轮询控制台
+fn tty_needs_poll() -> bool {
+    TTY.lock().foreground.is_some() || !TTY_READERS.is_empty()
+}
+
+/// 尚未设置时在当前 hart 上设置轮询定时器
+fn arm_tty_poll() {
+    if !TTY_POLL_ARMED.swap(true, Ordering::SeqCst) {
+        add_timer(get_time_ns() + TTY_POLL_INTERVAL_NS, TimerEvent::TtyPoll);
+    }
+}
+
+/// 轮询定时器到期：读取控制台，仍需轮询时重新设置
+pub fn tty_poll_expired() {
+    tty_poll();
+    TTY_POLL_ARMED.store(false, Ordering::SeqCst);
+    if tty_needs_poll() {
+        arm_tty_poll();
+    }
+}
+
+/// 使会话 session 以控制台为控制终端，前台进程组为 pgrp
+pub fn tty_set_session(session: Arc<TidHandle>, pgrp: Arc<TidHandle>) {
+    let mut tty = TTY.lock();
+    tty.session = Some(session);
+    tty.foreground = Some(pgrp);
+    drop(tty);
+    arm_tty_poll();
+}
+
+/// 把控制台已到达的字符读入输入缓冲，控制字符转换为发往前台进程组的信号
+/// 控制台没有输入中断，由时钟中断、轮询定时器与读终端的进程调用
+pub fn tty_poll() {
+    let mut signals = Vec::new();
+    let mut received = false;
+    {
+        // 其他 hart 正在读取控制台时直接返回
+        let mut tty = match TTY.try_lock() {
+            Some(tty) => tty,
+            None => return,
+        };
+        loop {
+            let c = console_getchar();
+            // `c > 255`是为了兼容OPENSBI，OPENSBI未获取字符时会返回-1
+            if c == 0 || c > 255 {
+                break;
+            }
+            let signum = match c as u8 {
+                CTRL_C => Some(SIGINT),
+                CTRL_BACKSLASH => Some(SIGQUIT),
+                CTRL_Z => Some(SIGTSTP),
+                _ => None,
+            };
+            match (signum, tty.pgid()) {
+                (Some(signum), Some(foreground)) => {
+                    // 与 Linux 默认行为相同，产生信号时丢弃尚未读取的输入
+                    tty.input.clear();
+                    signals.push((foreground, signum));
+                }
+                _ => {
+                    tty.input.push_back(c as u8);
+                    received = true;
+                }
+            }
+        }
+    }
+    if received {
+        TTY_READERS.wake_all();
+    }
+    for (pgid, signum) in signals {
+        send_signal_pgrp(pgid, SigInfo::new(signum, SI_KERNEL));
+    }
+}
+
+/// 取出一个已读入的字符
+pub fn tty_getchar() -> Option<u8> {
+    TTY.lock().input.pop_front()
+}
+
+/// 没有已读入的字符时阻塞，直到 `tty_poll` 读入新字符或收到信号
+pub fn tty_wait_input() {
+    let task = current_task().unwrap();
+    prepare_to_block();
+    TTY_READERS.register(&task);
+    arm_tty_poll();
+    // 登记之后再检查一次，此后读入的字符都会唤醒当前任务
+    tty_poll();
+    if TTY.lock().input.is_empty() && !current_has_signal() {
+        block_current_and_run_next();
+    }
+    TTY_READERS.unregister(&task);
+}
+
+/// 后台进程组访问控制终端时向其发送 signum（SIGTTIN 或 SIGTTOU）使其停止，
+/// 进程重新成为前台后系统调用自动重启；信号被忽略或屏蔽时读终端返回 EIO，其余操作照常进行
+pub fn tty_check_background(signum: u32) -> Result<(), isize> {
+    let (pgid, sid) = {
+        let process = current_process();
+        let inner = process.acquire_inner_lock();
+        (inner.pgid(), inner.sid())
+    };
+    let tty = TTY.lock();
+    if tty.sid() != Some(sid) || tty.pgid() == Some(pgid) {
+        return Ok(());
+    }
+    drop(tty);
+    if current_ignores_signal(signum) {
+        return if signum == SIGTTIN { Err(-EIO) } else { Ok(()) };
+    }
+    send_signal_pgrp(pgid, SigInfo::new(signum, SI_KERNEL));
+    Err(-ERESTARTSYS)
+}
+
+/// 会话首进程退出或放弃控制终端：终端不再属于该会话，前台进程组收到 SIGHUP 与 SIGCONT
+pub fn tty_release_session(sid: usize) {
+    let mut tty = TTY.lock();
+    if tty.sid() != Some(sid) {
+        return;
+    }
+    tty.session = None;
+    let foreground = match tty.foreground.take() {
+        Some(pgrp) => pgrp.0,
+        None => return,
+    };
+    drop(tty);
+    send_signal_pgrp(foreground, SigInfo::new(SIGHUP, SI_KERNEL));
+    send_signal_pgrp(foreground, SigInfo::new(SIGCONT, SI_KERNEL));
+}
+
+/// 控制台的终端 ioctl，未实现的命令按成功处理
+pub fn tty_ioctl(request: usize, arg: usize) -> isize {
+    let process = current_process();
+    let (pid, pgrp, session) = {
+        let inner = process.acquire_inner_lock();
+        (process.getpid(), Arc::clone(&inner.pgrp), Arc::clone(&inner.session))
+    };
+    let sid = session.0;
+    let token = current_user_token();
+    let is_ctty = TTY.lock().sid() == Some(sid);
+    match request {
+        TIOCGPGRP | TIOCGSID if !is_ctty => -ENOTTY,
+        TIOCGPGRP => {
+            *translated_refmut(token, arg as *mut i32) = TTY.lock().pgid().unwrap_or(0) as i32;
+            0
+        }
+        TIOCGSID => {
+            *translated_refmut(token, arg as *mut i32) = sid as i32;
+            0
+        }
+        TIOCSPGRP => {
+            if !is_ctty {
+                return -ENOTTY;
+            }
+            if let Err(errno) = tty_check_background(SIGTTOU) {
+                return errno;
+            }
+            let new_pgid = *translated_ref(token, arg as *const i32);
+            if new_pgid < 0 {
+                return -EINVAL;
+            }
+            // 只能切换到本会话中存在的进程组
+            let new_pgrp = process_group(new_pgid as usize).iter().find_map(|p| {
+                let inner = p.acquire_inner_lock();
+                (inner.sid() == sid).then(|| Arc::clone(&inner.pgrp))
+            });
+            match new_pgrp {
+                Some(new_pgrp) => {
+                    TTY.lock().foreground = Some(new_pgrp);
+                    arm_tty_poll();
+                    0
+                }
+                None => -EPERM,
+            }
+        }
+        TIOCSCTTY => {
+            // 只有会话首进程可以获得控制终端，arg 为 1 时可从其他会话抢占
+            let mut tty = TTY.lock();
+            if pid != sid || (tty.session.is_some() && tty.sid() != Some(sid) && arg != 1) {
+                return -EPERM;
+            }
+            tty.session = Some(session);
+            tty.foreground = Some(pgrp);
+            drop(tty);
+            arm_tty_poll();
+            0
+        }
+        TIOCNOTTY => {
+            if !is_ctty {
+                return -ENOTTY;
+            }
+            if pid == sid {
+                tty_release_session(sid);
+            }
+            0
+        }
+        _ => 0,
+    }
+}
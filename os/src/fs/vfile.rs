@@ -158,6 +158,16 @@ pub fn init_rootfs(){
     let _invalid = open_common_file("/", "dev/null/invalid", OpenFlags::CREATE | OpenFlags::RDWR ).unwrap();
 }
 
+/// 根目录中没有 name 时，把内核中内嵌的程序写入根目录，使其能像文件系统中的其他程序一样被 execve
+/// 镜像中已有的程序保持不变
+pub fn install_app(name: &str, data: &[u8]) {
+    if open_common_file("/", name, OpenFlags::RDONLY).is_some() {
+        return;
+    }
+    let app = open_common_file("/", name, OpenFlags::CREATE | OpenFlags::TRUNC | OpenFlags::WRONLY).unwrap();
+    assert_eq!(app.vfile.write_at(0, data), data.len());
+}
+
 bitflags! {
     pub struct OpenFlags: u32 {
         const RDONLY = 0;
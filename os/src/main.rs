@@ -20,6 +20,7 @@ mod board;
 mod console;
 mod config;
 mod drivers;
+mod fdt;
 mod fpu;
 mod fs;
 mod lang_items;
@@ -27,6 +28,7 @@ mod loader;
 mod mm;
 mod monitor;
 mod multicore;
+mod random;
 mod sbi;
 mod syscall;
 mod task;
@@ -59,7 +61,7 @@ static BOOT_COUNT: Lazy<Mutex<u32>> = Lazy::new(|| Mutex::new(0));
 
 #[no_mangle]
 pub fn rust_main() -> ! {
-    save_hartid(); // 这句话之前不能加任何函数调用，否则a0的值会被覆盖
+    let dtb = save_hartid(); // 这句话之前不能加任何函数调用，否则a0、a1的值会被覆盖
     let hartid = get_hartid();
     info!("Riscv hartid {} init ", hartid);
     if *BOOT_CORE_READY.lock() {
@@ -67,15 +69,17 @@ pub fn rust_main() -> ! {
         others_main(hartid);
     }
     clear_bss();
-    mm::init();
+    mm::init(dtb);
     mm::remap_test();
     fpu::init();
     trap::init();
     trap::enable_timer_interrupt();
+    trap::enable_software_interrupt();
     syscall::init();
     timer::set_next_trigger();
     fs::list_apps();
     fs::init_rootfs();
+    fs::install_app("user_shell", loader::get_usershell_binary());
     // block_device_test();
     task::add_initproc();
     unsafe { *(crate::monitor::SYSCALL_ENABLE as *mut u8) = 0 ;
@@ -100,6 +104,7 @@ fn others_main(hartid: usize) -> ! {
     fpu::init();
     trap::init();
     trap::enable_timer_interrupt();
+    trap::enable_software_interrupt();
     timer::set_next_trigger();
     info!("(Other Cores) Riscv hartid {} run ", hartid);
     {
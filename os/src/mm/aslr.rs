@@ -0,0 +1,66 @@
+//! 用户地址空间布局随机化 (ASLR)
+//! 栈、堆、mmap 基址及位置无关对象（动态链接器、PIE 程序）的加载基址在 exec 时随机偏移
+
+use crate::config::{
+    DYNAMIC_LINKER, HUGE_PAGE_SIZE, MMAP_BASE, PAGE_SIZE, PIE_BASE, USER_STACK_BASE,
+};
+use crate::random::rand_below;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// 启动时的全局开关，打开 no_aslr feature 时默认关闭，便于复现调试
+static ASLR_ENABLED: AtomicBool = AtomicBool::new(!cfg!(feature = "no_aslr"));
+
+/// personality(2) 中关闭随机化的标志，按进程生效（见 sys_personality）
+pub const ADDR_NO_RANDOMIZE: usize = 0x0040000;
+
+/// 各区域随机偏移的上限（页数）
+const STACK_RND_PAGES: usize = 0x4_0000; // 1GiB
+const MMAP_RND_PAGES: usize = 0x4000; // 64MiB
+const HEAP_RND_PAGES: usize = 0x2000; // 32MiB
+const DYN_RND_HUGE_PAGES: usize = 0x200; // 1GiB，按2MiB对齐以便大页映射
+
+pub fn aslr_enabled() -> bool {
+    ASLR_ENABLED.load(Ordering::Relaxed)
+}
+
+/// 一次 exec 所使用的用户地址空间布局
+#[derive(Clone, Copy, Debug)]
+pub struct UserLayout {
+    /// 用户栈基址（线程栈自此向上依次排布）
+    pub ustack_base: usize,
+    /// 堆基址相对 ELF 末尾的偏移
+    pub heap_offset: usize,
+    /// mmap 区域基址
+    pub mmap_base: usize,
+    /// 动态链接器的加载基址
+    pub dyn_base: usize,
+    /// ET_DYN 类型主程序的加载基址
+    pub exec_base: usize,
+}
+
+impl UserLayout {
+    /// 不随机化时的固定布局
+    pub fn fixed() -> Self {
+        Self {
+            ustack_base: USER_STACK_BASE,
+            heap_offset: 0,
+            mmap_base: MMAP_BASE,
+            dyn_base: DYNAMIC_LINKER,
+            exec_base: PIE_BASE,
+        }
+    }
+
+    /// personality 为进程的 personality(2) 值
+    pub fn new(personality: usize) -> Self {
+        if !aslr_enabled() || personality & ADDR_NO_RANDOMIZE != 0 {
+            return Self::fixed();
+        }
+        Self {
+            ustack_base: USER_STACK_BASE + rand_below(STACK_RND_PAGES) * PAGE_SIZE,
+            heap_offset: rand_below(HEAP_RND_PAGES) * PAGE_SIZE,
+            mmap_base: MMAP_BASE + rand_below(MMAP_RND_PAGES) * PAGE_SIZE,
+            dyn_base: DYNAMIC_LINKER + rand_below(DYN_RND_HUGE_PAGES) * HUGE_PAGE_SIZE,
+            exec_base: PIE_BASE + rand_below(DYN_RND_HUGE_PAGES) * HUGE_PAGE_SIZE,
+        }
+    }
+}
@@ -1,15 +1,18 @@
 use super::{PhysAddr, PhysPageNum};
-use crate::config::FSIMG_START_PAGENUM;
-use crate::config::FSIMG_END_PAGENUM;
-use crate::config::MEMORY_END;
-
+use crate::config::{MEMORY_END, PAGE_SIZE};
+use crate::fdt::parse_memory_map;
+use fat32_fs::FSIMG_BASE;
 
+use alloc::collections::BTreeSet;
+use alloc::vec;
 use alloc::vec::Vec;
-use hashbrown::HashMap;
 use core::fmt::{self, Debug, Formatter};
 use spin::Lazy;
 use spin::RwLock;
 
+/// 伙伴系统的阶数上限，最大块为 2^(MAX_ORDER-1) 页 (4MiB)
+pub const MAX_ORDER: usize = 11;
+
 pub struct FrameTracker {
     pub ppn: PhysPageNum,
 }
@@ -44,106 +47,250 @@ impl Drop for FrameTracker {
 trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPageNum>;
+    fn alloc_contiguous(&mut self, order: usize) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
     fn add_ref(&mut self, ppn: PhysPageNum);
     fn reduce_ref(&mut self, ppn: PhysPageNum);
     fn enquire_ref(& self, ppn: PhysPageNum)-> usize;
 }
 
-pub struct StackFrameAllocator {
-    current: usize,
+/// 伙伴系统物理页帧分配器
+/// free_lists[k] 保存所有空闲的 2^k 页块的起始ppn（按绝对ppn对齐）
+/// 引用计数与分配时的阶按页帧保存在数组 refcounter、alloc_order 中，下标为 ppn - base
+pub struct BuddyFrameAllocator {
+    base: usize,
     end: usize,
-    recycled: Vec<usize>,
-    refcounter: HashMap<usize, u8>,
+    free_lists: [BTreeSet<usize>; MAX_ORDER],
+    refcounter: Vec<u16>,
+    alloc_order: Vec<u8>,
+    /// used_frames[k] 为以 2^k 页块分配、尚未释放的页帧数
+    used_frames: [usize; MAX_ORDER],
+    total: usize,
 }
 
-impl StackFrameAllocator {
-    pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
-        self.current = l.0;
+impl BuddyFrameAllocator {
+    /// 管理 [l, r) 中与 memory 相交、且不在 reserved 中的页帧，区域均为 (起始地址, 长度)
+    pub fn init(
+        &mut self,
+        l: PhysPageNum,
+        r: PhysPageNum,
+        memory: &[(usize, usize)],
+        reserved: &[(usize, usize)],
+    ) {
+        self.base = l.0;
         self.end = r.0;
-        info!("FrameAllocator [0x{:x} - 0x{:x}]", self.current, self.end );
-        info!("Remain {} free physical frames", self.end - self.current);
+        self.refcounter = vec![0; r.0 - l.0];
+        self.alloc_order = vec![0; r.0 - l.0];
+        // 与物理内存区域求交，再扣除保留区域，将剩余的每一段可用内存加入伙伴系统
+        let mut usable: Vec<(usize, usize)> = memory
+            .iter()
+            .map(|&(start, len)| {
+                let s = PhysAddr::from(start).ceil().0.max(l.0);
+                let e = PhysAddr::from(start + len).floor().0.min(r.0);
+                (s, e)
+            })
+            .filter(|&(s, e)| s < e)
+            .collect();
+        for &(start, len) in reserved {
+            let rs = PhysAddr::from(start).floor().0;
+            let re = PhysAddr::from(start + len).ceil().0;
+            info!("FrameAllocator reserved [0x{:x} - 0x{:x}]", rs, re);
+            usable = usable
+                .into_iter()
+                .flat_map(|(s, e)| {
+                    let mut v = Vec::with_capacity(2);
+                    if rs >= e || re <= s {
+                        v.push((s, e));
+                    } else {
+                        if s < rs {
+                            v.push((s, rs));
+                        }
+                        if re < e {
+                            v.push((re, e));
+                        }
+                    }
+                    v
+                })
+                .collect();
+        }
+        for (s, e) in usable {
+            self.add_range(s, e);
+        }
+        info!("FrameAllocator [0x{:x} - 0x{:x}]", self.base, self.end);
+        info!("Remain {} free physical frames", self.total);
+    }
+
+    /// 将 [s, e) 切分为尽可能大的对齐块放入空闲链表
+    fn add_range(&mut self, mut s: usize, e: usize) {
+        while s < e {
+            let mut order = (s.trailing_zeros() as usize).min(MAX_ORDER - 1);
+            while s + (1 << order) > e {
+                order -= 1;
+            }
+            self.free_lists[order].insert(s);
+            self.total += 1 << order;
+            s += 1 << order;
+        }
+    }
+
+    #[inline(always)]
+    fn contains(&self, ppn: usize) -> bool {
+        ppn >= self.base && ppn < self.end
+    }
+
+    /// 将一个 2^order 页的块归还伙伴系统，并尽可能与伙伴合并
+    fn free_block(&mut self, mut ppn: usize, mut order: usize) {
+        while order < MAX_ORDER - 1 {
+            let buddy = ppn ^ (1 << order);
+            if !self.free_lists[order].remove(&buddy) {
+                break;
+            }
+            ppn = ppn.min(buddy);
+            order += 1;
+        }
+        self.free_lists[order].insert(ppn);
+    }
+
+    pub fn stat(&self) -> FrameAllocatorStat {
+        let mut free_blocks = [0usize; MAX_ORDER];
+        let mut free_frames = 0;
+        for (order, list) in self.free_lists.iter().enumerate() {
+            free_blocks[order] = list.len();
+            free_frames += list.len() << order;
+        }
+        let shared_frames = self.refcounter.iter().filter(|&&r| r > 1).count();
+        FrameAllocatorStat {
+            total_frames: self.total,
+            free_frames,
+            shared_frames,
+            free_blocks,
+            used_frames: self.used_frames,
+        }
     }
 }
-impl FrameAllocator for StackFrameAllocator {
+
+impl FrameAllocator for BuddyFrameAllocator {
     fn new() -> Self {
         Self {
-            current: 0,
+            base: 0,
             end: 0,
-            recycled: Vec::with_capacity(0x10000),
-            refcounter: HashMap::new(),
+            free_lists: Default::default(),
+            refcounter: Vec::new(),
+            alloc_order: Vec::new(),
+            used_frames: [0; MAX_ORDER],
+            total: 0,
         }
     }
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            // error!("+++ alloc ppn={:?}",ppn);
-            self.refcounter.insert(ppn, 1);
-            Some(ppn.into())
-        } else if self.current == self.end {
-            None
-        } else {
-            if self.current >= (FSIMG_START_PAGENUM - 1) && self.current < FSIMG_END_PAGENUM {
-                error!(" -------------------- FrameAllocator outoff 0x9000_0000 ------------------------- ");
-                self.current += FSIMG_END_PAGENUM-FSIMG_START_PAGENUM + 1;
-            }
-            self.current += 1;
-            self.refcounter.insert(self.current - 1, 1);
-            // error!("+++ alloc ppn={:?}",self.current - 1);
-            Some((self.current - 1).into())
+        self.alloc_contiguous(0)
+    }
+    fn alloc_contiguous(&mut self, order: usize) -> Option<PhysPageNum> {
+        if order >= MAX_ORDER {
+            return None;
+        }
+        // 找到不小于order的最小非空链表，逐级对半拆分
+        let mut k = (order..MAX_ORDER).find(|&k| !self.free_lists[k].is_empty())?;
+        let ppn = *self.free_lists[k].iter().next().unwrap();
+        self.free_lists[k].remove(&ppn);
+        while k > order {
+            k -= 1;
+            self.free_lists[k].insert(ppn + (1 << k));
         }
+        for i in ppn..ppn + (1 << order) {
+            self.refcounter[i - self.base] = 1;
+            self.alloc_order[i - self.base] = order as u8;
+        }
+        self.used_frames[order] += 1 << order;
+        Some(ppn.into())
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
         let ppn = ppn.0;
-        if let Some(ref_times) = self.refcounter.get_mut(&ppn){
-            *ref_times -= 1;
-            if *ref_times == 0 {
-                // info!("dealloc drop ppn:{:x}",ppn);
-                self.refcounter.remove(&ppn);
-                // validity check
-                if ppn >= self.current || self.recycled.iter().any(|&v| v == ppn) {
-                    panic!("Frame ppn={:#x} has not been allocated!", ppn);
-                }
-                // recycle
-                self.recycled.push(ppn);
-            }
-            // else {
-            //     // info!("dealloc reduce_ref ppn:{:x}",ppn);
-            // }
-        }else {
+        if !self.contains(ppn) || self.refcounter[ppn - self.base] == 0 {
             error!("dealloc ppn={:#x} no ref_times", ppn);
+            return;
+        }
+        let ref_times = &mut self.refcounter[ppn - self.base];
+        *ref_times -= 1;
+        if *ref_times == 0 {
+            // recycle
+            self.used_frames[self.alloc_order[ppn - self.base] as usize] -= 1;
+            self.free_block(ppn, 0);
         }
     }
     fn add_ref(&mut self, ppn: PhysPageNum) {
-        // info!("add_ref ppn:{:?}",ppn);
-        let ppn = ppn.0; 
-        let ref_times = self.refcounter.get_mut(&ppn).unwrap();
+        let ppn = ppn.0;
+        assert!(self.contains(ppn), "add_ref ppn={:#x} out of range", ppn);
+        let ref_times = &mut self.refcounter[ppn - self.base];
+        assert!(*ref_times != 0 && *ref_times != u16::MAX, "add_ref ppn={:#x} ref_times={}", ppn, ref_times);
         *ref_times += 1;
-        // if *ref_times>=3 {
-        //     println!("ref_times >=3");
-        // }
     }
     fn reduce_ref(&mut self, ppn: PhysPageNum) {
-        // info!("reduce_ref ppn:{:?}",ppn);
-        let ppn = ppn.0; 
-        let ref_times = self.refcounter.get_mut(&ppn).unwrap();
-        *ref_times -= 1;
+        let ppn = ppn.0;
+        assert!(self.contains(ppn), "reduce_ref ppn={:#x} out of range", ppn);
+        self.refcounter[ppn - self.base] -= 1;
     }
-    fn enquire_ref(&self, ppn: PhysPageNum) -> usize{
-        let ppn = ppn.0; 
-        let ref_times = self.refcounter.get(&ppn).unwrap();
-        (*ref_times).clone() as usize
+    fn enquire_ref(&self, ppn: PhysPageNum) -> usize {
+        let ppn = ppn.0;
+        assert!(self.contains(ppn), "enquire_ref ppn={:#x} out of range", ppn);
+        self.refcounter[ppn - self.base] as usize
     }
 }
 
-type FrameAllocatorImpl = StackFrameAllocator;
+/// 物理页帧使用情况，free_blocks[k] 为空闲的 2^k 页块数目
+#[derive(Clone, Copy, Debug)]
+pub struct FrameAllocatorStat {
+    pub total_frames: usize,
+    pub free_frames: usize,
+    /// 被多个地址空间引用（COW）的页帧数
+    pub shared_frames: usize,
+    pub free_blocks: [usize; MAX_ORDER],
+    /// used_frames[k] 为以 2^k 页块分配出去、尚未释放的页帧数，各阶之和等于 used_frames()
+    pub used_frames: [usize; MAX_ORDER],
+}
+
+impl FrameAllocatorStat {
+    pub fn used_frames(&self) -> usize {
+        self.total_frames - self.free_frames
+    }
+}
+
+type FrameAllocatorImpl = BuddyFrameAllocator;
 
 pub static FRAME_ALLOCATOR: Lazy<RwLock<FrameAllocatorImpl>> =
     Lazy::new(|| RwLock::new(FrameAllocatorImpl::new()));
 
-pub fn init_frame_allocator() {
+/// QEMU loader 装入内存的文件系统镜像，大小取自 FAT32 引导扇区
+/// 镜像不在 memory 描述的物理内存中或引导扇区无效时返回 None
+fn fsimg_region(memory: &[(usize, usize)]) -> Option<(usize, usize)> {
+    if !memory
+        .iter()
+        .any(|&(start, len)| FSIMG_BASE >= start && FSIMG_BASE + 512 <= start + len)
+    {
+        return None;
+    }
+    let sector = unsafe { core::slice::from_raw_parts(FSIMG_BASE as *const u8, 512) };
+    if sector[510] != 0x55 || sector[511] != 0xaa {
+        return None;
+    }
+    let bytes_per_sector = u16::from_le_bytes([sector[11], sector[12]]) as usize;
+    let total_sectors =
+        u32::from_le_bytes([sector[32], sector[33], sector[34], sector[35]]) as usize;
+    Some((FSIMG_BASE, bytes_per_sector * total_sectors))
+}
+
+/// 可用内存与保留区域取自设备树；没有设备树时管理 ekernel 到 MEMORY_END 的全部内存
+pub fn init_frame_allocator(dtb: usize) {
     extern "C" {
         fn ekernel();
     }
+    let (memory, mut reserved) = match parse_memory_map(dtb) {
+        Some(map) if !map.memory.is_empty() => (map.memory, map.reserved),
+        _ => {
+            warning!("FrameAllocator: no device tree at {:#x}", dtb);
+            (vec![(0, MEMORY_END)], Vec::new())
+        }
+    };
+    reserved.extend(fsimg_region(&memory));
     // gdb_println!(
     //     MAPPING_ENABLE,
     //     "[frame_allocator] manage pa[0x{:X} - 0x{:X}]",
@@ -153,6 +300,8 @@ pub fn init_frame_allocator() {
     FRAME_ALLOCATOR.write().init(
         PhysAddr::from(ekernel as usize).ceil(),
         PhysAddr::from(MEMORY_END).floor(),
+        &memory,
+        &reserved,
     );
     // frame_allocator_test();
 }
@@ -165,6 +314,23 @@ pub fn frame_alloc_without_clear() -> Option<FrameTracker> {
     FRAME_ALLOCATOR.write().alloc().map(FrameTracker::new_without_clear)
 }
 
+/// 分配 pages 个物理上连续的页帧（不清空），起始ppn按 2^order 对齐
+/// 超出 pages 的尾部页帧会立即归还；返回的每个页帧可单独释放
+pub fn frame_alloc_contiguous(pages: usize) -> Option<Vec<FrameTracker>> {
+    let order = pages.next_power_of_two().trailing_zeros() as usize;
+    let mut allocator = FRAME_ALLOCATOR.write();
+    let base = allocator.alloc_contiguous(order)?.0;
+    for ppn in base + pages..base + (1 << order) {
+        allocator.dealloc(ppn.into());
+    }
+    drop(allocator);
+    Some(
+        (base..base + pages)
+            .map(|ppn| FrameTracker::new_without_clear(ppn.into()))
+            .collect(),
+    )
+}
+
 pub fn frame_clean(ppn: PhysPageNum) {
     ppn.clear_page();
 }
@@ -192,6 +358,31 @@ pub fn frame_enquire_ref(ppn: PhysPageNum) -> usize {
         .enquire_ref(ppn)
 }
 
+pub fn frame_allocator_stat() -> FrameAllocatorStat {
+    FRAME_ALLOCATOR.read().stat()
+}
+
+#[allow(unused)]
+pub fn print_frame_allocator_stat() {
+    let stat = frame_allocator_stat();
+    info!(
+        "frames: total {} ({} KiB), used {}, free {}, shared {}",
+        stat.total_frames,
+        stat.total_frames * PAGE_SIZE / 1024,
+        stat.used_frames(),
+        stat.free_frames,
+        stat.shared_frames
+    );
+    for (order, (&free, &used)) in stat.free_blocks.iter().zip(stat.used_frames.iter()).enumerate() {
+        info!(
+            "  order {:>2} ({:>5} KiB): {} free blocks, {} frames used",
+            order,
+            (PAGE_SIZE << order) / 1024,
+            free,
+            used
+        );
+    }
+}
 
 #[allow(unused)]
 pub fn frame_allocator_test() {
@@ -210,5 +401,16 @@ pub fn frame_allocator_test() {
         v.push(frame);
     }
     drop(v);
+    let free_before = frame_allocator_stat().free_frames;
+    let blk = frame_alloc_contiguous(3).unwrap();
+    for (i, frame) in blk.iter().enumerate() {
+        assert_eq!(frame.ppn.0, blk[0].ppn.0 + i);
+    }
+    assert_eq!(blk[0].ppn.0 % 4, 0);
+    // 尾部多余的页帧已归还，3 个页帧计入 order 2
+    let used_before = frame_allocator_stat().used_frames[2];
+    drop(blk);
+    assert_eq!(frame_allocator_stat().used_frames[2], used_before - 3);
+    assert_eq!(frame_allocator_stat().free_frames, free_before);
     info!("frame_allocator_test passed!");
 }
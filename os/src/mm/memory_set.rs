@@ -1,22 +1,26 @@
 use super::frame_allocator::{frame_enquire_ref, frame_alloc_without_clear};
 use super::mmap::MmapArea;
+use super::UserLayout;
 use super::{frame_alloc, FrameTracker};
 use super::{PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
 use crate::config::{
-    aligned_down, is_aligned, DYNAMIC_LINKER, MEMORY_END, MMIO, PAGE_SIZE, SIGRETURN_TRAMPOLINE,
-    TRAMPOLINE, USER_STACK_BASE,
+    aligned_down, is_aligned, HUGE_PAGE_PAGES, MEMORY_END, MMIO, PAGE_SIZE, SIGRETURN_TRAMPOLINE,
+    TRAMPOLINE, VDSO_BASE,
 };
 use crate::fs::{open_common_file, OpenFlags};
 use crate::gdb_println;
 use crate::monitor::{MAPPING_ENABLE, QEMU};
+use super::vdso::VDSO;
 use crate::task::{
     AuxHeader, AT_BASE, AT_CLKTCK, AT_EGID, AT_ENTRY, AT_EUID, AT_FLAGS, AT_GID, AT_HWCAP,
-    AT_NOTELF, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM, AT_PLATFORM, AT_SECURE, AT_UID,
+    AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM, AT_SECURE, AT_SYSINFO_EHDR, AT_UID, HWCAP_ISA,
+    USER_HZ,
 };
 
-use alloc::string::{String, ToString};
+use alloc::collections::BTreeSet;
+use alloc::vec;
 use alloc::vec::Vec;
 use hashbrown::HashMap;
 use core::arch::asm;
@@ -39,60 +43,6 @@ extern "C" {
 
 pub static mut SATP: usize = 0;
 
-// TODO 此处仅仅在内核地址空间中保存一份lic.so, 可使用更优雅的方式解决
-// 还需在动态链接加载其不同时继续进行处理
-pub static KERNEL_DL_DATA: Lazy<RwLock<DLLMem>> = Lazy::new(|| RwLock::new(DLLMem::new()));
-
-pub struct DLLMem {
-    pub data: Vec<u8>,
-    pub name: String,
-}
-
-impl DLLMem {
-    pub fn new() -> Self {
-        Self {
-            data: Vec::with_capacity(0x1000),
-            name: "NULL".to_string(),
-        }
-    }
-    pub fn readso(&mut self, cwd: &str, path: &str) {
-        if let Some(app_vfile) = open_common_file(cwd, path, OpenFlags::RDONLY) {
-            self.name = path.to_string();
-            unsafe {
-                self.data = app_vfile.read_as_elf().to_vec();
-            }
-        } else {
-            error!(
-                "[execve load_dl] dynamic load dl {:#x?} false ... cwd is {:#x?}",
-                path, cwd
-            );
-            self.name = "NULL".to_string();
-            self.data.clear();
-        }
-    }
-}
-
-// fn read_dll(cwd: &str, path: &str) -> DLLMem {
-//     if let Some(app_vfile) = open_common_file(cwd, path, OpenFlags::RDONLY) {
-//         // let s = "libc.so".to_string();
-//         // let data = app_vfile.read_all();
-//         return
-//             DLLMem::new();
-//     } else {
-//         error!("[execve load_dl] dynamic load dl false");
-//         return DLLMem::new("NULL");
-//     }
-// }
-
-pub fn load_dll() {
-    let dl = KERNEL_DL_DATA.read();
-    debug!(
-        "load {} to kernel memoryset size:0x{:x} ......",
-        dl.name,
-        dl.data.len()
-    );
-}
-
 pub static KERNEL_SPACE: Lazy<RwLock<MemorySet>> =
     Lazy::new(|| RwLock::new(MemorySet::new_kernel()));
 
@@ -105,6 +55,44 @@ pub struct MemorySet {
     areas: Vec<MapArea>,
     heap_frames: HashMap<usize, FrameTracker>,
     pub mmap_areas: Vec<MmapArea>,
+    minor_faults: usize,
+    major_faults: usize,
+    max_resident_pages: usize,
+    /// mlock 锁定的页：madvise 不能释放，解除映射（munmap、brk 收缩、移除区域）时一并解锁
+    locked_pages: BTreeSet<usize>,
+}
+
+/// 地址空间的内存使用统计，除缺页次数外均以页为单位
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryStat {
+    /// 已建立的虚拟地址区域大小（不含堆，堆范围由进程维护）
+    pub vm_pages: usize,
+    /// 已映射到物理页的页数
+    pub resident_pages: usize,
+    /// 其中与其他地址空间共享的页数（COW页及文件页）
+    pub shared_pages: usize,
+    /// resident_pages 的峰值
+    pub max_resident_pages: usize,
+    /// 无需读文件即可处理的缺页（匿名页、堆、COW）
+    pub minor_faults: usize,
+    /// 需要读取文件数据的缺页（文件mmap）
+    pub major_faults: usize,
+    /// mlock 锁定的页数
+    pub locked_pages: usize,
+}
+
+impl MemoryStat {
+    /// 累加另一地址空间（已回收的子进程）的缺页次数和峰值
+    pub fn accumulate(&mut self, other: &MemoryStat) {
+        self.minor_faults += other.minor_faults;
+        self.major_faults += other.major_faults;
+        self.max_resident_pages = self.max_resident_pages.max(other.max_resident_pages);
+    }
+}
+
+#[inline(always)]
+fn range_pages(range: &VPNRange) -> usize {
+    range.get_end().0 - range.get_start().0
 }
 
 impl MemorySet {
@@ -114,6 +102,164 @@ impl MemorySet {
             areas: Vec::with_capacity(0x100),
             heap_frames: HashMap::new(),
             mmap_areas: Vec::with_capacity(0x100),
+            minor_faults: 0,
+            major_faults: 0,
+            max_resident_pages: 0,
+            locked_pages: BTreeSet::new(),
+        }
+    }
+    /// 已映射到物理页的页数，直接映射的只读ELF段视为常驻
+    pub fn resident_pages(&self) -> usize {
+        let areas: usize = self
+            .areas
+            .iter()
+            .map(|area| match area.area_type {
+                MapAreaType::ElfReadOnlyArea => range_pages(&area.vpn_range),
+                _ => area.data_frames.len(),
+            })
+            .sum();
+        let mmaps: usize = self.mmap_areas.iter().map(|area| area.data_frames.len()).sum();
+        areas + mmaps + self.heap_frames.len()
+    }
+    /// 记录一次已处理的缺页，vpn 落在文件mmap区域内的计为 major
+    pub fn record_fault(&mut self, vpn: VirtPageNum) {
+        let major = self.mmap_areas.iter().any(|area| {
+            vpn >= area.vpn_range.get_start()
+                && vpn < area.vpn_range.get_end()
+                && area.fd as isize != -1
+        });
+        self.count_fault(major);
+    }
+    fn count_fault(&mut self, major: bool) {
+        if major {
+            self.major_faults += 1;
+        } else {
+            self.minor_faults += 1;
+        }
+        self.max_resident_pages = self.max_resident_pages.max(self.resident_pages());
+    }
+    /// exec 时保留原地址空间的缺页次数和峰值
+    pub fn inherit_stat(&mut self, old: &MemorySet) {
+        self.minor_faults = old.minor_faults;
+        self.major_faults = old.major_faults;
+        self.max_resident_pages = old.max_resident_pages;
+    }
+    pub fn stat(&self) -> MemoryStat {
+        let shared = |ppn: PhysPageNum| frame_enquire_ref(ppn) > 1;
+        let mut shared_pages = 0;
+        for area in self.areas.iter() {
+            match area.area_type {
+                MapAreaType::ElfReadOnlyArea => shared_pages += range_pages(&area.vpn_range),
+                _ => {
+                    shared_pages += area.data_frames.values().filter(|f| shared(f.ppn)).count()
+                }
+            }
+        }
+        for area in self.mmap_areas.iter() {
+            shared_pages += area
+                .data_frames
+                .values()
+                .filter(|f| f.as_ref().map_or(true, |f| shared(f.ppn)))
+                .count();
+        }
+        shared_pages += self.heap_frames.values().filter(|f| shared(f.ppn)).count();
+        let vm_pages = self
+            .areas
+            .iter()
+            .map(|area| range_pages(&area.vpn_range))
+            .chain(self.mmap_areas.iter().map(|area| range_pages(&area.vpn_range)))
+            .sum();
+        let resident_pages = self.resident_pages();
+        MemoryStat {
+            vm_pages,
+            resident_pages,
+            shared_pages,
+            max_resident_pages: self.max_resident_pages.max(resident_pages),
+            minor_faults: self.minor_faults,
+            major_faults: self.major_faults,
+            locked_pages: self.locked_pages.len(),
+        }
+    }
+    /// vpn 是否落在某个已建立的区域内（不含堆）
+    pub fn contains_vpn(&self, vpn: VirtPageNum) -> bool {
+        self.areas
+            .iter()
+            .map(|area| &area.vpn_range)
+            .chain(self.mmap_areas.iter().map(|area| &area.vpn_range))
+            .any(|range| vpn >= range.get_start() && vpn < range.get_end())
+    }
+    /// vpn 当前是否已映射到物理页
+    pub fn is_resident(&self, vpn: VirtPageNum) -> bool {
+        self.translate(vpn).map_or(false, |pte| pte.is_valid())
+    }
+    /// 释放[start_vpn, end_vpn)内堆和mmap区域已分配的页，再次访问时重新缺页
+    /// 其余区域的页立即映射，不做处理；范围内有锁定页时失败返回-1
+    pub fn release_pages(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> isize {
+        if self.locked_pages.range(start_vpn.0..end_vpn.0).next().is_some() {
+            return -1;
+        }
+        for area in self.mmap_areas.iter_mut() {
+            let area_start = area.vpn_range.get_start();
+            let area_end = area.vpn_range.get_end();
+            if area_end <= start_vpn || area_start >= end_vpn {
+                continue;
+            }
+            area.unmap_range(
+                &mut self.page_table,
+                start_vpn.max(area_start),
+                end_vpn.min(area_end),
+            );
+        }
+        let dropped: Vec<(_, _)> = self
+            .heap_frames
+            .drain_filter(|vpn, _| *vpn >= start_vpn.0 && *vpn < end_vpn.0)
+            .collect();
+        for (vpn, _) in dropped.iter() {
+            self.page_table.unmap(VirtPageNum::from(*vpn));
+        }
+        unsafe {
+            asm!("sfence.vma");
+        }
+        0
+    }
+    /// 为[start_vpn, end_vpn)内尚未分配的堆页和mmap页预先分配页帧
+    pub fn populate(
+        &mut self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+        user_heap_base: usize,
+        user_heap_top: usize,
+    ) {
+        for vpn in VPNRange::new(start_vpn, end_vpn) {
+            if self.is_resident(vpn) {
+                continue;
+            }
+            let va = VirtAddr::from(vpn).0;
+            if self.insert_heap_dataframe(va, user_heap_base, user_heap_top) != 0 {
+                self.insert_mmap_dataframe(vpn);
+            }
+        }
+        unsafe {
+            asm!("sfence.vma");
+        }
+    }
+    /// 锁定[start_vpn, end_vpn)内的页（调用者需先populate）
+    pub fn lock_pages(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) {
+        self.locked_pages.extend(start_vpn.0..end_vpn.0);
+    }
+    /// 再锁定[start_vpn, end_vpn)后的锁定页总数
+    pub fn locked_pages_after(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> usize {
+        let already = self.locked_pages.range(start_vpn.0..end_vpn.0).count();
+        self.locked_pages.len() + (end_vpn.0 - start_vpn.0) - already
+    }
+    pub fn unlock_pages(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) {
+        let unlocked: Vec<usize> = self
+            .locked_pages
+            .range(start_vpn.0..end_vpn.0)
+            .copied()
+            .collect();
+        for vpn in unlocked {
+            self.locked_pages.remove(&vpn);
         }
     }
     pub fn token(&self) -> usize {
@@ -140,8 +286,42 @@ impl MemorySet {
             .enumerate()
             .find(|(_, area)| area.vpn_range.get_start() == start_vpn)
         {
+            let end_vpn = area.vpn_range.get_end();
             area.unmap(&mut self.page_table);
             self.areas.swap_remove(idx);
+            self.unlock_pages(start_vpn, end_vpn);
+        }
+    }
+    /// 移除包含 vpn 的区域
+    pub fn remove_area_containing(&mut self, vpn: VirtPageNum) {
+        if let Some(start) = self
+            .areas
+            .iter()
+            .map(|area| &area.vpn_range)
+            .find(|range| range.get_start() <= vpn && vpn < range.get_end())
+            .map(|range| range.get_start())
+        {
+            self.remove_area_with_start_vpn(start);
+        }
+    }
+    /// 移除除 keep 以外所有线程的 trap_cx 与用户栈区域，保留包含 keep 中任一页号的区域
+    pub fn remove_thread_areas_except(&mut self, keep: &[VirtPageNum]) {
+        let starts: Vec<VirtPageNum> = self
+            .areas
+            .iter()
+            .filter(|area| {
+                matches!(area.area_type, MapAreaType::TrapContext | MapAreaType::UserStack)
+            })
+            .map(|area| &area.vpn_range)
+            .filter(|range| {
+                !keep
+                    .iter()
+                    .any(|&vpn| range.get_start() <= vpn && vpn < range.get_end())
+            })
+            .map(|range| range.get_start())
+            .collect();
+        for start in starts {
+            self.remove_area_with_start_vpn(start);
         }
     }
     /// 在 MemorySet.page_table 中为 MapArea 创建页表项 , 页属性为MapArea 对应的属性( R W X U )
@@ -197,6 +377,14 @@ impl MemorySet {
             PTEFlags::R | PTEFlags::X | PTEFlags::U,
         );
     }
+    /// vDSO 页由所有用户地址空间共享
+    fn map_vdso(&mut self) {
+        self.page_table.map(
+            VirtAddr::from(VDSO_BASE).into(),
+            VDSO.ppn,
+            PTEFlags::R | PTEFlags::X | PTEFlags::U,
+        );
+    }
     /// Without kernel stacks.
     pub fn new_kernel() -> Self {
         let mut memory_set = Self::new_bare();
@@ -296,98 +484,78 @@ impl MemorySet {
         debug!("mapping done");
         memory_set
     }
-    /// load libc.so(elf) to DYNAMIC_LINKER
-    /// return value 0: erro,   other: ld入口地址 (&mut self, elf_data: &[u8])
-    pub fn load_dl(&mut self, elf: &ElfFile) -> usize {
-        let s = match elf.find_section_by_name(".interp") {
-            Some(s) => s,
-            None => return 0,
+    /// 把 PT_INTERP 指定的动态链接器装入到 dl_base
+    /// 只读段直接映射解释器文件的页缓存，由使用同一解释器的进程共享
+    /// 返回 Ok(None) 表示静态链接程序，Ok(Some(entry)) 中 entry 为动态链接器相对 dl_base 的入口
+    pub fn load_dl(&mut self, elf: &ElfFile, dl_base: usize) -> Result<Option<usize>, ()> {
+        let interp = (0..elf.header.pt2.ph_count())
+            .map(|i| elf.program_header(i).unwrap())
+            .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Interp));
+        let interp = match interp {
+            Some(interp) => interp,
+            None => return Ok(None),
         };
-        let s = s.raw_data(&elf).to_vec();
-        let mut s = String::from_utf8(s).unwrap();
-
+        let offset = interp.offset() as usize;
+        let path = elf
+            .input
+            .get(offset..offset + interp.file_size() as usize)
+            .ok_or(())?;
         // 移除末尾\0
-        s = s.strip_suffix("\0").unwrap_or(&s).to_string();
-
-        // info!("load_linker interp: {:?}", s);
-        // 手动转发到 libc.so
-        if s == "/lib/ld-musl-riscv64-sf.so.1" {
-            s = "libc.so".to_string();
-        }
-        // info!("load_linker interp: {:?}", s);
-        let memdll = &mut KERNEL_DL_DATA.write();
-        if s != memdll.name {
-            // info!("load_linker to mem");
-            memdll.readso("/", s.as_str());
-        }
+        let path = core::str::from_utf8(path).map_err(|_| ())?.trim_end_matches('\0');
+        let dl_file = open_common_file("/", path, OpenFlags::RDONLY).ok_or(())?;
+        let dl_data = unsafe { dl_file.read_as_elf() };
+        let dl = ElfFile::new(dl_data).map_err(|_| ())?;
 
-        let all_data = &memdll.data;
-        if all_data.len() == 0 {
-            return 0;
-        }
-        // println!("[load_dl]  KERNEL_DL_DATA.len():{}", all_data.len());
-        let elf_data = all_data.as_slice();
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
-        let elf_header = elf.header;
-        let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf-dl !");
-        let ph_count = elf_header.pt2.ph_count();
-
-        for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            let start_va: VirtAddr = (DYNAMIC_LINKER + ph.virtual_addr() as usize).into();
-            // let start_va: VirtAddr = (ph.virtual_addr() as usize).into(); // virtual_addr 应该是0
+        for i in 0..dl.header.pt2.ph_count() {
+            let ph = dl.program_header(i).unwrap();
+            if ph.get_type() != Ok(xmas_elf::program::Type::Load) {
+                continue;
+            }
+            let start_va: VirtAddr = (dl_base + ph.virtual_addr() as usize).into();
             let end_va: VirtAddr =
-                (DYNAMIC_LINKER + ph.virtual_addr() as usize + ph.mem_size() as usize).into();
-
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                // println!("[load_dl] start_va:{:#?},   end_va: {:#?} ", start_va, end_va);
-                let mut map_perm = MapPermission::U;
-                let mut area_type = MapAreaType::ElfReadOnlyArea;
-                let ph_flags = ph.flags();
-                if ph_flags.is_read() {
-                    map_perm |= MapPermission::R;
-                }
-                if ph_flags.is_write() {
-                    map_perm |= MapPermission::W;
-                    area_type = MapAreaType::ElfReadWriteArea;
-                }
-                if ph_flags.is_execute() {
-                    map_perm |= MapPermission::X;
-                }
-                let map_area = MapArea::new(area_type, start_va, end_va, MapType::Framed, map_perm);
-                // println!("[load_dl]  elf.input:{}, start:{},   end:{} ", &elf.input.len(), ph.offset() as usize, (ph.offset() + ph.file_size()) as usize);
-                if area_type == MapAreaType::ElfReadWriteArea {
-                    self.push(
-                        map_area,
-                        Some(
-                            &elf.input
-                                [ph.offset() as usize..(ph.offset() + ph.file_size()) as usize],
-                        ),
-                        start_va.page_offset(),
-                    );
-                } else {
-                    self.push(
-                        map_area,
-                        Some(
-                            &elf.input
-                                [ph.offset() as usize..(ph.offset() + ph.file_size()) as usize],
-                        ),
-                        start_va.page_offset(),
-                    );
-                }
+                (dl_base + ph.virtual_addr() as usize + ph.mem_size() as usize).into();
+            let mut map_perm = MapPermission::U;
+            let mut area_type = MapAreaType::ElfReadOnlyArea;
+            let ph_flags = ph.flags();
+            if ph_flags.is_read() {
+                map_perm |= MapPermission::R;
             }
+            if ph_flags.is_write() {
+                map_perm |= MapPermission::W;
+                area_type = MapAreaType::ElfReadWriteArea;
+            }
+            if ph_flags.is_execute() {
+                map_perm |= MapPermission::X;
+            }
+            let mut map_area = MapArea::new(area_type, start_va, end_va, MapType::Framed, map_perm);
+            let ph_offset = ph.offset() as usize;
+            let data = &dl_data[ph_offset..ph_offset + ph.file_size() as usize];
+            if area_type == MapAreaType::ElfReadWriteArea {
+                self.push(map_area, Some(data), start_va.page_offset());
+            } else {
+                map_area.add_direct_mapping_slice(data);
+                self.push_with_direct_mapping(map_area);
+            }
+            gdb_println!(
+                MAPPING_ENABLE,
+                "[user-dlmap] va[0x{:X} - 0x{:X}] {}",
+                start_va.0,
+                end_va.0,
+                path
+            );
         }
-        return elf_header.pt2.entry_point() as usize;
+        Ok(Some(dl.header.pt2.entry_point() as usize))
     }
     /// Include sections in elf and trampoline,
     /// also returns user_sp_base and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize, usize, Vec<AuxHeader>) {
+    /// 栈、堆及动态链接器的位置由 layout 决定
+    pub fn from_elf(elf_data: &[u8], layout: &UserLayout) -> (Self, usize, usize, usize, Vec<AuxHeader>) {
         assert!(is_aligned(elf_data.as_ptr() as usize));
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_sigreturn_trampoline();
         memory_set.map_trampoline();
+        memory_set.map_vdso();
         // map program headers of elf, with U flag
         let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
         let elf_header = elf.header;
@@ -395,100 +563,22 @@ impl MemorySet {
         assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
         let ph_count = elf_header.pt2.ph_count();
         let mut max_end_vpn = VirtPageNum(0);
-        let mut _at_base = 0usize;
-
-        // println!("[from_elf] program_header-2 : type is {:#?} ",ph.get_type().unwrap());
-        // run other programs
-        let mut auxv: Vec<AuxHeader> = Vec::with_capacity(64);
-
-        auxv.push(AuxHeader {
-            aux_type: AT_PHENT,
-            value: elf.header.pt2.ph_entry_size() as usize,
-        }); // ELF64 header 64bytes
-
-        auxv.push(AuxHeader {
-            aux_type: AT_PHNUM,
-            value: ph_count as usize,
-        });
-        auxv.push(AuxHeader {
-            aux_type: AT_PAGESZ,
-            value: PAGE_SIZE as usize,
-        });
-
-        auxv.push(AuxHeader {
-            aux_type: AT_BASE,
-            value: 0 as usize,
-        });
-
-        _at_base = memory_set.load_dl(&elf);
-
-        if _at_base != 0 {
-            auxv.push(AuxHeader {
-                aux_type: AT_BASE,
-                value: DYNAMIC_LINKER as usize,
-            });
-            _at_base += DYNAMIC_LINKER;
-        }
-
-        auxv.push(AuxHeader {
-            aux_type: AT_FLAGS,
-            value: 0 as usize,
-        });
-        auxv.push(AuxHeader {
-            aux_type: AT_ENTRY,
-            value: elf.header.pt2.entry_point() as usize,
-        });
-        auxv.push(AuxHeader {
-            aux_type: AT_UID,
-            value: 0 as usize,
-        });
-        auxv.push(AuxHeader {
-            aux_type: AT_EUID,
-            value: 0 as usize,
-        });
-        auxv.push(AuxHeader {
-            aux_type: AT_GID,
-            value: 0 as usize,
-        });
-        auxv.push(AuxHeader {
-            aux_type: AT_EGID,
-            value: 0 as usize,
-        });
-        auxv.push(AuxHeader {
-            aux_type: AT_PLATFORM,
-            value: 0 as usize,
-        });
-        auxv.push(AuxHeader {
-            aux_type: AT_HWCAP,
-            value: 0 as usize,
-        });
-        auxv.push(AuxHeader {
-            aux_type: AT_CLKTCK,
-            value: 100 as usize,
-        });
-        auxv.push(AuxHeader {
-            aux_type: AT_SECURE,
-            value: 0 as usize,
-        });
-        auxv.push(AuxHeader {
-            aux_type: AT_NOTELF,
-            value: 0x112d as usize,
-        });
-
-        let mut head_va = 0;
+        // 位置无关可执行文件的链接地址从 0 开始，整体加载到 exec_base
+        let bias = match elf_header.pt2.type_().as_type() {
+            xmas_elf::header::Type::SharedObject => layout.exec_base,
+            _ => 0,
+        };
+        let mut phdr_va = 0;
 
         for i in 0..ph_count {
             let ph = elf.program_header(i).unwrap();
             // println!("[from_elf] program_header-{:#?} : type is {:#?} ", i, ph.get_type().unwrap());
             // println!("[from_elf] virtual_addr : {:X},  mem_size is {:X} ", ph.virtual_addr(), ph.mem_size());
             if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
-                
+                let start_va: VirtAddr = (bias + ph.virtual_addr() as usize).into();
+                let end_va: VirtAddr = (bias + (ph.virtual_addr() + ph.mem_size()) as usize).into();
+
                 let mut map_perm = MapPermission::U;
-                if head_va == 0 {
-                    head_va = start_va.0;
-                }
                 let mut area_type = MapAreaType::ElfReadOnlyArea;
                 let ph_flags = ph.flags();
                 if ph_flags.is_read() {
@@ -509,6 +599,11 @@ impl MemorySet {
 
                 let ph_offset = ph.offset() as usize;
                 let ph_file_size = ph.file_size() as usize;
+                // 没有 PT_PHDR 时，由包含程序头表的段得到其虚拟地址
+                let ph_table = elf_header.pt2.ph_offset() as usize;
+                if phdr_va == 0 && ph_offset <= ph_table && ph_table < ph_offset + ph_file_size {
+                    phdr_va = start_va.0 + ph_table - ph_offset;
+                }
                 let data = &elf_data[ph_offset..(ph_offset + ph_file_size)];
                 // println!("[load_dl]  elf.input:{}, start:{},   end:{} ", &elf.input.len(), ph.offset() as usize, (ph.offset() + ph.file_size()) as usize);
                 if area_type == MapAreaType::ElfReadWriteArea {
@@ -523,28 +618,43 @@ impl MemorySet {
                     start_va.0,
                     end_va.0
                 );
+            } else if ph.get_type().unwrap() == xmas_elf::program::Type::Phdr {
+                phdr_va = bias + ph.virtual_addr() as usize;
             }
         }
 
-        let ph_head_addr = head_va + elf.header.pt2.ph_offset() as usize;
-        // println!("[from_elf] AT_PHDR  ph_head_addr is {:X} ", ph_head_addr);
-        auxv.push(AuxHeader {
-            aux_type: AT_PHDR,
-            value: ph_head_addr as usize,
-        });
+        let elf_entry = bias + elf_header.pt2.entry_point() as usize;
+        // 动态链接程序从动态链接器的入口开始执行
+        let (at_base, entry) = match memory_set.load_dl(&elf, layout.dyn_base) {
+            Ok(Some(dl_entry)) => (layout.dyn_base, layout.dyn_base + dl_entry),
+            Ok(None) => (0, elf_entry),
+            // 动态链接器不存在或无法解析
+            Err(()) => return (memory_set, 0, 0, 0, Vec::new()),
+        };
 
-        let entry: usize;
-        if _at_base == 0 {
-            // 静态链接程序
-            entry = elf.header.pt2.entry_point() as usize;
-        } else {
-            entry = _at_base;
-        }
+        let auxv = vec![
+            AuxHeader::new(AT_PHDR, phdr_va),
+            AuxHeader::new(AT_PHENT, elf_header.pt2.ph_entry_size() as usize),
+            AuxHeader::new(AT_PHNUM, ph_count as usize),
+            AuxHeader::new(AT_PAGESZ, PAGE_SIZE),
+            AuxHeader::new(AT_BASE, at_base),
+            AuxHeader::new(AT_FLAGS, 0),
+            AuxHeader::new(AT_ENTRY, elf_entry),
+            // 没有用户与组的概念，始终以 root 身份运行
+            AuxHeader::new(AT_UID, 0),
+            AuxHeader::new(AT_EUID, 0),
+            AuxHeader::new(AT_GID, 0),
+            AuxHeader::new(AT_EGID, 0),
+            AuxHeader::new(AT_SECURE, 0),
+            AuxHeader::new(AT_HWCAP, HWCAP_ISA),
+            AuxHeader::new(AT_CLKTCK, USER_HZ),
+            AuxHeader::new(AT_SYSINFO_EHDR, VDSO_BASE),
+        ];
 
         // println!("[from_elf] elf entry : {:X} ",elf.header.pt2.entry_point() as usize);
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let user_heap_base: usize = max_end_va.into();
-        (memory_set, USER_STACK_BASE, entry, user_heap_base, auxv)
+        let user_heap_base: usize = usize::from(max_end_va) + layout.heap_offset;
+        (memory_set, layout.ustack_base, entry, user_heap_base, auxv)
     }
 
     pub fn cow_from_existed_user(user_space: &mut MemorySet) -> MemorySet {
@@ -552,6 +662,7 @@ impl MemorySet {
         // map trampoline & strampoline
         memory_set.map_sigreturn_trampoline();
         memory_set.map_trampoline();
+        memory_set.map_vdso();
         
         for area in user_space.areas.iter() {
             let mut new_area = MapArea::from_another(area);
@@ -602,7 +713,7 @@ impl MemorySet {
         for area in user_space.mmap_areas.iter() {
             // error!("cp mmap");
             // 建立新的mmap_area，有vpn范围和dataframes（没有数据）
-            let new_area = MmapArea::from_another(area);
+            let new_area = MmapArea::from_another(area, parent_page_table);
             // 建立页表映射（物理页帧自动分配），同时添加dataframes
             memory_set.push_and_map_mmap_area(new_area);
             // 拷贝数据
@@ -642,6 +753,7 @@ impl MemorySet {
                 vpn,
                 self.page_table.translate(vpn).unwrap().flags() | PTEFlags::W,
             );
+            self.count_fault(false);
             return;
         }
         // info!("cow_alloc ref = 2");
@@ -651,16 +763,15 @@ impl MemorySet {
         // info!("cow_remapping  vpn:{:?}, former_ppn:{:?}, ppn:{:?}",vpn, former_ppn, ppn);
         if is_heap {
             self.heap_frames.insert(vpn.0, frame);
-            return;
-        }
-        for area in self.areas.iter_mut() {
-            let head_vpn = area.vpn_range.get_start();
-            let tail_vpn = area.vpn_range.get_end();
-            if vpn < tail_vpn && vpn >= head_vpn {
-                area.data_frames.insert(vpn.0, frame);
-                break;
-            }
+        } else if let Some(area) = self
+            .areas
+            .iter_mut()
+            .find(|area| vpn >= area.vpn_range.get_start() && vpn < area.vpn_range.get_end())
+        {
+            area.data_frames.insert(vpn.0, frame);
         }
+        // 写时复制不读文件，即使位于文件映射中也计为 minor
+        self.count_fault(false);
     }
     pub fn activate(&self) {
         let satp = self.page_table.token();
@@ -672,7 +783,7 @@ impl MemorySet {
     }
 
     /// 设置pte标志位，失败返回-1
-    pub fn set_pte_flags(&self, vpn: VirtPageNum, flags: PTEFlags) -> isize {
+    pub fn set_pte_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) -> isize {
         if self.page_table.set_pte_flags(vpn, flags).is_none() {
             -1
         } else {
@@ -688,6 +799,7 @@ impl MemorySet {
         self.areas.clear();
         self.mmap_areas.clear();
         self.heap_frames.clear();
+        self.locked_pages.clear();
     }
 
     /// 插入一个mmap区域
@@ -731,6 +843,44 @@ impl MemorySet {
         -1
     }
 
+    /// 解除[start_vpn, end_vpn)内的mmap映射，部分覆盖的区域被拆分，保留两侧剩余部分
+    pub fn munmap(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> isize {
+        self.unlock_pages(start_vpn, end_vpn);
+        let mut i = 0;
+        while i < self.mmap_areas.len() {
+            let area = &mut self.mmap_areas[i];
+            let area_start = area.vpn_range.get_start();
+            let area_end = area.vpn_range.get_end();
+            if area_end <= start_vpn || area_start >= end_vpn {
+                i += 1;
+                continue;
+            }
+            area.unmap_range(
+                &mut self.page_table,
+                start_vpn.max(area_start),
+                end_vpn.min(area_end),
+            );
+            let tail = if end_vpn < area_end {
+                Some(area.split_off(end_vpn))
+            } else {
+                None
+            };
+            if area_start < start_vpn {
+                area.vpn_range = VPNRange::new(area_start, start_vpn);
+                i += 1;
+            } else {
+                self.mmap_areas.swap_remove(i);
+            }
+            if let Some(tail) = tail {
+                self.mmap_areas.push(tail);
+            }
+        }
+        unsafe {
+            asm!("sfence.vma");
+        }
+        0
+    }
+
     pub fn insert_heap_dataframe(
         &mut self,
         va: usize,
@@ -753,6 +903,7 @@ impl MemorySet {
     pub fn remove_heap_dataframes(&mut self, prev_top: usize, current_top: usize) {
         // println!("remove_heap_dataframes {:#x?} {:#x}", prev_top, current_top);
         assert!(current_top < prev_top);
+        self.unlock_pages(VirtAddr::from(current_top).ceil(), VirtAddr::from(prev_top).ceil());
         let dropped: Vec<(_, _)> = self
             .heap_frames
             .drain_filter(|vpn, _| {
@@ -836,10 +987,31 @@ impl MapArea {
         page_table.unmap(vpn);
     }
     pub fn map(&mut self, page_table: &mut PageTable) {
+        if self.map_type == MapType::Identical && self.area_type == MapAreaType::KernelSpaceArea {
+            self.map_identical_huge(page_table);
+            return;
+        }
         for vpn in self.vpn_range {
             self.map_one(page_table, vpn);
         }
     }
+    /// 内核恒等映射中2MiB对齐的部分使用大页映射，减少页表占用和TLB缺失
+    fn map_identical_huge(&mut self, page_table: &mut PageTable) {
+        let end = self.vpn_range.get_end();
+        let mut vpn = self.vpn_range.get_start();
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        while vpn < end {
+            if vpn.0 % HUGE_PAGE_PAGES == 0
+                && vpn.0 + HUGE_PAGE_PAGES <= end.0
+                && page_table.map_huge(vpn, PhysPageNum(vpn.0), pte_flags)
+            {
+                vpn = VirtPageNum(vpn.0 + HUGE_PAGE_PAGES);
+                continue;
+            }
+            self.map_one(page_table, vpn);
+            vpn.step();
+        }
+    }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
         for vpn in self.vpn_range {
             self.unmap_one(page_table, vpn);
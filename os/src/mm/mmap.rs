@@ -1,12 +1,14 @@
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
 use hashbrown::HashMap;
 
 use crate::{
-    config::PAGE_SIZE,
+    config::{HUGE_PAGE_PAGES, PAGE_SIZE},
     fs::{File, FileClass},
 };
 
 use super::{
-    address::VPNRange, frame_alloc, frame_allocator::frame_alloc_without_clear,
+    address::VPNRange, frame_alloc, frame_alloc_contiguous, frame_allocator::frame_alloc_without_clear,
     page_table::PTEFlags, translated_byte_buffer, FrameTracker, MapPermission, PageTable, PhysAddr,
     PhysPageNum, UserBuffer, VirtAddr, VirtPageNum,
 };
@@ -27,6 +29,7 @@ bitflags! {
         const _X9 = 1 << 9;
         const _X10 = 1 << 10;
         const _X11 = 1 << 11;
+        const MAP_HUGETLB = 1 << 18;
     }
 }
 
@@ -42,6 +45,8 @@ pub struct MmapArea {
     pub fd: usize,
     pub offset: usize,
     pub data_frames: HashMap<usize, Option<FrameTracker>>,
+    /// 以2MiB大页映射的块的起始vpn（每块的512个页帧仍逐页记录在data_frames中）
+    pub huge_pages: BTreeSet<usize>,
 }
 
 impl MmapArea {
@@ -62,10 +67,18 @@ impl MmapArea {
             fd,
             offset,
             data_frames: HashMap::new(),
+            huge_pages: BTreeSet::new(),
         }
     }
 
-    pub fn from_another(another: &MmapArea) -> Self {
+    /// 私有可写映射，计入 RLIMIT_DATA
+    pub fn is_private_writable(&self) -> bool {
+        self.map_perm.contains(MapPermission::W)
+            && !MmapFlags::from_bits_truncate(self.flags).contains(MmapFlags::MAP_SHARED)
+    }
+
+    /// page_table为another所在的页表，用于判断大页是否已被拆分
+    pub fn from_another(another: &MmapArea, page_table: &PageTable) -> Self {
         let mut new_area = Self {
             vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
             map_perm: another.map_perm,
@@ -74,8 +87,24 @@ impl MmapArea {
             fd: another.fd,
             offset: another.offset,
             data_frames: HashMap::new(),
+            huge_pages: BTreeSet::new(),
         };
+        // 大页尽量仍以大页复制，分配连续页帧失败时退化为普通页
+        for &head in another.huge_pages.iter() {
+            if !page_table.is_huge(head.into()) {
+                continue;
+            }
+            if let Some(frames) = frame_alloc_contiguous(HUGE_PAGE_PAGES) {
+                for (i, frame) in frames.into_iter().enumerate() {
+                    new_area.data_frames.insert(head + i, Some(frame));
+                }
+                new_area.huge_pages.insert(head);
+            }
+        }
         for (vpn, _) in (&another.data_frames).into_iter() {
+            if new_area.data_frames.contains_key(vpn) {
+                continue;
+            }
             let frame = frame_alloc_without_clear().unwrap();
             // let frame = frame_alloc().unwrap();
             new_area.data_frames.insert(*vpn, Some(frame));
@@ -92,21 +121,34 @@ impl MmapArea {
             fd: another.fd,
             offset: another.offset,
             data_frames: HashMap::new(),
+            huge_pages: BTreeSet::new(),
         };
         new_area
     }
 
+    #[inline(always)]
+    fn huge_head(vpn: usize) -> usize {
+        vpn & !(HUGE_PAGE_PAGES - 1)
+    }
+
     /// 这里有问题：pte_flags可能被sys_mprotect修改，导致其与self.map_perm不一致.
     /// fake solution here.
     pub fn map_all(&self, page_table: &mut PageTable) {
+        // let pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap();
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap()
+            | PTEFlags::U
+            | PTEFlags::R
+            | PTEFlags::W;
+        for &head in self.huge_pages.iter() {
+            let ppn = self.data_frames[&head].as_ref().unwrap().ppn;
+            assert!(page_table.map_huge(head.into(), ppn, pte_flags));
+        }
         for (vpn, frame_unwrapped) in (&self.data_frames).into_iter() {
+            if self.huge_pages.contains(&Self::huge_head(*vpn)) {
+                continue;
+            }
             if let Some(frame) = frame_unwrapped {
                 let ppn = frame.ppn;
-                // let pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap();
-                let pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap()
-                    | PTEFlags::U
-                    | PTEFlags::R
-                    | PTEFlags::W;
                 page_table.map((*vpn).into(), ppn, pte_flags);
             }
         }
@@ -120,6 +162,9 @@ impl MmapArea {
         let token = page_table.token();
 
         if self.fd as isize == -1 {
+            if self.map_huge(page_table, vpn, pte_flags) {
+                return 0;
+            }
             let frame = frame_alloc().unwrap();
             let ppn = frame.ppn;
             self.data_frames.insert(vpn.0, Some(frame));
@@ -161,12 +206,119 @@ impl MmapArea {
         0
     }
 
+    /// 匿名映射中完整包含于区域内的2MiB对齐块，在首次缺页时整体以大页映射
+    /// （MAP_HUGETLB 仅影响区域的对齐方式），连续页帧不足时返回false退化为普通页
+    fn map_huge(&mut self, page_table: &mut PageTable, vpn: VirtPageNum, pte_flags: PTEFlags) -> bool {
+        let head = Self::huge_head(vpn.0);
+        if head < self.vpn_range.get_start().0
+            || head + HUGE_PAGE_PAGES > self.vpn_range.get_end().0
+            || (head..head + HUGE_PAGE_PAGES).any(|v| self.data_frames.contains_key(&v))
+        {
+            return false;
+        }
+        let frames = match frame_alloc_contiguous(HUGE_PAGE_PAGES) {
+            Some(frames) => frames,
+            None => return false,
+        };
+        if !page_table.map_huge(head.into(), frames[0].ppn, pte_flags) {
+            return false;
+        }
+        for (i, frame) in frames.into_iter().enumerate() {
+            frame.ppn.clear_page();
+            self.data_frames.insert(head + i, Some(frame));
+        }
+        self.huge_pages.insert(head);
+        true
+    }
+
     pub fn unmap(&self, page_table: &mut PageTable) {
+        for &head in self.huge_pages.iter() {
+            page_table.unmap_huge(head.into());
+        }
         for vpn in self.data_frames.keys() {
+            if self.huge_pages.contains(&Self::huge_head(*vpn)) {
+                continue;
+            }
+            page_table.unmap((*vpn).into());
+        }
+    }
+
+    /// MAP_SHARED 文件映射
+    fn is_shared_file(&self) -> bool {
+        self.fd as isize != -1
+            && MmapFlags::from_bits_truncate(self.flags).contains(MmapFlags::MAP_SHARED)
+    }
+
+    /// 文件页直接映射页缓存，fork 复制得到的私有页帧在丢弃前需写回页缓存，否则其中的修改会丢失
+    fn write_back(&self, vpn: usize, frame: &FrameTracker) {
+        if let Some(FileClass::File(f)) = &self.fd_one {
+            let file_off = self.offset + (vpn - self.vpn_range.get_start().0) * PAGE_SIZE;
+            let size = f.file_size();
+            if file_off >= size {
+                return;
+            }
+            let len = (size - file_off).min(PAGE_SIZE);
+            unsafe {
+                let cache = f.get_data_cache_physaddr(file_off) as *mut u8;
+                core::slice::from_raw_parts_mut(cache, len)
+                    .copy_from_slice(&frame.ppn.slice_u8()[..len]);
+            }
+        }
+    }
+
+    /// 解除[start, end)内已映射页的映射并释放页帧，部分覆盖的大页先拆分
+    /// MAP_SHARED 文件映射的私有页帧先写回页缓存
+    pub fn unmap_range(&mut self, page_table: &mut PageTable, start: VirtPageNum, end: VirtPageNum) {
+        let (start, end) = (start.0, end.0);
+        let heads: Vec<usize> = self
+            .huge_pages
+            .range(Self::huge_head(start)..end)
+            .copied()
+            .collect();
+        for head in heads {
+            self.huge_pages.remove(&head);
+            if head >= start && head + HUGE_PAGE_PAGES <= end {
+                page_table.unmap_huge(head.into());
+                for vpn in head..head + HUGE_PAGE_PAGES {
+                    self.data_frames.remove(&vpn);
+                }
+            } else {
+                page_table.split_huge(head.into());
+            }
+        }
+        let dropped: Vec<(_, _)> = self
+            .data_frames
+            .drain_filter(|vpn, _| *vpn >= start && *vpn < end)
+            .collect();
+        let shared_file = self.is_shared_file();
+        for (vpn, frame) in dropped.iter() {
+            if let (true, Some(frame)) = (shared_file, frame) {
+                self.write_back(*vpn, frame);
+            }
             page_table.unmap((*vpn).into());
         }
     }
 
+    /// 在at处将区域一分为二，self保留前半部分，返回后半部分
+    pub fn split_off(&mut self, at: VirtPageNum) -> Self {
+        let start = self.vpn_range.get_start();
+        let end = self.vpn_range.get_end();
+        assert!(start < at && at < end);
+        let mut tail = Self::new(
+            at,
+            end,
+            self.map_perm,
+            self.flags,
+            self.fd_one.clone(),
+            self.fd,
+            self.offset + (at.0 - start.0) * PAGE_SIZE,
+        );
+        tail.data_frames = self.data_frames.drain_filter(|vpn, _| *vpn >= at.0).collect();
+        tail.huge_pages = self.huge_pages.split_off(&at.0);
+        self.vpn_range = VPNRange::new(start, at);
+        tail
+    }
+
     /// 仅在mmaparea中插入映射
     pub fn insert_tracker(&mut self, vpn: VirtPageNum, ppn: PhysPageNum) {
         self.data_frames
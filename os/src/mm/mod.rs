@@ -1,23 +1,30 @@
 pub(crate) mod address;
+mod aslr;
 mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod mmap;
 mod page_table;
+mod vdso;
 
 pub use address::VPNRange;
+pub use aslr::{UserLayout, ADDR_NO_RANDOMIZE};
 pub use address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
 use core::arch::asm;
-pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker};
-pub use memory_set::{remap_test, load_dll};
-pub use memory_set::{kernel_token, MapPermission, MapAreaType, MemorySet, KERNEL_SPACE};
+pub use frame_allocator::{
+    frame_alloc, frame_alloc_contiguous, frame_allocator_stat, frame_dealloc, FrameAllocatorStat,
+    FrameTracker,
+};
+pub use memory_set::remap_test;
+pub use memory_set::{kernel_token, MapPermission, MapAreaType, MemorySet, MemoryStat, KERNEL_SPACE};
 pub use mmap::{MmapArea, MmapFlags, FdOne};
 pub use page_table::*;
 use riscv::register::satp;
 
-pub fn init() {
+/// dtb 为设备树的物理地址，从中读取可用内存与保留区域
+pub fn init(dtb: usize) {
     heap_allocator::init_heap();
-    frame_allocator::init_frame_allocator();
+    frame_allocator::init_frame_allocator(dtb);
     KERNEL_SPACE.write().activate();
 }
 
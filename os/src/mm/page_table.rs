@@ -1,4 +1,4 @@
-use crate::config::{aligned_up, aligned_down, PAGE_SIZE};
+use crate::config::{aligned_up, aligned_down, HUGE_PAGE_PAGES, PAGE_SIZE};
 use crate::task::current_process;
 
 use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
@@ -6,6 +6,7 @@ use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
+use core::arch::asm;
 
 bitflags! {
     pub struct PTEFlags: u8 {
@@ -53,6 +54,10 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    /// R W X 任一置位即为叶子页表项（否则指向下一级页表）
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid() && (self.flags() & (PTEFlags::R | PTEFlags::W | PTEFlags::X)) != PTEFlags::empty()
+    }
     pub fn set_cow(&mut self) {
         (*self).bits = self.bits | (1 << 9);
         // let _ = self.flags() & (!PTEFlags::W);
@@ -100,6 +105,10 @@ impl PageTable {
                 result = Some(pte);
                 break;
             }
+            if pte.is_leaf() {
+                // 需要修改大页中的某一页，先将大页拆分为普通页
+                self.split_leaf(pte, i, vpn);
+            }
             if !pte.is_valid() {
                 let frame = frame_alloc().unwrap();
                 // 只有第三级页表可置A D 标志位  | PTEFlags::A | PTEFlags::D
@@ -111,24 +120,99 @@ impl PageTable {
         }
         result
     }
-    fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    /// 查找vpn对应的叶子页表项，同时返回其所在的级数（1: 2MiB大页，2: 4KiB普通页）
+    fn find_leaf_pte(&self, vpn: VirtPageNum) -> Option<(&mut PageTableEntry, usize)> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result: Option<&mut PageTableEntry> = None;
         for (i, idx) in idxs.iter().enumerate() {
             let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
-                result = Some(pte);
-                break;
+            if i == 2 || pte.is_leaf() {
+                return Some((pte, i));
             }
             if !pte.is_valid() {
                 return None;
             }
             ppn = pte.ppn();
         }
-        result
+        None
+    }
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        match self.find_leaf_pte(vpn) {
+            Some((pte, 2)) => Some(pte),
+            _ => None,
+        }
+    }
+    /// 将第1级的大页叶子页表项拆分为512个普通页表项，标志位（含COW位）保持不变
+    /// vpn 为大页内任一页，拆分后刷新整个 2MiB 范围的 TLB，避免继续使用旧的大页表项
+    fn split_leaf(&mut self, pte: &mut PageTableEntry, level: usize, vpn: VirtPageNum) {
+        assert_eq!(level, 1, "only megapages can be split");
+        let frame = frame_alloc().unwrap();
+        let base_ppn = pte.ppn().0;
+        let low_bits = pte.bits & 0x3ff;
+        for (i, sub_pte) in frame.ppn.get_pte_array().iter_mut().enumerate() {
+            sub_pte.bits = (base_ppn + i) << 10 | low_bits;
+        }
+        *pte = PageTableEntry::new(frame.ppn, PTEFlags::V | PTEFlags::A | PTEFlags::D);
+        self.frames.as_mut().unwrap().push(frame);
+        let head = vpn.0 / HUGE_PAGE_PAGES * HUGE_PAGE_PAGES;
+        for vpn in head..head + HUGE_PAGE_PAGES {
+            let va = VirtAddr::from(VirtPageNum(vpn)).0;
+            unsafe { asm!("sfence.vma {}", in(reg) va) };
+        }
     }
-    pub fn set_pte_flags(&self, vpn: VirtPageNum, flags: PTEFlags) -> Option<&mut PageTableEntry> {
+    /// 若vpn位于大页中，将该大页拆分为普通页，返回是否发生了拆分
+    pub fn split_huge(&mut self, vpn: VirtPageNum) -> bool {
+        let pte = match self.find_leaf_pte(vpn) {
+            Some((pte, 1)) => pte as *mut PageTableEntry,
+            _ => return false,
+        };
+        self.split_leaf(unsafe { &mut *pte }, 1, vpn);
+        true
+    }
+    /// vpn 是否位于一个2MiB大页中
+    pub fn is_huge(&self, vpn: VirtPageNum) -> bool {
+        matches!(self.find_leaf_pte(vpn), Some((_, 1)))
+    }
+    /// 建立2MiB大页映射，vpn和ppn均需按512页对齐
+    /// 若该范围内已有普通页映射则失败，返回false
+    pub fn map_huge(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> bool {
+        assert!(vpn.0 % HUGE_PAGE_PAGES == 0 && ppn.0 % HUGE_PAGE_PAGES == 0);
+        let idxs = vpn.indexes();
+        let root_pte = &mut self.root_ppn.get_pte_array()[idxs[0]];
+        if !root_pte.is_valid() {
+            let frame = frame_alloc().unwrap();
+            *root_pte = PageTableEntry::new(frame.ppn, PTEFlags::V | PTEFlags::A | PTEFlags::D);
+            self.frames.as_mut().unwrap().push(frame);
+        }
+        let pte = &mut root_pte.ppn().get_pte_array()[idxs[1]];
+        if pte.is_leaf() {
+            panic!("vpn {:?} is mapped before mapping", vpn);
+        }
+        if pte.is_valid() {
+            // 已存在第三级页表，只有其中没有有效映射时才能替换为大页
+            if pte.ppn().get_pte_array().iter().any(|sub_pte| sub_pte.is_valid()) {
+                return false;
+            }
+        }
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V | PTEFlags::A | PTEFlags::D);
+        true
+    }
+    /// 解除以vpn开头的2MiB范围的映射，若大页已被拆分则逐页解除
+    pub fn unmap_huge(&mut self, vpn: VirtPageNum) {
+        assert!(vpn.0 % HUGE_PAGE_PAGES == 0);
+        if let Some((pte, 1)) = self.find_leaf_pte(vpn) {
+            *pte = PageTableEntry::empty();
+            return;
+        }
+        for i in 0..HUGE_PAGE_PAGES {
+            if let Some(pte) = self.find_pte(VirtPageNum(vpn.0 + i)) {
+                *pte = PageTableEntry::empty();
+            }
+        }
+    }
+    pub fn set_pte_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) -> Option<&mut PageTableEntry> {
+        // 只修改大页中一页的权限时需要先拆分
+        self.split_huge(vpn);
         if let Some(pte) = self.find_pte(vpn) {
             if !pte.is_valid() {
                 return None;
@@ -148,6 +232,7 @@ impl PageTable {
     }
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
+        self.split_huge(vpn);
         let pte = self.find_pte(vpn).unwrap();
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
@@ -160,8 +245,14 @@ impl PageTable {
         // pte.set_cow();
         ppn.slice_u64().copy_from_slice(former_ppn.slice_u64());
     }
+    /// 对于大页中的vpn，返回的页表项ppn已加上页内偏移
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).map(|pte| *pte)
+        self.find_leaf_pte(vpn).map(|(pte, level)| {
+            let offset = vpn.0 & ((1usize << (9 * (2 - level))) - 1);
+            PageTableEntry {
+                bits: pte.bits + (offset << 10),
+            }
+        })
     }
     /// Todo: multi-borrowing problem?
     pub fn translate_vpn_with_lazycheck(&self, vpn: VirtPageNum) -> Option<PhysPageNum> {
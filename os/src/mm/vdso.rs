@@ -0,0 +1,94 @@
+//! vDSO
+//! 映射到每个用户地址空间 VDSO_BASE 处的只读页，地址通过 AT_SYSINFO_EHDR 传给用户程序。
+//! 目前不导出任何函数，只是一个合法的空共享对象，libc 查找不到符号时回退到系统调用
+
+use super::{frame_alloc, FrameTracker};
+use crate::config::PAGE_SIZE;
+use spin::Lazy;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const DYN_OFF: usize = EHDR_SIZE + 2 * PHDR_SIZE;
+const DYN_COUNT: usize = 7;
+const HASH_OFF: usize = DYN_OFF + DYN_COUNT * 16;
+const SYMTAB_OFF: usize = HASH_OFF + 16;
+const STRTAB_OFF: usize = SYMTAB_OFF + 24;
+const STRTAB: &[u8] = b"\0linux-vdso.so.1\0";
+
+const ET_DYN: u64 = 3;
+const EM_RISCV: u64 = 243;
+/// EF_RISCV_RVC | EF_RISCV_FLOAT_ABI_DOUBLE
+const E_FLAGS: u64 = 0x5;
+const PT_LOAD: u64 = 1;
+const PT_DYNAMIC: u64 = 2;
+const PF_R: u64 = 4;
+const PF_X: u64 = 1;
+const DT_HASH: u64 = 4;
+const DT_STRTAB: u64 = 5;
+const DT_SYMTAB: u64 = 6;
+const DT_STRSZ: u64 = 10;
+const DT_SYMENT: u64 = 11;
+const DT_SONAME: u64 = 14;
+
+fn put(page: &mut [u8], offset: usize, size: usize, value: u64) {
+    page[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+}
+
+/// 在页中构造 vDSO 的 ELF 映像，链接地址为 0
+fn build(page: &mut [u8]) {
+    // ELF 头
+    page[..8].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    for (offset, size, value) in [
+        (16, 2, ET_DYN),
+        (18, 2, EM_RISCV),
+        (20, 4, 1),
+        (32, 8, EHDR_SIZE as u64),
+        (48, 4, E_FLAGS),
+        (52, 2, EHDR_SIZE as u64),
+        (54, 2, PHDR_SIZE as u64),
+        (56, 2, 2),
+        (58, 2, 64),
+    ] {
+        put(page, offset, size, value);
+    }
+    // 程序头：覆盖整页的 PT_LOAD 与 PT_DYNAMIC
+    let dyn_size = (DYN_COUNT * 16) as u64;
+    let phdrs = [
+        (PT_LOAD, PF_R | PF_X, 0, PAGE_SIZE as u64, PAGE_SIZE as u64),
+        (PT_DYNAMIC, PF_R, DYN_OFF as u64, dyn_size, 8),
+    ];
+    for (i, &(p_type, p_flags, offset, size, align)) in phdrs.iter().enumerate() {
+        let ph = EHDR_SIZE + i * PHDR_SIZE;
+        put(page, ph, 4, p_type);
+        put(page, ph + 4, 4, p_flags);
+        put(page, ph + 8, 8, offset);
+        put(page, ph + 16, 8, offset);
+        put(page, ph + 24, 8, offset);
+        put(page, ph + 32, 8, size);
+        put(page, ph + 40, 8, size);
+        put(page, ph + 48, 8, align);
+    }
+    // 动态段，最后一项为 DT_NULL
+    let dynamic = [
+        (DT_HASH, HASH_OFF as u64),
+        (DT_STRTAB, STRTAB_OFF as u64),
+        (DT_SYMTAB, SYMTAB_OFF as u64),
+        (DT_STRSZ, STRTAB.len() as u64),
+        (DT_SYMENT, 24),
+        (DT_SONAME, 1),
+    ];
+    for (i, &(tag, value)) in dynamic.iter().enumerate() {
+        put(page, DYN_OFF + i * 16, 8, tag);
+        put(page, DYN_OFF + i * 16 + 8, 8, value);
+    }
+    // 散列表 nbucket = nchain = 1，符号表只有空符号，均为全零
+    put(page, HASH_OFF, 4, 1);
+    put(page, HASH_OFF + 4, 4, 1);
+    page[STRTAB_OFF..STRTAB_OFF + STRTAB.len()].copy_from_slice(STRTAB);
+}
+
+pub static VDSO: Lazy<FrameTracker> = Lazy::new(|| {
+    let frame = frame_alloc().unwrap();
+    build(frame.ppn.slice_u8());
+    frame
+});
@@ -9,11 +9,14 @@ pub fn get_hartid() -> usize {
     hartid
 }
 
-pub fn save_hartid() {
+/// 将 a0 中的 hartid 保存到 tp，返回 a1 中由 SBI 传入的设备树物理地址
+pub fn save_hartid() -> usize {
+    let dtb: usize;
     unsafe {
         // core::arch::asm!("mv tp, x10", in("x10") hartid);
-        core::arch::asm!("mv tp, a0");
+        core::arch::asm!("mv tp, a0", "mv {}, a1", out(reg) dtb);
     }
+    dtb
 }
 
 pub fn wakeup_other_cores(boot_hartid: usize) {
@@ -0,0 +1,78 @@
+//! 内核随机数源
+//! 没有硬件随机数发生器，以 time 计数器、hartid 等作为熵，
+//! 每次取随机数时再混入当前时间，经 xorshift64* 输出
+
+use crate::multicore::get_hartid;
+use riscv::register::time;
+use spin::{Lazy, Mutex};
+
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        let mut rng = Self { state: 0x9e37_79b9_7f4a_7c15 };
+        rng.add_entropy(seed);
+        rng
+    }
+
+    /// splitmix64 混合后并入状态，保证状态非零
+    fn add_entropy(&mut self, entropy: u64) {
+        let mut z = entropy.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        self.state ^= z;
+        if self.state == 0 {
+            self.state = 0x9e37_79b9_7f4a_7c15;
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+static RNG: Lazy<Mutex<Rng>> = Lazy::new(|| {
+    let stack_var = 0usize;
+    let seed = (time::read() as u64)
+        ^ ((get_hartid() as u64) << 56)
+        ^ ((&stack_var as *const usize as u64) << 17);
+    Mutex::new(Rng::new(seed))
+});
+
+/// 向随机数源补充熵（如中断到达的时间）
+#[allow(unused)]
+pub fn add_entropy(entropy: usize) {
+    RNG.lock().add_entropy(entropy as u64);
+}
+
+pub fn rand_usize() -> usize {
+    let mut rng = RNG.lock();
+    rng.add_entropy(time::read() as u64);
+    rng.next_u64() as usize
+}
+
+/// 返回 [0, bound) 内的随机数，bound 为 0 时返回 0
+pub fn rand_below(bound: usize) -> usize {
+    if bound == 0 {
+        0
+    } else {
+        rand_usize() % bound
+    }
+}
+
+pub fn fill_random(buf: &mut [u8]) {
+    let mut rng = RNG.lock();
+    rng.add_entropy(time::read() as u64);
+    for chunk in buf.chunks_mut(8) {
+        let bytes = rng.next_u64().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
@@ -52,8 +52,9 @@ pub fn shutdown() -> ! {
 }
 
 // todo SBIv2.0之后 send—ipi 改变了
+/// legacy 接口的参数是指向 hart 掩码的指针，内核栈上的地址在 SBI 中同样可以访问
 pub fn sbi_send_ipi(mask: usize) {
-    sbi_call(SBI_SEND_IPI, NONE, [mask, 0, 0]);
+    sbi_call(SBI_SEND_IPI, NONE, [&mask as *const usize as usize, 0, 0]);
 }
 
 pub fn sbi_hart_start(hartid: usize, start_addr: usize, a1: usize) -> usize {
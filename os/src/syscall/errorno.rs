@@ -34,3 +34,8 @@ pub const EMLINK: isize = 31; /* Too many links */
 pub const EPIPE: isize = 32; /* Broken pipe */
 pub const EDOM: isize = 33; /* Math argument out of domain of func */
 pub const ERANGE: isize = 34; /* Math result not representable */
+pub const ELOOP: isize = 40; /* Too many symbolic links encountered */
+pub const ETIMEDOUT: isize = 110; /* Connection timed out */
+
+/// 仅在内核内部使用：系统调用被信号打断，返回用户态前根据 SA_RESTART 决定重启或返回 EINTR
+pub const ERESTARTSYS: isize = 512;
@@ -1,6 +1,6 @@
 use crate::fs::{
     make_pipe, open_common_file, open_device_file, path2vec, remove_vfile_idx, BitOpt, DType,
-    FSDirent, FdSet, File, FileClass, IOVec, Kstat, OSFile, OpenFlags, Pollfd, Statfs, POLLIN,
+    FSDirent, FdSet, File, FileClass, IOVec, Kstat, OSFile, OpenFlags, Pollfd, PollTable, Statfs, POLLIN, POLLNVAL, POLLOUT,
     SEEK_CUR, SEEK_END, SEEK_SET, S_IFCHR, S_IFDIR, S_IFREG, S_IRWXG, S_IRWXO, S_IRWXU, is_abs_path,
 };
 use crate::gdb_println;
@@ -11,8 +11,11 @@ use crate::mm::{
 
 use crate::monitor::{QEMU, SYSCALL_ENABLE};
 use crate::syscall::process;
-use crate::task::{current_process, current_user_token, suspend_current_and_run_next, TimeSpec};
-use crate::timer::{get_time_ns, NSEC_PER_SEC};
+use crate::task::{
+    current_has_signal, current_process, current_task, current_user_token, send_signal,
+    set_temporary_sigmask, SigInfo, TimeSpec, RLIMIT_FSIZE, RLIMIT_NOFILE, SIGXFSZ, SI_KERNEL,
+};
+use crate::timer::{get_time_ns, timespec_to_ns, NSEC_PER_SEC};
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec;
@@ -21,19 +24,38 @@ use core::mem::size_of;
 use fat32_fs::DIRENT_SZ;
 
 use super::errorno::*;
+use super::read_timespec;
 
 const AT_FDCWD: isize = -100;
 const UTIME_NOW: usize = (1 << 30) - 1;
 const UTIME_OMIT: usize = (1 << 30) - 2;
 
+/// 受 RLIMIT_FSIZE 限制，从普通文件的当前偏移起最多可写入的字节数，偏移已达上限时为 None
+fn fsize_allowed(file: &FileClass, len: usize, limit: usize) -> Option<usize> {
+    match file {
+        FileClass::File(osfile) if len > 0 => {
+            let offset = osfile.offset();
+            (offset < limit).then(|| len.min(limit - offset))
+        }
+        _ => Some(len),
+    }
+}
+
+/// 文件大小超过 RLIMIT_FSIZE，向当前线程发送 SIGXFSZ
+fn fsize_exceeded() -> isize {
+    send_signal(current_task().unwrap(), SigInfo::new(SIGXFSZ, SI_KERNEL));
+    -EFBIG
+}
+
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     let token = current_user_token();
     let process = current_process();
     let inner = process.acquire_inner_lock();
-    if fd >= inner.fd_table.len() {
+    let fd_table = inner.fd_table.lock();
+    if fd >= fd_table.len() {
         return -EBADF;
     }
-    if let Some(file) = &inner.fd_table[fd] {
+    if let Some(file) = &fd_table[fd] {
         let f: Arc<dyn File + Send + Sync>;
         match file {
             FileClass::File(fi) => f = fi.clone(),
@@ -42,9 +64,15 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
         if !f.writable() {
             return -EINVAL;
         }
+        let allowed = fsize_allowed(file, len, inner.rlimit(RLIMIT_FSIZE));
         // release current task TCB manually to avoid multi-borrow
+        drop(fd_table);
         drop(inner);
         drop(process);
+        let len = match allowed {
+            Some(len) => len,
+            None => return fsize_exceeded(),
+        };
         let ret = f.write(UserBuffer::new(translated_byte_buffer(token, buf, len)));
         if fd >= 2 {
             gdb_println!(
@@ -66,10 +94,11 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
     let token = current_user_token();
     let process = current_process();
     let inner = process.acquire_inner_lock();
-    if fd >= inner.fd_table.len() {
+    let fd_table = inner.fd_table.lock();
+    if fd >= fd_table.len() {
         return -EBADF;
     }
-    if let Some(file) = &inner.fd_table[fd] {
+    if let Some(file) = &fd_table[fd] {
         let f: Arc<dyn File + Send + Sync>;
         match file {
             FileClass::File(fi) => f = fi.clone(),
@@ -80,6 +109,7 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
         }
         // release current task TCB manually to avoid multi-borrow
         // 为什么要提前drop掉？因为在read/write的过程可能会触发suspend_current/exit_current
+        drop(fd_table);
         drop(inner);
         drop(process);
         let ret = f.read(UserBuffer::new(translated_byte_buffer(token, buf, len)));
@@ -107,7 +137,8 @@ pub fn sys_open_at(dirfd: isize, path: *const u8, flags: u32, _mode: u32) -> isi
     let mut path = translated_str(token, path);
 
     let process = current_process();
-    let mut inner = process.acquire_inner_lock();
+    let inner = process.acquire_inner_lock();
+    let mut fd_table = inner.fd_table.lock();
 
     let flags = OpenFlags::from_bits(flags).unwrap();
     gdb_println!(
@@ -138,28 +169,45 @@ pub fn sys_open_at(dirfd: isize, path: *const u8, flags: u32, _mode: u32) -> isi
     //     }
     // };
 
+    let limit = inner.rlimit(RLIMIT_NOFILE);
     let ret;
     if dirfd == AT_FDCWD && !is_abs_path(&path) {
-        ret = if let Some(devfile) = open_device_file(&inner.cwd, path.as_str(), flags) {
-            let fd = inner.alloc_fd(0);
-            inner.fd_table[fd] = Some(FileClass::Abs(devfile));
-            fd as isize
-        } else if let Some(vfile) = open_common_file(&inner.cwd, path.as_str(), flags) {
-            let fd = inner.alloc_fd(0);
-            inner.fd_table[fd] = Some(FileClass::File(vfile));
-            fd as isize
+        ret = if let Some(devfile) = open_device_file(&inner.cwd.lock(), path.as_str(), flags) {
+            match fd_table.alloc_fd(0, limit) {
+                Ok(fd) => {
+                    fd_table[fd] = Some(FileClass::Abs(devfile));
+                    fd as isize
+                }
+                Err(errno) => errno,
+            }
+        } else if let Some(vfile) = open_common_file(&inner.cwd.lock(), path.as_str(), flags) {
+            match fd_table.alloc_fd(0, limit) {
+                Ok(fd) => {
+                    fd_table[fd] = Some(FileClass::File(vfile));
+                    fd as isize
+                }
+                Err(errno) => errno,
+            }
         } else {
             -ENOENT
         }
     } else {
         ret = if let Some(devfile) = open_device_file("/", path.as_str(), flags) {
-            let fd = inner.alloc_fd(0);
-            inner.fd_table[fd] = Some(FileClass::Abs(devfile));
-            fd as isize
+            match fd_table.alloc_fd(0, limit) {
+                Ok(fd) => {
+                    fd_table[fd] = Some(FileClass::Abs(devfile));
+                    fd as isize
+                }
+                Err(errno) => errno,
+            }
         } else if let Some(vfile) = open_common_file("/", path.as_str(), flags) {
-            let fd = inner.alloc_fd(0);
-            inner.fd_table[fd] = Some(FileClass::File(vfile));
-            fd as isize
+            match fd_table.alloc_fd(0, limit) {
+                Ok(fd) => {
+                    fd_table[fd] = Some(FileClass::File(vfile));
+                    fd as isize
+                }
+                Err(errno) => errno,
+            }
         } else {
             -ENOENT
         }
@@ -179,12 +227,13 @@ pub fn sys_open_at(dirfd: isize, path: *const u8, flags: u32, _mode: u32) -> isi
 
 pub fn sys_close(fd: usize) -> isize {
     let process = current_process();
-    let mut inner = process.acquire_inner_lock();
-    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
+    let inner = process.acquire_inner_lock();
+    let mut fd_table = inner.fd_table.lock();
+    if fd >= fd_table.len() || fd_table[fd].is_none() {
         gdb_println!(SYSCALL_ENABLE, "sys_close(fd: {}) = {}", fd, -EPERM);
         return -EPERM;
     }
-    inner.fd_table[fd].take();
+    fd_table[fd].take();
     gdb_println!(SYSCALL_ENABLE, "sys_close(fd: {}) = {}", fd, 0);
     0
 }
@@ -194,12 +243,23 @@ pub fn sys_pipe2(pipe: *mut u32, flags: u32) -> isize {
     let token = current_user_token();
     let flags = OpenFlags::from_bits(flags).unwrap();
 
-    let mut inner = process.acquire_inner_lock();
+    let inner = process.acquire_inner_lock();
+    let mut fd_table = inner.fd_table.lock();
+    let limit = inner.rlimit(RLIMIT_NOFILE);
     let (pipe_read, pipe_write) = make_pipe(flags);
-    let read_fd = inner.alloc_fd(0);
-    inner.fd_table[read_fd] = Some(FileClass::Abs(pipe_read));
-    let write_fd = inner.alloc_fd(0);
-    inner.fd_table[write_fd] = Some(FileClass::Abs(pipe_write));
+    let read_fd = match fd_table.alloc_fd(0, limit) {
+        Ok(fd) => fd,
+        Err(errno) => return errno,
+    };
+    fd_table[read_fd] = Some(FileClass::Abs(pipe_read));
+    let write_fd = match fd_table.alloc_fd(0, limit) {
+        Ok(fd) => fd,
+        Err(errno) => {
+            fd_table[read_fd] = None;
+            return errno;
+        }
+    };
+    fd_table[write_fd] = Some(FileClass::Abs(pipe_write));
     *translated_refmut(token, pipe) = read_fd as u32;
     *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd as u32;
 
@@ -215,42 +275,43 @@ pub fn sys_pipe2(pipe: *mut u32, flags: u32) -> isize {
 
 pub fn sys_dup(fd: usize) -> isize {
     let process = current_process();
-    let mut inner = process.acquire_inner_lock();
-    if fd >= inner.fd_table.len() {
+    let inner = process.acquire_inner_lock();
+    let mut fd_table = inner.fd_table.lock();
+    if fd >= fd_table.len() {
         return -EPERM;
     }
-    if inner.fd_table[fd].is_none() {
+    if fd_table[fd].is_none() {
         return -EPERM;
     }
 
-    if inner.fd_table.len() > inner.fd_max {
-        return -EMFILE;
-    }
-
-    let new_fd = inner.alloc_fd(0);
-    inner.fd_table[new_fd] = inner.fd_table[fd].clone();
+    let new_fd = match fd_table.alloc_fd(0, inner.rlimit(RLIMIT_NOFILE)) {
+        Ok(fd) => fd,
+        Err(errno) => return errno,
+    };
+    fd_table[new_fd] = fd_table[fd].clone();
     gdb_println!(SYSCALL_ENABLE, "sys_dup(fd: {}) = {}", fd, new_fd);
     new_fd as isize
 }
 
 pub fn sys_dup3(old_fd: usize, new_fd: usize) -> isize {
     let process = current_process();
-    let mut inner = process.acquire_inner_lock();
-    if old_fd >= inner.fd_table.len() {
+    let inner = process.acquire_inner_lock();
+    let mut fd_table = inner.fd_table.lock();
+    if old_fd >= fd_table.len() {
         return -EPERM;
     }
-    if inner.fd_table[old_fd].is_none() {
+    if fd_table[old_fd].is_none() {
         return -EPERM;
     }
 
-    if old_fd > inner.fd_max {
-        return -EMFILE;
+    if new_fd >= inner.rlimit(RLIMIT_NOFILE) {
+        return -EBADF;
     }
 
-    while new_fd >= inner.fd_table.len() {
-        inner.fd_table.push(None);
+    while new_fd >= fd_table.len() {
+        fd_table.push(None);
     }
-    inner.fd_table[new_fd] = inner.fd_table[old_fd].clone();
+    fd_table[new_fd] = fd_table[old_fd].clone();
     gdb_println!(
         SYSCALL_ENABLE,
         "sys_dup3(old_fd: {}, new_fd: {}) = {}",
@@ -267,6 +328,7 @@ pub fn sys_fstat(fd: isize, buf: *mut u8) -> isize {
     let process = current_process();
     let buf_vec = translated_byte_buffer(token, buf, size_of::<Kstat>());
     let inner = process.acquire_inner_lock();
+    let fd_table = inner.fd_table.lock();
     let mut userbuf = UserBuffer::new(buf_vec);
 
     let ret = if fd == AT_FDCWD {
@@ -274,7 +336,7 @@ pub fn sys_fstat(fd: isize, buf: *mut u8) -> isize {
         //     open_common_file(&cwd, "", OpenFlags::RDONLY).unwrap(),
         //     &mut userbuf,
         // )
-        let cwd = inner.cwd.clone();
+        let cwd = inner.cwd.lock().clone();
         userbuf.copy_to_user(
             open_common_file(&cwd, "", OpenFlags::RDONLY)
                 .unwrap()
@@ -294,7 +356,7 @@ pub fn sys_fstat(fd: isize, buf: *mut u8) -> isize {
     //         -EPERM
     //     }
     // };
-    } else if let Some(Some(FileClass::File(f))) = inner.fd_table.get(fd as usize) {
+    } else if let Some(Some(FileClass::File(f))) = fd_table.get(fd as usize) {
         userbuf.copy_to_user(f.stat().as_bytes());
         0
     } else {
@@ -333,7 +395,7 @@ pub fn sys_fstatat(dirfd: isize, path: *mut u8, buf: *mut u8) -> isize {
     // };
     let ret;
     if !is_abs_path(&path) && dirfd == AT_FDCWD {
-        ret = if let Some(osfile) = open_common_file(&process.acquire_inner_lock().cwd, &path, OpenFlags::RDONLY) {
+        ret = if let Some(osfile) = open_common_file(&process.acquire_inner_lock().cwd.lock(), &path, OpenFlags::RDONLY) {
             userbuf.copy_to_user(osfile.stat().as_bytes());
             0
         } else {
@@ -367,7 +429,7 @@ pub fn sys_getcwd(buf: *mut u8, size: usize) -> isize {
     let inner = process.acquire_inner_lock();
 
     let mut user_buf = UserBuffer::new(buf_vec);
-    let mut cwd = inner.cwd.clone();
+    let mut cwd = inner.cwd.lock().clone();
     cwd.push('\0');
     let cwd_str = cwd.as_str();
 
@@ -391,7 +453,7 @@ pub fn sys_mkdirat(dirfd: isize, path: *const u8, _mode: u32) -> isize {
     let path = translated_str(token, path);
 
     let cwd = if dirfd == AT_FDCWD && !is_abs_path(&path) {
-        process.acquire_inner_lock().cwd.clone()
+        process.acquire_inner_lock().cwd.lock().clone()
     } else {
         String::from("/")
     };
@@ -438,10 +500,11 @@ pub fn sys_chdir(path: *const u8) -> isize {
     let process = current_process();
     let token = current_user_token();
     let mut path = translated_str(token, path);
-    let mut inner = process.acquire_inner_lock();
+    let inner = process.acquire_inner_lock();
+    let mut cwd = inner.cwd.lock();
 
     let old_cwd = if !is_abs_path(&path) {
-        inner.cwd.clone()
+        cwd.clone()
     } else {
         String::from("/")
     };
@@ -452,7 +515,7 @@ pub fn sys_chdir(path: *const u8) -> isize {
                     if !path.ends_with("/") {
                         path.push('/');
                     }
-                    inner.cwd = path.clone();
+                    *cwd = path.clone();
                 } else {
                     assert!(old_cwd.ends_with("/"));
                     let pathv = path2vec(&path);
@@ -468,11 +531,11 @@ pub fn sys_chdir(path: *const u8) -> isize {
                             cwdv.push(path_element);
                         }
                     }
-                    inner.cwd = String::from("/");
+                    *cwd = String::from("/");
                     for &cwd_element in cwdv.iter() {
                         if cwd_element != "" {
-                            inner.cwd.push_str(cwd_element);
-                            inner.cwd.push('/');
+                            cwd.push_str(cwd_element);
+                            cwd.push('/');
                         }
                     }
                 }
@@ -525,7 +588,8 @@ pub fn sys_getdents64(fd: isize, buf: *mut u8, len: usize) -> isize {
     let process = current_process();
     let buf_vec = translated_byte_buffer(token, buf, len);
     let inner = process.acquire_inner_lock();
-    let cwd = inner.cwd.clone();
+    let fd_table = inner.fd_table.lock();
+    let cwd = inner.cwd.lock().clone();
 
     let mut userbuf = UserBuffer::new(buf_vec);
 
@@ -535,10 +599,10 @@ pub fn sys_getdents64(fd: isize, buf: *mut u8, len: usize) -> isize {
             &mut userbuf,
             len,
         )
-    } else if fd < 0 || fd >= inner.fd_table.len() as isize {
+    } else if fd < 0 || fd >= fd_table.len() as isize {
         -EPERM
     } else {
-        if let Some(file) = inner.fd_table[fd as usize].clone() {
+        if let Some(file) = fd_table[fd as usize].clone() {
             match file {
                 FileClass::File(f) => getdents64_inner(f, &mut userbuf, len),
                 _ => -EPERM,
@@ -580,13 +644,15 @@ pub fn sys_unlinkat(dirfd: isize, path: *const u8, _: u32) -> isize {
     let process = current_process();
     let token = current_user_token();
     let inner = process.acquire_inner_lock();
+    let fd_table = inner.fd_table.lock();
     let path = translated_str(token, path);
-    let mut base_path = inner.cwd.as_str();
+    let cwd = inner.cwd.lock();
+    let mut base_path = cwd.as_str();
     // 如果path是绝对路径，则dirfd被忽略
     if is_abs_path(&path) {
         base_path = "/";
     } else if dirfd != AT_FDCWD {
-        if let Some(Some(FileClass::File(osfile))) = inner.fd_table.get(dirfd as usize) {
+        if let Some(Some(FileClass::File(osfile))) = fd_table.get(dirfd as usize) {
             if let Some(osfile) = osfile.find(path.as_str(), OpenFlags::empty()) {
                 osfile.remove();
                 gdb_println!(
@@ -621,9 +687,29 @@ pub fn sys_unlinkat(dirfd: isize, path: *const u8, _: u32) -> isize {
     return -ENOENT;
 }
 
-pub fn sys_ioctl() -> isize {
-    gdb_println!(SYSCALL_ENABLE, "sys_ioctl(...) = 0");
-    0
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    let process = current_process();
+    let inner = process.acquire_inner_lock();
+    let fd_table = inner.fd_table.lock();
+    let file: Arc<dyn File + Send + Sync> = match fd_table.get(fd) {
+        Some(Some(FileClass::File(f))) => f.clone(),
+        Some(Some(FileClass::Abs(f))) => f.clone(),
+        _ => return -EBADF,
+    };
+    // 终端的 ioctl 会再次获取进程锁
+    drop(fd_table);
+    drop(inner);
+    drop(process);
+    let ret = file.ioctl(request, arg);
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_ioctl(fd: {}, request: {:#x}, arg: {:#x}) = {}",
+        fd,
+        request,
+        arg,
+        ret
+    );
+    ret
 }
 
 pub const F_DUPFD: u32 = 0;
@@ -634,19 +720,24 @@ pub const F_DUPFD_CLOEXEC: u32 = 1030;
 
 pub fn sys_fcntl(fd: usize, cmd: u32, arg: usize) -> isize {
     let process = current_process();
-    let mut inner = process.acquire_inner_lock();
+    let inner = process.acquire_inner_lock();
+    let mut fd_table = inner.fd_table.lock();
 
-    if fd > inner.fd_table.len() {
+    if fd > fd_table.len() {
         return -1;
     }
 
     let ret = {
-        if let Some(_file) = &mut inner.fd_table[fd] {
+        if let Some(_file) = &mut fd_table[fd] {
             match cmd {
                 F_DUPFD_CLOEXEC | F_DUPFD => {
-                    let new_fd = inner.alloc_fd(arg);
-                    inner.fd_table[new_fd] = inner.fd_table[fd].clone();
-                    new_fd as isize
+                    match fd_table.alloc_fd(arg, inner.rlimit(RLIMIT_NOFILE)) {
+                        Ok(new_fd) => {
+                            fd_table[new_fd] = fd_table[fd].clone();
+                            new_fd as isize
+                        }
+                        Err(errno) => errno,
+                    }
                 }
                 F_GETFD | F_SETFD => 0,
                 _ => 0, // WARNING!!!
@@ -672,12 +763,13 @@ pub fn sys_readv(fd: usize, iov: *mut IOVec, iocnt: usize) -> isize {
     let token = current_user_token();
     let process = current_process();
     let inner = process.acquire_inner_lock();
+    let fd_table = inner.fd_table.lock();
 
     let mut ret = 0isize;
-    if fd >= inner.fd_table.len() {
+    if fd >= fd_table.len() {
         return -EPERM;
     }
-    if let Some(file) = &inner.fd_table[fd] {
+    if let Some(file) = &fd_table[fd] {
         let f: Arc<dyn File + Send + Sync>;
         match file {
             FileClass::File(fi) => f = fi.clone(),
@@ -686,10 +778,22 @@ pub fn sys_readv(fd: usize, iov: *mut IOVec, iocnt: usize) -> isize {
         if !f.readable() {
             return -EPERM;
         }
+        // 与 sys_read 相同，读取可能阻塞并检查信号，需先释放进程锁
+        drop(fd_table);
+        drop(inner);
+        drop(process);
         for i in 0..iocnt {
             let iovec = translated_ref(token, unsafe { iov.add(i) });
             let buf = translated_byte_buffer(token, iovec.iov_base, iovec.iov_len);
-            ret += f.read(UserBuffer::new(buf)) as isize;
+            let n = f.read(UserBuffer::new(buf)) as isize;
+            if n < 0 {
+                // 被信号打断，已传输的数据优先返回
+                if ret == 0 {
+                    ret = n;
+                }
+                break;
+            }
+            ret += n;
         }
     }
 
@@ -709,27 +813,55 @@ pub fn sys_writev(fd: usize, iov: *const IOVec, iocnt: usize) -> isize {
     let token = current_user_token();
     let process = current_process();
     let inner = process.acquire_inner_lock();
+    let fd_table = inner.fd_table.lock();
 
     let mut ret = 0isize;
 
-    if fd >= inner.fd_table.len() {
+    if fd >= fd_table.len() {
         return -EPERM;
     }
 
-    if let Some(file) = &inner.fd_table[fd] {
+    if let Some(file) = &fd_table[fd] {
+        let file = file.clone();
         let f: Arc<dyn File + Send + Sync>;
-        match file {
+        match &file {
             FileClass::File(fi) => f = fi.clone(),
             FileClass::Abs(fi) => f = fi.clone(),
         }
         if !f.writable() {
             return -EPERM;
         }
+        let limit = inner.rlimit(RLIMIT_FSIZE);
+        // 与 sys_write 相同，写入可能阻塞并检查信号，需先释放进程锁
+        drop(fd_table);
+        drop(inner);
+        drop(process);
 
         for i in 0..iocnt {
             let iovec = translated_ref(token, unsafe { iov.add(i) });
-            let buf = translated_byte_buffer(token, iovec.iov_base, iovec.iov_len);
-            ret += f.write(UserBuffer::new(buf)) as isize;
+            let len = match fsize_allowed(&file, iovec.iov_len, limit) {
+                Some(len) => len,
+                // 已写入部分数据时返回已写入的字节数
+                None => {
+                    if ret == 0 {
+                        ret = fsize_exceeded();
+                    }
+                    break;
+                }
+            };
+            let buf = translated_byte_buffer(token, iovec.iov_base, len);
+            let n = f.write(UserBuffer::new(buf)) as isize;
+            if n < 0 {
+                // 被信号打断，已传输的数据优先返回
+                if ret == 0 {
+                    ret = n;
+                }
+                break;
+            }
+            ret += n;
+            if len < iovec.iov_len {
+                break;
+            }
         }
     }
 
@@ -749,9 +881,10 @@ pub fn sys_sendfile(out_fd: usize, in_fd: usize, offset: *mut usize, count: usiz
     let token = current_user_token();
     let process = current_process();
     let inner = process.acquire_inner_lock();
+    let fd_table = inner.fd_table.lock();
 
-    let fout = inner.fd_table.get(out_fd).unwrap_or(&None);
-    let fin = inner.fd_table.get(in_fd).unwrap_or(&None);
+    let fout = fd_table.get(out_fd).unwrap_or(&None);
+    let fin = fd_table.get(in_fd).unwrap_or(&None);
 
     if fin.is_none() || fout.is_none() {
         return -EPERM;
@@ -774,17 +907,25 @@ pub fn sys_sendfile(out_fd: usize, in_fd: usize, offset: *mut usize, count: usiz
 
         let fout = fout.clone().unwrap();
         let fout_inner: Arc<dyn File + Send + Sync>;
-        match fout {
+        match &fout {
             FileClass::File(fi) => fout_inner = fi.clone(),
             FileClass::Abs(fi) => fout_inner = fi.clone(),
         }
         if !fout_inner.writable() {
             return -EPERM;
         }
+        let allowed = fsize_allowed(&fout, count.min(0x2000), inner.rlimit(RLIMIT_FSIZE));
+        drop(fd_table);
+        drop(inner);
+        drop(process);
+        let len = match allowed {
+            Some(len) => len,
+            None => return fsize_exceeded(),
+        };
 
         // sendfile
         // 这里我们仍然采用UserBuffer，虽然实际上没有移入用户空间
-        let mut buf = vec![0u8; 0x2000];
+        let mut buf = vec![0u8; len];
 
         let buf_vec_read = unsafe {
             UserBuffVec::from_single_slice(core::slice::from_raw_parts_mut(
@@ -832,18 +973,20 @@ pub fn sys_utimensat(
     let process = current_process();
     let token = current_user_token();
     let inner = process.acquire_inner_lock();
+    let fd_table = inner.fd_table.lock();
     let path = if ppath as usize != 0 {
         translated_str(token, ppath)
     } else {
         String::from(".")
     };
-    let mut base_path = inner.cwd.as_str();
+    let cwd = inner.cwd.lock();
+    let mut base_path = cwd.as_str();
     // 如果path是绝对路径，则dirfd被忽略
     if is_abs_path(&path) {
         base_path = "/";
     } else if dirfd != AT_FDCWD {
         let dirfd = dirfd as usize;
-        if dirfd >= inner.fd_table.len() {
+        if dirfd >= fd_table.len() {
             gdb_println!(
                 SYSCALL_ENABLE,
                 "sys_utimensat(dirfd = {}, path = {:#?}) = {}",
@@ -853,7 +996,7 @@ pub fn sys_utimensat(
             );
             return -EBADF;
         }
-        if let Some(FileClass::File(osfile)) = inner.fd_table[dirfd].clone() {
+        if let Some(FileClass::File(osfile)) = fd_table[dirfd].clone() {
             if ppath as usize == 0 {
                 do_utimensat(osfile, times, token);
                 gdb_println!(
@@ -931,14 +1074,16 @@ pub fn sys_faccessat(dirfd: isize, path: *const u8, _mode: usize, flags: usize)
     let process = current_process();
     let token = current_user_token();
     let inner = process.acquire_inner_lock();
+    let fd_table = inner.fd_table.lock();
     let path = translated_str(token, path);
     let flags = OpenFlags::from_bits(flags as u32).unwrap();
-    let mut base_path = inner.cwd.as_str();
+    let cwd = inner.cwd.lock();
+    let mut base_path = cwd.as_str();
     if is_abs_path(&path) {
         base_path = "/";
     } else if dirfd != AT_FDCWD {
         let dirfd = dirfd as usize;
-        if dirfd >= inner.fd_table.len() {
+        if dirfd >= fd_table.len() {
             gdb_println!(
                 SYSCALL_ENABLE,
                 "sys_faccessat(dirfd = {}, path = {:#?}, flags: {:#?}) = {}",
@@ -949,7 +1094,7 @@ pub fn sys_faccessat(dirfd: isize, path: *const u8, _mode: usize, flags: usize)
             );
             return -EBADF;
         }
-        if let Some(FileClass::File(osfile)) = &inner.fd_table[dirfd] {
+        if let Some(FileClass::File(osfile)) = &fd_table[dirfd] {
             if let Some(_) = osfile.find(path.as_str(), flags) {
                 gdb_println!(
                     SYSCALL_ENABLE,
@@ -994,26 +1139,89 @@ pub fn sys_faccessat(dirfd: isize, path: *const u8, _mode: usize, flags: usize)
     return -ENOENT;
 }
 
-pub fn sys_ppoll(fds: *mut Pollfd, nfds: usize, timeout: i32) -> isize {
+/// 检查一遍所有 fd 的状态，返回就绪的 fd 个数，检查前把当前任务登记到 fd 的等待队列
+fn poll_once(fds: *mut Pollfd, nfds: usize, table: &mut PollTable) -> isize {
     let token = current_user_token();
     let process = current_process();
     let inner = process.acquire_inner_lock();
-
+    let fd_table = inner.fd_table.lock();
     let mut ret = 0isize;
 
     for i in 0..nfds {
-        let mut pollfd = translated_refmut(token, unsafe { fds.add(i) });
-        if let Some(f) = inner.fd_table.get(pollfd.fd as usize) {
-            if f.is_some() {
-                pollfd.revents |= POLLIN;
-                ret += 1;
+        let pollfd = translated_refmut(token, unsafe { fds.add(i) });
+        pollfd.revents = 0;
+        match fd_table.get(pollfd.fd as usize) {
+            Some(Some(file)) => {
+                let f: Arc<dyn File + Send + Sync> = match file {
+                    FileClass::File(fi) => fi.clone(),
+                    FileClass::Abs(fi) => fi.clone(),
+                };
+                table.add(&f);
+                if pollfd.events & POLLIN != 0 && f.readable() && !f.read_blocking() {
+                    pollfd.revents |= POLLIN;
+                }
+                if pollfd.events & POLLOUT != 0 && f.writable() && !f.write_blocking() {
+                    pollfd.revents |= POLLOUT;
+                }
             }
+            _ => pollfd.revents |= POLLNVAL,
+        }
+        if pollfd.revents != 0 {
+            ret += 1;
+        }
+    }
+    ret
+}
+
+/// ppoll/pselect 的 sigmask 参数不为 NULL 时，等待期间以其替换信号屏蔽字
+fn apply_wait_sigmask(sigmask: *const u64, sigsetsize: usize) -> isize {
+    if sigmask.is_null() {
+        return 0;
+    }
+    if sigsetsize != 8 {
+        return -EINVAL;
+    }
+    let mask = *translated_ref(current_user_token(), sigmask);
+    set_temporary_sigmask(&current_task().unwrap(), mask);
+    0
+}
+
+/// timeout 为 NULL 时一直等待到有 fd 就绪
+pub fn sys_ppoll(
+    fds: *mut Pollfd,
+    nfds: usize,
+    timeout: *const u64,
+    sigmask: *const u64,
+    sigsetsize: usize,
+) -> isize {
+    let deadline = if timeout.is_null() {
+        None
+    } else {
+        match read_timespec(current_user_token(), timeout) {
+            Some(ns) => Some(get_time_ns().saturating_add(ns)),
+            None => return -EINVAL,
         }
+    };
+    let errno = apply_wait_sigmask(sigmask, sigsetsize);
+    if errno != 0 {
+        return errno;
     }
 
+    let mut table = PollTable::new();
+    let ret = loop {
+        let ret = poll_once(fds, nfds, &mut table);
+        if ret != 0 || deadline.map_or(false, |deadline| get_time_ns() >= deadline) {
+            break ret;
+        }
+        if current_has_signal() {
+            break -EINTR;
+        }
+        table.wait(deadline);
+    };
+
     gdb_println!(
         SYSCALL_ENABLE,
-        "sys_ppoll(fds: {:#x?}, nfds = {:x?}, timeout: {}) = {}",
+        "sys_ppoll(fds: {:#x?}, nfds = {:x?}, timeout: {:?}) = {}",
         fds,
         nfds,
         timeout,
@@ -1033,14 +1241,32 @@ pub fn sys_pselect(
     wfds: *mut FdSet,
     efds: *mut FdSet,
     timeout: *mut TimeSpec,
+    sigmask: *const [usize; 2],
 ) -> isize {
     let token = current_user_token();
     let mut ret = 0isize;
 
-    let time = translated_refmut(token, timeout);
-    if time.tv_sec == 0 && time.tv_usec == 0 {
+    // 第六个参数指向 { const sigset_t *ss; size_t ss_len; }
+    if !sigmask.is_null() {
+        let [ss, ss_len] = *translated_ref(token, sigmask);
+        let errno = apply_wait_sigmask(ss as *const u64, ss_len);
+        if errno != 0 {
+            return errno;
+        }
+    }
+
+    // timeout 为 NULL 时一直等待；pselect6 的超时为 timespec，第二个字段实为纳秒
+    let time = if timeout.is_null() {
+        None
+    } else {
+        Some(*translated_ref(token, timeout))
+    };
+    let deadline =
+        time.map(|time| get_time_ns().saturating_add(timespec_to_ns(time.tv_sec, time.tv_usec)));
+    if time.map_or(false, |time| time.is_zero()) {
         let process = current_process();
         let inner = process.acquire_inner_lock();
+        let fd_table = inner.fd_table.lock();
         ////pselect非阻塞处理 todo todo
         // 处理 read fd set
         if rfds as usize != 0 {
@@ -1056,9 +1282,9 @@ pub fn sys_pselect(
                 //read fs set 直接当中的返回可用fd
                 // let Some(file) = &inner.fd_table[fd]
                 if select_rfd.u128_get_bit(i) {
-                    if let Some(f) = inner.fd_table.get(i) {
+                    if let Some(f) = fd_table.get(i) {
                         if f.is_some() {
-                            if let Some(file) = &inner.fd_table[i] {
+                            if let Some(file) = &fd_table[i] {
                                 let f: Arc<dyn File + Send + Sync>;
                                 match file {
                                     FileClass::File(fi) => f = fi.clone(),
@@ -1091,9 +1317,9 @@ pub fn sys_pselect(
 
             for i in 0..nfds as usize {
                 if select_wfd.u128_get_bit(i) {
-                    if let Some(f) = inner.fd_table.get(i) {
+                    if let Some(f) = fd_table.get(i) {
                         if f.is_some() {
-                            if let Some(file) = &inner.fd_table[i] {
+                            if let Some(file) = &fd_table[i] {
                                 let f: Arc<dyn File + Send + Sync>;
                                 match file {
                                     FileClass::File(fi) => f = fi.clone(),
@@ -1152,19 +1378,21 @@ pub fn sys_pselect(
             erro_fds.u128_clear_all();
         }
 
+        let mut table = PollTable::new();
         loop {
             let process = current_process();
             let inner = process.acquire_inner_lock();
-            let mut ret = 0isize;
+            let fd_table = inner.fd_table.lock();
+            ret = 0;
 
             //处理 read fd set
             if rfd_clone != 0 {
                 let read_fds = translated_refmut(token, rfds);
                 for i in 0..nfds as usize {
                     if rfd_clone.u128_get_bit(i) {
-                        if let Some(f) = inner.fd_table.get(i) {
+                        if let Some(f) = fd_table.get(i) {
                             if f.is_some() {
-                                if let Some(file) = &inner.fd_table[i] {
+                                if let Some(file) = &fd_table[i] {
                                     let f: Arc<dyn File + Send + Sync>;
                                     match file {
                                         FileClass::File(fi) => f = fi.clone(),
@@ -1173,6 +1401,7 @@ pub fn sys_pselect(
                                     if !f.readable() {
                                         return -1; //可能是错的
                                     }
+                                    table.add(&f);
                                     if f.read_blocking() {
                                         //fd 不可用
                                         read_fds.u128_set_bit(i, false);
@@ -1192,9 +1421,9 @@ pub fn sys_pselect(
                 let write_fds = translated_refmut(token, wfds);
                 for i in 0..nfds as usize {
                     if wfd_clone.u128_get_bit(i) {
-                        if let Some(f) = inner.fd_table.get(i) {
+                        if let Some(f) = fd_table.get(i) {
                             if f.is_some() {
-                                if let Some(file) = &inner.fd_table[i] {
+                                if let Some(file) = &fd_table[i] {
                                     let f: Arc<dyn File + Send + Sync>;
                                     match file {
                                         FileClass::File(fi) => f = fi.clone(),
@@ -1203,6 +1432,7 @@ pub fn sys_pselect(
                                     if !f.writable() {
                                         return -1; //可能是错的
                                     }
+                                    table.add(&f);
                                     if f.write_blocking() {
                                         //fd 不可用
                                         write_fds.u128_set_bit(i, false);
@@ -1227,9 +1457,17 @@ pub fn sys_pselect(
                 //     efds,
                 //     time,
                 // );
+                drop(fd_table);
                 drop(inner);
                 drop(process);
-                suspend_current_and_run_next();
+                if deadline.map_or(false, |deadline| get_time_ns() >= deadline) {
+                    break;
+                }
+                if current_has_signal() {
+                    ret = -EINTR;
+                    break;
+                }
+                table.wait(deadline);
             } else {
                 gdb_println!(
                     SYSCALL_ENABLE,
@@ -1261,9 +1499,11 @@ pub fn sys_renameat2(
     let process = current_process();
     let token = current_user_token();
     let inner = process.acquire_inner_lock();
+    let fd_table = inner.fd_table.lock();
     let old_path = translated_str(token, old_path);
     let new_path = translated_str(token, new_path);
-    let cwd = inner.cwd.as_str();
+    let cwd = inner.cwd.lock();
+    let cwd = cwd.as_str();
     let old_file;
     let new_file;
 
@@ -1274,10 +1514,10 @@ pub fn sys_renameat2(
         }
     } else if old_fd != AT_FDCWD {
         let old_fd = old_fd as usize;
-        if old_fd >= inner.fd_table.len() {
+        if old_fd >= fd_table.len() {
             return -EBADF;
         }
-        if let Some(FileClass::File(osfile)) = &inner.fd_table[old_fd] {
+        if let Some(FileClass::File(osfile)) = &fd_table[old_fd] {
             match osfile.find(old_path.as_str(), OpenFlags::empty()) {
                 Some(tmp_file) => old_file = tmp_file.clone(),
                 None => return -ENOENT,
@@ -1306,10 +1546,10 @@ pub fn sys_renameat2(
         }
     } else if new_fd != AT_FDCWD {
         let new_fd = new_fd as usize;
-        if new_fd >= inner.fd_table.len() {
+        if new_fd >= fd_table.len() {
             return -EBADF;
         }
-        if let Some(FileClass::File(osfile)) = &inner.fd_table[new_fd] {
+        if let Some(FileClass::File(osfile)) = &fd_table[new_fd] {
             match osfile.find(new_path.as_str(), open_flags) {
                 Some(tmp_file) => new_file = tmp_file.clone(),
                 None => return -ENOENT,
@@ -1354,8 +1594,9 @@ pub fn sys_readdir(abs_path: *const u8, buf: *mut u8, len: usize) -> isize {
 pub fn sys_lseek(fd: usize, offset: usize, whence: usize) -> isize {
     let process = current_process();
     let inner = process.acquire_inner_lock();
+    let fd_table = inner.fd_table.lock();
 
-    let ret = if let Some(Some(f)) = inner.fd_table.get(fd) {
+    let ret = if let Some(Some(f)) = fd_table.get(fd) {
         match f {
             FileClass::File(fi) => {
                 let new_off: isize = match whence {
@@ -1391,7 +1632,8 @@ pub fn sys_pread64(fd: usize, buf: *mut u8, count: usize, offset: usize) -> isiz
     let token = current_user_token();
     let process = current_process();
     let inner = process.acquire_inner_lock();
-    let ret = if let Some(Some(f)) = inner.fd_table.get(fd) {
+    let fd_table = inner.fd_table.lock();
+    let ret = if let Some(Some(f)) = fd_table.get(fd) {
         match f {
             FileClass::File(fi) => {
                 let old_off = fi.offset();
@@ -32,26 +32,48 @@ pub const SYSCALL_FSTATAT: usize = 79;
 pub const SYSCALL_FSTAT: usize = 80;
 pub const SYSCALL_FSYNC: usize = 82;
 pub const SYSCALL_UTIMENSAT: usize = 88;
+pub const SYSCALL_PERSONALITY: usize = 92;
 pub const SYSCALL_EXIT: usize = 93;
 pub const SYSCALL_EXIT_GRUOP: usize = 94;
+pub const SYSCALL_WAITID: usize = 95;
 pub const SYSCALL_SET_TID_ADDRESS: usize = 96;
 pub const SYSCALL_FUTEX: usize = 98;
 pub const SYSCALL_NANOSLEEP: usize = 101;
 pub const SYSCALL_GETITIMER: usize = 102;
 pub const SYSCALL_SETITIMER: usize = 103;
 pub const SYSCALL_CLOCK_GETTIME: usize = 113;
+pub const SYSCALL_CLOCK_NANOSLEEP: usize = 115;
 pub const SYSCALL_SYSLOG: usize = 116;
+pub const SYSCALL_SCHED_SETPARAM: usize = 118;
+pub const SYSCALL_SCHED_SETSCHEDULER: usize = 119;
+pub const SYSCALL_SCHED_GETSCHEDULER: usize = 120;
+pub const SYSCALL_SCHED_GETPARAM: usize = 121;
+pub const SYSCALL_SCHED_SETAFFINITY: usize = 122;
+pub const SYSCALL_SCHED_GETAFFINITY: usize = 123;
 pub const SYSCALL_SCHED_YIELD: usize = 124;
+pub const SYSCALL_SCHED_GET_PRIORITY_MAX: usize = 125;
+pub const SYSCALL_SCHED_GET_PRIORITY_MIN: usize = 126;
 pub const SYSCALL_KILL: usize = 129;
 pub const SYSCALL_TKILL: usize = 130;
+pub const SYSCALL_SIGALTSTACK: usize = 132;
 pub const SYSCALL_SIGACTION: usize = 134;
 pub const SYSCALL_SIGPROCMASK: usize = 135;
+pub const SYSCALL_RT_SIGPENDING: usize = 136;
+pub const SYSCALL_RT_SIGTIMEDWAIT: usize = 137;
+pub const SYSCALL_RT_SIGQUEUEINFO: usize = 138;
 pub const SYSCALL_SIGRETURN: usize = 139;
+pub const SYSCALL_SETPRIORITY: usize = 140;
+pub const SYSCALL_GETPRIORITY: usize = 141;
 pub const SYSCALL_TIMES: usize = 153;
 pub const SYSCALL_SETPGID: usize = 154;
 pub const SYSCALL_GETPGID: usize = 155;
+pub const SYSCALL_GETSID: usize = 156;
+pub const SYSCALL_SETSID: usize = 157;
 pub const SYSCALL_UNAME: usize = 160;
+pub const SYSCALL_GETRLIMIT: usize = 163;
+pub const SYSCALL_SETRLIMIT: usize = 164;
 pub const SYSCALL_GETRUSAGE: usize = 165;
+pub const SYSCALL_GETCPU: usize = 168;
 pub const SYSCALL_GETTIMEOFDAY: usize = 169;
 pub const SYSCALL_GETPID: usize = 172;
 pub const SYSCALL_GETPPID: usize = 173;
@@ -70,12 +92,19 @@ pub const SYSCALL_CLONE: usize = 220;
 pub const SYSCALL_EXECVE: usize = 221;
 pub const SYSCALL_MMAP: usize = 222;
 pub const SYSCALL_MPROTECT: usize = 226;
+pub const SYSCALL_MLOCK: usize = 228;
+pub const SYSCALL_MUNLOCK: usize = 229;
+pub const SYSCALL_MINCORE: usize = 232;
+pub const SYSCALL_MADVISE: usize = 233;
 pub const SYSCALL_WAIT4: usize = 260;
 pub const SYSCALL_PRLIMIT: usize = 261;
 pub const SYSCALL_RENAMEAT2: usize = 276;
+pub const SYSCALL_PIDFD_SEND_SIGNAL: usize = 424;
+pub const SYSCALL_PIDFD_OPEN: usize = 434;
 
 pub const SYSCALL_TOGGLE_TRACE: usize = 0xf000;
 pub const SYSCALL_READDIR: usize = 0xf001;
+pub const SYSCALL_IDLE_STAT: usize = 0xf002;
 pub const SYSCALL_SHUTDOWN: usize = 0xffff;
 
 mod errorno;
@@ -132,6 +161,7 @@ pub fn init() {
         SYSCALL_TABLE[SYSCALL_FSTATAT] = sys_fstatat as usize;
         SYSCALL_TABLE[SYSCALL_FSTAT] = sys_fstat as usize;
         SYSCALL_TABLE[SYSCALL_UTIMENSAT] = sys_utimensat as usize;
+        SYSCALL_TABLE[SYSCALL_PERSONALITY] = sys_personality as usize;
         SYSCALL_TABLE[SYSCALL_EXIT] = sys_exit as usize;
         SYSCALL_TABLE[SYSCALL_EXIT_GRUOP] = sys_exit_group as usize;
         SYSCALL_TABLE[SYSCALL_SET_TID_ADDRESS] = sys_set_tid_address as usize;
@@ -140,17 +170,38 @@ pub fn init() {
         SYSCALL_TABLE[SYSCALL_GETITIMER] = sys_getitimer as usize;
         SYSCALL_TABLE[SYSCALL_SETITIMER] = sys_setitimer as usize;
         SYSCALL_TABLE[SYSCALL_CLOCK_GETTIME] = sys_clock_get_time as usize;
+        SYSCALL_TABLE[SYSCALL_CLOCK_NANOSLEEP] = sys_clock_nanosleep as usize;
         SYSCALL_TABLE[SYSCALL_SYSLOG] = sys_syslog as usize;
+        SYSCALL_TABLE[SYSCALL_SCHED_SETPARAM] = sys_sched_setparam as usize;
+        SYSCALL_TABLE[SYSCALL_SCHED_SETSCHEDULER] = sys_sched_setscheduler as usize;
+        SYSCALL_TABLE[SYSCALL_SCHED_GETSCHEDULER] = sys_sched_getscheduler as usize;
+        SYSCALL_TABLE[SYSCALL_SCHED_GETPARAM] = sys_sched_getparam as usize;
+        SYSCALL_TABLE[SYSCALL_SCHED_SETAFFINITY] = sys_sched_setaffinity as usize;
+        SYSCALL_TABLE[SYSCALL_SCHED_GETAFFINITY] = sys_sched_getaffinity as usize;
         SYSCALL_TABLE[SYSCALL_SCHED_YIELD] = sys_yield as usize;
+        SYSCALL_TABLE[SYSCALL_SCHED_GET_PRIORITY_MAX] = sys_sched_get_priority_max as usize;
+        SYSCALL_TABLE[SYSCALL_SCHED_GET_PRIORITY_MIN] = sys_sched_get_priority_min as usize;
         SYSCALL_TABLE[SYSCALL_KILL] = sys_kill as usize;
         SYSCALL_TABLE[SYSCALL_TKILL] = sys_tkill as usize;
+        SYSCALL_TABLE[SYSCALL_SIGALTSTACK] = sys_sigaltstack as usize;
         SYSCALL_TABLE[SYSCALL_SIGACTION] = sys_sigaction as usize;
         SYSCALL_TABLE[SYSCALL_SIGPROCMASK] = sys_sigprocmask as usize;
+        SYSCALL_TABLE[SYSCALL_RT_SIGPENDING] = sys_rt_sigpending as usize;
+        SYSCALL_TABLE[SYSCALL_RT_SIGTIMEDWAIT] = sys_rt_sigtimedwait as usize;
+        SYSCALL_TABLE[SYSCALL_RT_SIGQUEUEINFO] = sys_rt_sigqueueinfo as usize;
         SYSCALL_TABLE[SYSCALL_SIGRETURN] = sys_sigreturn as usize;
+        SYSCALL_TABLE[SYSCALL_SETPRIORITY] = sys_setpriority as usize;
+        SYSCALL_TABLE[SYSCALL_GETPRIORITY] = sys_getpriority as usize;
         SYSCALL_TABLE[SYSCALL_TIMES] = sys_times as usize;
         SYSCALL_TABLE[SYSCALL_SETPGID] = sys_setpgid as usize;
         SYSCALL_TABLE[SYSCALL_GETPGID] = sys_getpgid as usize;
+        SYSCALL_TABLE[SYSCALL_GETSID] = sys_getsid as usize;
+        SYSCALL_TABLE[SYSCALL_SETSID] = sys_setsid as usize;
         SYSCALL_TABLE[SYSCALL_UNAME] = sys_uname as usize;
+        SYSCALL_TABLE[SYSCALL_GETRLIMIT] = sys_getrlimit as usize;
+        SYSCALL_TABLE[SYSCALL_SETRLIMIT] = sys_setrlimit as usize;
+        SYSCALL_TABLE[SYSCALL_GETRUSAGE] = sys_getrusage as usize;
+        SYSCALL_TABLE[SYSCALL_GETCPU] = sys_getcpu as usize;
         SYSCALL_TABLE[SYSCALL_GETTIMEOFDAY] = sys_get_time as usize;
         SYSCALL_TABLE[SYSCALL_GETPID] = sys_getpid as usize;
         SYSCALL_TABLE[SYSCALL_GETPPID] = sys_getppid as usize;
@@ -165,11 +216,19 @@ pub fn init() {
         SYSCALL_TABLE[SYSCALL_EXECVE] = sys_exec as usize;
         SYSCALL_TABLE[SYSCALL_MMAP] = sys_mmap as usize;
         SYSCALL_TABLE[SYSCALL_MPROTECT] = sys_mprotect as usize;
+        SYSCALL_TABLE[SYSCALL_MLOCK] = sys_mlock as usize;
+        SYSCALL_TABLE[SYSCALL_MUNLOCK] = sys_munlock as usize;
+        SYSCALL_TABLE[SYSCALL_MINCORE] = sys_mincore as usize;
+        SYSCALL_TABLE[SYSCALL_MADVISE] = sys_madvise as usize;
         SYSCALL_TABLE[SYSCALL_WAIT4] = sys_waitpid as usize;
+        SYSCALL_TABLE[SYSCALL_WAITID] = sys_waitid as usize;
         SYSCALL_TABLE[SYSCALL_PRLIMIT] = sys_prlimit as usize;
         SYSCALL_TABLE[SYSCALL_RENAMEAT2] = sys_renameat2 as usize;
+        SYSCALL_TABLE[SYSCALL_PIDFD_SEND_SIGNAL] = sys_pidfd_send_signal as usize;
+        SYSCALL_TABLE[SYSCALL_PIDFD_OPEN] = sys_pidfd_open as usize;
         SYSCALL_TABLE[SYSCALL_TOGGLE_TRACE] = sys_toggle_trace as usize;
         SYSCALL_TABLE[SYSCALL_READDIR] = sys_readdir as usize;
+        SYSCALL_TABLE[SYSCALL_IDLE_STAT] = sys_idle_stat as usize;
         SYSCALL_TABLE[SYSCALL_SHUTDOWN] = sys_shutdown as usize;
     }
 }
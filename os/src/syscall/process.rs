@@ -1,18 +1,17 @@
 use core::arch::asm;
 use core::mem::size_of;
 use core::slice::from_raw_parts;
-use core::sync::atomic::Ordering;
 
-use crate::config::{aligned_up, PAGE_SIZE, FDMAX, CLOCK_FREQ};
+use crate::config::{aligned_down, aligned_up, PAGE_SIZE, CLOCK_FREQ, ARG_MAX};
 use crate::console::{
     clear_log_buf, read_all_log_buf, read_clear_log_buf, read_log_buf, unread_size, LOG_BUF_LEN,
 };
-use crate::fs::{open_common_file, OpenFlags, print_inner};
+use crate::fs::{print_inner, FileClass, PidFd};
+use crate::board::MAX_CPU_NUM;
 use crate::gdb_println;
-use crate::loader::get_usershell_binary;
 use crate::mm::{
-    translated_byte_buffer, translated_ref, translated_refmut, translated_str, PTEFlags,
-    UserBuffer, VirtAddr, VirtPageNum,
+    frame_allocator_stat, translated_byte_buffer, translated_ref, translated_refmut, translated_str,
+    MemoryStat, PTEFlags, UserBuffer, VirtAddr, VirtPageNum,
 };
 use crate::monitor::{QEMU, SYSCALL_ENABLE};
 use crate::multicore::get_hartid;
@@ -20,16 +19,21 @@ use crate::sbi::shutdown;
 use crate::syscall::process;
 use crate::task::{
     current_process, current_task, current_user_token, exit_current_and_run_next, is_signal_valid,
-    suspend_current_and_run_next, tid2task, SigAction, UContext, SIG_DFL, ClearChildTid, ITimerSpec, TimeSpec, current_trap_cx, __FA, block_current_and_run_next,
+    suspend_current_and_run_next, tid2task, SigAction, TID2TCB, UContext, SIG_DFL, ClearChildTid, ITimerSpec, TimeSpec, ITIMER_REAL, ITIMER_VIRTUAL, ITIMER_PROF, current_trap_cx, __FA, block_current_and_run_next, prepare_to_block,
+    hart_idle_stat, online_harts, HartIdleStat, SchedPolicy, TaskControlBlock, ALL_CPUS_MASK, MAX_RT_PRIO, MIN_RT_PRIO,
+    exited_status, stopped_status, JobEvent, CONTINUED_STATUS, current_has_signal,
+    process_group, all_processes, ProcessControlBlock, do_execve, ExecParams, remove_from_pid2process,
+    child_status_code, CpuTimes, SigInfo, pid2process, thread_count, RLimit64, RLIM_NLIMITS,
+    RLIMIT_MEMLOCK, RLIMIT_NOFILE, RLIMIT_NPROC,
 };
 use crate::test::{enable_ttimer_output, stop_ttimer, print_ttimer, start_ttimer};
-use crate::timer::{get_time_ns, get_time_us, NSEC_PER_SEC, USEC_PER_SEC, get_time};
+use crate::timer::{arm_itimer_real, get_time_ns, get_time_us, NSEC_PER_SEC, USEC_PER_SEC, get_time};
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 // use fat32_fs::sync_all;
 
-use super::errorno::{EINVAL, EPERM, ESRCH, ECHILD};
+use super::errorno::{EAGAIN, EBADF, EINVAL, ENOMEM, EPERM, ESRCH, ECHILD, ERESTARTSYS, E2BIG};
 
 pub fn sys_unknown() -> isize {
     gdb_println!(
@@ -50,9 +54,20 @@ pub fn sys_toggle_trace() -> isize {
     0
 }
 
+/// 将各 hart 的空闲统计写入 buf（最多 count 项），返回 hart 数
+pub fn sys_idle_stat(buf: *mut HartIdleStat, count: usize) -> isize {
+    let token = current_user_token();
+    let harts = count.min(MAX_CPU_NUM);
+    for hartid in 0..harts {
+        *translated_refmut(token, unsafe { buf.add(hartid) }) = hart_idle_stat(hartid);
+    }
+    gdb_println!(SYSCALL_ENABLE, "sys_idle_stat(buf: {:?}, count: {}) = {}", buf, count, harts);
+    harts as isize
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     gdb_println!(SYSCALL_ENABLE, "sys_exit(exit_code: {} ) = ?", exit_code);
-    exit_current_and_run_next(exit_code, false);
+    exit_current_and_run_next(exited_status(exit_code), false);
 }
 
 pub fn sys_exit_group(exit_code: i32) -> ! {
@@ -61,7 +76,7 @@ pub fn sys_exit_group(exit_code: i32) -> ! {
         "sys_exit_group(exit_code: {}) = ?",
         exit_code
     );
-    exit_current_and_run_next(exit_code, true);
+    exit_current_and_run_next(exited_status(exit_code), true);
 }
 
 pub fn sys_yield() -> isize {
@@ -135,11 +150,35 @@ pub fn sys_clone(
 ) -> isize {
     let current_process = current_process();
     let flags = CloneFlags::from_bits(flags).unwrap();
+    // 共享信号处理函数要求共享地址空间，线程要求共享信号处理函数
+    // CLONE_PIDFD 与 CLONE_PARENT_SETTID 都通过 ptid 返回，不能同时使用
+    if flags.contains(CloneFlags::CLONE_SIGHAND) && !flags.contains(CloneFlags::CLONE_VM)
+        || flags.contains(CloneFlags::CLONE_THREAD) && !flags.contains(CloneFlags::CLONE_SIGHAND)
+        || flags.contains(CloneFlags::CLONE_PIDFD)
+            && flags.intersects(CloneFlags::CLONE_THREAD | CloneFlags::CLONE_PARENT_SETTID)
+    {
+        return -EINVAL;
+    }
+    // RLIMIT_NPROC 限制全系统线程与进程的总数，所有进程同属一个用户
+    if thread_count() >= current_process.acquire_inner_lock().rlimit(RLIMIT_NPROC) {
+        return -EAGAIN;
+    }
+    // 子进程创建后无法撤销，先确认还能分配 pidfd
+    if flags.contains(CloneFlags::CLONE_PIDFD) {
+        let inner = current_process.acquire_inner_lock();
+        let limit = inner.rlimit(RLIMIT_NOFILE);
+        if let Err(errno) = inner.fd_table.lock().alloc_fd(0, limit) {
+            return errno;
+        }
+    }
 
     let ret = if flags.contains(CloneFlags::CLONE_THREAD) {
         // create a thread here
         let task = current_task().unwrap();
-        let new_task = current_process.clone_thread(task, flags, stack_ptr as usize, newtls);
+        let new_task = match current_process.clone_thread(task, flags, stack_ptr as usize, newtls) {
+            Some(new_task) => new_task,
+            None => return -EAGAIN,
+        };
         let mut new_task_inner = new_task.acquire_inner_lock();
         let new_tid = new_task_inner.gettid();
         if flags.contains(CloneFlags::CLONE_PARENT_SETTID) && ptid_ptr as usize != 0 {
@@ -155,7 +194,11 @@ pub fn sys_clone(
         }
         new_tid
     } else {
-        let new_process = current_process.fork(flags, stack_ptr as usize, newtls);
+        let task = current_task().unwrap();
+        let new_process = match current_process.fork(task, flags, stack_ptr as usize, newtls) {
+            Some(new_process) => new_process,
+            None => return -EAGAIN,
+        };
         let new_process_inner = new_process.acquire_inner_lock();
         let new_task = new_process_inner.get_task(0);
         let mut new_task_inner = new_task.acquire_inner_lock();
@@ -176,6 +219,27 @@ pub fn sys_clone(
             *translated_refmut(new_process_inner.get_user_token(), ctid_ptr) =
             new_pid;
         }
+        drop(new_task_inner);
+        drop(new_process_inner);
+        if flags.contains(CloneFlags::CLONE_PIDFD) && ptid_ptr as usize != 0 {
+            // 与其他线程竞争描述符而失败时，子进程照常运行，只是没有 pidfd
+            match install_pidfd(&new_process, false) {
+                Ok(pidfd) => *translated_refmut(current_user_token(), ptid_ptr) = pidfd as u32,
+                Err(errno) => return errno,
+            }
+        }
+        // vfork 的调用线程挂起，直到子进程 execve 或退出
+        if flags.contains(CloneFlags::CLONE_VFORK) {
+            loop {
+                prepare_to_block();
+                if new_process.acquire_inner_lock().vfork_parent.is_none()
+                    || current_task().unwrap().acquire_inner_lock().killed
+                {
+                    break;
+                }
+                block_current_and_run_next();
+            }
+        }
         new_pid as usize
     };
     gdb_println!(
@@ -195,158 +259,377 @@ pub fn sys_clone(
     ret as isize
 }
 
-pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
-    let token = current_user_token();
-    let mut path = translated_str(token, path);
-    let mut args_vec: Vec<String> = Vec::with_capacity(16);
-
+/// 读取用户空间以空指针结尾的字符串数组，total 累计字符串与指针数组的大小，超过 limit 时返回 E2BIG
+fn translated_str_array(
+    token: usize,
+    mut ptr: *const usize,
+    total: &mut usize,
+    limit: usize,
+) -> Result<Vec<String>, isize> {
+    let mut strings: Vec<String> = Vec::with_capacity(16);
+    // 与 Linux 相同，数组指针为空时视为空数组
+    if ptr.is_null() {
+        return Ok(strings);
+    }
     loop {
-        let arg_str_ptr = *translated_ref(token, args);
-        if arg_str_ptr == 0 {
+        let str_ptr = *translated_ref(token, ptr);
+        if str_ptr == 0 {
             break;
         }
-        args_vec.push(translated_str(token, arg_str_ptr as *const u8));
+        let string = translated_str(token, str_ptr as *const u8);
+        *total += string.len() + 1 + size_of::<usize>();
+        if *total > limit {
+            return Err(-E2BIG);
+        }
+        strings.push(string);
         unsafe {
-            args = args.add(1);
+            ptr = ptr.add(1);
         }
     }
+    Ok(strings)
+}
 
-    // run usershell
-    if path == "user_shell" {
-        let process = current_process();
-        match process.exec(get_usershell_binary(), &args_vec) {
-            Some(task) => {
-                task.acquire_inner_lock().__save_info_to_fast_access();
-                unsafe {
-                    __FA[get_hartid()].__user_token = process.acquire_inner_lock().get_user_token();
-                }
-                unsafe {
-                    asm!("sfence.vma");
-                    asm!("fence.i");
-                }
-                // return argc because cx.x[10] will be covered with it later
-                return 0
-            },
-            None => return -EPERM
+pub fn sys_exec(path: *const u8, args: *const usize, envp: *const usize) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    // 参数与环境变量映射在用户栈之上，总大小不超过 ARG_MAX，不受 RLIMIT_STACK 影响
+    let mut total = 0;
+    let strings = translated_str_array(token, args, &mut total, ARG_MAX).and_then(|args_vec| {
+        let envs_vec = translated_str_array(token, envp, &mut total, ARG_MAX)?;
+        Ok((args_vec, envs_vec))
+    });
+    let (args_vec, envs_vec) = match strings {
+        Ok(strings) => strings,
+        Err(errno) => {
+            gdb_println!(SYSCALL_ENABLE, "sys_exec(path: {:?}, ...) = {}", path, errno);
+            return errno;
         }
-        return 0;
-    }
-
-    // 执行./xxx.sh时，自动转化为 /busybox sh ./xxx.sh
-    if path.ends_with(".sh") {
-        args_vec.insert(0, String::from("sh"));
-        args_vec.insert(0, String::from("/busybox"));
-        path = String::from("/busybox");
-    }
-
-    let process =  current_process();
-    let cwd = process.acquire_inner_lock().cwd.clone();
-    // run other programs
-    let ret = if let Some(app_vfile) = open_common_file(cwd.as_str(), path.as_str(), OpenFlags::RDONLY) {
-        let all_data = unsafe { app_vfile.read_as_elf() };
-        match process.exec(all_data, &args_vec) {
-            Some(task) => {
-                task.acquire_inner_lock().__save_info_to_fast_access();
-                unsafe {
-                    __FA[get_hartid()].__user_token = process.acquire_inner_lock().get_user_token();
-                }
-                unsafe {
-                    asm!("sfence.vma");
-                    asm!("fence.i");
-                }
-                // return argc because cx.x[10] will be covered with it later
-                0
-            },
-            None => -EPERM}
-    } else {
-        -EPERM
     };
-
     gdb_println!(
         SYSCALL_ENABLE,
-        "sys_exec(path: {:?}, args: {:x?} ) = {}",
+        "sys_exec(path: {:?}, args: {:x?}, envs: {:x?}) = ...",
         path,
         args_vec,
-        ret
+        envs_vec
     );
 
+    let process = current_process();
+    let cwd = process.acquire_inner_lock().cwd.lock().clone();
+    let params = ExecParams {
+        path,
+        args: args_vec,
+        envs: envs_vec,
+    };
+    let ret = match do_execve(&process, cwd.as_str(), params) {
+        Ok(task) => {
+            task.acquire_inner_lock().__save_info_to_fast_access();
+            unsafe {
+                __FA[get_hartid()].__user_token = process.acquire_inner_lock().get_user_token();
+            }
+            unsafe {
+                asm!("sfence.vma");
+                asm!("fence.i");
+            }
+            // return argc because cx.x[10] will be covered with it later
+            0
+        }
+        Err(errno) => errno,
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_exec(...) = {}", ret);
     ret
 }
 
-const WNOHANG: isize = 1;
+const WNOHANG: u32 = 1;
+const WUNTRACED: u32 = 2;
+/// waitid 中与 WUNTRACED 相同
+const WSTOPPED: u32 = WUNTRACED;
+const WEXITED: u32 = 4;
+const WCONTINUED: u32 = 8;
+/// 只报告状态变化，子进程仍可再次被等待
+const WNOWAIT: u32 = 0x0100_0000;
+/// 以下三个只影响对 clone 子进程的选择，目前所有子进程同样对待
+const __WNOTHREAD: u32 = 0x2000_0000;
+const __WALL: u32 = 0x4000_0000;
+const __WCLONE: u32 = 0x8000_0000;
+
+const P_ALL: usize = 0;
+const P_PID: usize = 1;
+const P_PGID: usize = 2;
+const P_PIDFD: usize = 3;
+
+/// wait 系列系统调用等待的子进程
+enum WaitTarget {
+    Any,
+    Pid(usize),
+    Pgid(usize),
+}
+
+impl WaitTarget {
+    fn matches(&self, child: &Arc<ProcessControlBlock>) -> bool {
+        match *self {
+            WaitTarget::Any => true,
+            WaitTarget::Pid(pid) => child.getpid() == pid,
+            WaitTarget::Pgid(pgid) => child.acquire_inner_lock().pgid() == pgid,
+        }
+    }
+}
+
+/// 子进程的状态变化，status 为 wait 状态编码
+struct WaitEvent {
+    pid: usize,
+    status: i32,
+    rusage: RUsage,
+}
 
-/// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, wstatus: *mut i32, options: isize) -> isize {
+/// 等待 target 中的子进程发生 options 指定的状态变化（WEXITED、WSTOPPED、WCONTINUED）
+/// 没有符合的子进程时返回 ECHILD，WNOHANG 时尚无状态变化返回 None
+fn do_wait(target: WaitTarget, options: u32) -> Result<Option<WaitEvent>, isize> {
     loop {
-        let mut found = false; // when WNOHANG is set
-        let mut exit_info = None;
-        {
-            let process = current_process();
-            let mut inner = process.acquire_inner_lock();
-
-            for (idx, child) in inner.children.iter().enumerate() {
-                let cpid = child.getpid();
-                if pid == -1 || child.getpid() == pid as usize {
-                    found = true;
-                    let child_inner = child.acquire_inner_lock();
-                    // *** here we do not recycle tasks 
-                    if child_inner.is_zombie {
-                        exit_info = Some((idx, cpid));
-                        let exit_code = child_inner.exit_code;
-                        if wstatus as usize != 0 {
-                            *translated_refmut(inner.memory_set.token(), wstatus) =
-                                (exit_code & 0xff) << 8;
-                        }
-                        break;
+        prepare_to_block();
+        let process = current_process();
+        let mut inner = process.acquire_inner_lock();
+        let mut found = false;
+        let mut event = None;
+        for (idx, child) in inner.children.iter().enumerate() {
+            if !target.matches(child) {
+                continue;
+            }
+            found = true;
+            let mut child_inner = child.acquire_inner_lock();
+            let status = if child_inner.is_zombie {
+                Some(child_inner.exit_code).filter(|_| options & WEXITED != 0)
+            } else {
+                match child_inner.job_event {
+                    Some(JobEvent::Stopped(signum)) if options & WSTOPPED != 0 => {
+                        Some(stopped_status(signum))
                     }
+                    Some(JobEvent::Continued) if options & WCONTINUED != 0 => Some(CONTINUED_STATUS),
+                    _ => None,
                 }
+            };
+            let status = match status {
+                Some(status) => status,
+                None => continue,
+            };
+            // 停止/继续事件被取走后不再重复报告，子进程仍保留在 children 中
+            if !child_inner.is_zombie && options & WNOWAIT == 0 {
+                child_inner.job_event = None;
             }
-            if let Some((idx, cpid)) = exit_info {
-                let p = inner.children.remove(idx);
-                assert_eq!(Arc::strong_count(&p), 1);
-                drop(p);
-                gdb_println!(
-                    SYSCALL_ENABLE,
-                    "sys_waitpid(pid: {}, wstatus: {:#x?}, options: {}) = {}",
-                    pid,
-                    wstatus,
-                    options,
-                    cpid
-                );
-                return cpid as isize;
+            // 子进程自身及其已回收后代的资源使用
+            let mut stat = child_inner.memory_stat();
+            stat.accumulate(&child_inner.children_mem_stat);
+            let mut times = child_inner.cpu_times();
+            times.accumulate(&child_inner.children_times);
+            let reap = child_inner.is_zombie && options & WNOWAIT == 0;
+            event = Some((idx, child.getpid(), status, stat, times, reap));
+            break;
+        }
+        if let Some((idx, pid, status, stat, times, reap)) = event {
+            if reap {
+                let child = inner.children.remove(idx);
+                remove_from_pid2process(pid);
+                // pidfd 可能仍临时持有子进程的引用，最后一个引用释放时回收
+                drop(child);
+                inner.children_mem_stat.accumulate(&stat);
+                inner.children_times.accumulate(&times);
             }
-            // ---- release current PCB automatically
-        }
-        // not found yet
-        assert!(!found || exit_info.is_none());
-        if !found || options == WNOHANG {
-            gdb_println!(
-                SYSCALL_ENABLE,
-                "sys_waitpid(pid: {}, wstatus: {:#x?}, options: {}) = {}",
+            return Ok(Some(WaitEvent {
                 pid,
-                wstatus,
-                options,
-                -ECHILD
-            );
-            return -ECHILD;
+                status,
+                rusage: RUsage::new(&stat, &times),
+            }));
+        }
+        drop(inner);
+        drop(process);
+        if !found {
+            return Err(-ECHILD);
+        }
+        if options & WNOHANG != 0 {
+            return Ok(None);
+        }
+        if current_has_signal() {
+            return Err(-ERESTARTSYS);
         }
         block_current_and_run_next();
     }
 }
 
+/// wait4：pid 为 -1 时等待任意子进程，0 时等待与调用者同组的子进程，小于 -1 时等待进程组 -pid 中的子进程
+/// WNOHANG 时尚无子进程状态变化返回 0
+pub fn sys_waitpid(pid: isize, wstatus: *mut i32, options: u32, rusage: *mut u8) -> isize {
+    let target = match pid {
+        -1 => WaitTarget::Any,
+        0 => WaitTarget::Pgid(current_process().acquire_inner_lock().pgid()),
+        pid if pid < 0 => WaitTarget::Pgid(pid.unsigned_abs()),
+        pid => WaitTarget::Pid(pid as usize),
+    };
+    let ret = if options & !(WNOHANG | WUNTRACED | WCONTINUED | __WNOTHREAD | __WALL | __WCLONE) != 0 {
+        -EINVAL
+    } else {
+        match do_wait(target, options | WEXITED) {
+            Ok(Some(event)) => {
+                let token = current_user_token();
+                if !wstatus.is_null() {
+                    *translated_refmut(token, wstatus) = event.status;
+                }
+                if !rusage.is_null() {
+                    event.rusage.copy_to_user(token, rusage);
+                }
+                event.pid as isize
+            }
+            Ok(None) => 0,
+            Err(errno) => errno,
+        }
+    };
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_waitpid(pid: {}, wstatus: {:#x?}, options: {:#x}) = {}",
+        pid,
+        wstatus,
+        options,
+        ret
+    );
+    ret
+}
+
+/// waitid：成功返回 0，infop 中填写子进程的 si_pid、si_code 与 si_status，
+/// WNOHANG 时尚无子进程状态变化则将其清零
+pub fn sys_waitid(idtype: usize, id: isize, infop: *mut SigInfo, options: u32, rusage: *mut u8) -> isize {
+    let mut pidfd_nonblock = false;
+    let target = match idtype {
+        P_ALL => Ok(WaitTarget::Any),
+        P_PID if id > 0 => Ok(WaitTarget::Pid(id as usize)),
+        P_PGID if id == 0 => Ok(WaitTarget::Pgid(current_process().acquire_inner_lock().pgid())),
+        P_PGID if id > 0 => Ok(WaitTarget::Pgid(id as usize)),
+        // pidfd 指向的进程已被回收时没有可等待的子进程
+        P_PIDFD if id >= 0 => match pidfd_get(id as usize) {
+            Ok((Some(process), nonblock)) => {
+                pidfd_nonblock = nonblock;
+                Ok(WaitTarget::Pid(process.getpid()))
+            }
+            Ok((None, _)) => Err(-ECHILD),
+            Err(errno) => Err(errno),
+        },
+        _ => Err(-EINVAL),
+    };
+    let valid = WNOHANG | WNOWAIT | WEXITED | WSTOPPED | WCONTINUED | __WNOTHREAD | __WALL | __WCLONE;
+    let ret = match target {
+        Ok(_) if options & !valid != 0 || options & (WEXITED | WSTOPPED | WCONTINUED) == 0 => -EINVAL,
+        Ok(target) => match do_wait(target, if pidfd_nonblock { options | WNOHANG } else { options }) {
+            // 非阻塞的 pidfd 在子进程尚无状态变化时返回 EAGAIN
+            Ok(None) if pidfd_nonblock && options & WNOHANG == 0 => -EAGAIN,
+            Ok(event) => {
+                let token = current_user_token();
+                if !infop.is_null() {
+                    let info = match &event {
+                        Some(event) => {
+                            let (code, status) = child_status_code(event.status);
+                            SigInfo::child(code, event.pid, 0, status)
+                        }
+                        None => SigInfo::new(0, 0),
+                    };
+                    UserBuffer::new(translated_byte_buffer(
+                        token,
+                        infop as *const u8,
+                        size_of::<SigInfo>(),
+                    ))
+                    .copy_to_user(info.as_bytes());
+                }
+                match event {
+                    Some(event) if !rusage.is_null() => event.rusage.copy_to_user(token, rusage),
+                    _ => {}
+                }
+                0
+            }
+            Err(errno) => errno,
+        },
+        Err(errno) => errno,
+    };
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_waitid(idtype: {}, id: {}, infop: {:#x?}, options: {:#x}) = {}",
+        idtype,
+        id,
+        infop,
+        options,
+        ret
+    );
+    ret
+}
+
+/// 与 O_NONBLOCK 相同
+const PIDFD_NONBLOCK: u32 = 0x800;
+
+/// 在当前进程中为 target 分配 pidfd
+fn install_pidfd(target: &Arc<ProcessControlBlock>, nonblock: bool) -> Result<usize, isize> {
+    let process = current_process();
+    let inner = process.acquire_inner_lock();
+    let mut fd_table = inner.fd_table.lock();
+    let fd = fd_table.alloc_fd(0, inner.rlimit(RLIMIT_NOFILE))?;
+    fd_table[fd] = Some(FileClass::Abs(Arc::new(PidFd::new(target, nonblock))));
+    Ok(fd)
+}
+
+/// 取得 pidfd 指向的进程（已被回收时为 None）及其是否非阻塞
+pub fn pidfd_get(fd: usize) -> Result<(Option<Arc<ProcessControlBlock>>, bool), isize> {
+    let process = current_process();
+    let inner = process.acquire_inner_lock();
+    let fd_table = inner.fd_table.lock();
+    match fd_table.get(fd) {
+        Some(Some(FileClass::Abs(file))) => file
+            .as_pidfd()
+            .map(|pidfd| (pidfd.process(), pidfd.nonblock()))
+            .ok_or(-EINVAL),
+        Some(Some(_)) => Err(-EINVAL),
+        _ => Err(-EBADF),
+    }
+}
+
+/// 为进程 pid 创建 pidfd，进程退出后 pidfd 可读，pid 须为进程而非线程的 id
+pub fn sys_pidfd_open(pid: isize, flags: u32) -> isize {
+    let ret = if pid <= 0 || flags & !PIDFD_NONBLOCK != 0 {
+        -EINVAL
+    } else {
+        match pid2process(pid as usize) {
+            Some(target) => match install_pidfd(&target, flags & PIDFD_NONBLOCK != 0) {
+                Ok(fd) => fd as isize,
+                Err(errno) => errno,
+            },
+            None => -ESRCH,
+        }
+    };
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_pidfd_open(pid: {}, flags: {:#x}) = {}",
+        pid,
+        flags,
+        ret
+    );
+    ret
+}
+
 pub fn sys_brk(addr: usize) -> isize {
     let process = current_process();
-    let mut inner = process.acquire_inner_lock();
+    let inner = process.acquire_inner_lock();
+    let mut mm = inner.mm.lock();
     // println!("syscall brk addr = {:x?}, base = {:x?}, top = {:x?}", addr, inner.user_heap_base, inner.user_heap_top);
     let ret = if addr == 0 {
-        inner.user_heap_top as isize
-    } else if addr >= inner.user_heap_base {
-        if addr < inner.user_heap_top {
-            let prev_top = inner.user_heap_top;
-            inner.memory_set.remove_heap_dataframes(prev_top, addr);
+        mm.user_heap_top as isize
+    } else if addr > mm.user_heap_top
+        && !mm.may_expand_vm(
+            &inner.rlimits,
+            (aligned_up(addr) - aligned_up(mm.user_heap_top)) / PAGE_SIZE,
+            true,
+        )
+    {
+        // 超出 RLIMIT_AS 或 RLIMIT_DATA 时与 Linux 相同返回原来的堆顶
+        mm.user_heap_top as isize
+    } else if addr >= mm.user_heap_base {
+        if addr < mm.user_heap_top {
+            let prev_top = mm.user_heap_top;
+            mm.memory_set.remove_heap_dataframes(prev_top, addr);
         }
-        inner.user_heap_top = addr as usize;
+        mm.user_heap_top = addr as usize;
         addr as isize
     } else {
         -EPERM
@@ -393,6 +676,144 @@ pub fn sys_munmap(start: usize, _len: usize) -> isize {
     ret
 }
 
+const MADV_NORMAL: usize = 0;
+const MADV_RANDOM: usize = 1;
+const MADV_SEQUENTIAL: usize = 2;
+const MADV_WILLNEED: usize = 3;
+const MADV_DONTNEED: usize = 4;
+const MADV_FREE: usize = 8;
+
+/// 检查并转换用户给出的地址范围，start需页对齐
+fn user_vpn_range(start: usize, len: usize) -> Option<(VirtPageNum, VirtPageNum)> {
+    if start % PAGE_SIZE != 0 || start.checked_add(len).is_none() {
+        return None;
+    }
+    Some((VirtAddr::from(start).floor(), VirtAddr::from(start + len).ceil()))
+}
+
+pub fn sys_madvise(start: usize, len: usize, advice: usize) -> isize {
+    let ret = match user_vpn_range(start, len) {
+        None => -EINVAL,
+        Some((start_vpn, end_vpn)) => {
+            let process = current_process();
+            let inner = process.acquire_inner_lock();
+            let mut mm = inner.mm.lock();
+            if !mm.is_mapped_range(start_vpn, end_vpn) {
+                -ENOMEM
+            } else {
+                match advice {
+                    MADV_NORMAL | MADV_RANDOM | MADV_SEQUENTIAL => 0,
+                    MADV_WILLNEED => {
+                        let (heap_base, heap_top) = (mm.user_heap_base, mm.user_heap_top);
+                        mm.memory_set.populate(start_vpn, end_vpn, heap_base, heap_top);
+                        0
+                    }
+                    // 没有延迟回收机制，MADV_FREE 与 MADV_DONTNEED 相同，立即释放
+                    MADV_DONTNEED | MADV_FREE => {
+                        if mm.memory_set.release_pages(start_vpn, end_vpn) == 0 {
+                            0
+                        } else {
+                            -EINVAL
+                        }
+                    }
+                    _ => -EINVAL,
+                }
+            }
+        }
+    };
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_madvise(start: {:#x?}, len: {:#x?}, advice: {}) = {}",
+        start,
+        len,
+        advice,
+        ret
+    );
+    ret
+}
+
+pub fn sys_mincore(start: usize, len: usize, vec: *mut u8) -> isize {
+    let ret = match user_vpn_range(start, len) {
+        None => -EINVAL,
+        Some((start_vpn, end_vpn)) => {
+            let process = current_process();
+            let inner = process.acquire_inner_lock();
+            let mm = inner.mm.lock();
+            if !mm.is_mapped_range(start_vpn, end_vpn) {
+                -ENOMEM
+            } else {
+                let token = mm.memory_set.token();
+                let residency: Vec<u8> = (start_vpn.0..end_vpn.0)
+                    .map(|vpn| mm.memory_set.is_resident(VirtPageNum(vpn)) as u8)
+                    .collect();
+                drop(mm);
+                drop(inner);
+                let mut p = vec as usize;
+                for r in residency {
+                    *translated_refmut(token, p as *mut u8) = r;
+                    p += 1;
+                }
+                0
+            }
+        }
+    };
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_mincore(start: {:#x?}, len: {:#x?}, vec: {:#x?}) = {}",
+        start,
+        len,
+        vec,
+        ret
+    );
+    ret
+}
+
+/// 锁定前先分配好范围内的所有页，锁定的总量受 RLIMIT_MEMLOCK 限制
+pub fn sys_mlock(start: usize, len: usize) -> isize {
+    let ret = match user_vpn_range(aligned_down(start), len + start % PAGE_SIZE) {
+        None => -EINVAL,
+        Some((start_vpn, end_vpn)) => {
+            let process = current_process();
+            let inner = process.acquire_inner_lock();
+            let limit = inner.rlimit(RLIMIT_MEMLOCK);
+            let mut mm = inner.mm.lock();
+            if !mm.is_mapped_range(start_vpn, end_vpn) {
+                -ENOMEM
+            } else if limit == 0 {
+                -EPERM
+            } else if mm.memory_set.locked_pages_after(start_vpn, end_vpn) * PAGE_SIZE > limit {
+                -ENOMEM
+            } else {
+                let (heap_base, heap_top) = (mm.user_heap_base, mm.user_heap_top);
+                mm.memory_set.populate(start_vpn, end_vpn, heap_base, heap_top);
+                mm.memory_set.lock_pages(start_vpn, end_vpn);
+                0
+            }
+        }
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_mlock(start: {:#x?}, len: {:#x?}) = {}", start, len, ret);
+    ret
+}
+
+pub fn sys_munlock(start: usize, len: usize) -> isize {
+    let ret = match user_vpn_range(aligned_down(start), len + start % PAGE_SIZE) {
+        None => -EINVAL,
+        Some((start_vpn, end_vpn)) => {
+            let process = current_process();
+            let inner = process.acquire_inner_lock();
+            let mut mm = inner.mm.lock();
+            if !mm.is_mapped_range(start_vpn, end_vpn) {
+                -ENOMEM
+            } else {
+                mm.memory_set.unlock_pages(start_vpn, end_vpn);
+                0
+            }
+        }
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_munlock(start: {:#x?}, len: {:#x?}) = {}", start, len, ret);
+    ret
+}
+
 pub fn sys_getppid() -> isize {
     let parent = current_process()
         .acquire_inner_lock()
@@ -401,7 +822,7 @@ pub fn sys_getppid() -> isize {
         .unwrap()
         .upgrade()
         .unwrap();
-    let ret = parent.pid.load(Ordering::Relaxed) as isize;
+    let ret = parent.getpid() as isize;
     gdb_println!(SYSCALL_ENABLE, "sys_getppid() = {}", ret);
     ret
 }
@@ -512,17 +933,336 @@ pub fn sys_clock_get_time(_clk_id: usize, tp: *mut u64) -> isize {
     0
 }
 
-pub fn sys_setpgid() -> isize {
-    gdb_println!(SYSCALL_ENABLE, "sys_setpgid(...) = 0");
+/// pid 为 0 表示调用者自身，否则查找该进程
+fn find_process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    if pid == 0 {
+        return Some(current_process());
+    }
+    tid2task(pid)
+        .and_then(|task| task.process.upgrade())
+        .filter(|process| process.getpid() == pid)
+}
+
+fn do_setpgid(pid: usize, pgid: isize) -> isize {
+    if pgid < 0 {
+        return -EINVAL;
+    }
+    let current = current_process();
+    // 只能修改自身或子进程的进程组
+    let target = if pid == 0 || pid == current.getpid() {
+        current.clone()
+    } else {
+        let child = current
+            .acquire_inner_lock()
+            .children
+            .iter()
+            .find(|child| child.getpid() == pid)
+            .cloned();
+        match child {
+            Some(child) => child,
+            None => return -ESRCH,
+        }
+    };
+    let target_pid = target.getpid();
+    let pgid = if pgid == 0 { target_pid } else { pgid as usize };
+    let sid = current.acquire_inner_lock().sid();
+    let target_sid = target.acquire_inner_lock().sid();
+    // 不能修改其他会话中的子进程，会话首进程也不能离开自己的进程组
+    if target_sid != sid || target_pid == target_sid {
+        return -EPERM;
+    }
+    // 只能加入本会话中已存在的进程组，或以自身为首进程创建新的进程组
+    let pgrp = if pgid == target_pid {
+        Some(Arc::clone(&target.pid))
+    } else {
+        process_group(pgid).iter().find_map(|p| {
+            let inner = p.acquire_inner_lock();
+            (inner.sid() == sid).then(|| Arc::clone(&inner.pgrp))
+        })
+    };
+    match pgrp {
+        Some(pgrp) => {
+            target.acquire_inner_lock().pgrp = pgrp;
+            0
+        }
+        None => -EPERM,
+    }
+}
+
+pub fn sys_setpgid(pid: usize, pgid: isize) -> isize {
+    let ret = do_setpgid(pid, pgid);
+    gdb_println!(SYSCALL_ENABLE, "sys_setpgid(pid: {}, pgid: {}) = {}", pid, pgid, ret);
+    ret
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    let ret = match find_process(pid) {
+        Some(process) => process.acquire_inner_lock().pgid() as isize,
+        None => -ESRCH,
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_getpgid(pid: {}) = {}", pid, ret);
+    ret
+}
+
+pub fn sys_getsid(pid: usize) -> isize {
+    let ret = match find_process(pid) {
+        Some(process) => process.acquire_inner_lock().sid() as isize,
+        None => -ESRCH,
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_getsid(pid: {}) = {}", pid, ret);
+    ret
+}
+
+/// 创建以调用者为首进程的新会话与进程组，新会话没有控制终端
+pub fn sys_setsid() -> isize {
+    let process = current_process();
+    let pid = process.getpid();
+    // 进程组首进程不能创建新会话，否则同组的其他进程将与它分属不同会话
+    let ret = if !process_group(pid).is_empty() {
+        -EPERM
+    } else {
+        let mut inner = process.acquire_inner_lock();
+        inner.pgrp = Arc::clone(&process.pid);
+        inner.session = Arc::clone(&process.pid);
+        pid as isize
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_setsid() = {}", ret);
+    ret
+}
+
+/// 仅记录执行域标志（如 ADDR_NO_RANDOMIZE），下次 exec 时生效
+/// persona 为 0xffffffff 时只查询，返回原值
+pub fn sys_personality(persona: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    let ret = inner.personality as isize;
+    if persona as u32 != 0xffff_ffff {
+        inner.personality = persona as u32 as usize;
+    }
+    gdb_println!(SYSCALL_ENABLE, "sys_personality(persona: {:#x}) = {:#x}", persona, ret);
+    ret
+}
+
+const PRIO_PROCESS: usize = 0;
+const PRIO_PGRP: usize = 1;
+const PRIO_USER: usize = 2;
+
+/// who 为 0 表示调用者自身，否则为线程号
+fn sched_target(who: usize) -> Option<Arc<TaskControlBlock>> {
+    if who == 0 {
+        current_task()
+    } else {
+        tid2task(who)
+    }
+}
+
+/// setpriority/getpriority 作用的线程
+/// PRIO_PROCESS 与 Linux 相同按线程号查找；PRIO_PGRP 为进程组中所有进程的线程；
+/// 所有进程同属 uid 0，PRIO_USER 的 who 为 0 时为全部线程，否则没有匹配的线程
+fn prio_targets(which: usize, who: usize) -> Result<Vec<Arc<TaskControlBlock>>, isize> {
+    let processes = match which {
+        PRIO_PROCESS => return Ok(sched_target(who).into_iter().collect()),
+        PRIO_PGRP => {
+            let pgid = if who == 0 {
+                current_process().acquire_inner_lock().pgid()
+            } else {
+                who
+            };
+            process_group(pgid)
+        }
+        PRIO_USER if who == 0 => all_processes(),
+        PRIO_USER => Vec::new(),
+        _ => return Err(-EINVAL),
+    };
+    Ok(processes
+        .iter()
+        .flat_map(|process| {
+            let inner = process.acquire_inner_lock();
+            inner.tasks.iter().flatten().cloned().collect::<Vec<_>>()
+        })
+        .collect())
+}
+
+pub fn sys_setpriority(which: usize, who: usize, prio: isize) -> isize {
+    let ret = match prio_targets(which, who) {
+        Ok(tasks) if tasks.is_empty() => -ESRCH,
+        Ok(tasks) => {
+            for task in tasks {
+                task.sched.lock().set_nice(prio);
+            }
+            0
+        }
+        Err(errno) => errno,
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_setpriority(which: {}, who: {}, prio: {}) = {}", which, who, prio, ret);
+    ret
+}
+
+/// 与 Linux 的系统调用一致，返回 20 - nice，避免返回负数与错误码混淆
+/// 匹配多个线程时返回其中最高的优先级（最小的 nice）
+pub fn sys_getpriority(which: usize, who: usize) -> isize {
+    let ret = match prio_targets(which, who) {
+        Ok(tasks) => tasks
+            .iter()
+            .map(|task| task.sched.lock().nice)
+            .min()
+            .map_or(-ESRCH, |nice| 20 - nice),
+        Err(errno) => errno,
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_getpriority(which: {}, who: {}) = {}", which, who, ret);
+    ret
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SchedParam {
+    sched_priority: i32,
+}
+
+/// 实时策略的优先级为 1~99，SCHED_OTHER 的优先级只能为 0
+fn sched_prio_valid(policy: SchedPolicy, prio: i32) -> bool {
+    if policy.is_rt() {
+        (MIN_RT_PRIO as i32..=MAX_RT_PRIO as i32).contains(&prio)
+    } else {
+        prio == 0
+    }
+}
+
+fn sched_set(pid: usize, policy: Option<SchedPolicy>, param: *const SchedParam) -> isize {
+    if param.is_null() {
+        return -EINVAL;
+    }
+    let prio = translated_ref(current_user_token(), param).sched_priority;
+    let task = match sched_target(pid) {
+        Some(task) => task,
+        None => return -ESRCH,
+    };
+    let mut se = task.sched.lock();
+    let policy = policy.unwrap_or(se.policy);
+    if !sched_prio_valid(policy, prio) {
+        return -EINVAL;
+    }
+    se.policy = policy;
+    se.rt_priority = prio as usize;
     0
 }
 
-pub fn sys_getpgid() -> isize {
-    gdb_println!(SYSCALL_ENABLE, "sys_getpgid(...) = 0");
+pub fn sys_sched_setscheduler(pid: usize, policy: usize, param: *const SchedParam) -> isize {
+    let ret = match SchedPolicy::from_raw(policy) {
+        Some(policy) => sched_set(pid, Some(policy), param),
+        None => -EINVAL,
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_sched_setscheduler(pid: {}, policy: {}, param: {:?}) = {}", pid, policy, param, ret);
+    ret
+}
+
+pub fn sys_sched_getscheduler(pid: usize) -> isize {
+    let ret = match sched_target(pid) {
+        Some(task) => task.sched.lock().policy.to_raw() as isize,
+        None => -ESRCH,
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_sched_getscheduler(pid: {}) = {}", pid, ret);
+    ret
+}
+
+pub fn sys_sched_setparam(pid: usize, param: *const SchedParam) -> isize {
+    let ret = sched_set(pid, None, param);
+    gdb_println!(SYSCALL_ENABLE, "sys_sched_setparam(pid: {}, param: {:?}) = {}", pid, param, ret);
+    ret
+}
+
+pub fn sys_sched_getparam(pid: usize, param: *mut SchedParam) -> isize {
+    let ret = if param.is_null() {
+        -EINVAL
+    } else if let Some(task) = sched_target(pid) {
+        let prio = task.sched.lock().rt_priority as i32;
+        *translated_refmut(current_user_token(), param) = SchedParam { sched_priority: prio };
+        0
+    } else {
+        -ESRCH
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_sched_getparam(pid: {}, param: {:?}) = {}", pid, param, ret);
+    ret
+}
+
+pub fn sys_sched_get_priority_max(policy: usize) -> isize {
+    match SchedPolicy::from_raw(policy) {
+        Some(policy) if policy.is_rt() => MAX_RT_PRIO as isize,
+        Some(_) => 0,
+        None => -EINVAL,
+    }
+}
+
+pub fn sys_sched_get_priority_min(policy: usize) -> isize {
+    match SchedPolicy::from_raw(policy) {
+        Some(policy) if policy.is_rt() => MIN_RT_PRIO as isize,
+        Some(_) => 0,
+        None => -EINVAL,
+    }
+}
+
+/// CPU 掩码以一个 usize 表示，足以容纳所有 hart
+pub fn sys_sched_setaffinity(pid: usize, cpusetsize: usize, mask: *const u8) -> isize {
+    let ret = sched_setaffinity(pid, cpusetsize, mask);
+    gdb_println!(SYSCALL_ENABLE, "sys_sched_setaffinity(pid: {}, cpusetsize: {}, mask: {:?}) = {}", pid, cpusetsize, mask, ret);
+    ret
+}
+
+fn sched_setaffinity(pid: usize, cpusetsize: usize, mask: *const u8) -> isize {
+    if mask.is_null() {
+        return -EINVAL;
+    }
+    let mut bytes = [0u8; size_of::<usize>()];
+    let len = cpusetsize.min(bytes.len());
+    UserBuffer::new(translated_byte_buffer(current_user_token(), mask, len))
+        .copy_from_user(&mut bytes[..len]);
+    let cpus = usize::from_ne_bytes(bytes) & ALL_CPUS_MASK;
+    if cpus & online_harts() == 0 {
+        return -EINVAL;
+    }
+    let task = match sched_target(pid) {
+        Some(task) => task,
+        None => return -ESRCH,
+    };
+    task.sched.lock().cpus_allowed = cpus;
+    // 当前 hart 不再允许运行调用者时，立即让出CPU以迁移到其他 hart
+    if Arc::ptr_eq(&task, &current_task().unwrap()) && cpus & (1 << get_hartid()) == 0 {
+        drop(task);
+        suspend_current_and_run_next();
+    }
     0
 }
 
-#[repr(packed)]
+/// 成功时返回写入的字节数
+pub fn sys_sched_getaffinity(pid: usize, cpusetsize: usize, mask: *mut u8) -> isize {
+    let ret = if mask.is_null() || cpusetsize < size_of::<usize>() {
+        -EINVAL
+    } else if let Some(task) = sched_target(pid) {
+        let cpus = task.sched.lock().cpus_allowed & online_harts();
+        UserBuffer::new(translated_byte_buffer(current_user_token(), mask as *const u8, size_of::<usize>()))
+            .copy_to_user(&cpus.to_ne_bytes());
+        size_of::<usize>() as isize
+    } else {
+        -ESRCH
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_sched_getaffinity(pid: {}, cpusetsize: {}, mask: {:?}) = {}", pid, cpusetsize, mask, ret);
+    ret
+}
+
+pub fn sys_getcpu(cpu: *mut u32, node: *mut u32) -> isize {
+    let token = current_user_token();
+    let hartid = get_hartid();
+    if !cpu.is_null() {
+        *translated_refmut(token, cpu) = hartid as u32;
+    }
+    if !node.is_null() {
+        *translated_refmut(token, node) = 0;
+    }
+    gdb_println!(SYSCALL_ENABLE, "sys_getcpu(cpu: {:?}, node: {:?}) = 0, hart {}", cpu, node, hartid);
+    0
+}
+
+#[repr(C)]
 #[allow(unused)]
 pub struct Sysinfo {
     uptime: isize,
@@ -567,7 +1307,14 @@ impl Sysinfo {
 pub fn sys_sysinfo(buf: *mut u8) -> isize {
     let token = current_user_token();
     let buf_vec = translated_byte_buffer(token, buf, size_of::<Sysinfo>());
-    let sysinfo = Sysinfo::new();
+    let mut sysinfo = Sysinfo::new();
+    let stat = frame_allocator_stat();
+    sysinfo.uptime = (get_time_us() / USEC_PER_SEC) as isize;
+    sysinfo.totalram = stat.total_frames * PAGE_SIZE;
+    sysinfo.freeram = stat.free_frames * PAGE_SIZE;
+    sysinfo.sharedram = stat.shared_frames * PAGE_SIZE;
+    sysinfo.procs = TID2TCB.read().len() as u16;
+    sysinfo.mem_unit = 1;
 
     let mut userbuf = UserBuffer::new(buf_vec);
     userbuf.copy_to_user(sysinfo.as_bytes());
@@ -575,6 +1322,87 @@ pub fn sys_sysinfo(buf: *mut u8) -> isize {
     return 0;
 }
 
+#[repr(C)]
+#[derive(Default)]
+pub struct RUsage {
+    ru_utime: TimeSpec,
+    ru_stime: TimeSpec,
+    ru_maxrss: usize, // KiB
+    ru_ixrss: usize,
+    ru_idrss: usize,
+    ru_isrss: usize,
+    ru_minflt: usize,
+    ru_majflt: usize,
+    ru_nswap: usize,
+    ru_inblock: usize,
+    ru_oublock: usize,
+    ru_msgsnd: usize,
+    ru_msgrcv: usize,
+    ru_nsignals: usize,
+    ru_nvcsw: usize,
+    ru_nivcsw: usize,
+}
+
+impl RUsage {
+    /// 目前只统计 CPU 时间与内存相关字段，其余保持为0
+    /// ru_ixrss/ru_idrss 为当前共享与非共享常驻内存（KiB），不是随时间的积分
+    pub fn new(stat: &MemoryStat, times: &CpuTimes) -> Self {
+        Self {
+            ru_utime: TimeSpec::from_ns(times.utime),
+            ru_stime: TimeSpec::from_ns(times.stime),
+            ru_maxrss: stat.max_resident_pages * PAGE_SIZE / 1024,
+            ru_ixrss: stat.shared_pages * PAGE_SIZE / 1024,
+            ru_idrss: stat.resident_pages.saturating_sub(stat.shared_pages) * PAGE_SIZE / 1024,
+            ru_minflt: stat.minor_faults,
+            ru_majflt: stat.major_faults,
+            ..Default::default()
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { from_raw_parts(self as *const _ as usize as *const u8, size_of::<RUsage>()) }
+    }
+
+    pub fn copy_to_user(&self, token: usize, buf: *mut u8) {
+        let buf_vec = translated_byte_buffer(token, buf, size_of::<RUsage>());
+        UserBuffer::new(buf_vec).copy_to_user(self.as_bytes());
+    }
+}
+
+const RUSAGE_SELF: isize = 0;
+const RUSAGE_CHILDREN: isize = -1;
+const RUSAGE_THREAD: isize = 1;
+
+pub fn sys_getrusage(who: isize, usage: *mut u8) -> isize {
+    let process = current_process();
+    let inner = process.acquire_inner_lock();
+    let (stat, times) = match who {
+        RUSAGE_SELF => (inner.memory_stat(), inner.cpu_times()),
+        RUSAGE_THREAD => (
+            inner.memory_stat(),
+            current_task().unwrap().sched.lock().cpu_times(),
+        ),
+        RUSAGE_CHILDREN => (inner.children_mem_stat, inner.children_times),
+        _ => {
+            gdb_println!(SYSCALL_ENABLE, "sys_getrusage(who: {}, usage: {:#x?}) = {}", who, usage, -EINVAL);
+            return -EINVAL;
+        }
+    };
+    let token = inner.get_user_token();
+    drop(inner);
+    RUsage::new(&stat, &times).copy_to_user(token, usage);
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_getrusage(who: {}, usage: {:#x?}) = 0, maxrss = {}KiB, minflt = {}, majflt = {}",
+        who,
+        usage,
+        stat.max_resident_pages * PAGE_SIZE / 1024,
+        stat.minor_faults,
+        stat.major_faults
+    );
+    0
+}
+
 // const SYSLOG_ACTION_CLOSE: isize = 0;
 // const SYSLOG_ACTION_OPEN: isize = 1;
 const SYSLOG_ACTION_READ: isize = 2;
@@ -635,20 +1463,21 @@ pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> isize {
         return -EINVAL;
     }
     let process = current_process();
-    let mut inner = process.acquire_inner_lock();
+    let inner = process.acquire_inner_lock();
+    let mut mm = inner.mm.lock();
     let start_vpn = addr / PAGE_SIZE;
     let flags = PTEFlags::from_bits((prot as u8) << 1).unwrap();
 
     for i in 0..len / PAGE_SIZE {
         let vpn = VirtPageNum::from(start_vpn + i);
         // 尝试直接改变pte_flags
-        if (&mut inner.memory_set).set_pte_flags(vpn, flags) == 0 {
+        if (&mut mm.memory_set).set_pte_flags(vpn, flags) == 0 {
             continue;
         }
         // failed
         let vaddr: usize = VirtAddr::from(vpn).into();
-        if inner.check_lazy(vaddr,true) == 0 {
-            if (&mut inner.memory_set).set_pte_flags(vpn, flags) == 0 {
+        if mm.check_lazy(vaddr,true) == 0 {
+            if (&mut mm.memory_set).set_pte_flags(vpn, flags) == 0 {
                 continue;
             }
         }
@@ -669,118 +1498,152 @@ pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> isize {
     0
 }
 
-// SYSCALL_PRLIMIT
-#[derive(Clone,Copy,Debug)]
-pub struct RLimit64 {
-	pub rlim_cur: usize ,
-	pub rlim_max: usize ,
-}
-// The resource argument :
-// const RLIMIT_CPU : usize = 0;
-// const RLIMIT_FSIZE : usize = 1;
-// const RLIMIT_DATA : usize = 2;
-// const RLIMIT_STACK : usize = 3;
-// const RLIMIT_CORE : usize = 4;
-// const RLIMIT_RSS : usize = 5;
-// const RLIMIT_NPROC : usize = 6;
-const RLIMIT_NOFILE : usize = 7;
-// const RLIMIT_MEMLOCK : usize = 8;
-// const RLIMIT_AS : usize = 9;
-// const RLIMIT_LOCKS : usize = 10;
-// const RLIMIT_SIGPENDING : usize = 11;
-// const RLIMIT_MSGQUEUE : usize = 12;
-// const RLIMIT_NICE : usize = 13;
-// const RLIMIT_RTPRIO : usize = 14;
-// const RLIMIT_RTTIME : usize = 15;
-// const RLIM_NLIMITS : usize = 16;
-/// 仅实现不完整的RLIMIT_NOFILE
-pub fn sys_prlimit(pid:usize, resource:usize, rlimit:*const RLimit64, old_rlimit: *mut RLimit64) -> isize {
-    let token = current_user_token();
-    let process = current_process();
+/// 读取并修改进程的资源限制，软限制不能超过硬限制
+/// 只有 root 用户，提高硬限制不受限制；RLIMIT_STACK 超过 USER_STACK_SIZE 的部分不起作用
+fn do_prlimit(
+    process: &Arc<ProcessControlBlock>,
+    resource: usize,
+    new_limit: Option<RLimit64>,
+) -> Result<RLimit64, isize> {
+    if resource >= RLIM_NLIMITS {
+        return Err(-EINVAL);
+    }
     let mut inner = process.acquire_inner_lock();
-    let ret = match resource{
-        RLIMIT_NOFILE => {
-            // 仅仅记录值到inner.fd_max
-            if rlimit as usize != 0 {
-                let _rlimit = translated_ref(token, rlimit);
-                inner.fd_max =  _rlimit.rlim_max - 1;
-            }
-            if old_rlimit as usize != 0 && inner.fd_max != FDMAX {
-                let _old_rlimit = translated_refmut(token, old_rlimit);
-                _old_rlimit.rlim_cur = inner.fd_max + 1;
-                _old_rlimit.rlim_max = inner.fd_max + 1;
-            }
-            0
+    let old_limit = inner.rlimits[resource];
+    if let Some(new_limit) = new_limit {
+        if new_limit.rlim_cur > new_limit.rlim_max {
+            return Err(-EINVAL);
         }
-        _ => {
-            gdb_println!(
-                SYSCALL_ENABLE,
-                "sys_prlimit() unsupport resource:{}",
-                resource,
-            );
+        inner.rlimits[resource] = new_limit;
+    }
+    Ok(old_limit)
+}
+
+/// pid 为 0 时读取或修改当前进程的资源限制，rlimit 与 old_rlimit 均可为空
+pub fn sys_prlimit(pid: usize, resource: usize, rlimit: *const RLimit64, old_rlimit: *mut RLimit64) -> isize {
+    let token = current_user_token();
+    let target = if pid == 0 {
+        Some(current_process())
+    } else {
+        pid2process(pid)
+    };
+    let new_limit = (!rlimit.is_null()).then(|| *translated_ref(token, rlimit));
+    let ret = match target.map(|process| do_prlimit(&process, resource, new_limit)) {
+        Some(Ok(old_limit)) => {
+            if !old_rlimit.is_null() {
+                *translated_refmut(token, old_rlimit) = old_limit;
+            }
             0
         }
+        Some(Err(errno)) => errno,
+        None => -ESRCH,
     };
     gdb_println!(
         SYSCALL_ENABLE,
-        "sys_prlimit(pid: {:x?}, resource: {:x?}, rlimit: {:#?}, old_rlimit: {:#?} ) = {}",
+        "sys_prlimit(pid: {}, resource: {}, rlimit: {:?}, old_rlimit: {:#x?}) = {}",
         pid,
         resource,
-        rlimit,
+        new_limit,
         old_rlimit,
         ret
     );
     ret
 }
 
-pub fn sys_getitimer(which: isize, curr_value: *mut ITimerSpec) -> isize{
-    let token = current_user_token();
-    if curr_value as usize != 0{
-        let mut itimer = current_task().unwrap().acquire_inner_lock().itimer;
-        let u_itimer = translated_refmut(token, curr_value);
-        if !itimer.is_zero(){
-            itimer.it_value = itimer.it_value - crate::timer::get_timespec();
+pub fn sys_getrlimit(resource: usize, rlimit: *mut RLimit64) -> isize {
+    let ret = match do_prlimit(&current_process(), resource, None) {
+        Ok(limit) => {
+            *translated_refmut(current_user_token(), rlimit) = limit;
+            0
         }
-        // userbuf.write(itimer.as_bytes());
-        u_itimer.it_value = itimer.it_value;
-        u_itimer.it_interval = itimer.it_interval;
+        Err(errno) => errno,
+    };
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_getrlimit(resource: {}, rlimit: {:#x?}) = {}",
+        resource,
+        rlimit,
+        ret
+    );
+    ret
+}
 
-        gdb_println!(SYSCALL_ENABLE, "sys_getitimer(which: {}, curr_value: {:?}) = {},", which, u_itimer, 0);
+pub fn sys_setrlimit(resource: usize, rlimit: *const RLimit64) -> isize {
+    let new_limit = *translated_ref(current_user_token(), rlimit);
+    let ret = match do_prlimit(&current_process(), resource, Some(new_limit)) {
+        Ok(_) => 0,
+        Err(errno) => errno,
+    };
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_setrlimit(resource: {}, rlimit: {:?}) = {}",
+        resource,
+        new_limit,
+        ret
+    );
+    ret
+}
 
-        0
-    }
-    else{
-        gdb_println!(SYSCALL_ENABLE, "sys_getitimer(which: {}, curr_value: {}) = {},", which, 0, 0);
-        -1
+/// 任务中保存的 it_value 为到期的绝对时刻，返回给用户时换算为剩余时间
+fn itimer_remaining(itimer: ITimerSpec) -> ITimerSpec {
+    let mut itimer = itimer;
+    if !itimer.it_value.is_zero() {
+        itimer.it_value = itimer.it_value - crate::timer::get_timespec();
     }
+    itimer
+}
+
+pub fn sys_getitimer(which: isize, curr_value: *mut ITimerSpec) -> isize {
+    let ret = if curr_value.is_null() {
+        -EINVAL
+    } else {
+        let inner = current_task().unwrap().acquire_inner_lock();
+        let itimer = match which {
+            ITIMER_REAL => Some(itimer_remaining(inner.itimer)),
+            ITIMER_VIRTUAL | ITIMER_PROF => Some(inner.itimer_cpu[(which - 1) as usize]),
+            _ => None,
+        };
+        drop(inner);
+        match itimer {
+            Some(itimer) => {
+                *translated_refmut(current_user_token(), curr_value) = itimer;
+                0
+            }
+            None => -EINVAL,
+        }
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_getitimer(which: {}, curr_value: {:?}) = {}", which, curr_value, ret);
+    ret
 }
 
-pub fn sys_setitimer(which: isize, new_value: *mut ITimerSpec, old_value: *mut ITimerSpec) -> isize{
+/// ITIMER_REAL 到期时由内核定时器发送 SIGALRM
+/// ITIMER_VIRTUAL 与 ITIMER_PROF 只记录设置的值，不会到期
+pub fn sys_setitimer(which: isize, new_value: *const ITimerSpec, old_value: *mut ITimerSpec) -> isize {
+    if !(ITIMER_REAL..=ITIMER_PROF).contains(&which) || new_value.is_null() {
+        gdb_println!(SYSCALL_ENABLE, "sys_setitimer(which: {}, ...) = {}", which, -EINVAL);
+        return -EINVAL;
+    }
     let token = current_user_token();
-    if old_value as usize != 0{
-        let mut itimer = current_task().unwrap().acquire_inner_lock().itimer;
-        // let mut buf_vec = translated_byte_buffer(token, old_value, size_of::<ITimerSpec>());
-        // 使用UserBuffer结构，以便于跨页读写
-        // let mut userbuf = UserBuffer::new(buf_vec);
-        let u_old_itimer = translated_refmut(token, old_value);
-        if !itimer.is_zero(){
-            itimer.it_value = itimer.it_value - crate::timer::get_timespec();
-        }
-        // itimer_old = itimer;
-        u_old_itimer.it_interval = itimer.it_interval;
-        u_old_itimer.it_value = itimer.it_value;
-        // userbuf.write(itimer.as_bytes());
-        gdb_println!(SYSCALL_ENABLE, "----old_itimer: {:?} ---- task old_itimer: {:?} ", u_old_itimer, itimer);
-    }
-    // let mut itimer = ITimerSpec::new();
-    let u_new_itimer = translated_refmut(token, old_value);
-    let mut itimer = current_task().unwrap().acquire_inner_lock().itimer;
-    itimer.it_interval = u_new_itimer.it_interval;
-    itimer.it_value = u_new_itimer.it_value;
-    gdb_println!(SYSCALL_ENABLE, "sys_setitimer(which: {}, new_itimer: {:?}, old_itimer: {:?}) = {}", which, u_new_itimer, old_value, 0);
-    if !itimer.it_value.is_zero(){
+    let task = current_task().unwrap();
+    let mut itimer = *translated_ref(token, new_value);
+    gdb_println!(SYSCALL_ENABLE, "sys_setitimer(which: {}, new_value: {:?}, old_value: {:?}) = 0", which, itimer, old_value);
+    if which != ITIMER_REAL {
+        let old = core::mem::replace(
+            &mut task.acquire_inner_lock().itimer_cpu[(which - 1) as usize],
+            itimer,
+        );
+        if !old_value.is_null() {
+            *translated_refmut(token, old_value) = old;
+        }
+        return 0;
+    }
+    if !old_value.is_null() {
+        let itimer = task.acquire_inner_lock().itimer;
+        *translated_refmut(token, old_value) = itimer_remaining(itimer);
+    }
+    if !itimer.it_value.is_zero() {
         itimer.it_value = itimer.it_value + crate::timer::get_timespec();
     }
-    // current_task().unwrap().acquire_inner_lock().itimer = itimer;
+    arm_itimer_real(&task, itimer);
     0
 }
\ No newline at end of file
@@ -1,35 +1,75 @@
 use crate::{
     gdb_println,
-    mm::{translated_ref, translated_refmut},
+    mm::{translated_byte_buffer, translated_ref, translated_refmut, UserBuffer},
     monitor::{QEMU, SYSCALL_ENABLE},
-    syscall::sys_sleep,
+    syscall::{pidfd_get, sys_sleep},
     task::{
-        current_process, current_task, current_user_token, is_signal_valid,
-        suspend_current_and_run_next, tid2task, SAFlags, SigAction, UContext, SIGKILL, SIG_DFL,
+        block_current_and_run_next, current_process, current_task, current_user_token,
+        prepare_to_block,
+        dequeue_signal, discard_signals, force_signal, has_unmasked_signal, is_signal_catchable,
+        is_signal_valid, restore_signal_frame, send_signal, send_signal_process, sig_bit,
+        sig_default_action, suspend_current_and_run_next, tid2task, all_processes, process_group,
+        ProcessControlBlock, INITPROC,
+        SAFlags, SigAction, SigDefault, SigInfo, SignalStack, MINSIGSTKSZ, SIGSEGV, SIG_DFL,
+        SIG_IGN, SI_KERNEL, SI_TKILL, SI_USER, SS_DISABLE, SS_ONSTACK, UNBLOCKABLE_SIGNALS,
     },
+    timer::{get_time_ns, sleep_until},
 };
+use alloc::sync::Arc;
+use core::mem::size_of;
 
-use super::errorno::{EINVAL, ESRCH};
+use super::errorno::{EAGAIN, EINTR, EINVAL, ENOMEM, EPERM, ESRCH};
+use super::read_timespec;
 
-fn do_tkill(tid: usize, signum: u32) -> isize {
-    if let Some(task) = tid2task(tid) {
-        if is_signal_valid(signum) {
-            let mut inner = task.acquire_inner_lock();
-            inner.add_signal(signum);
-            if signum == SIGKILL {
-                inner.killed = true;
+/// 查找信号的目标进程，信号 0 只检查目标是否存在
+fn signal_target(pid: usize, signum: u32) -> Result<Option<Arc<ProcessControlBlock>>, isize> {
+    let process = tid2task(pid)
+        .and_then(|task| task.process.upgrade())
+        .ok_or(-ESRCH)?;
+    if !is_signal_valid(signum) {
+        return Err(-EINVAL);
+    }
+    Ok(if signum == 0 { None } else { Some(process) })
+}
+
+/// pid 大于 0 时发送给该进程，为 0 时发送给调用者所在的进程组，
+/// 为 -1 时发送给除 initproc 与调用者之外的所有进程，小于 -1 时发送给进程组 -pid
+pub fn sys_kill(pid: isize, signum: u32) -> isize {
+    let ret = if pid > 0 {
+        match signal_target(pid as usize, signum) {
+            Ok(Some(process)) => {
+                let sender = current_process().getpid();
+                send_signal_process(&process, SigInfo::from_sender(signum, SI_USER, sender, 0))
             }
+            Ok(None) => 0,
+            Err(errno) => errno,
+        }
+    } else if !is_signal_valid(signum) {
+        -EINVAL
+    } else {
+        let process = current_process();
+        let sender = process.getpid();
+        let targets = match pid {
+            0 => process_group(process.acquire_inner_lock().pgid()),
+            -1 => all_processes()
+                .into_iter()
+                .filter(|p| p.getpid() != sender && !Arc::ptr_eq(p, &INITPROC))
+                .collect(),
+            pid => process_group(pid.unsigned_abs()),
+        };
+        if targets.is_empty() {
+            -ESRCH
+        } else if signum == 0 {
             0
         } else {
-            -EINVAL
+            let info = SigInfo::from_sender(signum, SI_USER, sender, 0);
+            // 只要有一个进程接收成功即返回 0
+            targets
+                .iter()
+                .map(|target| send_signal_process(target, info))
+                .fold(-ESRCH, |ret, err| if ret == 0 { 0 } else { err })
         }
-    } else {
-        -ESRCH
-    }
-}
-
-pub fn sys_kill(pid: usize, signum: u32) -> isize {
-    let ret = do_tkill(pid, signum);
+    };
     gdb_println!(
         SYSCALL_ENABLE,
         "sys_kill(pid: {}, signum: {}) = {}",
@@ -41,7 +81,16 @@ pub fn sys_kill(pid: usize, signum: u32) -> isize {
 }
 
 pub fn sys_tkill(tid: usize, signum: u32) -> isize {
-    let ret = do_tkill(tid, signum);
+    let ret = match tid2task(tid) {
+        Some(_) if !is_signal_valid(signum) => -EINVAL,
+        // 信号 0 只检查目标是否存在
+        Some(_) if signum == 0 => 0,
+        Some(task) => {
+            let sender = current_process().getpid();
+            send_signal(task, SigInfo::from_sender(signum, SI_TKILL, sender, 0))
+        }
+        None => -ESRCH,
+    };
     gdb_println!(
         SYSCALL_ENABLE,
         "sys_tkill(tid: {}, signum: {}) = {}",
@@ -52,13 +101,94 @@ pub fn sys_tkill(tid: usize, signum: u32) -> isize {
     ret
 }
 
+/// 发送携带用户给出的 siginfo 的信号，实时信号按发送顺序排队
+pub fn sys_rt_sigqueueinfo(pid: usize, signum: u32, uinfo: *const SigInfo) -> isize {
+    let token = current_user_token();
+    let mut info = SigInfo::new(signum, 0);
+    UserBuffer::new(translated_byte_buffer(
+        token,
+        uinfo as *const u8,
+        size_of::<SigInfo>(),
+    ))
+    .copy_from_user(info.as_bytes_mut());
+    let ret = match signal_target(pid, signum) {
+        // 只能向自身发送 si_code 冒充 kill 或内核的信号
+        Ok(_) if (info.si_code >= 0 || info.si_code == SI_TKILL)
+            && pid != current_process().getpid() =>
+        {
+            -EPERM
+        }
+        Ok(Some(process)) => {
+            info.si_signo = signum as i32;
+            send_signal_process(&process, info)
+        }
+        Ok(None) => 0,
+        Err(errno) => errno,
+    };
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_rt_sigqueueinfo(pid: {}, signum: {}, uinfo: {:#x?}) = {}",
+        pid,
+        signum,
+        uinfo,
+        ret
+    );
+    ret
+}
+
+/// 向 pidfd 指向的进程发送信号，uinfo 为空时与 kill 相同；进程已退出时返回 ESRCH
+pub fn sys_pidfd_send_signal(pidfd: usize, signum: u32, uinfo: *const SigInfo, flags: u32) -> isize {
+    let sender = current_process().getpid();
+    let ret = match pidfd_get(pidfd) {
+        _ if flags != 0 || !is_signal_valid(signum) => -EINVAL,
+        Ok((Some(process), _)) if !process.acquire_inner_lock().is_zombie => {
+            let info = if uinfo.is_null() {
+                Ok(SigInfo::from_sender(signum, SI_USER, sender, 0))
+            } else {
+                let mut info = SigInfo::new(signum, 0);
+                UserBuffer::new(translated_byte_buffer(
+                    current_user_token(),
+                    uinfo as *const u8,
+                    size_of::<SigInfo>(),
+                ))
+                .copy_from_user(info.as_bytes_mut());
+                // 与 rt_sigqueueinfo 相同，只能向自身发送 si_code 冒充 kill 或内核的信号
+                if info.signum() != signum {
+                    Err(-EINVAL)
+                } else if (info.si_code >= 0 || info.si_code == SI_TKILL) && process.getpid() != sender {
+                    Err(-EPERM)
+                } else {
+                    Ok(info)
+                }
+            };
+            match info {
+                Ok(_) if signum == 0 => 0,
+                Ok(info) => send_signal_process(&process, info),
+                Err(errno) => errno,
+            }
+        }
+        Ok(_) => -ESRCH,
+        Err(errno) => errno,
+    };
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_pidfd_send_signal(pidfd: {}, signum: {}, uinfo: {:#x?}, flags: {:#x}) = {}",
+        pidfd,
+        signum,
+        uinfo,
+        flags,
+        ret
+    );
+    ret
+}
+
 pub fn sys_sigaction(signum: u32, sa_ptr: *const SigAction, oldsa_ptr: *mut SigAction) -> isize {
     let token = current_user_token();
     let process = current_process();
-    let mut inner = process.acquire_inner_lock();
+    let inner = process.acquire_inner_lock();
 
-    // signum超出范围，返回错误
-    if !is_signal_valid(signum) {
+    // signum超出范围，或试图改变 SIGKILL/SIGSTOP 的处理方式，返回错误
+    if signum == 0 || !is_signal_valid(signum) || (sa_ptr as usize != 0 && !is_signal_catchable(signum)) {
         gdb_println!(
             SYSCALL_ENABLE,
             "sys_sigaction(signum: {}, sigaction = {:#x?}, old_sigaction = {:#x?} ) = {}",
@@ -70,26 +200,24 @@ pub fn sys_sigaction(signum: u32, sa_ptr: *const SigAction, oldsa_ptr: *mut SigA
         return -EINVAL;
     }
 
+    // 将旧的sigaction保存到指定位置
+    if oldsa_ptr as usize != 0 {
+        *translated_refmut(token, oldsa_ptr) = inner.sigactions.lock()[signum as usize];
+    }
+
     // 当sigaction存在时， 在pcb中注册给定的signaction
     if sa_ptr as usize != 0 {
-        // 如果旧的sigaction存在，则将它保存到指定位置.否则置为 SIG_DFL
-        let old = inner.sigactions[signum as usize];
-        if old.is_valid() {
-            if oldsa_ptr as usize != 0 {
-                // println!("arg old_sigaction !=0  ");
-                *translated_refmut(token, oldsa_ptr) = old;
-            }
-        } else {
-            if oldsa_ptr as usize != 0 {
-                let sigact_old = translated_refmut(token, oldsa_ptr);
-                sigact_old.sa_handler = SIG_DFL;
-                sigact_old.sa_sigaction = 0;
-                sigact_old.sa_mask = 0;
-            }
+        let mut sigaction = *translated_ref(token, sa_ptr);
+        sigaction.sa_flags = SAFlags::from_bits_truncate(sigaction.sa_flags.bits());
+        sigaction.sa_mask &= !UNBLOCKABLE_SIGNALS;
+        inner.sigactions.lock()[signum as usize] = sigaction;
+        drop(inner);
+        // 改为忽略后，已处于待处理状态的该信号被丢弃
+        let ignored = sigaction.sa_handler == SIG_IGN
+            || (sigaction.sa_handler == SIG_DFL && sig_default_action(signum) == SigDefault::Ign);
+        if ignored {
+            discard_signals(&process, sig_bit(signum));
         }
-
-        //在pcb中注册给定的signaction
-        inner.sigactions[signum as usize] = *translated_ref(token, sa_ptr);
     }
 
     gdb_println!(
@@ -104,33 +232,153 @@ pub fn sys_sigaction(signum: u32, sa_ptr: *const SigAction, oldsa_ptr: *mut SigA
 }
 
 pub fn sys_sigreturn() -> isize {
-    // #[cfg(feature = "sig_delay")]
-    // for _ in 0..15 {
-    //     suspend_current_and_run_next();
-    // }
+    let task = current_task().unwrap();
+    let a0 = match restore_signal_frame(&task, &current_process()) {
+        Some(a0) => a0,
+        None => {
+            // 信号帧已被破坏，无法恢复上下文
+            force_signal(&task, SigInfo::new(SIGSEGV, SI_KERNEL));
+            0
+        }
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_sigreturn() = {:#x}", a0);
+    // 返回值写回 a0，保持被打断时的寄存器内容
+    a0 as isize
+}
+
+pub fn sys_sigaltstack(ss: *const SignalStack, old_ss: *mut SignalStack) -> isize {
     let token = current_user_token();
     let task = current_task().unwrap();
-    let mut task_inner = task.acquire_inner_lock();
+    let sp = task.acquire_inner_lock().get_trap_cx().x[2];
+    let current = task.acquire_inner_lock().sigaltstack;
+    let on_stack = current.is_enabled() && current.contains(sp);
 
-    let trap_cx = task_inner.get_trap_cx();
-    let mc_pc_ptr = trap_cx.x[2] + UContext::pc_offset();
-    drop(trap_cx);
+    if !old_ss.is_null() {
+        let mut old = current;
+        if on_stack {
+            old.ss_flags |= SS_ONSTACK;
+        }
+        *translated_refmut(token, old_ss) = old;
+    }
 
-    let (signum, flags) = task_inner.signal_context_restore();
+    let mut ret = 0;
+    if !ss.is_null() {
+        let new = *translated_ref(token, ss);
+        ret = if on_stack {
+            // 正在备用栈上执行处理函数时不能修改
+            -EPERM
+        } else if new.ss_flags & SS_DISABLE != 0 {
+            task.acquire_inner_lock().sigaltstack = SignalStack::new();
+            0
+        } else if new.ss_flags & !SS_ONSTACK != 0 {
+            -EINVAL
+        } else if new.ss_size < MINSIGSTKSZ {
+            -ENOMEM
+        } else {
+            task.acquire_inner_lock().sigaltstack = SignalStack {
+                ss_flags: 0,
+                ..new
+            };
+            0
+        };
+    }
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_sigaltstack(ss: {:#x?}, old_ss: {:#x?}) = {}",
+        ss,
+        old_ss,
+        ret
+    );
+    ret
+}
 
-    if flags.contains(SAFlags::SA_SIGINFO) {
-        let mc_pc = *translated_ref(token, mc_pc_ptr as *mut u64) as usize;
-        gdb_println!(
-            SYSCALL_ENABLE,
-            "original sepc: {:#x?}, mc_pc = {:#x?}",
-            task_inner.get_trap_cx().sepc,
-            mc_pc
-        );
-        task_inner.get_trap_cx().sepc = mc_pc; // 确保SIGCANCEL的正确性，使程序跳转到sig_exit
+/// 被屏蔽且待处理的信号
+pub fn sys_rt_sigpending(set: *mut u64, sigsetsize: usize) -> isize {
+    if sigsetsize != 8 {
+        return -EINVAL;
     }
+    let task = current_task().unwrap();
+    let process = current_process();
+    let pending = {
+        let process_inner = process.acquire_inner_lock();
+        let task_inner = task.acquire_inner_lock();
+        (task_inner.pending.signals | process_inner.shared_pending.signals) & task_inner.sigmask
+    };
+    *translated_refmut(process.acquire_inner_lock().get_user_token(), set) = pending;
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_rt_sigpending(set: {:#x?}) = 0, pending: {:#x}",
+        set,
+        pending
+    );
+    0
+}
 
-    gdb_println!(SYSCALL_ENABLE, "sys_sigreturn() = 0");
-    return 0;
+/// 同步等待 set 中的信号，返回信号编号；超时返回 -EAGAIN，被其他信号打断返回 -EINTR
+pub fn sys_rt_sigtimedwait(
+    set: *const u64,
+    uinfo: *mut SigInfo,
+    timeout: *const u64,
+    sigsetsize: usize,
+) -> isize {
+    if sigsetsize != 8 {
+        return -EINVAL;
+    }
+    let token = current_user_token();
+    // SIGKILL 与 SIGSTOP 不能被等待
+    let wanted = *translated_ref(token, set) & !UNBLOCKABLE_SIGNALS;
+    let deadline = if timeout.is_null() {
+        None
+    } else {
+        match read_timespec(token, timeout) {
+            Some(ns) => Some(get_time_ns().saturating_add(ns)),
+            None => return -EINVAL,
+        }
+    };
+
+    let task = current_task().unwrap();
+    let process = current_process();
+    // 等待期间临时解除对 set 中信号的屏蔽，使发送给进程的这些信号能唤醒本线程
+    let saved_mask = {
+        let mut task_inner = task.acquire_inner_lock();
+        let mask = task_inner.sigmask;
+        task_inner.sigmask &= !wanted;
+        mask
+    };
+    let ret = loop {
+        prepare_to_block();
+        if let Some(info) = dequeue_signal(&task, &process, !wanted) {
+            if !uinfo.is_null() {
+                UserBuffer::new(translated_byte_buffer(
+                    token,
+                    uinfo as *const u8,
+                    size_of::<SigInfo>(),
+                ))
+                .copy_to_user(info.as_bytes());
+            }
+            break info.si_signo as isize;
+        }
+        if has_unmasked_signal(&task, &process) {
+            break -EINTR;
+        }
+        match deadline {
+            Some(deadline) if get_time_ns() >= deadline => break -EAGAIN,
+            Some(deadline) => {
+                sleep_until(deadline);
+            }
+            None => block_current_and_run_next(),
+        }
+    };
+    task.acquire_inner_lock().sigmask = saved_mask;
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_rt_sigtimedwait(set: {:#x?}, uinfo: {:#x?}, timeout: {:#x?}) = {}",
+        set,
+        uinfo,
+        timeout,
+        ret
+    );
+    ret
 }
 
 const SIG_BLOCK: usize = 0;
@@ -158,7 +406,7 @@ pub fn sys_sigprocmask(how: usize, set: *const u64, old_set: *mut u64, sigsetsiz
             SIG_SETMASK => mask = new_set,
             _ => panic!("ENOSYS"),
         }
-        task_inner.sigmask = mask;
+        task_inner.sigmask = mask & !UNBLOCKABLE_SIGNALS;
     }
 
     gdb_println!(
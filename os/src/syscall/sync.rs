@@ -6,50 +6,92 @@ use spin::{Lazy, RwLock};
 use core::sync::atomic::{AtomicU32, Ordering};
 
 use crate::gdb_println;
-use crate::mm::translated_ref;
+use crate::mm::{translated_ref, translated_refmut};
 
 use crate::monitor::{QEMU, SYSCALL_ENABLE};
 // use crate::sync::{Condvar, Mutex, MutexBlocking, MutexSpin, Semaphore};
 use crate::task::{
-    block_current_and_run_next, current_task, current_user_token, suspend_current_and_run_next,
-    unblock_task, TaskControlBlock,
+    block_current_and_run_next, current_has_signal, current_task, current_user_token,
+    prepare_to_block, unblock_task, TaskControlBlock,
 };
-use crate::timer::{get_time_us, USEC_PER_SEC};
+use crate::timer::{get_time_ns, sleep_until, timespec_to_ns, NSEC_PER_SEC};
 
-use super::errorno::{EAGAIN, EPERM};
+use super::errorno::{EAGAIN, EINTR, EINVAL, EPERM, ERESTARTSYS, ETIMEDOUT};
 
-pub fn sys_sleep(req: *mut u64) -> isize {
-    let token = current_user_token();
-    let sec = *translated_ref(token, req);
-    let usec = *translated_ref(token, unsafe { req.add(1) });
-    drop(token);
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_MONOTONIC: usize = 1;
+const TIMER_ABSTIME: usize = 1;
 
-    let t = sec as usize * USEC_PER_SEC + usec as usize;
-    let start_time = get_time_us();
+/// 读取用户态的 timespec，返回纳秒数
+pub fn read_timespec(token: usize, ts: *const u64) -> Option<usize> {
+    let sec = *translated_ref(token, ts) as usize;
+    let nsec = *translated_ref(token, unsafe { ts.add(1) }) as usize;
+    if nsec >= NSEC_PER_SEC {
+        return None;
+    }
+    Some(timespec_to_ns(sec, nsec))
+}
 
-    while get_time_us() - start_time < t {
-        suspend_current_and_run_next();
+/// 阻塞至 deadline，被信号打断时返回 -EINTR，并在 rem 非空时写回剩余时间
+fn do_nanosleep(deadline: usize, rem: *mut u64) -> isize {
+    loop {
+        prepare_to_block();
+        let now = get_time_ns();
+        if now >= deadline {
+            return 0;
+        }
+        if current_has_signal() {
+            if !rem.is_null() {
+                let token = current_user_token();
+                let left = deadline - now;
+                *translated_refmut(token, rem) = (left / NSEC_PER_SEC) as u64;
+                *translated_refmut(token, unsafe { rem.add(1) }) = (left % NSEC_PER_SEC) as u64;
+            }
+            return -EINTR;
+        }
+        sleep_until(deadline);
     }
-    gdb_println!(SYSCALL_ENABLE, "sys_sleep(s: {}, us: {})", sec, usec);
-    0
+}
+
+pub fn sys_sleep(req: *const u64, rem: *mut u64) -> isize {
+    let ret = match read_timespec(current_user_token(), req) {
+        Some(ns) => do_nanosleep(get_time_ns().saturating_add(ns), rem),
+        None => -EINVAL,
+    };
+    gdb_println!(SYSCALL_ENABLE, "sys_nanosleep(req: {:?}, rem: {:?}) = {}", req, rem, ret);
+    ret
+}
+
+/// 两种时钟均以开机时间计，TIMER_ABSTIME 时 req 为绝对时刻
+pub fn sys_clock_nanosleep(clock_id: usize, flags: usize, req: *const u64, rem: *mut u64) -> isize {
+    let ret = if clock_id != CLOCK_REALTIME && clock_id != CLOCK_MONOTONIC {
+        -EINVAL
+    } else {
+        match read_timespec(current_user_token(), req) {
+            Some(ns) if flags & TIMER_ABSTIME != 0 => do_nanosleep(ns, core::ptr::null_mut()),
+            Some(ns) => do_nanosleep(get_time_ns().saturating_add(ns), rem),
+            None => -EINVAL,
+        }
+    };
+    gdb_println!(
+        SYSCALL_ENABLE,
+        "sys_clock_nanosleep(clock_id: {}, flags: {}, req: {:?}, rem: {:?}) = {}",
+        clock_id,
+        flags,
+        req,
+        rem,
+        ret
+    );
+    ret
 }
 
 pub struct FutexWaiter {
     pub task: Arc<TaskControlBlock>,
-    expire_time: usize
 }
 
 impl FutexWaiter {
-    pub fn new(task: Arc<TaskControlBlock>, current_time: usize, timeout: usize) -> Self {
-        Self {
-            task,
-            expire_time: if current_time <= usize::MAX - timeout { current_time + timeout } else {usize::MAX}
-        }
-    }
-
-    pub fn check_expire(&self) -> bool {
-        // debug!("cur = {}, exp = {}", get_time_us(), self.expire_time);
-        get_time_us() >= self.expire_time
+    pub fn new(task: Arc<TaskControlBlock>) -> Self {
+        Self { task }
     }
 }
 
@@ -125,14 +167,14 @@ pub fn sys_futex(
     }
     let ret = match cmd {
         FUTEX_WAIT => {
-            let t = if timeout as usize != 0 {
-                let sec = *translated_ref(token, timeout);
-                let usec = *translated_ref(token, unsafe { timeout.add(1) });
-                sec as usize * USEC_PER_SEC + usec as usize
+            if timeout.is_null() {
+                futex_wait(uaddr as usize, val, None)
             } else {
-                usize::MAX // inf
-            };
-            futex_wait(uaddr as usize, val, t)
+                match read_timespec(token, timeout) {
+                    Some(ns) => futex_wait(uaddr as usize, val, Some(get_time_ns().saturating_add(ns))),
+                    None => -EINVAL,
+                }
+            }
         }
         FUTEX_WAKE => futex_wake(uaddr as usize, val),
         FUTEX_REQUEUE => futex_requeue(uaddr as usize, val, uaddr2 as usize, timeout as u32),
@@ -152,7 +194,8 @@ pub fn sys_futex(
     return ret;
 }
 
-pub fn futex_wait(uaddr: usize, val: u32, timeout: usize) -> isize {
+/// deadline 为绝对时刻 (ns)，None 表示无限等待
+pub fn futex_wait(uaddr: usize, val: u32, deadline: Option<usize>) -> isize {
     // futex_wait_setup
     let mut fq_writer = FUTEX_QUEUE.write();
     let flag = fq_writer.contains_key(&uaddr);
@@ -164,6 +207,8 @@ pub fn futex_wait(uaddr: usize, val: u32, timeout: usize) -> isize {
     };
     fq.waiters_inc();
     let mut fq_lock = fq.chain.write();
+    // 持有队列锁时开始等待，futex_wake 需获取同一把锁，不会早于此处
+    prepare_to_block();
     let token = current_user_token();
     let uval = translated_ref(token, uaddr as *const AtomicU32);
     // debug!(
@@ -183,35 +228,56 @@ pub fn futex_wait(uaddr: usize, val: u32, timeout: usize) -> isize {
 
     // futex_wait_queue_me
     let task = current_task().unwrap();
-    fq_lock.push_back(FutexWaiter::new(task.clone(), get_time_us(), timeout));
+    fq_lock.push_back(FutexWaiter::new(task.clone()));
     drop(fq_lock);
     drop(fq_writer);
 
-    // warning: Auto waking-up has not been implemented yet
-    block_current_and_run_next();
-    // let start_time = get_time_us();
-    // while get_time_us() - start_time < timeout {
-    //     suspend_current_and_run_next();
-    // }
+    let timed_out = match deadline {
+        Some(deadline) => sleep_until(deadline),
+        None => {
+            block_current_and_run_next();
+            false
+        }
+    };
 
-    // // unqueue_me
-    // let mut fq_writer = FUTEX_QUEUE.write();
-    // if let Some(fq) = fq_writer.get(&uaddr) {
-    //     let len = fq.chain.read().len();
-    //     let mut fq_lock = fq.chain.write();
-    //     for i in 0..len {
-    //         if Arc::ptr_eq(&fq_lock[i], &q) {
-    //             fq_lock.remove(i);
-    //             break;
-    //         }
-    //     }
-    //     drop(fq_lock);
-    //     fq.waiters_dec();
-    //     if fq.waiters() == 0 {
-    //         fq_writer.remove(&uaddr);
-    //     }
-    // }
-    return 0;
+    // unqueue_me：若仍在等待队列中，说明不是被 futex_wake 唤醒的
+    if !unqueue_waiter(&task) {
+        return 0;
+    }
+    if timed_out {
+        -ETIMEDOUT
+    } else if deadline.is_some() {
+        -EINTR
+    } else {
+        // 无超时的等待被信号打断后可以重新开始
+        -ERESTARTSYS
+    }
+}
+
+/// 将任务从 futex 等待队列中移除，返回其是否仍在队列中
+/// 任务可能已被 FUTEX_REQUEUE 移到其他地址的队列，因此需查找所有队列
+/// 被终止而不再运行的线程也由此移出队列，以免占用唤醒名额并持有其任务控制块
+pub fn unqueue_waiter(task: &Arc<TaskControlBlock>) -> bool {
+    let mut fq_writer = FUTEX_QUEUE.write();
+    let mut found = None;
+    for (addr, fq) in fq_writer.iter() {
+        let mut fq_lock = fq.chain.write();
+        if let Some(idx) = fq_lock.iter().position(|w| Arc::ptr_eq(&w.task, task)) {
+            fq_lock.remove(idx);
+            drop(fq_lock);
+            fq.waiters_dec();
+            found = Some((*addr, fq.waiters() == 0));
+            break;
+        }
+    }
+    match found {
+        Some((addr, true)) => {
+            fq_writer.remove(&addr);
+            true
+        }
+        Some(_) => true,
+        None => false,
+    }
 }
 
 pub fn futex_wake(uaddr: usize, nr_wake: u32) -> isize {
@@ -9,6 +9,12 @@ pub struct AuxHeader {
     pub value: usize,
 }
 
+impl AuxHeader {
+    pub fn new(aux_type: usize, value: usize) -> Self {
+        Self { aux_type, value }
+    }
+}
+
 impl Debug for AuxHeader {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!(
@@ -18,6 +24,22 @@ impl Debug for AuxHeader {
     }
 }
 
+/// RISC-V 的 AT_HWCAP 中第 n 位表示第 n 个字母的单字母扩展
+const fn isa_hwcap(isa: &[u8]) -> usize {
+    let mut hwcap = 0;
+    let mut i = 0;
+    while i < isa.len() {
+        hwcap |= 1 << (isa[i] - b'a');
+        i += 1;
+    }
+    hwcap
+}
+
+/// 内核以 rv64gc 为目标，所支持的平台均实现了 IMAFDC 扩展
+pub const HWCAP_ISA: usize = isa_hwcap(b"imafdc");
+/// times(2) 等接口所用的时钟频率，与 Linux 的 USER_HZ 相同
+pub const USER_HZ: usize = 100;
+
 // Execution of programs
 pub const AT_NULL: usize = 0; /* end of vector */
 pub const AT_IGNORE: usize = 1; /* entry should be ignored */
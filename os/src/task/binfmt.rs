@@ -0,0 +1,128 @@
+//! 可执行文件格式
+//! execve 按文件开头的魔数在注册表中查找处理函数：ELF 由 `ProcessControlBlock::exec` 装入，
+//! `#!` 脚本改为执行其解释器，解释器本身也可以是脚本，最多嵌套 `MAX_INTERP_DEPTH` 层
+
+use super::{ProcessControlBlock, TaskControlBlock};
+use crate::fs::{open_common_file, OpenFlags};
+use crate::syscall::{ELOOP, ENOENT, ENOEXEC};
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::{Lazy, RwLock};
+
+/// 解释器最多嵌套的层数，与 Linux 相同
+pub const MAX_INTERP_DEPTH: usize = 4;
+/// `#!` 行的最大长度，超出部分被截断
+const SHEBANG_MAX: usize = 256;
+
+/// execve 的参数，脚本处理函数会把它改写为对解释器的调用
+pub struct ExecParams {
+    pub path: String,
+    pub args: Vec<String>,
+    pub envs: Vec<String>,
+}
+
+pub enum LoadResult {
+    /// 新程序已装入当前进程
+    Loaded(Arc<TaskControlBlock>),
+    /// 改为执行 `ExecParams` 中的解释器
+    Interpreter,
+}
+
+pub type LoadFn = fn(&Arc<ProcessControlBlock>, &[u8], &mut ExecParams) -> Result<LoadResult, isize>;
+
+pub struct BinaryFormat {
+    pub name: &'static str,
+    /// 文件开头需要匹配的字节
+    pub magic: &'static [u8],
+    pub load: LoadFn,
+}
+
+static BINARY_FORMATS: Lazy<RwLock<Vec<BinaryFormat>>> = Lazy::new(|| {
+    RwLock::new(vec![
+        BinaryFormat {
+            name: "elf",
+            magic: b"\x7fELF",
+            load: load_elf,
+        },
+        BinaryFormat {
+            name: "script",
+            magic: b"#!",
+            load: load_script,
+        },
+    ])
+});
+
+/// 注册新的可执行文件格式，先注册的格式优先匹配
+pub fn register_binfmt(format: BinaryFormat) {
+    BINARY_FORMATS.write().push(format);
+}
+
+fn load_elf(
+    process: &Arc<ProcessControlBlock>,
+    data: &[u8],
+    params: &mut ExecParams,
+) -> Result<LoadResult, isize> {
+    if xmas_elf::ElfFile::new(data).is_err() {
+        return Err(-ENOEXEC);
+    }
+    process
+        .exec(data, &params.args, &params.envs)
+        .map(LoadResult::Loaded)
+        .ok_or(-ENOEXEC)
+}
+
+/// 解析 `#!interpreter [arg]`，解释器之后的内容去掉首尾空白后作为一个参数
+fn load_script(
+    _process: &Arc<ProcessControlBlock>,
+    data: &[u8],
+    params: &mut ExecParams,
+) -> Result<LoadResult, isize> {
+    let line = &data[2..data.len().min(SHEBANG_MAX)];
+    let line = match line.iter().position(|&c| c == b'\n') {
+        Some(end) => &line[..end],
+        None => line,
+    };
+    let line = core::str::from_utf8(line).map_err(|_| -ENOEXEC)?.trim();
+    let (interp, arg) = match line.split_once(|c: char| c == ' ' || c == '\t') {
+        Some((interp, arg)) => (interp, Some(arg.trim())),
+        None => (line, None),
+    };
+    if interp.is_empty() {
+        return Err(-ENOEXEC);
+    }
+    // argv[0] 替换为解释器、可选参数与脚本路径
+    let mut args = Vec::with_capacity(params.args.len() + 2);
+    args.push(interp.to_string());
+    args.extend(arg.filter(|arg| !arg.is_empty()).map(String::from));
+    args.push(params.path.clone());
+    args.extend(params.args.drain(..).skip(1));
+    params.args = args;
+    params.path = interp.to_string();
+    Ok(LoadResult::Interpreter)
+}
+
+/// 以 params 替换 process 的程序映像，返回新的主线程或负的错误码
+pub fn do_execve(
+    process: &Arc<ProcessControlBlock>,
+    cwd: &str,
+    mut params: ExecParams,
+) -> Result<Arc<TaskControlBlock>, isize> {
+    for _ in 0..=MAX_INTERP_DEPTH {
+        let file = open_common_file(cwd, params.path.as_str(), OpenFlags::RDONLY).ok_or(-ENOENT)?;
+        let data = unsafe { file.read_as_elf() };
+        // 持有注册表读锁期间只复制处理函数，装入程序时不持有锁
+        let load = BINARY_FORMATS
+            .read()
+            .iter()
+            .find(|format| data.starts_with(format.magic))
+            .map(|format| format.load)
+            .ok_or(-ENOEXEC)?;
+        match load(process, data, &mut params)? {
+            LoadResult::Loaded(task) => return Ok(task),
+            LoadResult::Interpreter => continue,
+        }
+    }
+    Err(-ELOOP)
+}
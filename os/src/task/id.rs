@@ -1,5 +1,8 @@
-use super::ProcessControlBlock;
-use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT_BASE, USER_STACK_SIZE};
+use super::{ProcessControlBlock, RLIMIT_STACK};
+use crate::config::{
+    aligned_up, ARG_MAX, KERNEL_STACK_SIZE, PAGE_SIZE, PID_MAX, TRAMPOLINE, TRAP_CONTEXT_BASE,
+    USER_STACK_SIZE,
+};
 use crate::mm::{MapPermission, PhysPageNum, VirtAddr, KERNEL_SPACE, MmapArea, MapAreaType};
 
 use crate::gdb_println;
@@ -13,50 +16,61 @@ use spin::{Lazy, RwLock};
 pub struct RecycleAllocator {
     current: usize,
     recycled: Vec<usize>,
+    /// 分配的 id 小于 max
+    max: usize,
 }
 
 impl RecycleAllocator {
-    pub fn new() -> Self {
+    pub fn new(max: usize) -> Self {
         RecycleAllocator {
             current: 0,
             recycled: Vec::with_capacity(0x1000),
+            max,
         }
     }
-    pub fn alloc(&mut self) -> usize {
+    /// id 耗尽时返回 None
+    pub fn alloc(&mut self) -> Option<usize> {
         if let Some(id) = self.recycled.pop() {
-            id
-        } else {
+            Some(id)
+        } else if self.current < self.max {
             self.current += 1;
-            self.current - 1
+            Some(self.current - 1)
+        } else {
+            None
         }
     }
-    // pub fn dealloc(&mut self, id: usize) {
-    //     assert!(id < self.current);
-    //     assert!(
-    //         !self.recycled.iter().any(|i| *i == id),
-    //         "id {} has been deallocated!",
-    //         id
-    //     );
-    //     self.recycled.push(id);
-    // }
+    pub fn dealloc(&mut self, id: usize) {
+        assert!(id < self.current);
+        debug_assert!(
+            !self.recycled.iter().any(|i| *i == id),
+            "id {} has been deallocated!",
+            id
+        );
+        self.recycled.push(id);
+    }
 }
 
+/// pid 与 tid 从同一空间中分配，均小于 PID_MAX
 static TID_ALLOCATOR: Lazy<RwLock<RecycleAllocator>> =
-    Lazy::new(|| RwLock::new(RecycleAllocator::new()));
+    Lazy::new(|| RwLock::new(RecycleAllocator::new(PID_MAX)));
 static KSTACK_ALLOCATOR: Lazy<RwLock<RecycleAllocator>> =
-    Lazy::new(|| RwLock::new(RecycleAllocator::new()));
+    Lazy::new(|| RwLock::new(RecycleAllocator::new(usize::MAX)));
 
+/// 释放时 tid 被回收。进程的 pid 即主线程的 tid，由进程与主线程共同持有，
+/// 作为进程组号或会话号时还由组中各进程（及以其为前台进程组或会话的终端）持有，
+/// 进程被回收（已从 PID2PCB 中移除）、主线程退出且不再被用作进程组号或会话号后才能分配给新的任务
 pub struct TidHandle(pub usize);
 
-pub fn tid_alloc() -> TidHandle {
-    TidHandle(TID_ALLOCATOR.write().alloc())
+/// tid 耗尽时返回 None
+pub fn tid_alloc() -> Option<TidHandle> {
+    TID_ALLOCATOR.write().alloc().map(TidHandle)
 }
 
-// impl Drop for TidHandle {
-//     fn drop(&mut self) {
-//         TID_ALLOCATOR.write().dealloc(self.0);
-//     }
-// }
+impl Drop for TidHandle {
+    fn drop(&mut self) {
+        TID_ALLOCATOR.write().dealloc(self.0);
+    }
+}
 
 /// Return (bottom, top) of a kernel stack in kernel space.
 pub fn kernel_stack_position(kstack_id: usize) -> (usize, usize) {
@@ -68,7 +82,7 @@ pub fn kernel_stack_position(kstack_id: usize) -> (usize, usize) {
 pub struct KernelStack(pub usize);
 
 pub fn kstack_alloc() -> KernelStack {
-    let kstack_id = KSTACK_ALLOCATOR.write().alloc();
+    let kstack_id = KSTACK_ALLOCATOR.write().alloc().unwrap();
     let (kstack_bottom, kstack_top) = kernel_stack_position(kstack_id);
     gdb_println!(
         MAPPING_ENABLE,
@@ -93,6 +107,7 @@ impl Drop for KernelStack {
         KERNEL_SPACE
             .write()
             .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
+        KSTACK_ALLOCATOR.write().dealloc(self.0);
     }
 }
 
@@ -104,108 +119,158 @@ impl KernelStack {
 }
 
 pub struct TaskUserRes {
-    pub tid: TidHandle,
-    pub rel_tid: usize, // 相对主线程的tid值（主线程为0，其余的为1, 2, 3, ...)
+    /// 主线程的 tid 同时由进程作为 pid 持有
+    pub tid: Arc<TidHandle>,
+    pub rel_tid: usize, // 在进程 tasks 中的下标（主线程为0，其余的为1, 2, 3, ...)
+    /// trap_cx 与用户栈在地址空间中的槽位，共享地址空间的所有线程各占一个
+    pub slot: usize,
     pub ustack_base: usize,
     pub process: Weak<ProcessControlBlock>,
 }
 
-fn trap_cx_bottom_from_tid(rel_tid: usize) -> usize {
-    // debug!("trap_cx_bottom_from_tid: rel_tid = {}", rel_tid);
-    TRAP_CONTEXT_BASE - rel_tid * PAGE_SIZE
+pub fn trap_cx_bottom_from_slot(slot: usize) -> usize {
+    // debug!("trap_cx_bottom_from_slot: slot = {}", slot);
+    TRAP_CONTEXT_BASE - slot * PAGE_SIZE
+}
+
+/// 槽位在用户栈之上为 execve 的参数预留的空间，多出的一页存放 auxv 等
+const USTACK_ARG_SIZE: usize = ARG_MAX + PAGE_SIZE;
+
+pub fn ustack_bottom_from_slot(ustack_base: usize, slot: usize) -> usize {
+    ustack_base + slot * (PAGE_SIZE + USER_STACK_SIZE + USTACK_ARG_SIZE)
+}
+
+pub fn ustack_top_from_slot(ustack_base: usize, slot: usize) -> usize {
+    ustack_bottom_from_slot(ustack_base, slot) + USER_STACK_SIZE + USTACK_ARG_SIZE
+}
+
+/// 槽位中自栈顶向下映射的用户栈大小，受 RLIMIT_STACK 限制，至少一页
+fn user_stack_size(stack_limit: usize) -> usize {
+    (stack_limit & !(PAGE_SIZE - 1)).clamp(PAGE_SIZE, USER_STACK_SIZE)
 }
 
-fn ustack_bottom_from_tid(ustack_base: usize, rel_tid: usize) -> usize {
-    ustack_base + rel_tid * (PAGE_SIZE + USER_STACK_SIZE)
+/// 在 process 的地址空间中找到第一个空闲的槽位，分配 trap_cx 与用户栈，返回槽位
+/// arg_size 为 execve 压入栈顶的参数大小，映射在用户栈之上，其他线程为 0
+/// 需要获取进程锁，调用者不能持有任务锁
+pub fn alloc_user_slot(
+    process: &ProcessControlBlock,
+    ustack_base: usize,
+    tid: usize,
+    arg_size: usize,
+) -> usize {
+    let process_inner = process.acquire_inner_lock();
+    let mut mm = process_inner.mm.lock();
+    let slot = (0..)
+        .find(|&slot| {
+            !mm.memory_set
+                .contains_vpn(VirtAddr::from(trap_cx_bottom_from_slot(slot)).floor())
+        })
+        .unwrap();
+    // alloc user stack
+    let ustack_top = ustack_top_from_slot(ustack_base, slot);
+    let ustack_bottom = ustack_top
+        - aligned_up(arg_size.min(USTACK_ARG_SIZE))
+        - user_stack_size(process_inner.rlimit(RLIMIT_STACK));
+    gdb_println!(
+        MAPPING_ENABLE,
+        "[user-stack-map] tid:{} va[0x{:X} - 0x{:X}]",
+        tid,
+        ustack_bottom,
+        ustack_top
+    );
+    mm.memory_set.insert_framed_area(
+        MapAreaType::UserStack,
+        ustack_bottom.into(),
+        ustack_top.into(),
+        MapPermission::R | MapPermission::W | MapPermission::U,
+    );
+    // alloc trap_cx
+    let trap_cx_bottom = trap_cx_bottom_from_slot(slot);
+    let trap_cx_top = trap_cx_bottom + PAGE_SIZE;
+    gdb_println!(
+        MAPPING_ENABLE,
+        "[trap_cx-map] onepage va[0x{:X} - 0x{:X}]",
+        trap_cx_bottom,
+        trap_cx_top
+    );
+    mm.memory_set.insert_framed_area(
+        MapAreaType::TrapContext,
+        trap_cx_bottom.into(),
+        trap_cx_top.into(),
+        MapPermission::R | MapPermission::W,
+    );
+    slot
+}
+
+/// 释放 process 地址空间中槽位 slot 的 trap_cx 与用户栈，调用者不能持有任务锁
+pub fn dealloc_user_slot(process: &ProcessControlBlock, ustack_base: usize, slot: usize) {
+    let process_inner = process.acquire_inner_lock();
+    let mut mm = process_inner.mm.lock();
+    // dealloc ustack manually，用户栈的大小取决于分配时的 RLIMIT_STACK，按栈顶的页查找
+    let ustack_top_page: VirtAddr = (ustack_top_from_slot(ustack_base, slot) - PAGE_SIZE).into();
+    mm.memory_set
+        .remove_area_containing(ustack_top_page.into());
+    // dealloc trap_cx manually
+    let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_slot(slot).into();
+    mm.memory_set
+        .remove_area_with_start_vpn(trap_cx_bottom_va.into());
+}
+
+/// 槽位 slot 的 trap_cx 所在的物理页
+pub fn slot_trap_cx_ppn(process: &ProcessControlBlock, slot: usize) -> PhysPageNum {
+    let process_inner = process.acquire_inner_lock();
+    let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_slot(slot).into();
+    let ppn = process_inner
+        .mm
+        .lock()
+        .memory_set
+        .translate(trap_cx_bottom_va.into())
+        .unwrap()
+        .ppn();
+    ppn
 }
 
 impl TaskUserRes {
+    /// slot 为 None 时在地址空间中分配新的槽位及其 trap_cx 与用户栈，
+    /// 否则沿用地址空间中已有的槽位（fork 时随地址空间复制而来）
+    /// rel_tid 初始为 0，非主线程加入进程时再确定
     pub fn new(
         process: Arc<ProcessControlBlock>,
+        tid: Arc<TidHandle>,
         ustack_base: usize,
-        pid: isize,
-        alloc_user_res: bool,
+        slot: Option<usize>,
     ) -> Self {
-        let tid = tid_alloc();
-        let rel_tid = if pid < 0 { 0 } else { tid.0 - pid as usize };
-        let task_user_res = Self {
+        let mut task_user_res = Self {
             tid,
-            rel_tid,
+            rel_tid: 0,
+            slot: slot.unwrap_or(0),
             ustack_base,
             process: Arc::downgrade(&process),
         };
-        if alloc_user_res {
+        if slot.is_none() {
             task_user_res.alloc_user_res();
         }
         task_user_res
     }
 
-    pub fn alloc_user_res(&self) {
+    /// 在地址空间中找到第一个空闲的槽位，分配 trap_cx 与用户栈
+    pub fn alloc_user_res(&mut self) {
         let process = self.process.upgrade().unwrap();
-        let mut process_inner = process.acquire_inner_lock();
-        // alloc user stack
-        let ustack_bottom = ustack_bottom_from_tid(self.ustack_base, self.rel_tid);
-        let ustack_top = ustack_bottom + USER_STACK_SIZE;
-        gdb_println!(
-            MAPPING_ENABLE,
-            "[user-stack-map] tid:{} va[0x{:X} - 0x{:X}]",
-            self.tid.0,
-            ustack_bottom,
-            ustack_top
-        );
-        process_inner.memory_set.insert_framed_area(
-            MapAreaType::UserStack,
-            ustack_bottom.into(),
-            ustack_top.into(),
-            MapPermission::R | MapPermission::W | MapPermission::U,
-        );
-        // alloc trap_cx
-        let trap_cx_bottom = trap_cx_bottom_from_tid(self.rel_tid);
-        let trap_cx_top = trap_cx_bottom + PAGE_SIZE;
-        gdb_println!(
-            MAPPING_ENABLE,
-            "[trap_cx-map] onepage va[0x{:X} - 0x{:X}]",
-            trap_cx_bottom,
-            trap_cx_top
-        );
-        process_inner.memory_set.insert_framed_area(
-            MapAreaType::TrapContext,
-            trap_cx_bottom.into(),
-            trap_cx_top.into(),
-            MapPermission::R | MapPermission::W,
-        );
+        self.slot = alloc_user_slot(&process, self.ustack_base, self.tid.0, 0);
     }
 
-    fn dealloc_user_res(&self) {
-        // dealloc tid
+    pub fn dealloc_user_res(&self) {
         let process = self.process.upgrade().unwrap();
-        let mut process_inner = process.acquire_inner_lock();
-        // dealloc ustack manually
-        let ustack_bottom_va: VirtAddr =
-            ustack_bottom_from_tid(self.ustack_base, self.rel_tid).into();
-        process_inner
-            .memory_set
-            .remove_area_with_start_vpn(ustack_bottom_va.into());
-        // dealloc trap_cx manually
-        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(self.rel_tid).into();
-        process_inner
-            .memory_set
-            .remove_area_with_start_vpn(trap_cx_bottom_va.into());
+        dealloc_user_slot(&process, self.ustack_base, self.slot);
     }
 
     pub fn trap_cx_user_va(&self) -> usize {
-        trap_cx_bottom_from_tid(self.rel_tid)
+        trap_cx_bottom_from_slot(self.slot)
     }
 
     pub fn trap_cx_ppn(&self) -> PhysPageNum {
         let process = self.process.upgrade().unwrap();
-        let process_inner = process.acquire_inner_lock();
-        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(self.rel_tid).into();
-        process_inner
-            .memory_set
-            .translate(trap_cx_bottom_va.into())
-            .unwrap()
-            .ppn()
+        slot_trap_cx_ppn(&process, self.slot)
     }
 
     pub fn ustack_base(&self) -> usize {
@@ -213,7 +278,7 @@ impl TaskUserRes {
     }
 
     pub fn ustack_top(&self) -> usize {
-        ustack_bottom_from_tid(self.ustack_base, self.rel_tid) + USER_STACK_SIZE
+        ustack_top_from_slot(self.ustack_base, self.slot)
     }
 }
 
@@ -0,0 +1,123 @@
+//! 空闲 hart 的休眠与唤醒
+//! 没有可运行的任务时 hart 执行 wfi 休眠，直到时钟中断或其他 hart 发来的 IPI 将其唤醒
+
+use super::manager::{has_ready_task, load_balance_tick};
+use crate::board::MAX_CPU_NUM;
+use crate::fs::tty_poll;
+use crate::multicore::get_hartid;
+use crate::sbi::sbi_send_ipi;
+use crate::timer::{check_timers, get_time_ns, set_next_trigger, tick_stopped};
+use core::arch::asm;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use riscv::register::sip;
+
+/// 正在（或即将）执行 wfi 的 hart 掩码
+static IDLE_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+pub struct IdleStat {
+    /// 累计休眠时间 (ns)
+    idle_ns: AtomicU64,
+    /// 从 wfi 中被唤醒的次数
+    wakeups: AtomicU64,
+    /// 收到的 IPI 次数
+    ipis: AtomicU64,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const IDLE_STAT_INIT: IdleStat = IdleStat {
+    idle_ns: AtomicU64::new(0),
+    wakeups: AtomicU64::new(0),
+    ipis: AtomicU64::new(0),
+};
+
+static IDLE_STATS: [IdleStat; MAX_CPU_NUM] = [IDLE_STAT_INIT; MAX_CPU_NUM];
+
+/// 供用户态读取的单个 hart 的空闲统计
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HartIdleStat {
+    pub idle_ns: u64,
+    pub wakeups: u64,
+    pub ipis: u64,
+}
+
+pub fn hart_idle_stat(hartid: usize) -> HartIdleStat {
+    let stat = &IDLE_STATS[hartid];
+    HartIdleStat {
+        idle_ns: stat.idle_ns.load(Ordering::Relaxed),
+        wakeups: stat.wakeups.load(Ordering::Relaxed),
+        ipis: stat.ipis.load(Ordering::Relaxed),
+    }
+}
+
+pub fn is_hart_idle(hartid: usize) -> bool {
+    IDLE_HARTS.load(Ordering::SeqCst) & (1 << hartid) != 0
+}
+
+/// 任务被放入 hartid 的就绪队列后调用
+/// 若该 hart 正在休眠或已停止时间片时钟 (NO_HZ)，需要让它重新开始调度
+pub fn kick_hart(hartid: usize) {
+    if hartid == get_hartid() {
+        restart_tick();
+    } else if is_hart_idle(hartid) || tick_stopped(hartid) {
+        sbi_send_ipi(1 << hartid);
+    }
+}
+
+/// 使正在 hartid 上运行的任务立即陷入内核，以便在返回用户态前处理新的信号
+pub fn interrupt_hart(hartid: usize) {
+    if hartid != get_hartid() {
+        sbi_send_ipi(1 << hartid);
+    }
+}
+
+/// 就绪队列中有了新任务，NO_HZ 下停止的时间片时钟需要重新开始
+fn restart_tick() {
+    if tick_stopped(get_hartid()) {
+        set_next_trigger();
+    }
+}
+
+fn clear_ipi() {
+    // SSIP 位于 sip 的第 1 位
+    unsafe { asm!("csrc sip, {}", in(reg) 1 << 1) };
+}
+
+/// 本 hart 无任务可运行时调用，返回后调度循环重新取任务
+pub fn idle_wait() {
+    let hartid = get_hartid();
+    let stat = &IDLE_STATS[hartid];
+    IDLE_HARTS.fetch_or(1 << hartid, Ordering::SeqCst);
+    // 置位后再检查一次队列：此前入队的任务不会发送 IPI，此后入队的任务发出的 IPI 会使 wfi 立即返回
+    if !has_ready_task(hartid) {
+        let start = get_time_ns() as u64;
+        // 内核态不开启 sstatus.SIE，wfi 在 sie 中使能的中断挂起时即返回，随后在此处轮询处理
+        unsafe { asm!("wfi") };
+        stat.idle_ns
+            .fetch_add(get_time_ns() as u64 - start, Ordering::Relaxed);
+        stat.wakeups.fetch_add(1, Ordering::Relaxed);
+    }
+    IDLE_HARTS.fetch_and(!(1 << hartid), Ordering::SeqCst);
+
+    let pending = sip::read();
+    if pending.ssoft() {
+        clear_ipi();
+        stat.ipis.fetch_add(1, Ordering::Relaxed);
+        restart_tick();
+    }
+    if pending.stimer() {
+        // 处理到期的定时器，重新设置时钟的同时清除 STIP
+        check_timers();
+        load_balance_tick();
+        tty_poll();
+    }
+}
+
+/// 处理运行用户程序时收到的 IPI
+pub fn handle_ipi() {
+    clear_ipi();
+    IDLE_STATS[get_hartid()]
+        .ipis
+        .fetch_add(1, Ordering::Relaxed);
+    restart_tick();
+}
@@ -1,94 +1,374 @@
-use super::{ProcessControlBlock, TaskControlBlock};
+use super::idle::kick_hart;
+#[cfg(feature = "no_hz")]
+use super::idle::is_hart_idle;
+use super::sched::{
+    sched_slice, SchedEntity, SchedPolicy, SCHED_LATENCY_NS, SCHED_MIN_GRANULARITY_NS,
+};
+use super::{ProcessControlBlock, TaskControlBlock, TaskStatus};
+use crate::board::MAX_CPU_NUM;
+use crate::multicore::get_hartid;
 
-use alloc::collections::VecDeque;
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use hashbrown::HashMap;
 use spin::{Lazy, Mutex, RwLock};
 
+/// 每隔多少个时钟中断进行一次负载均衡
+const BALANCE_INTERVAL: usize = 10;
+
+/// 单个 hart 的就绪队列
 pub struct TaskManager {
-    ready_queue: VecDeque<Arc<TaskControlBlock>>,
-    waiting_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// SCHED_FIFO/SCHED_RR 任务，按 (实时优先级降序, 入队顺序) 排列
+    rt_queue: BTreeMap<(Reverse<usize>, u64), Arc<TaskControlBlock>>,
+    /// SCHED_OTHER 任务，按 (vruntime, 入队顺序) 排列，值中记录入队时的权重
+    cfs_queue: BTreeMap<(u64, u64), (Arc<TaskControlBlock>, u64)>,
+    /// cfs_queue 中任务的权重之和
+    cfs_load: u64,
+    /// 就绪队列中出现过的最小 vruntime，单调不减
+    min_vruntime: u64,
+    seq: u64,
+    /// 距上次负载均衡经过的时钟中断数
+    balance_ticks: usize,
 }
 
-/// A simple FIFO scheduler.
+/// 实时任务优先；普通任务中 vruntime 最小者优先（CFS）
 impl TaskManager {
     pub fn new() -> Self {
         Self {
-            ready_queue: VecDeque::new(),
-            waiting_queue: VecDeque::new(),
+            rt_queue: BTreeMap::new(),
+            cfs_queue: BTreeMap::new(),
+            cfs_load: 0,
+            min_vruntime: 0,
+            seq: 0,
+            balance_ticks: 0,
         }
     }
     pub fn add_to_ready_queue(&mut self, task: Arc<TaskControlBlock>) {
-        self.ready_queue.push_back(task);
+        self.seq += 1;
+        let mut se = task.sched.lock();
+        if se.migrated {
+            // 从其他 hart 迁移而来，换算到本队列的 vruntime 基准
+            se.vruntime += self.min_vruntime;
+            se.migrated = false;
+        }
+        if se.policy.is_rt() {
+            let key = (Reverse(se.rt_priority), self.seq);
+            drop(se);
+            self.rt_queue.insert(key, task);
+        } else {
+            if se.fresh {
+                // 新任务从当前的 min_vruntime 开始，不能凭借较小的初值抢占CPU
+                se.vruntime = se.vruntime.max(self.min_vruntime);
+                se.fresh = false;
+            }
+            let key = (se.vruntime, self.seq);
+            let weight = se.weight();
+            drop(se);
+            self.cfs_load += weight;
+            self.cfs_queue.insert(key, (task, weight));
+        }
     }
     pub fn fetch_from_ready_queue(&mut self) -> Option<Arc<TaskControlBlock>> {
-        self.ready_queue.pop_front()
+        if let Some(&key) = self.rt_queue.keys().next() {
+            return self.rt_queue.remove(&key);
+        }
+        let key = *self.cfs_queue.keys().next()?;
+        self.min_vruntime = self.min_vruntime.max(key.0);
+        self.remove_cfs(&key)
+    }
+    fn remove_cfs(&mut self, key: &(u64, u64)) -> Option<Arc<TaskControlBlock>> {
+        let (task, weight) = self.cfs_queue.remove(key)?;
+        self.cfs_load -= weight;
+        Some(task)
+    }
+    /// 睡眠后被唤醒的任务最多获得半个调度周期的补偿，使交互式任务能尽快响应
+    fn wakeup(&mut self, task: Arc<TaskControlBlock>) {
+        {
+            let mut se = task.sched.lock();
+            if !se.policy.is_rt() && !se.migrated {
+                let floor = self.min_vruntime.saturating_sub(SCHED_LATENCY_NS / 2);
+                se.vruntime = se.vruntime.max(floor);
+            }
+        }
+        self.add_to_ready_queue(task);
     }
-    pub fn add_to_waiting_queue(&mut self, task: Arc<TaskControlBlock>) {
-        self.waiting_queue.push_back(task);
+    /// 取出至多 max 个允许在 hartid 上运行的任务，优先迁移 vruntime 最大（最不“热”）的普通任务
+    fn detach_tasks(&mut self, hartid: usize, max: usize) -> Vec<Arc<TaskControlBlock>> {
+        let cfs_keys: Vec<_> = self
+            .cfs_queue
+            .iter()
+            .rev()
+            .filter(|(_, (task, _))| task.sched.lock().allowed_on(hartid))
+            .map(|(key, _)| *key)
+            .take(max)
+            .collect();
+        let mut tasks: Vec<_> = cfs_keys
+            .iter()
+            .filter_map(|key| self.remove_cfs(key))
+            .collect();
+        if tasks.len() < max {
+            let rt_keys: Vec<_> = self
+                .rt_queue
+                .iter()
+                .rev()
+                .filter(|(_, task)| task.sched.lock().allowed_on(hartid))
+                .map(|(key, _)| *key)
+                .take(max - tasks.len())
+                .collect();
+            tasks.extend(rt_keys.iter().filter_map(|key| self.rt_queue.remove(key)));
+        }
+        for task in tasks.iter() {
+            let mut se = task.sched.lock();
+            se.vruntime = se.vruntime.saturating_sub(self.min_vruntime);
+            se.migrated = true;
+        }
+        tasks
+    }
+    /// 当前任务 se 是否应在时钟中断时让出CPU
+    /// 普通任务用完按权重分得的时间片，或领先队首任务超过一个时间片时才被抢占，
+    /// 至少运行 SCHED_MIN_GRANULARITY_NS
+    fn need_resched(&self, se: &SchedEntity) -> bool {
+        let top_rt = self.rt_queue.keys().next().map(|(Reverse(prio), _)| *prio);
+        match se.policy {
+            SchedPolicy::Fifo => top_rt.map_or(false, |prio| prio > se.rt_priority),
+            SchedPolicy::Rr => top_rt.map_or(false, |prio| prio >= se.rt_priority),
+            SchedPolicy::Other => {
+                if top_rt.is_some() {
+                    return true;
+                }
+                let leftmost = match self.cfs_queue.keys().next() {
+                    Some(&(vruntime, _)) => vruntime,
+                    None => return false,
+                };
+                let weight = se.weight();
+                let slice = sched_slice(weight, self.cfs_load + weight, self.cfs_queue.len() + 1);
+                let ran = se.exec_runtime();
+                ran >= slice
+                    || (ran >= SCHED_MIN_GRANULARITY_NS
+                        && se.current_vruntime().saturating_sub(leftmost) > slice)
+            }
+        }
+    }
+    fn ready_count(&self) -> usize {
+        self.rt_queue.len() + self.cfs_queue.len()
     }
 }
 
-pub static TASK_MANAGER: Lazy<Mutex<TaskManager>> = Lazy::new(|| Mutex::new(TaskManager::new()));
+/// 每个 hart 各自的就绪队列，下标为 hartid
+pub static TASK_MANAGERS: Lazy<Vec<Mutex<TaskManager>>> =
+    Lazy::new(|| (0..MAX_CPU_NUM).map(|_| Mutex::new(TaskManager::new())).collect());
+/// 阻塞的任务不属于任何 hart
+pub static WAITING_QUEUE: Lazy<Mutex<VecDeque<Arc<TaskControlBlock>>>> =
+    Lazy::new(|| Mutex::new(VecDeque::new()));
+/// 已进入调度循环的 hart 掩码
+static ONLINE_HARTS: AtomicUsize = AtomicUsize::new(0);
 pub static PID2PCB: Lazy<RwLock<HashMap<usize, Arc<ProcessControlBlock>>>> =
     Lazy::new(|| RwLock::new(HashMap::new()));
 pub static TID2TCB: Lazy<RwLock<HashMap<usize, Arc<TaskControlBlock>>>> =
     Lazy::new(|| RwLock::new(HashMap::new()));
 
+pub fn mark_hart_online(hartid: usize) {
+    ONLINE_HARTS.fetch_or(1 << hartid, Ordering::SeqCst);
+}
+
+pub fn online_harts() -> usize {
+    ONLINE_HARTS.load(Ordering::SeqCst)
+}
+
+/// 为任务选择入队的 hart：优先上次运行的 hart，新任务放到最空闲的 hart
+/// NO_HZ 下上次运行的 hart 忙碌时改用空闲的 hart
+fn select_hart(se: &SchedEntity) -> usize {
+    let mask = se.cpus_allowed & online_harts();
+    if mask == 0 {
+        // 启动阶段还没有 hart 进入调度循环
+        return get_hartid();
+    }
+    let prev_allowed = !se.fresh && mask & (1 << se.cpu) != 0;
+    // NO_HZ 下空闲的 hart 不会周期性地做负载均衡，需要在入队时就把任务交给它
+    #[cfg(feature = "no_hz")]
+    {
+        let idle = (0..MAX_CPU_NUM).find(|&hartid| mask & (1 << hartid) != 0 && is_hart_idle(hartid));
+        if let Some(hartid) = idle {
+            if !prev_allowed || !is_hart_idle(se.cpu) {
+                return hartid;
+            }
+        }
+    }
+    if prev_allowed {
+        return se.cpu;
+    }
+    (0..MAX_CPU_NUM)
+        .filter(|hartid| mask & (1 << hartid) != 0)
+        .min_by_key(|&hartid| TASK_MANAGERS[hartid].lock().ready_count())
+        .unwrap()
+}
+
+/// 就绪任务最多的其他 hart 及其任务数
+fn find_busiest(hartid: usize) -> Option<(usize, usize)> {
+    (0..MAX_CPU_NUM)
+        .filter(|&other| other != hartid && online_harts() & (1 << other) != 0)
+        .map(|other| (other, TASK_MANAGERS[other].lock().ready_count()))
+        .max_by_key(|&(_, count)| count)
+        .filter(|&(_, count)| count > 0)
+}
+
+/// 从 busiest 迁移至多 max 个任务到 hartid，两个队列的锁不会同时持有
+fn migrate_tasks(busiest: usize, hartid: usize, max: usize) -> usize {
+    let tasks = TASK_MANAGERS[busiest].lock().detach_tasks(hartid, max);
+    let count = tasks.len();
+    let mut local = TASK_MANAGERS[hartid].lock();
+    for task in tasks {
+        local.add_to_ready_queue(task);
+    }
+    count
+}
+
 pub fn add_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER.lock().add_to_ready_queue(task);
+    let se = *task.sched.lock();
+    let hartid = select_hart(&se);
+    TASK_MANAGERS[hartid].lock().add_to_ready_queue(task);
+    kick_hart(hartid);
 }
 
+pub fn has_ready_task(hartid: usize) -> bool {
+    TASK_MANAGERS[hartid].lock().ready_count() > 0
+}
+
+/// 本地队列为空时从最繁忙的 hart 窃取任务
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
-    TASK_MANAGER.lock().fetch_from_ready_queue()
+    let hartid = get_hartid();
+    loop {
+        let mut task = TASK_MANAGERS[hartid].lock().fetch_from_ready_queue();
+        if task.is_none() {
+            if let Some((busiest, _)) = find_busiest(hartid) {
+                if migrate_tasks(busiest, hartid, 1) > 0 {
+                    task = TASK_MANAGERS[hartid].lock().fetch_from_ready_queue();
+                }
+            }
+        }
+        let task = task?;
+        // 任务排队期间其 CPU 亲和性可能已被修改
+        if task.sched.lock().allowed_on(hartid) {
+            return Some(task);
+        }
+        add_task(task);
+    }
 }
 
+/// 周期性负载均衡：从最繁忙的 hart 拉取任务，使两者的就绪任务数大致相当
+pub fn load_balance_tick() {
+    let hartid = get_hartid();
+    let local = {
+        let mut manager = TASK_MANAGERS[hartid].lock();
+        manager.balance_ticks += 1;
+        if manager.balance_ticks < BALANCE_INTERVAL {
+            return;
+        }
+        manager.balance_ticks = 0;
+        manager.ready_count()
+    };
+    if let Some((busiest, count)) = find_busiest(hartid) {
+        if count > local + 1 {
+            migrate_tasks(busiest, hartid, (count - local) / 2);
+        }
+    }
+}
+
+/// 任务切换出去之后由调度循环调用：放入阻塞队列，若其间已被唤醒则直接放回就绪队列
+/// 锁顺序为 WAITING_QUEUE -> 任务 inner，与 unblock_task 一致
 pub fn block_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER.lock().add_to_waiting_queue(task);
+    let mut waiting_queue = WAITING_QUEUE.lock();
+    let mut inner = task.acquire_inner_lock();
+    if inner.wakeup_pending {
+        inner.wakeup_pending = false;
+        inner.task_status = TaskStatus::Ready;
+        drop(inner);
+        drop(waiting_queue);
+        add_task(task);
+    } else {
+        drop(inner);
+        waiting_queue.push_back(task);
+    }
 }
 
 pub fn unblock_task(task: Arc<TaskControlBlock>) {
-    let mut mlock = TASK_MANAGER.lock();
-    let p = mlock
-        .waiting_queue
-        .iter()
-        .enumerate()
-        .find(|(_, t)| Arc::ptr_eq(t, &task))
-        .map(|(idx, t)| (idx, t.clone()));
-
-    if let Some((idx, task)) = p {
-        mlock.waiting_queue.remove(idx);
-        mlock.add_to_ready_queue(task);
+    let mut waiting_queue = WAITING_QUEUE.lock();
+    let idx = waiting_queue.iter().position(|t| Arc::ptr_eq(t, &task));
+    if idx.and_then(|idx| waiting_queue.remove(idx)).is_none() {
+        // 任务尚未切换出去（或并未阻塞），记下这次唤醒
+        task.acquire_inner_lock().wakeup_pending = true;
+        return;
     }
+    drop(waiting_queue);
+    task.acquire_inner_lock().task_status = TaskStatus::Ready;
+    let se = *task.sched.lock();
+    let hartid = select_hart(&se);
+    TASK_MANAGERS[hartid].lock().wakeup(task);
+    kick_hart(hartid);
+}
+
+/// 时钟中断时判断当前任务是否需要被抢占
+pub fn need_resched(task: &Arc<TaskControlBlock>) -> bool {
+    // 先复制调度信息并释放 sched 锁，再获取就绪队列的锁
+    let se = *task.sched.lock();
+    TASK_MANAGERS[get_hartid()].lock().need_resched(&se)
 }
 
 #[allow(unused)]
 pub fn task_count() -> usize {
-    TASK_MANAGER.lock().ready_queue.clone().into_iter().count()
+    TASK_MANAGERS.iter().map(|manager| manager.lock().ready_count()).sum()
 }
 
-// #[allow(unused)]
-// pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
-//     let map = PID2PCB.read();
-//     map.get(&pid).map(Arc::clone)
-// }
+/// 包括尚未被回收的僵尸进程
+pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    let map = PID2PCB.read();
+    map.get(&pid).map(Arc::clone)
+}
 
-// pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
-//     PID2PCB.write().insert(pid, process);
-// }
+pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
+    PID2PCB.write().insert(pid, process);
+}
 
-// pub fn remove_from_pid2process(pid: usize) {
-//     let mut map = PID2PCB.write();
-//     if map.remove(&pid).is_none() {
-//         panic!("cannot find pid {} in pid2process!", pid);
-//     }
-// }
+/// 进程被父进程回收时移除，此后进程释放时 pid 才可能被重新分配
+pub fn remove_from_pid2process(pid: usize) {
+    let mut map = PID2PCB.write();
+    if map.remove(&pid).is_none() {
+        panic!("cannot find pid {} in pid2process!", pid);
+    }
+}
+
+/// 系统中未退出的线程数，受 RLIMIT_NPROC 限制
+/// 内核没有用户的概念，所有进程同属一个用户，因此按全系统计数
+pub fn thread_count() -> usize {
+    TID2TCB.read().len()
+}
 
 pub fn tid2task(tid: usize) -> Option<Arc<TaskControlBlock>> {
     let map = TID2TCB.read();
     map.get(&tid).map(Arc::clone)
 }
 
+/// 所有未退出的进程，即主线程仍在 TID2TCB 中的进程
+pub fn all_processes() -> Vec<Arc<ProcessControlBlock>> {
+    TID2TCB
+        .read()
+        .iter()
+        .filter_map(|(&tid, task)| task.process.upgrade().filter(|p| p.getpid() == tid))
+        .collect()
+}
+
+/// 进程组 pgid 中的所有进程
+/// fork 在持有进程锁时修改 TID2TCB，因此需在释放 TID2TCB 的锁之后再读取 pgid
+pub fn process_group(pgid: usize) -> Vec<Arc<ProcessControlBlock>> {
+    all_processes()
+        .into_iter()
+        .filter(|p| p.acquire_inner_lock().pgid() == pgid)
+        .collect()
+}
+
 pub fn insert_into_tid2task(tid: usize, task: Arc<TaskControlBlock>) {
     TID2TCB.write().insert(tid, task);
 }
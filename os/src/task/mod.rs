@@ -1,44 +1,53 @@
 mod aux;
+mod binfmt;
 mod context;
 mod id;
+mod idle;
 mod manager;
 mod process;
 mod processor;
+mod rlimit;
+mod sched;
 mod siginfo;
+mod signal;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 mod time_info;
 mod utils;
 
-use core::mem::size_of;
-
 use crate::{
-    config::SIGRETURN_TRAMPOLINE,
+    fs::{tty_release_session, tty_set_session},
     gdb_println,
     loader::get_initproc_binary,
     mm::{translated_byte_buffer, translated_refmut, UserBuffer},
     monitor::{QEMU, SYSCALL_ENABLE},
-    syscall::futex_wake,
+    syscall::{futex_wake, EINTR},
+    trap::TrapContext,
 };
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use manager::fetch_task;
 use process::ProcessControlBlock;
-use spin::Lazy;
+use spin::{Lazy, Mutex};
 use switch::__switch;
 
 pub use aux::*;
+pub use binfmt::{do_execve, register_binfmt, BinaryFormat, ExecParams, LoadResult};
 pub use context::TaskContext;
 pub use id::{kstack_alloc, tid_alloc, KernelStack, TidHandle};
+pub use idle::{handle_ipi, hart_idle_stat, HartIdleStat};
 pub use manager::*;
 pub use process::*;
 pub use processor::*;
+pub use rlimit::*;
+pub use sched::*;
 pub use siginfo::*;
+pub use signal::*;
 pub use task::*;
 pub use time_info::*;
 
 pub fn suspend_current_and_run_next() {
-    // wakeup_futex_waiters();
     // 将原来的take_current改为current_task，也就是说suspend之后，task仍然保留在processor中
     let task = current_task().unwrap();
 
@@ -49,7 +58,7 @@ pub fn suspend_current_and_run_next() {
     if task_inner.killed {
         drop(task_inner);
         drop(task);
-        exit_current_and_run_next(-(SIGKILL as i32), false);
+        exit_current_and_run_next(signaled_status(SIGKILL, false), true);
     }
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
 
@@ -71,15 +80,28 @@ pub fn suspend_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
+/// 开始一次等待：丢弃之前遗留的唤醒，此后的唤醒使随后的 `block_current_and_run_next` 立即返回
+/// 调用者需在检查等待条件、或在自己的等待队列中登记之前调用，每轮等待调用一次
+pub fn prepare_to_block() {
+    current_task().unwrap().acquire_inner_lock().wakeup_pending = false;
+}
+
+/// 阻塞当前任务，唤醒可能是虚假的，调用者需重新检查等待条件
+/// 只有 `prepare_to_block` 之后的唤醒才会使其立即返回
 pub fn block_current_and_run_next() {
     let task = current_task().unwrap();
 
     // ---- access current TCB exclusively
     let mut task_inner = task.acquire_inner_lock();
+    if task_inner.wakeup_pending {
+        // 在阻塞之前已经被唤醒
+        task_inner.wakeup_pending = false;
+        return;
+    }
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
-    // Change status to Ready
+    // Change status to Blocking
+    // 与 add_task 相同，放入阻塞队列延后到切换到 idle 控制流之后，避免其他核在保存上下文前将其唤醒运行
     task_inner.task_status = TaskStatus::Blocking;
-    block_task(task.clone());
 
     drop(task_inner);
     drop(task);
@@ -88,15 +110,42 @@ pub fn block_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
+/// exit_code 为 wait 状态编码，见 `exited_status` 与 `signaled_status`
+/// 主线程退出或 exit_group 时由调用线程终止其余线程，待它们全部退出后再释放进程的资源；
+/// 进程已在整体退出（其他线程的 exit_group 或 execve）时只退出调用线程
 pub fn exit_current_and_run_next(exit_code: i32, is_exit_group: bool) -> ! {
-    let task = take_current_task().unwrap();
-    let mut task_inner = task.acquire_inner_lock();
+    // 任务仍作为 current 保留在 processor 中，切换到调度循环后才释放，此前内核栈不能被回收
+    let task = current_task().unwrap();
     let process = task.process.upgrade().unwrap();
-    let rel_tid = task_inner.get_relative_tid();
+    let rel_tid = match task.acquire_inner_lock().res.as_ref() {
+        Some(res) => res.rel_tid,
+        // 用户资源已经释放，不会再返回用户态
+        None => {
+            drop(process);
+            drop(task);
+            let mut _unused = TaskContext::zero_init();
+            schedule(&mut _unused as *mut _);
+            panic!("Shouldn't reach here in `exit_current_and_run_next`!")
+        }
+    };
+    let group_leader = (rel_tid == 0 || is_exit_group) && {
+        let mut process_inner = process.acquire_inner_lock();
+        !core::mem::replace(&mut process_inner.group_exit, true)
+    };
+    if group_leader {
+        // 其余线程可能正在其他 hart 上运行，退出之前仍在使用地址空间
+        process.zap_other_threads(&task);
+        process.wait_other_threads(&task);
+    }
+
+    let mut task_inner = task.acquire_inner_lock();
+    let clear_child_tid = task_inner.clear_child_tid.take();
+    let res = task_inner.res.take().unwrap();
+    task_inner.task_status = TaskStatus::Zombie;
+    drop(task_inner);
 
     // do futex_wake if clear_child_tid is set
-    if let Some(p) = &task_inner.clear_child_tid {
-        // debug!("p = {:#x?}", p);
+    if let Some(p) = clear_child_tid {
         *translated_refmut(
             process.acquire_inner_lock().get_user_token(),
             p.addr as *mut u32,
@@ -104,22 +153,36 @@ pub fn exit_current_and_run_next(exit_code: i32, is_exit_group: bool) -> ! {
         futex_wake(p.addr, 1);
     }
 
-    remove_from_tid2task(task_inner.gettid());
-
-    // record exit code
-    task_inner.res = None;
-
-    drop(task_inner);
+    remove_from_tid2task(res.tid.0);
+    // 释放 trap_cx 与用户栈需要获取进程锁，不能持有任务锁
+    drop(res);
+    // 结算本次运行时间，父进程回收时读取
+    task.sched.lock().stop_running();
+    // 已退出线程在 tasks 中的位置留给新线程，其 CPU 时间计入进程，负责进程退出的主线程保留到进程被回收
+    if rel_tid != 0 || !group_leader {
+        let mut process_inner = process.acquire_inner_lock();
+        let times = task.sched.lock().cpu_times();
+        process_inner.exited_times.accumulate(&times);
+        process_inner.tasks[rel_tid] = None;
+        // 最后一个被终止的线程唤醒等待者
+        let waiter = process_inner
+            .group_exit_waiter
+            .clone()
+            .filter(|waiter| process_inner.tasks.iter().flatten().all(|t| Arc::ptr_eq(t, waiter)));
+        drop(process_inner);
+        if let Some(waiter) = waiter {
+            unblock_task(waiter);
+        }
+    }
     drop(task);
 
-    // however, if this is the main thread of current process
-    // the process should terminate at once
-    if rel_tid == 0 || is_exit_group {
-        // remove_from_pid2process(process.getpid());
+    if group_leader {
+        // pid 在进程被父进程回收时才从 PID2PCB 中移除
         let mut initproc_inner = INITPROC.acquire_inner_lock();
         let mut process_inner = process.acquire_inner_lock();
         // mark this process as a zombie process
         process_inner.is_zombie = true;
+        process.set_exited();
         // record exit code of main process
         process_inner.exit_code = exit_code;
 
@@ -133,32 +196,29 @@ pub fn exit_current_and_run_next(exit_code: i32, is_exit_group: bool) -> ! {
 
         drop(initproc_inner);
 
-        // deallocate user res (including tid/trap_cx/ustack) of all threads
-        // it has to be done before we dealloc the whole memory_set
-        // otherwise they will be deallocated twice
-        for task in process_inner.tasks.iter().filter(|t| t.is_some()) {
-            let task = task.as_ref().unwrap();
-            let mut task_inner = task.acquire_inner_lock();
-            task_inner.res = None;
-        }
-
         process_inner.children.clear();
         // deallocate other data in user space i.e. program code/data section
-        process_inner.memory_set.recycle_data_pages();
+        // 地址空间仍被 CLONE_VM 创建的进程使用时保留
+        if Arc::strong_count(&process_inner.mm) == 1 {
+            process_inner.mm.lock().memory_set.recycle_data_pages();
+        }
         // drop file descriptors
-        process_inner.fd_table.clear();
-
+        // 文件描述符表可能与其他进程共享，只放弃对它的引用
+        process_inner.fd_table = Arc::new(Mutex::new(FdTable::new(Vec::new())));
+        let sid = process_inner.sid();
+        let vfork_parent = process_inner.vfork_parent.take();
+
+        drop(process_inner);
+        if let Some(parent_task) = vfork_parent {
+            unblock_task(parent_task);
+        }
+        // 会话首进程退出时释放控制终端
+        if process.getpid() == sid {
+            tty_release_session(sid);
+        }
         // notify parent to recycle me
-        let ptask = process_inner
-            .parent
-            .as_ref()
-            .unwrap()
-            .upgrade()
-            .unwrap()
-            .acquire_inner_lock()
-            .get_task(0);
-
-        unblock_task(ptask.clone());
+        let (code, status) = child_status_code(exit_code);
+        notify_parent(&process, code, status);
     }
     drop(process);
     // we do not have to save task context
@@ -172,80 +232,119 @@ pub static INITPROC: Lazy<Arc<ProcessControlBlock>> =
 
 pub fn add_initproc() {
     let _initproc = INITPROC.clone();
+    // 初始进程的会话以控制台为控制终端
+    let (session, pgrp) = {
+        let inner = INITPROC.acquire_inner_lock();
+        (Arc::clone(&inner.session), Arc::clone(&inner.pgrp))
+    };
+    tty_set_session(session, pgrp);
 }
 
-pub fn perform_signals_of_current() {
+/// 返回用户态前处理当前线程的待处理信号
+/// restart_a0 不为 None 表示刚返回 ERESTARTSYS 的系统调用，值为其原始的 a0 参数
+pub fn perform_signals_of_current(restart_a0: Option<usize>) {
     let task = current_task().unwrap();
-    let mut task_inner = task.acquire_inner_lock();
-
-    // 禁止中断嵌套 & 提前退出，
-    if task_inner.pending_signals == 0 || task_inner.is_signaling() {
-        return;
-    }
-
     let process = current_process();
 
+    // 进程已被（可能是其他线程收到的）停止信号停止
+    wait_while_stopped(&task, &process);
+
     loop {
-        // 取出pending的第一个signal
-        let signum;
-        match task_inner.fetch_signal() {
-            Some(s) => signum = s,
-            None => return,
+        // 取出pending的第一个未被屏蔽的signal
+        let blocked = task.acquire_inner_lock().sigmask;
+        let info = match dequeue_signal(&task, &process, blocked) {
+            Some(info) => info,
+            None => {
+                // 恢复 ppoll/pselect 临时替换的屏蔽字，原屏蔽字下可递送的信号仍需处理
+                let mut task_inner = task.acquire_inner_lock();
+                match task_inner.saved_sigmask.take() {
+                    Some(mask) => {
+                        task_inner.sigmask = mask;
+                        continue;
+                    }
+                    None => break,
+                }
+            }
         };
+        let signum = info.signum();
 
-        let process_inner = process.acquire_inner_lock();
-        let sigaction = process_inner.sigactions[signum as usize];
-        // 如果信号对应的处理函数存在，则做好跳转到handler的准备
-        let handler = sigaction.sa_handler;
-        if sigaction.sa_handler == SIG_IGN {
-            return;
+        let sigaction = process.acquire_inner_lock().sigactions.lock()[signum as usize];
+        let handler = if is_signal_catchable(signum) {
+            sigaction.sa_handler
+        } else {
+            SIG_DFL
+        };
+        if handler == SIG_IGN {
+            continue;
         }
-        if sigaction.sa_handler == SIG_DFL {
-            //SIG_DFL 终止程序
-            if signum == SIGKILL || signum == SIGSEGV {
-                gdb_println!(
-                    SYSCALL_ENABLE,
-                    "[perform_signals_of_current]-fn pid:{} signal_num:{}, SIG_DFL kill process",
-                    current_tid(),
-                    signum
-                );
-                drop(process_inner);
-                drop(process);
-                drop(task_inner);
-                drop(task);
-                exit_current_and_run_next(-(signum as i32), false);
+        if handler == SIG_DFL {
+            match sig_default_action(signum) {
+                // SIGCONT 在发送时已恢复进程运行
+                SigDefault::Ign | SigDefault::Cont => {}
+                SigDefault::Stop => stop_current_process(&task, &process, signum),
+                action => {
+                    gdb_println!(
+                        SYSCALL_ENABLE,
+                        "[perform_signals_of_current]-fn pid:{} signal_num:{}, SIG_DFL kill process",
+                        current_tid(),
+                        signum
+                    );
+                    // 不生成 core 文件，RLIMIT_CORE 不为零时只在状态中标记
+                    let core_dump = action == SigDefault::Core
+                        && process.acquire_inner_lock().rlimit(RLIMIT_CORE) != 0;
+                    drop(process);
+                    drop(task);
+                    exit_current_and_run_next(signaled_status(signum, core_dump), true);
+                }
             }
-            return;
+            continue;
         }
 
-        // 准备跳到signal handler
-        // 保存当前trap_cx
-        task_inner.signal_context_save(signum, sigaction.sa_flags);
-
-        extern "C" {
-            fn __sigreturn();
-            fn __alltraps();
+        // 被打断的系统调用：SA_RESTART 时重新执行，否则返回 EINTR
+        if let Some(a0) = restart_a0 {
+            let trap_cx = task.acquire_inner_lock().get_trap_cx();
+            if sigaction.sa_flags.contains(SAFlags::SA_RESTART) {
+                restart_syscall(trap_cx, a0);
+            } else {
+                trap_cx.x[10] = -EINTR as usize;
+            }
         }
-        let mut trap_cx = task_inner.get_trap_cx();
-        trap_cx.x[1] = __sigreturn as usize - __alltraps as usize + SIGRETURN_TRAMPOLINE; // ra
-        trap_cx.x[10] = signum as usize; // a0 (args0 = signum)
-
-        if sigaction.sa_flags.contains(SAFlags::SA_SIGINFO) {
-            let token = current_user_token();
-            let mc_pc_ptr = trap_cx.x[2] + UContext::pc_offset();   
-            trap_cx.x[2] -= size_of::<UContext>(); // sp -= sizeof(ucontext)
-            trap_cx.x[12] = trap_cx.x[2]; // a2  = sp
-            *translated_refmut(token, mc_pc_ptr as *mut u64) = trap_cx.sepc as u64;
+        if setup_signal_frame(&task, &process, &info, &sigaction) {
+            return;
         }
-        // debug!("prepare to jump to `handler`, original sepc = {:#x?}", trap_cx.sepc);
-
-        trap_cx.sepc = handler; // sepc = handler
-        return;
+        // 信号帧无法写入用户栈，以 SIGSEGV 终止进程
+        force_sigsegv(&task, &process, signum);
+    }
+    // 没有执行信号处理函数，被打断的系统调用自动重启
+    if let Some(a0) = restart_a0 {
+        restart_syscall(task.acquire_inner_lock().get_trap_cx(), a0);
     }
 }
 
-pub fn current_add_signal(signum: u32) {
-    let task = current_task().unwrap();
-    let mut task_inner = task.acquire_inner_lock();
-    task_inner.add_signal(signum);
+/// 回到 ecall 指令重新执行系统调用
+fn restart_syscall(trap_cx: &mut TrapContext, a0: usize) {
+    trap_cx.sepc -= 4;
+    trap_cx.x[10] = a0;
+}
+
+/// 当前线程是否有未被屏蔽的待处理信号，阻塞中的系统调用据此提前返回
+pub fn current_has_signal() -> bool {
+    has_unmasked_signal(&current_task().unwrap(), &current_process())
+}
+
+/// 当前线程触发的同步异常，见 `force_signal`
+pub fn current_force_signal(info: SigInfo) {
+    force_signal(&current_task().unwrap(), info);
+}
+
+/// 无法建立或恢复信号帧时发送 SIGSEGV；若出错的正是 SIGSEGV 的处理，则恢复其默认动作以避免循环
+pub fn force_sigsegv(
+    task: &Arc<TaskControlBlock>,
+    process: &Arc<ProcessControlBlock>,
+    signum: u32,
+) {
+    if signum == SIGSEGV {
+        process.acquire_inner_lock().sigactions.lock()[SIGSEGV as usize] = SigAction::new();
+    }
+    force_signal(task, SigInfo::new(SIGSEGV, SI_KERNEL));
 }
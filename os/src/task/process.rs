@@ -1,17 +1,31 @@
 use core::arch::asm;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
 
+use super::id::{
+    alloc_user_slot, dealloc_user_slot, slot_trap_cx_ppn, tid_alloc, trap_cx_bottom_from_slot,
+    ustack_top_from_slot, TidHandle,
+};
 use super::{TaskControlBlock, MAX_SIGNUM};
-use super::{add_task, insert_into_tid2task, SigAction};
-use crate::config::{is_aligned, FDMAX, MMAP_BASE, PAGE_SIZE, aligned_up};
-use crate::fs::{FileClass, Stdin, Stdout};
+use super::idle::interrupt_hart;
+use super::{
+    add_task, block_current_and_run_next, current_task, insert_into_pid2process, insert_into_tid2task, remove_from_tid2task,
+    unblock_task, default_rlimits, CpuTimes, RLimits, SchedEntity, SigAction, SigPending,
+    SignalStack, TaskStatus, RLIMIT_AS, RLIMIT_DATA, SIGKILL, SIG_IGN,
+};
+use crate::config::{
+    aligned_down, aligned_up, huge_aligned_up, is_aligned, HUGE_PAGE_SIZE, PAGE_SIZE,
+};
+use crate::fs::{FileClass, PollQueue, Stdin, Stdout};
 use crate::mm::{
-    translated_refmut, MapPermission, MemorySet, MmapArea, MmapFlags, VirtAddr, KERNEL_SPACE, VirtPageNum,
+    translated_refmut, MapPermission, MemorySet, MemoryStat, MmapArea, MmapFlags, PTEFlags,
+    PageTableEntry, UserLayout, VirtAddr, KERNEL_SPACE, VirtPageNum,
 };
 use crate::mm::address::StepByOne;
 use crate::multicore::get_hartid;
-use crate::syscall::CloneFlags;
-use crate::task::{AuxHeader, AT_EXECFN, AT_NULL, AT_RANDOM};
+use crate::random::fill_random;
+use crate::syscall::{unqueue_waiter, CloneFlags, EMFILE, ENOMEM};
+use crate::task::{AuxHeader, AT_EXECFN, AT_NULL, AT_PLATFORM, AT_RANDOM};
 use crate::trap::{trap_handler, TrapContext};
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
@@ -21,47 +35,197 @@ use spin::{Mutex, MutexGuard};
 // use spin::Mutex;
 
 pub struct ProcessControlBlock {
-    pub pid: AtomicUsize,
+    /// 与主线程及以它为进程组或会话的进程共同持有，均释放后 pid 才能再次分配
+    pub pid: Arc<TidHandle>,
+    /// 与 inner 中的 is_zombie 同时设置，供 pidfd 在不获取进程锁的情况下读取
+    exited: AtomicBool,
+    /// 在指向该进程的 pidfd 上 poll 的任务，进程退出时唤醒
+    pub exit_poll_queue: Arc<PollQueue>,
     inner: Arc<Mutex<ProcessControlBlockInner>>,
 }
 
 pub struct ProcessControlBlockInner {
     pub is_zombie: bool,
-    pub memory_set: MemorySet,
+    /// 地址空间，CLONE_VM 创建的进程之间共享
+    pub mm: Arc<Mutex<ProcessMm>>,
     pub parent: Option<Weak<ProcessControlBlock>>,
     pub children: Vec<Arc<ProcessControlBlock>>,
     pub exit_code: i32,
-    pub fd_max: usize,
-    pub fd_table: FdTable,
-    pub sigactions: [SigAction; MAX_SIGNUM as usize],
+    /// 文件描述符表，CLONE_FILES 创建的进程之间共享
+    pub fd_table: Arc<Mutex<FdTable>>,
+    /// 信号处理函数，CLONE_SIGHAND 创建的进程之间共享
+    pub sigactions: Arc<Mutex<SigActions>>,
+    /// 发送给整个进程的待处理信号，由任一未屏蔽该信号的线程处理
+    pub shared_pending: SigPending,
     pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
-    pub cwd: String,
+    /// 当前工作目录，CLONE_FS 创建的进程之间共享
+    pub cwd: Arc<Mutex<String>>,
+    /// personality(2) 设置的执行域标志，fork 和 exec 时保留
+    pub personality: usize,
+    /// 已回收子进程（及其后代）的内存统计之和
+    pub children_mem_stat: MemoryStat,
+    /// 已退出线程的 CPU 时间之和
+    pub exited_times: CpuTimes,
+    /// 已回收子进程（及其后代）的 CPU 时间之和
+    pub children_times: CpuTimes,
+    /// 进程是否因停止信号而停止，停止期间所有线程在返回用户态前阻塞
+    pub stopped: bool,
+    /// 尚未被父进程 wait 取走的停止/继续事件
+    pub job_event: Option<JobEvent>,
+    /// 进程组，kill 与 wait4 可以指定整个进程组，终端只允许前台进程组读取
+    /// 持有组长的 pid，组中仍有进程时该 pid 不会被重新分配
+    pub pgrp: Arc<TidHandle>,
+    /// 会话，会话首进程可以获得控制终端，同样持有首进程的 pid
+    pub session: Arc<TidHandle>,
+    /// CLONE_VFORK 创建时被挂起的父线程，子进程 execve 或退出时唤醒
+    pub vfork_parent: Option<Arc<TaskControlBlock>>,
+    /// 资源限制，fork 时复制，exec 后保留
+    pub rlimits: RLimits,
+    /// exit_group、主线程退出或 execve 正在终止其余线程，被终止的线程退出时只释放自身的资源
+    pub group_exit: bool,
+    /// 等待其余线程全部退出的线程，见 `wait_other_threads`
+    pub group_exit_waiter: Option<Arc<TaskControlBlock>>,
+}
+
+/// 进程的用户地址空间及其堆与 mmap 区域的范围
+pub struct ProcessMm {
+    pub memory_set: MemorySet,
     pub user_heap_base: usize, // user heap
     pub user_heap_top: usize,
-    pub mmap_area_top: usize, // mmap area
+    pub mmap_area_base: usize, // mmap area
+    pub mmap_area_top: usize,
 }
 
-pub type FdTable = Vec<Option<FileClass>>;
+/// 可通过 WUNTRACED / WCONTINUED 等待的状态变化
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum JobEvent {
+    Stopped(u32),
+    Continued,
+}
+
+#[derive(Clone)]
+pub struct FdTable(Vec<Option<FileClass>>);
+pub type SigActions = [SigAction; MAX_SIGNUM as usize + 1];
 pub type ProcessInnerLock<'a> = MutexGuard<'a, ProcessControlBlockInner>;
 
+impl FdTable {
+    pub fn new(files: Vec<Option<FileClass>>) -> Self {
+        Self(files)
+    }
+
+    /// 不小于 minfd 的最小空闲描述符，不小于 limit（RLIMIT_NOFILE）时返回 EMFILE
+    pub fn alloc_fd(&mut self, minfd: usize, limit: usize) -> Result<usize, isize> {
+        let len = self.0.len();
+        let fd = (minfd..len)
+            .find(|&idx| self.0[idx].is_none())
+            .unwrap_or(len.max(minfd));
+        if fd >= limit {
+            return Err(-EMFILE);
+        }
+        while self.0.len() <= fd {
+            self.0.push(None);
+        }
+        Ok(fd)
+    }
+}
+
+impl Deref for FdTable {
+    type Target = Vec<Option<FileClass>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for FdTable {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl ProcessMm {
+    pub fn token(&self) -> usize {
+        self.memory_set.token()
+    }
+
+    /// 进程内存统计，虚拟大小包含堆
+    pub fn memory_stat(&self) -> MemoryStat {
+        let mut stat = self.memory_set.stat();
+        stat.vm_pages += (aligned_up(self.user_heap_top) - self.user_heap_base) / PAGE_SIZE;
+        stat
+    }
+
+    /// 计入 RLIMIT_DATA 的页数：堆与私有可写映射
+    pub fn data_pages(&self) -> usize {
+        let heap_pages = (aligned_up(self.user_heap_top) - self.user_heap_base) / PAGE_SIZE;
+        let mmap_pages: usize = self
+            .memory_set
+            .mmap_areas
+            .iter()
+            .filter(|area| area.is_private_writable())
+            .map(|area| area.vpn_range.get_end().0 - area.vpn_range.get_start().0)
+            .sum();
+        heap_pages + mmap_pages
+    }
+
+    /// 地址空间再增加 pages 页后是否仍在 RLIMIT_AS 之内，data 为真时还须在 RLIMIT_DATA 之内
+    pub fn may_expand_vm(&self, rlimits: &RLimits, pages: usize, data: bool) -> bool {
+        let limit_pages = |resource: usize| rlimits[resource].rlim_cur / PAGE_SIZE;
+        self.memory_stat().vm_pages + pages <= limit_pages(RLIMIT_AS)
+            && (!data || self.data_pages() + pages <= limit_pages(RLIMIT_DATA))
+    }
+
+    /// [start_vpn, end_vpn)内的每一页是否都属于已建立的区域或堆
+    pub fn is_mapped_range(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        let heap_start = VirtAddr::from(self.user_heap_base).floor();
+        let heap_end = VirtAddr::from(self.user_heap_top).ceil();
+        (start_vpn.0..end_vpn.0).all(|vpn| {
+            let vpn = VirtPageNum(vpn);
+            (vpn >= heap_start && vpn < heap_end) || self.memory_set.contains_vpn(vpn)
+        })
+    }
+}
+
 impl ProcessControlBlockInner {
     #[allow(unused)]
     pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
+        self.mm.lock().token()
+    }
+
+    pub fn memory_stat(&self) -> MemoryStat {
+        self.mm.lock().memory_stat()
     }
 
-    pub fn alloc_fd(&mut self, minfd: usize) -> usize {
-        let len = self.fd_table.len();
-        (minfd..len)
-            .find(|&idx| self.fd_table[idx].is_none())
-            .unwrap_or_else(|| {
-                self.fd_table.push(None);
-                len
-            })
+    pub fn check_lazy(&self, vaddr: usize, is_load: bool) -> isize {
+        self.mm.lock().check_lazy(vaddr, is_load)
     }
 
-    pub fn thread_count(&self) -> usize {
-        self.tasks.len()
+    pub fn prepare_user_access(&self, start: usize, len: usize, write: bool) -> bool {
+        self.mm.lock().prepare_user_access(start, len, write)
+    }
+
+    /// 已退出线程与现有线程的 CPU 时间之和
+    pub fn cpu_times(&self) -> CpuTimes {
+        let mut times = self.exited_times;
+        for task in self.tasks.iter().flatten() {
+            times.accumulate(&task.sched.lock().cpu_times());
+        }
+        times
+    }
+
+    /// 进程组号
+    pub fn pgid(&self) -> usize {
+        self.pgrp.0
+    }
+
+    /// 会话号
+    pub fn sid(&self) -> usize {
+        self.session.0
+    }
+
+    /// 资源 resource 的软限制
+    pub fn rlimit(&self, resource: usize) -> usize {
+        self.rlimits[resource].rlim_cur
     }
 
     pub fn get_task(&self, tid: usize) -> Arc<TaskControlBlock> {
@@ -75,45 +239,72 @@ impl ProcessControlBlock {
     }
 
     pub fn getpid(&self) -> usize {
-        self.pid.load(Ordering::Acquire)
+        self.pid.0
+    }
+
+    /// 进程已退出（成为僵尸进程），不获取进程锁
+    pub fn exited(&self) -> bool {
+        self.exited.load(Ordering::Acquire)
+    }
+
+    /// 由退出的进程在持有进程锁、设置 is_zombie 时调用
+    pub fn set_exited(&self) {
+        self.exited.store(true, Ordering::Release);
+        self.exit_poll_queue.wake_all();
     }
 
     pub fn new(elf_data: &[u8]) -> Arc<Self> {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, ustack_base, entry_point, uheap_base, _) = MemorySet::from_elf(elf_data);
+        let layout = UserLayout::new(0);
+        let (memory_set, ustack_base, entry_point, uheap_base, _) =
+            MemorySet::from_elf(elf_data, &layout);
         // allocate a pid
+        let pid = Arc::new(tid_alloc().unwrap());
         let process = Arc::new(Self {
-            pid: AtomicUsize::new(0),
+            pid: Arc::clone(&pid),
+            exited: AtomicBool::new(false),
+            exit_poll_queue: Arc::new(PollQueue::new()),
             inner: Arc::new(Mutex::new(ProcessControlBlockInner {
                 is_zombie: false,
-                memory_set,
+                mm: Arc::new(Mutex::new(ProcessMm {
+                    memory_set,
+                    user_heap_base: uheap_base,
+                    user_heap_top: uheap_base,
+                    mmap_area_base: layout.mmap_base,
+                    mmap_area_top: layout.mmap_base,
+                })),
                 parent: None,
                 children: Vec::with_capacity(10),
                 exit_code: 0,
-                fd_max: FDMAX,
-                fd_table: vec![
+                fd_table: Arc::new(Mutex::new(FdTable::new(vec![
                     // 0 -> stdin
                     Some(FileClass::Abs(Arc::new(Stdin))),
                     // 1 -> stdout
                     Some(FileClass::Abs(Arc::new(Stdout))),
                     // 2 -> stderr
                     Some(FileClass::Abs(Arc::new(Stdout))),
-                ],
-                sigactions: [SigAction::new(); MAX_SIGNUM as usize],
+                ]))),
+                sigactions: Arc::new(Mutex::new([SigAction::new(); MAX_SIGNUM as usize + 1])),
+                shared_pending: SigPending::new(),
                 tasks: Vec::with_capacity(10),
-                cwd: String::from("/"),
-                user_heap_base: uheap_base,
-                user_heap_top: uheap_base,
-                mmap_area_top: MMAP_BASE,
+                cwd: Arc::new(Mutex::new(String::from("/"))),
+                personality: 0,
+                children_mem_stat: MemoryStat::default(),
+                exited_times: CpuTimes::default(),
+                children_times: CpuTimes::default(),
+                stopped: false,
+                job_event: None,
+                // 初始进程是第一个会话与进程组的首进程
+                pgrp: Arc::clone(&pid),
+                session: Arc::clone(&pid),
+                vfork_parent: None,
+                rlimits: default_rlimits(),
+                group_exit: false,
+                group_exit_waiter: None,
             })),
         });
         // create a main thread, we should allocate ustack and trap_cx here
-        let task = Arc::new(TaskControlBlock::new(
-            Arc::clone(&process),
-            ustack_base,
-            -1,
-            true,
-        ));
+        let task = Arc::new(TaskControlBlock::new(Arc::clone(&process), pid, ustack_base, None));
         insert_into_tid2task(task.acquire_inner_lock().gettid(), Arc::clone(&task));
 
         // prepare trap_cx of main thread
@@ -133,75 +324,96 @@ impl ProcessControlBlock {
         );
         // add main thread to the process
         let mut process_inner = process.acquire_inner_lock();
-        // set pid
-        process.pid.store(task_inner.gettid(), Ordering::Release);
         process_inner.tasks.push(Some(Arc::clone(&task)));
 
         drop(task_inner);
         drop(process_inner);
 
-        // insert_into_pid2process(process.getpid(), Arc::clone(&process));
+        insert_into_pid2process(process.getpid(), Arc::clone(&process));
         // add main thread to scheduler
         add_task(task);
         process
     }
 
-    /// Only support processes with a single thread.
-    pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: &Vec<String>) -> Option<Arc<TaskControlBlock>> {
-        let mut inner = self.acquire_inner_lock();
-        assert_eq!(inner.thread_count(), 1);
+    /// 由当前线程调用，成功后它成为进程唯一的线程
+    pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: &Vec<String>, env: &Vec<String>) -> Option<Arc<TaskControlBlock>> {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, ustack_base, entry_point, uheap_base, mut auxv) =
-            MemorySet::from_elf(elf_data);
+        let layout = UserLayout::new(self.acquire_inner_lock().personality);
+        let (mut memory_set, ustack_base, entry_point, uheap_base, mut auxv) =
+            MemorySet::from_elf(elf_data, &layout);
         if ustack_base == 0 && entry_point == 0 && uheap_base == 0 {
             return None;
         }
         let new_token = memory_set.token();
+        // 新程序已装入，除非进程正在退出，不会再失败返回
+        let task = current_task().unwrap();
+        if !self.de_thread(&task) {
+            // 其他线程正在使进程退出，本线程返回后随之退出
+            return None;
+        }
+        // 原地址空间可能仍被 CLONE_VM 创建的进程使用，先释放调用线程在其中的 trap_cx 与用户栈
+        // 释放时需要获取进程锁，不能持有任务锁
+        let (old_ustack_base, old_slot, tid) = {
+            let task_inner = task.acquire_inner_lock();
+            let res = task_inner.res.as_ref().unwrap();
+            (res.ustack_base, res.slot, res.tid.0)
+        };
+        dealloc_user_slot(self, old_ustack_base, old_slot);
+        let mut inner = self.acquire_inner_lock();
 
         // substitute memory_set
-        inner.memory_set = memory_set;
-
+        memory_set.inherit_stat(&inner.mm.lock().memory_set);
         // ****设置用户堆顶和mmap顶端位置****
-        inner.user_heap_base = uheap_base;
-        inner.user_heap_top = uheap_base;
-        inner.mmap_area_top = MMAP_BASE;
-        
-        let task = inner.get_task(0);
+        inner.mm = Arc::new(Mutex::new(ProcessMm {
+            memory_set,
+            user_heap_base: uheap_base,
+            user_heap_top: uheap_base,
+            mmap_area_base: layout.mmap_base,
+            mmap_area_top: layout.mmap_base,
+        }));
+        // 与其他进程共享的文件描述符表与信号处理函数在 exec 后不再共享
+        if Arc::strong_count(&inner.fd_table) > 1 {
+            let fd_table = inner.fd_table.lock().clone();
+            inner.fd_table = Arc::new(Mutex::new(fd_table));
+        }
+        // 新程序中不存在原来的信号处理函数，已捕获的信号恢复为默认处理，忽略的信号保持忽略
+        let mut sigactions = *inner.sigactions.lock();
+        for sigaction in sigactions.iter_mut() {
+            if sigaction.sa_handler != SIG_IGN {
+                *sigaction = SigAction::new();
+            }
+        }
+        inner.sigactions = Arc::new(Mutex::new(sigactions));
+        let vfork_parent = inner.vfork_parent.take();
         drop(inner);
+        // 地址空间已不再与 vfork 的父进程共享，父进程可以继续运行
+        if let Some(parent_task) = vfork_parent {
+            unblock_task(parent_task);
+        }
 
         // then we alloc user resource for main thread again
         // since memory_set has been changed
+        // 参数与环境变量的字符串及指针数组压在用户栈之上，auxv 等另占一页
+        let arg_size = args
+            .iter()
+            .chain(env.iter())
+            .map(|s| s.len() + 1 + core::mem::size_of::<usize>())
+            .sum::<usize>()
+            + 2 * core::mem::size_of::<usize>()
+            + PAGE_SIZE;
+        let slot = alloc_user_slot(self, ustack_base, tid, arg_size);
+        let trap_cx_ppn = slot_trap_cx_ppn(self, slot);
+        let mut user_sp = ustack_top_from_slot(ustack_base, slot);
         let mut task_inner = task.acquire_inner_lock();
         let res = task_inner.res.as_mut().unwrap();
         res.ustack_base = ustack_base;
-        res.alloc_user_res();
-        let trap_cx_ppn = res.trap_cx_ppn();
-        let mut user_sp = res.ustack_top();
-        drop(res);
+        res.slot = slot;
         task_inner.trap_cx_ppn = trap_cx_ppn;
+        // 原来的备用信号栈不再属于新程序
+        task_inner.sigaltstack = SignalStack::new();
 
         ////////////// push env strings ///////////////////
 
-        let mut envp: Vec<usize> = Vec::with_capacity(1);
-        envp.push(0);
-        let mut env: Vec<String> = Vec::with_capacity(30);
-        // env.push(String::from("SHELL=/bin/sh"));
-        // env.push(String::from("PWD=/"));
-        // env.push(String::from("USER=root"));
-        // env.push(String::from("MOTD_SHOWN=pam"));
-        // env.push(String::from("LANG=C.UTF-8"));
-        // env.push(String::from(
-        //     "INVOCATION_ID=e9500a871cf044d9886a157f53826684",
-        // ));
-        // env.push(String::from("TERM=vt220"));
-        // env.push(String::from("SHLVL=2"));
-        // env.push(String::from("JOURNAL_STREAM=8:9265"));
-        // env.push(String::from("OLDPWD=/root"));
-        // env.push(String::from("_=busybox"));
-        // env.push(String::from("LOGNAME=root"));
-        // env.push(String::from("HOME=/"));
-        env.push(String::from("PATH=/"));
-        // env.push(String::from("LD_LIBRARY_PATH=/lib64"));
         let mut envp: Vec<usize> = (0..=env.len()).collect();
         envp[env.len()] = 0;
 
@@ -246,18 +458,24 @@ impl ProcessControlBlock {
             p += 1;
         }
         *translated_refmut(new_token, p as *mut u8) = 0;
+        auxv.push(AuxHeader {
+            aux_type: AT_PLATFORM,
+            value: user_sp,
+        });
 
         ////////////// rand bytes ///////////////////
         user_sp -= 16;
-        // p = user_sp;
+        p = user_sp;
         auxv.push(AuxHeader {
             aux_type: AT_RANDOM,
             value: user_sp,
         });
-        // for i in 0..0xf {
-        //     *translated_refmut(new_token, p as *mut u8) = i as u8;
-        //     p += 1;
-        // }
+        let mut rand_bytes = [0u8; 16];
+        fill_random(&mut rand_bytes);
+        for b in rand_bytes {
+            *translated_refmut(new_token, p as *mut u8) = b;
+            p += 1;
+        }
 
         ////////////// padding //////////////////////
         user_sp -= user_sp % 16;
@@ -334,72 +552,127 @@ impl ProcessControlBlock {
         Some(task.clone())
     }
 
-    /// Only support processes with a single thread.
-    pub fn fork(self: &Arc<Self>, flags: CloneFlags, stack: usize, newtls: usize) -> Arc<Self> {
+    /// 多线程进程 fork 时只复制调用线程，它成为子进程的主线程
+    /// CLONE_VM、CLONE_FILES、CLONE_FS、CLONE_SIGHAND 时与父进程共享相应的资源而不是复制
+    /// pid 耗尽时返回 None
+    pub fn fork(
+        self: &Arc<Self>,
+        parent_task: Arc<TaskControlBlock>,
+        flags: CloneFlags,
+        stack: usize,
+        newtls: usize,
+    ) -> Option<Arc<Self>> {
+        let pid = Arc::new(tid_alloc()?);
         let mut parent = self.acquire_inner_lock();
-        assert_eq!(parent.thread_count(), 1);
-        // clone parent's memory_set completely including trampoline/ustacks/trap_cxs
-        // 复制trap_cx和ustack等内存区域均在这里
-        // 因此后面不需要再allow_user_res了
-        // todo cow
-        // let memory_set = MemorySet::from_existed_user(&parent.memory_set);
-        let memory_set = MemorySet::cow_from_existed_user(&mut parent.memory_set);
-        // copy fd table
-        let mut new_fd_table = Vec::with_capacity(1024);
-        for fd in parent.fd_table.iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
-                new_fd_table.push(None);
-            }
-        }
+        let (caller_slot, ustack_base) = {
+            let parent_task_inner = parent_task.acquire_inner_lock();
+            let res = parent_task_inner.res.as_ref().unwrap();
+            (res.slot, res.ustack_base())
+        };
+        let (mm, slot) = if flags.contains(CloneFlags::CLONE_VM) {
+            // 子进程主线程在共享的地址空间中另外分配 trap_cx 与用户栈
+            (Arc::clone(&parent.mm), None)
+        } else {
+            let mut parent_mm = parent.mm.lock();
+            // clone parent's memory_set completely including trampoline/ustacks/trap_cxs
+            // 复制trap_cx和ustack等内存区域均在这里
+            // 因此后面不需要再allow_user_res了
+            // todo cow
+            // let memory_set = MemorySet::from_existed_user(&parent.memory_set);
+            let mut memory_set = MemorySet::cow_from_existed_user(&mut parent_mm.memory_set);
+            // 只有调用线程的 trap_cx 与用户栈属于子进程，子进程在其上继续运行
+            let keep = [
+                VirtAddr::from(trap_cx_bottom_from_slot(caller_slot)).floor(),
+                VirtAddr::from(ustack_top_from_slot(ustack_base, caller_slot) - PAGE_SIZE).floor(),
+            ];
+            memory_set.remove_thread_areas_except(&keep);
+            let mm = ProcessMm {
+                memory_set,
+                user_heap_base: parent_mm.user_heap_base,
+                user_heap_top: parent_mm.user_heap_top,
+                mmap_area_base: parent_mm.mmap_area_base,
+                mmap_area_top: parent_mm.mmap_area_top,
+            };
+            (Arc::new(Mutex::new(mm)), Some(caller_slot))
+        };
+        let fd_table = if flags.contains(CloneFlags::CLONE_FILES) {
+            Arc::clone(&parent.fd_table)
+        } else {
+            Arc::new(Mutex::new(parent.fd_table.lock().clone()))
+        };
+        let cwd = if flags.contains(CloneFlags::CLONE_FS) {
+            Arc::clone(&parent.cwd)
+        } else {
+            Arc::new(Mutex::new(parent.cwd.lock().clone()))
+        };
+        let sigactions = if flags.contains(CloneFlags::CLONE_SIGHAND) {
+            Arc::clone(&parent.sigactions)
+        } else {
+            Arc::new(Mutex::new(*parent.sigactions.lock()))
+        };
 
         // create child process pcb
         let child = Arc::new(Self {
-            pid: AtomicUsize::new(0),
+            pid: Arc::clone(&pid),
+            exited: AtomicBool::new(false),
+            exit_poll_queue: Arc::new(PollQueue::new()),
             inner: Arc::new(Mutex::new(ProcessControlBlockInner {
                 is_zombie: false,
-                memory_set,
+                mm,
                 parent: Some(Arc::downgrade(self)),
                 children: Vec::with_capacity(10),
                 exit_code: 0,
-                fd_max: FDMAX,
-                fd_table: new_fd_table,
-                sigactions: parent.sigactions.clone(),
+                fd_table,
+                sigactions,
+                shared_pending: SigPending::new(),
                 tasks: Vec::with_capacity(10),
-                cwd: parent.cwd.clone(),
-                user_heap_base: parent.user_heap_base,
-                user_heap_top: parent.user_heap_top,
-                mmap_area_top: parent.mmap_area_top,
+                cwd,
+                personality: parent.personality,
+                children_mem_stat: MemoryStat::default(),
+                exited_times: CpuTimes::default(),
+                children_times: CpuTimes::default(),
+                stopped: false,
+                job_event: None,
+                pgrp: Arc::clone(&parent.pgrp),
+                session: Arc::clone(&parent.session),
+                vfork_parent: if flags.contains(CloneFlags::CLONE_VFORK) {
+                    Some(Arc::clone(&parent_task))
+                } else {
+                    None
+                },
+                rlimits: parent.rlimits,
+                group_exit: false,
+                group_exit_waiter: None,
             })),
         });
         // add child
         parent.children.push(Arc::clone(&child));
         // create main thread of child process
-        let task = Arc::new(TaskControlBlock::new(
-            Arc::clone(&child),
-            parent
-                .get_task(0)
-                .acquire_inner_lock()
-                .res
-                .as_ref()
-                .unwrap()
-                .ustack_base(),
-            -1,
-            // here we do not allocate trap_cx or ustack again
-            // but mention that we allocate a new kstack here
-            false,
-        ));
+        // 不共享地址空间时 trap_cx 与用户栈已随地址空间复制，只需分配新的内核栈
+        let task = Arc::new(TaskControlBlock::new(Arc::clone(&child), pid, ustack_base, slot));
         insert_into_tid2task(task.acquire_inner_lock().gettid(), Arc::clone(&task));
+        // 子进程继承父线程的调度策略与优先级
+        let parent_sched = *parent_task.sched.lock();
+        *task.sched.lock() = SchedEntity::inherit(&parent_sched);
+        // 信号掩码与备用信号栈同样继承，待处理信号不继承
+        let (parent_sigmask, parent_altstack) = {
+            let parent_task_inner = parent_task.acquire_inner_lock();
+            (parent_task_inner.sigmask, parent_task_inner.sigaltstack)
+        };
+        let mut task_inner = task.acquire_inner_lock();
+        task_inner.sigmask = parent_sigmask;
+        task_inner.sigaltstack = parent_altstack;
+        drop(task_inner);
 
         // attach task to child process
         let mut child_inner = child.acquire_inner_lock();
         let task_inner = task.acquire_inner_lock();
-        child.pid.store(task_inner.gettid(), Ordering::Relaxed);
         child_inner.tasks.push(Some(Arc::clone(&task)));
         drop(child_inner);
-        // modify kstack_top in trap_cx of this thread
         let trap_cx = task_inner.get_trap_cx();
+        // 共享地址空间时 trap_cx 是新分配的，从调用线程复制
+        *trap_cx = *parent_task.acquire_inner_lock().get_trap_cx();
+        // modify kstack_top in trap_cx of this thread
         trap_cx.kernel_sp = task.kstack.get_top();
         // sys_fork return value ...
         if stack != 0 {
@@ -412,10 +685,87 @@ impl ProcessControlBlock {
         }
 
         drop(task_inner);
-        // insert_into_pid2process(child.getpid(), Arc::clone(&child));
+        insert_into_pid2process(child.getpid(), Arc::clone(&child));
         // add this thread to scheduler
         add_task(task);
-        child
+        Some(child)
+    }
+
+    /// 终止除 current 以外的所有线程：向其发送 SIGKILL 并唤醒，正在其他 hart 上运行的线程通过 IPI 立即陷入内核
+    /// 被终止的线程在返回用户态前自行退出并释放用户资源，调用者需随后调用 `wait_other_threads`
+    pub fn zap_other_threads(&self, current: &Arc<TaskControlBlock>) {
+        let others: Vec<_> = self
+            .acquire_inner_lock()
+            .tasks
+            .iter()
+            .flatten()
+            .filter(|task| !Arc::ptr_eq(task, current))
+            .cloned()
+            .collect();
+        for task in others {
+            let mut task_inner = task.acquire_inner_lock();
+            task_inner.killed = true;
+            task_inner.add_signal(SIGKILL);
+            let status = task_inner.task_status;
+            drop(task_inner);
+            unqueue_waiter(&task);
+            match status {
+                TaskStatus::Blocking => unblock_task(task),
+                TaskStatus::Running => interrupt_hart(task.sched.lock().cpu),
+                _ => {}
+            }
+        }
+    }
+
+    /// 等待 `zap_other_threads` 终止的线程全部退出，此后才能替换地址空间或释放进程的资源
+    pub fn wait_other_threads(&self, current: &Arc<TaskControlBlock>) {
+        loop {
+            let mut inner = self.acquire_inner_lock();
+            // 开始等待，见 `prepare_to_block`
+            current.acquire_inner_lock().wakeup_pending = false;
+            if inner.tasks.iter().flatten().all(|task| Arc::ptr_eq(task, current)) {
+                inner.group_exit_waiter = None;
+                return;
+            }
+            inner.group_exit_waiter = Some(Arc::clone(current));
+            drop(inner);
+            block_current_and_run_next();
+        }
+    }
+
+    /// execve 时终止其余线程并等待它们退出，调用线程接替主线程的 tid（即 pid）成为主线程
+    /// 进程已在整体退出时返回 false
+    fn de_thread(&self, current: &Arc<TaskControlBlock>) -> bool {
+        {
+            let mut inner = self.acquire_inner_lock();
+            if inner.group_exit {
+                return false;
+            }
+            inner.group_exit = true;
+        }
+        self.zap_other_threads(current);
+        self.wait_other_threads(current);
+
+        // 原主线程已退出并释放了它的用户资源，其 tid 仍由进程作为 pid 持有
+        let mut current_inner = current.acquire_inner_lock();
+        let res = current_inner.res.as_mut().unwrap();
+        let old_tid = if res.rel_tid != 0 {
+            res.rel_tid = 0;
+            Some(core::mem::replace(&mut res.tid, Arc::clone(&self.pid)))
+        } else {
+            None
+        };
+        drop(current_inner);
+        if let Some(old_tid) = old_tid {
+            remove_from_tid2task(old_tid.0);
+            insert_into_tid2task(self.getpid(), Arc::clone(current));
+        }
+
+        let mut inner = self.acquire_inner_lock();
+        inner.tasks.clear();
+        inner.tasks.push(Some(Arc::clone(current)));
+        inner.group_exit = false;
+        true
     }
 
     pub fn clone_thread(
@@ -424,35 +774,49 @@ impl ProcessControlBlock {
         flags: CloneFlags,
         stack: usize,
         newtls: usize,
-    ) -> Arc<TaskControlBlock> {
-        let pid = self.getpid();
+    ) -> Option<Arc<TaskControlBlock>> {
+        let tid = Arc::new(tid_alloc()?);
         // only the main thread can create a sub-thread
         assert_eq!(parent_task.acquire_inner_lock().get_relative_tid(), 0);
         // create main thread of child process
         let task = Arc::new(TaskControlBlock::new(
             Arc::clone(self),
+            tid,
             parent_task
                 .acquire_inner_lock()
                 .res
                 .as_ref()
                 .unwrap()
                 .ustack_base(),
-            pid as isize,
             // mention that we allocate a new kstack / ustack / trap_cx here
-            true,
+            None,
         ));
         insert_into_tid2task(task.acquire_inner_lock().gettid(), Arc::clone(&task));
+        let parent_sched = *parent_task.sched.lock();
+        *task.sched.lock() = SchedEntity::inherit(&parent_sched);
+        let parent_sigmask = parent_task.acquire_inner_lock().sigmask;
+        task.acquire_inner_lock().sigmask = parent_sigmask;
 
         // attach task to process
+        // 占用 tasks 中第一个空位，已退出线程的位置会被重用
         let mut process_inner = self.acquire_inner_lock();
-        let task_inner = task.acquire_inner_lock();
-        let task_rel_tid = task_inner.get_relative_tid();
-        let tasks = &mut process_inner.tasks;
-
-        while tasks.len() < task_rel_tid + 1 {
-            tasks.push(None);
+        let mut task_inner = task.acquire_inner_lock();
+        if process_inner.group_exit {
+            // 已错过 zap_other_threads，首次陷入内核时随进程退出
+            task_inner.killed = true;
+            task_inner.add_signal(SIGKILL);
         }
+        let tasks = &mut process_inner.tasks;
+        let task_rel_tid = match tasks.iter().position(Option::is_none) {
+            Some(idx) => idx,
+            None => {
+                tasks.push(None);
+                tasks.len() - 1
+            }
+        };
+        task_inner.res.as_mut().unwrap().rel_tid = task_rel_tid;
         tasks[task_rel_tid] = Some(Arc::clone(&task));
+        drop(process_inner);
         let trap_cx = task_inner.get_trap_cx();
 
         // copy trap_cx from the parent thread
@@ -473,7 +837,7 @@ impl ProcessControlBlock {
         // add this thread to scheduler
         add_task(Arc::clone(&task));
 
-        task
+        Some(task)
         // child
     }
 
@@ -491,207 +855,75 @@ impl ProcessControlBlock {
         // 目前mmap区域只能不断向上增长，无回收重整内存
         // 目前不检查fd是否合法
         // assert!(is_aligned(start) && is_aligned(len));
-        let mut inner = self.acquire_inner_lock();
+        let inner = self.acquire_inner_lock();
+        let mut mm = inner.mm.lock();
+        let mmap_flags = MmapFlags::from_bits_truncate(flags);
+        let hugetlb = mmap_flags.contains(MmapFlags::MAP_HUGETLB);
+        // MAP_HUGETLB 的长度按大页取整
+        let len = if hugetlb {
+            huge_aligned_up(len)
+        } else {
+            aligned_up(len)
+        };
         let start = if start != 0 {
             aligned_up(start)
-        }else {
-            inner.mmap_area_top
+        } else if hugetlb || (fd == -1 && len >= HUGE_PAGE_SIZE) {
+            // 较大的匿名映射按2MiB对齐，以便缺页时使用大页
+            huge_aligned_up(mm.mmap_area_top)
+        } else {
+            mm.mmap_area_top
         };
-        let len = aligned_up(len);
-        // assert_eq!(start, inner.mmap_area_top);
+        // assert_eq!(start, mm.mmap_area_top);
 
         let start_vpn = VirtAddr::from(start).floor();
         let end_vpn = VirtAddr::from(start + len).floor();
         let map_perm = MapPermission::from_bits((prot << 1) as u8).unwrap() | MapPermission::U;
-        let mmap_flags = MmapFlags::from_bits(flags).unwrap();
         // TODO
         let mmap_fdone: crate::mm::FdOne; // = inner.fd_table[fd as usize].clone();
         if fd == -1 {
             // 转发到fd2, 标准错误输出
-            mmap_fdone = inner.fd_table[2].clone();
+            mmap_fdone = inner.fd_table.lock()[2].clone();
         } else {
-            mmap_fdone = inner.fd_table[fd as usize].clone();
+            mmap_fdone = inner.fd_table.lock()[fd as usize].clone();
         }
         let fixed = mmap_flags.contains(MmapFlags::MAP_FIXED);
         // println!("mmap_flags: {:#?} , flags: 0x{:x}",mmap_flags,flags);
+        let data = map_perm.contains(MapPermission::W) && !mmap_flags.contains(MmapFlags::MAP_SHARED);
+        if !mm.may_expand_vm(&inner.rlimits, end_vpn.0 - start_vpn.0, data) {
+            return -ENOMEM;
+        }
 
-        if !fixed {
-            // 一般情况mmap,注意，此处不判断fd是否有效
-            inner.memory_set.push_mmap_area(MmapArea::new(
-                start_vpn,
-                end_vpn,
-                map_perm,
-                flags,
-                mmap_fdone,
-                fd as usize,
-                offset,
-            ));
-        } else {
-            // fixed 区域
-            //TODO 可能有部分区间重叠情况考虑不到位
-            // println!("fixed handle start ...");
-            // println!("[new mmap in] start_vpn:{:#?}  end_vpn:{:#?}",start_vpn,end_vpn);
-            // let mut collision = false;
-            let mut old_perm = MapPermission::U;
-            let mut old_start = VirtAddr::from(0).floor();
-            let mut old_end = VirtAddr::from(0).floor();
-            let mut old_flags = 0usize;
-            let mut old_fdone = mmap_fdone.clone();
-            let mut old_fd = 0usize;
-            let mut old_offset = 0usize;
-            loop {
-                let mut loop_flag = true;
-                // let mut index = 0;
-                // for (i,mmap_area) in inner.memory_set.mmap_areas.iter().enumerate(){
-                for mmap_area in inner.memory_set.mmap_areas.iter() {
-                    // 在此处提取old_area相关信息
-                    // 1                  1
-                    // fix area        |----- - -
-                    // old area           |----|
-                    // 3                    3
-                    // fix area          |-- - - -
-                    // old area        |-----|
-                    if (start_vpn < mmap_area.vpn_range.get_start() && end_vpn > mmap_area.vpn_range.get_start())
-                        || (start_vpn >= mmap_area.vpn_range.get_start() && start_vpn < mmap_area.vpn_range.get_end())
-                    {
-                        // index = i;
-                        old_perm = mmap_area.map_perm;
-                        old_start = mmap_area.vpn_range.get_start();
-                        old_end = mmap_area.vpn_range.get_end();
-                        old_flags = mmap_area.flags;
-                        old_offset = mmap_area.offset;
-                        old_fdone = mmap_area.fd_one.clone();
-                        old_fd = mmap_area.fd;
-                        loop_flag = false;
-                        // collision = true;
-                    }
-                }
-
-                if loop_flag {
-                    // println!("break ...");
-                    break;
-                }
-                // println!("fixed handle real start ...");
-                inner.memory_set.remove_mmap_area_with_start_vpn(old_start);
-                // fix area        |-----|
-                // old area           |----|
-                if start_vpn <= old_start && end_vpn > old_start && end_vpn < old_end {
-                    // println!("fixed situation 1");
-                    let u_old_start: usize = old_start.into();
-                    // 向上取整页
-                    old_offset = old_offset
-                        + ((len + start - u_old_start + PAGE_SIZE - 1) / PAGE_SIZE) * PAGE_SIZE;
-                    old_start = VirtAddr::from(start + len).ceil();
-                    // println!("[part-1]fixed situation 1  start_vpn:{:#?}  end_vpn:{:#?}",old_start,old_end);
-                    // println!("[part-2]fixed situation 1  start_vpn:{:#?}  end_vpn:{:#?}",start_vpn,end_vpn);
-                    inner.memory_set.push_mmap_area(MmapArea::new(
-                        old_start,
-                        old_end,
-                        old_perm,
-                        old_flags,
-                        old_fdone.clone(),
-                        old_fd,
-                        old_offset,
-                    ));
-                } else
-                // fix area        |----------|
-                // old area           |----|
-                // 刚好完全覆盖的情况也在此处
-                if start_vpn <= old_start && end_vpn >= old_end {
-                    // println!("fixed situation 2");
-                    // println!("[part-2]fixed situation 2  start_vpn:{:#?}  end_vpn:{:#?}",start_vpn,end_vpn);
-                } else
-                // fix area          |--|
-                // old area        |-----|
-                if start_vpn >= old_start && end_vpn <= old_end {
-                    // println!("fixed situation 3");
-                    if end_vpn != old_end {
-                        // 向上取整页
-                        let u_old_start: usize = old_start.into();
-                        let part3_offset = old_offset
-                            + ((len + start - u_old_start + PAGE_SIZE - 1) / PAGE_SIZE) * PAGE_SIZE;
-                        let part3_start = VirtAddr::from(start + len).ceil();
-                        let part3_end = old_end;
-                        let part3_perm = old_perm;
-                        let part3_flags = old_flags;
-                        let part3_fd = old_fd;
-                        // println!("[part-3]fixed situation 3  start_vpn:{:#?}  end_vpn:{:#?}",part3_start,part3_end);
-                        inner.memory_set.push_mmap_area(MmapArea::new(
-                            part3_start,
-                            part3_end,
-                            part3_perm,
-                            part3_flags,
-                            old_fdone.clone(),
-                            part3_fd,
-                            part3_offset,
-                        ));
-                    }
-
-                    // println!("[part-2]fixed situation 3  start_vpn:{:#?}  end_vpn:{:#?}",start_vpn,end_vpn);
-                    if start_vpn != old_start {
-                        // 原区域作为第一段
-                        old_end = VirtAddr::from(start + PAGE_SIZE - 1).floor();
-                        // println!("[part-1]fixed situation 3  start_vpn:{:#?}  end_vpn:{:#?}",old_start,old_end);
-                        inner.memory_set.push_mmap_area(MmapArea::new(
-                            old_start,
-                            old_end,
-                            old_perm,
-                            old_flags,
-                            old_fdone.clone(),
-                            old_fd,
-                            old_offset,
-                        ));
-                    }
-                } else
-                // fix area          |-------|
-                // old area        |-----|
-                if start_vpn > old_start && end_vpn > old_end {
-                    // println!("fixed situation 4");
-                    // 原区域作为第一段
-                    old_end = VirtAddr::from(start + PAGE_SIZE - 1).floor();
-                    // println!("[part-1]fixed situation 4  start_vpn:{:#?}  end_vpn:{:#?}",old_start,old_end);
-                    // println!("[part-2]fixed situation 4  start_vpn:{:#?}  end_vpn:{:#?}",start_vpn,end_vpn);
-                    inner.memory_set.push_mmap_area(MmapArea::new(
-                        old_start,
-                        old_end,
-                        old_perm,
-                        old_flags,
-                        old_fdone.clone(),
-                        old_fd,
-                        old_offset,
-                    ));
-                }
-            }
-            inner.memory_set.push_mmap_area(MmapArea::new(
-                start_vpn,
-                end_vpn,
-                map_perm,
-                flags,
-                mmap_fdone,
-                fd as usize,
-                offset,
-            ));
-            // if !collision {
-            //     println!("[mmap-fixed handle] no collision start_vpn:{:#?}  end_vpn:{:#?}  perm :{:#?}",start_vpn,end_vpn,map_perm);
-            // }
+        if fixed {
+            // fixed 区域先解除与之重叠的旧映射，旧区域被拆分保留两侧
+            mm.memory_set.munmap(start_vpn, end_vpn);
         }
+        // 注意，此处不判断fd是否有效
+        mm.memory_set.push_mmap_area(MmapArea::new(
+            start_vpn,
+            end_vpn,
+            map_perm,
+            flags,
+            mmap_fdone,
+            fd as usize,
+            offset,
+        ));
         // 维护最高mmap区域地址值
-        if inner.mmap_area_top < VirtAddr::from(end_vpn).0 {
-            inner.mmap_area_top = VirtAddr::from(end_vpn).0;
+        if mm.mmap_area_top < VirtAddr::from(end_vpn).0 {
+            mm.mmap_area_top = VirtAddr::from(end_vpn).0;
         }
 
         start as isize
     }
 
-    pub fn munmap(&self, start: usize, _len: usize) -> isize {
+    pub fn munmap(&self, start: usize, len: usize) -> isize {
         // assert!(is_aligned(start));
-        let mut inner = self.acquire_inner_lock();
         let start_vpn = VirtAddr::from(start).floor();
-        inner.memory_set.remove_mmap_area_with_start_vpn(start_vpn)
+        let end_vpn = VirtAddr::from(start + len).ceil();
+        self.acquire_inner_lock().mm.lock().memory_set.munmap(start_vpn, end_vpn)
     }
 }
 
-impl ProcessControlBlockInner {
+impl ProcessMm {
     fn lazy_alloc_mmap_page(&mut self, vaddr: usize) -> isize {
         // let vpn = VirtAddr::from(vaddr).floor();
         // self.memory_set.insert_mmap_dataframe(vpn)
@@ -724,14 +956,16 @@ impl ProcessControlBlockInner {
         }
         let heap_base = self.user_heap_base;
         let heap_top = self.user_heap_top;
+        let mmap_base = self.mmap_area_base;
         let mmap_top = self.mmap_area_top;
         let mut ret:isize = 0;
+        let mut cow = false;
         if is_load {
             if vaddr >= heap_base && vaddr < heap_top {
                     // println!("[kernel] lazy_alloc heap memory {:#x?}", vaddr);
                     // println!("is_load? {:#x?}", is_load);
                     ret = self.lazy_alloc_heap_page(vaddr);
-                } else if vaddr >= MMAP_BASE && vaddr < mmap_top {
+                } else if vaddr >= mmap_base && vaddr < mmap_top {
                     // println!("[kernel] lazy_alloc mmap memory {:#x?}", vaddr);
                     // println!("is_load? {:#x?}", is_load);
                     ret = self.lazy_alloc_mmap_page(vaddr);
@@ -746,13 +980,14 @@ impl ProcessControlBlockInner {
                     // cow_alloc(vpn, former_ppn);
                     let former_ppn = pte.ppn();
                     self.memory_set.cow_alloc(vpn, former_ppn, vaddr >= heap_base && vaddr < heap_top);
+                    cow = true;
                     ret = 0;
                 }else if !pte.is_valid() {
                     if vaddr >= heap_base && vaddr < heap_top {
                         // println!("[kernel] lazy_alloc heap memory {:#x?}", vaddr);
                         // println!("is_load? {:#x?}", is_load);
                         ret = self.lazy_alloc_heap_page(vaddr);
-                    } else if vaddr >= MMAP_BASE && vaddr < mmap_top {
+                    } else if vaddr >= mmap_base && vaddr < mmap_top {
                         // println!("[kernel] lazy_alloc mmap memory {:#x?}", vaddr);
                         // println!("is_load? {:#x?}", is_load);
                         ret = self.lazy_alloc_mmap_page(vaddr);
@@ -768,7 +1003,7 @@ impl ProcessControlBlockInner {
                     // println!("[kernel] lazy_alloc heap memory {:#x?}", vaddr);
                     // println!("is_load? {:#x?}", is_load);
                     ret = self.lazy_alloc_heap_page(vaddr);
-                } else if vaddr >= MMAP_BASE && vaddr < mmap_top {
+                } else if vaddr >= mmap_base && vaddr < mmap_top {
                     // println!("[kernel] lazy_alloc mmap memory {:#x?}", vaddr);
                     // println!("is_load? {:#x?}", is_load);
                     ret = self.lazy_alloc_mmap_page(vaddr);
@@ -779,6 +1014,10 @@ impl ProcessControlBlockInner {
         }
 
         if ret == 0 {
+            // 写时复制已在 cow_alloc 中计数
+            if !cow {
+                self.memory_set.record_fault(VirtAddr::from(vaddr).floor());
+            }
             unsafe {
                 asm!("sfence.vma");
                 asm!("fence.i");
@@ -786,4 +1025,31 @@ impl ProcessControlBlockInner {
         }
         ret
     }
+
+    /// 内核直接访问用户内存前确保 [start, start + len) 均为用户页面：
+    /// 先分配惰性映射的页面，写入时还要先完成写时复制，失败时返回 false
+    pub fn prepare_user_access(&mut self, start: usize, len: usize, write: bool) -> bool {
+        let end = match start.checked_add(len) {
+            Some(end) => end,
+            None => return false,
+        };
+        let accessible = |pte: PageTableEntry| {
+            pte.is_valid()
+                && pte.flags().contains(PTEFlags::U)
+                && (!write || (pte.writable() && !pte.is_cow()))
+        };
+        let mut va = aligned_down(start);
+        while va < end {
+            let vpn = VirtAddr::from(va).floor();
+            if !self.memory_set.translate(vpn).map_or(false, accessible) {
+                let fixed = self.check_lazy(va, !write) == 0
+                    && self.memory_set.translate(vpn).map_or(false, accessible);
+                if !fixed {
+                    return false;
+                }
+            }
+            va += PAGE_SIZE;
+        }
+        true
+    }
 }
@@ -1,7 +1,8 @@
 use core::cell::{RefCell, RefMut};
 
-use super::{__switch, add_task};
-use super::{fetch_task, TaskStatus};
+use super::{__switch, add_task, block_task};
+use super::idle::idle_wait;
+use super::{fetch_task, mark_hart_online, TaskStatus};
 use super::{ProcessControlBlock, TaskContext, TaskControlBlock};
 
 use crate::board::MAX_CPU_NUM;
@@ -87,6 +88,7 @@ pub static PROCESSORS: Lazy<[Processor; MAX_CPU_NUM]> = Lazy::new(|| {
 });
 
 pub fn run_tasks() {
+    mark_hart_online(get_hartid());
     loop {
         let mut processor = PROCESSORS[get_hartid()].inner_exclusive_access();
 
@@ -94,13 +96,22 @@ pub fn run_tasks() {
         // 但是若如此做，则内核栈会被其他核“趁虚而入”
         // 将suspend_current_and_run_next中的add_task延后到调度完成后
         if let Some(last_task) = processor.take_current() {
-            // Do not enqueue blocking tasks!
-            if last_task.acquire_inner_lock().task_status == TaskStatus::Ready {
-                add_task(last_task);
+            // 结算上一个任务的运行时间，更新其 vruntime
+            last_task.sched.lock().stop_running();
+            let status = last_task.acquire_inner_lock().task_status;
+            match status {
+                TaskStatus::Ready => add_task(last_task),
+                TaskStatus::Blocking => block_task(last_task),
+                // 已退出的任务在这里释放其内核栈，此时已不在其上运行
+                _ => {}
             }
         }
 
         if let Some(task) = fetch_task() {
+            // 已退出的线程不再运行
+            if task.acquire_inner_lock().res.is_none() {
+                continue;
+            }
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
 
             unsafe {
@@ -113,6 +124,7 @@ pub fn run_tasks() {
             task_inner.task_status = TaskStatus::Running;
             task_inner.__save_info_to_fast_access();
             drop(task_inner);
+            task.sched.lock().start_running();
             // release coming task TCB manually
             // println!("[cpu {}] switch to process {}", get_hartid(), task.process.upgrade().unwrap().getpid());
             processor.current = Some(task);
@@ -122,10 +134,14 @@ pub fn run_tasks() {
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
+        } else {
+            drop(processor);
+            idle_wait();
         }
     }
 }
 
+#[allow(unused)]
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
     PROCESSORS[get_hartid()]
         .inner_exclusive_access()
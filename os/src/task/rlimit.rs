@@ -0,0 +1,80 @@
+//! 进程的资源限制，fork 时复制，exec 后保留
+
+use super::{current_process, send_signal_process, SigInfo, SIGKILL, SIGXCPU, SI_KERNEL};
+use crate::config::{FDMAX, PID_MAX, SIGPENDING_MAX, USER_STACK_SIZE};
+use crate::timer::NSEC_PER_SEC;
+
+pub const RLIMIT_CPU: usize = 0;
+pub const RLIMIT_FSIZE: usize = 1;
+pub const RLIMIT_DATA: usize = 2;
+pub const RLIMIT_STACK: usize = 3;
+pub const RLIMIT_CORE: usize = 4;
+pub const RLIMIT_RSS: usize = 5;
+pub const RLIMIT_NPROC: usize = 6;
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIMIT_MEMLOCK: usize = 8;
+pub const RLIMIT_AS: usize = 9;
+pub const RLIMIT_LOCKS: usize = 10;
+pub const RLIMIT_SIGPENDING: usize = 11;
+pub const RLIMIT_MSGQUEUE: usize = 12;
+pub const RLIMIT_NICE: usize = 13;
+pub const RLIMIT_RTPRIO: usize = 14;
+pub const RLIMIT_RTTIME: usize = 15;
+pub const RLIM_NLIMITS: usize = 16;
+
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// 与 Linux 的 struct rlimit64 布局一致
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RLimit64 {
+    pub rlim_cur: usize,
+    pub rlim_max: usize,
+}
+
+impl RLimit64 {
+    pub const fn new(rlim_cur: usize, rlim_max: usize) -> Self {
+        Self { rlim_cur, rlim_max }
+    }
+}
+
+pub type RLimits = [RLimit64; RLIM_NLIMITS];
+
+/// initproc 的资源限制，其余进程均由其继承
+pub fn default_rlimits() -> RLimits {
+    let mut rlimits = [RLimit64::new(RLIM_INFINITY, RLIM_INFINITY); RLIM_NLIMITS];
+    // 用户栈预先映射，不能超过 USER_STACK_SIZE
+    rlimits[RLIMIT_STACK] = RLimit64::new(USER_STACK_SIZE, RLIM_INFINITY);
+    rlimits[RLIMIT_CORE] = RLimit64::new(0, RLIM_INFINITY);
+    rlimits[RLIMIT_NPROC] = RLimit64::new(PID_MAX, PID_MAX);
+    rlimits[RLIMIT_NOFILE] = RLimit64::new(FDMAX + 1, FDMAX + 1);
+    rlimits[RLIMIT_MEMLOCK] = RLimit64::new(8 << 20, 8 << 20);
+    rlimits[RLIMIT_SIGPENDING] = RLimit64::new(SIGPENDING_MAX, SIGPENDING_MAX);
+    rlimits[RLIMIT_MSGQUEUE] = RLimit64::new(819200, 819200);
+    rlimits[RLIMIT_NICE] = RLimit64::new(0, 0);
+    rlimits[RLIMIT_RTPRIO] = RLimit64::new(0, 0);
+    rlimits
+}
+
+/// 时钟中断时检查当前进程的 CPU 时间（秒）
+/// 达到软限制时发送 SIGXCPU，并与 Linux 相同将软限制加一秒，以便每秒提醒一次；达到硬限制时发送 SIGKILL
+pub fn check_cpu_rlimit() {
+    let process = current_process();
+    let mut inner = process.acquire_inner_lock();
+    let limit = inner.rlimits[RLIMIT_CPU];
+    if limit.rlim_cur == RLIM_INFINITY {
+        return;
+    }
+    let times = inner.cpu_times();
+    let secs = ((times.utime + times.stime) / NSEC_PER_SEC as u64) as usize;
+    let signum = if secs >= limit.rlim_max {
+        SIGKILL
+    } else if secs >= limit.rlim_cur {
+        inner.rlimits[RLIMIT_CPU].rlim_cur = limit.rlim_cur + 1;
+        SIGXCPU
+    } else {
+        return;
+    };
+    drop(inner);
+    send_signal_process(&process, SigInfo::new(signum, SI_KERNEL));
+}
@@ -0,0 +1,207 @@
+//! 调度实体与调度策略
+//! SCHED_OTHER 按 CFS 方式以虚拟运行时间 (vruntime) 排序，nice 值决定权重；
+//! SCHED_FIFO / SCHED_RR 为实时策略，总是优先于 SCHED_OTHER 运行
+
+use crate::board::MAX_CPU_NUM;
+use crate::multicore::get_hartid;
+use crate::timer::get_time_ns;
+
+pub const SCHED_OTHER: usize = 0;
+pub const SCHED_FIFO: usize = 1;
+pub const SCHED_RR: usize = 2;
+
+pub const MIN_NICE: isize = -20;
+pub const MAX_NICE: isize = 19;
+pub const MIN_RT_PRIO: usize = 1;
+pub const MAX_RT_PRIO: usize = 99;
+
+/// 所有 hart 组成的 CPU 掩码
+pub const ALL_CPUS_MASK: usize = (1 << MAX_CPU_NUM) - 1;
+
+/// nice 为 0 时的权重
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// 被唤醒的任务最多获得的 vruntime 补偿 (ns)，避免长时间睡眠的任务独占CPU
+pub const SCHED_LATENCY_NS: u64 = 6_000_000;
+
+/// 普通任务每次至少运行的时间 (ns)，取一个时钟周期
+pub const SCHED_MIN_GRANULARITY_NS: u64 = 10_000_000;
+
+/// 就绪任务按权重分配的调度周期 (ns)，任务较多时延长为每个任务至少 SCHED_MIN_GRANULARITY_NS
+const SCHED_PERIOD_NS: u64 = 40_000_000;
+
+/// 与 Linux 相同的 nice -> 权重表，相邻 nice 值的 CPU 份额相差约 10%
+const PRIO_TO_WEIGHT: [u64; 40] = [
+    /* -20 */ 88761, 71755, 56483, 46273, 36291,
+    /* -15 */ 29154, 23254, 18705, 14949, 11916,
+    /* -10 */ 9548, 7620, 6100, 4904, 3906,
+    /*  -5 */ 3121, 2501, 1991, 1586, 1277,
+    /*   0 */ 1024, 820, 655, 526, 423,
+    /*   5 */ 335, 272, 215, 172, 137,
+    /*  10 */ 110, 87, 70, 56, 45,
+    /*  15 */ 36, 29, 23, 18, 15,
+];
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SchedPolicy {
+    Other,
+    Fifo,
+    Rr,
+}
+
+impl SchedPolicy {
+    pub fn from_raw(policy: usize) -> Option<Self> {
+        match policy {
+            SCHED_OTHER => Some(Self::Other),
+            SCHED_FIFO => Some(Self::Fifo),
+            SCHED_RR => Some(Self::Rr),
+            _ => None,
+        }
+    }
+
+    pub fn to_raw(self) -> usize {
+        match self {
+            Self::Other => SCHED_OTHER,
+            Self::Fifo => SCHED_FIFO,
+            Self::Rr => SCHED_RR,
+        }
+    }
+
+    pub fn is_rt(self) -> bool {
+        self != Self::Other
+    }
+}
+
+/// 每个线程的调度信息，单独加锁，持有该锁时不再获取其他锁
+#[derive(Copy, Clone, Debug)]
+pub struct SchedEntity {
+    pub policy: SchedPolicy,
+    pub nice: isize,
+    /// 实时优先级，1~99，越大越优先；SCHED_OTHER 为 0
+    pub rt_priority: usize,
+    pub vruntime: u64,
+    /// 累计实际运行时间 (ns)
+    pub sum_exec_runtime: u64,
+    /// 其中在用户态运行的时间 (ns)
+    pub utime: u64,
+    /// 本次开始运行的时刻，未运行时为 None
+    exec_start: Option<u64>,
+    /// 本次返回用户态的时刻，在内核中时为 None
+    user_start: Option<u64>,
+    /// 尚未入过就绪队列的新任务
+    pub fresh: bool,
+    /// 允许运行的 hart 掩码
+    pub cpus_allowed: usize,
+    /// 最近一次运行所在的 hart
+    pub cpu: usize,
+    /// 正在 hart 间迁移，vruntime 暂存为相对源队列 min_vruntime 的值
+    pub migrated: bool,
+}
+
+impl SchedEntity {
+    pub fn new() -> Self {
+        Self {
+            policy: SchedPolicy::Other,
+            nice: 0,
+            rt_priority: 0,
+            vruntime: 0,
+            sum_exec_runtime: 0,
+            utime: 0,
+            exec_start: None,
+            user_start: None,
+            fresh: true,
+            cpus_allowed: ALL_CPUS_MASK,
+            cpu: get_hartid(),
+            migrated: false,
+        }
+    }
+
+    /// fork/clone 时继承父线程的调度策略和优先级，运行时间从头计算
+    pub fn inherit(parent: &SchedEntity) -> Self {
+        Self {
+            policy: parent.policy,
+            nice: parent.nice,
+            rt_priority: parent.rt_priority,
+            vruntime: parent.vruntime,
+            cpus_allowed: parent.cpus_allowed,
+            cpu: parent.cpu,
+            ..Self::new()
+        }
+    }
+
+    pub fn weight(&self) -> u64 {
+        PRIO_TO_WEIGHT[(self.nice - MIN_NICE) as usize]
+    }
+
+    /// 本次已连续运行的时间 (ns)
+    pub fn exec_runtime(&self) -> u64 {
+        self.exec_start
+            .map_or(0, |start| (get_time_ns() as u64).saturating_sub(start))
+    }
+
+    /// 计入本次运行时间的 vruntime，正在运行的任务要到让出CPU时才结算
+    pub fn current_vruntime(&self) -> u64 {
+        self.vruntime + self.exec_runtime() * NICE_0_WEIGHT / self.weight()
+    }
+
+    pub fn set_nice(&mut self, nice: isize) {
+        self.nice = nice.max(MIN_NICE).min(MAX_NICE);
+    }
+
+    pub fn allowed_on(&self, hartid: usize) -> bool {
+        self.cpus_allowed & (1 << hartid) != 0
+    }
+
+    pub fn start_running(&mut self) {
+        self.cpu = get_hartid();
+        self.exec_start = Some(get_time_ns() as u64);
+    }
+
+    /// 结算本次运行时间，按权重折算为虚拟运行时间
+    pub fn stop_running(&mut self) {
+        if let Some(start) = self.exec_start.take() {
+            let delta = (get_time_ns() as u64).saturating_sub(start);
+            self.sum_exec_runtime += delta;
+            self.vruntime += delta * NICE_0_WEIGHT / self.weight();
+        }
+    }
+
+    /// 从内核返回用户态
+    pub fn enter_user(&mut self) {
+        self.user_start = Some(get_time_ns() as u64);
+    }
+
+    /// 陷入内核时结算用户态运行时间
+    pub fn leave_user(&mut self) {
+        if let Some(start) = self.user_start.take() {
+            self.utime += (get_time_ns() as u64).saturating_sub(start);
+        }
+    }
+
+    pub fn cpu_times(&self) -> CpuTimes {
+        CpuTimes {
+            utime: self.utime,
+            stime: self.sum_exec_runtime.saturating_sub(self.utime),
+        }
+    }
+}
+
+/// 用户态与内核态的运行时间 (ns)，用于 getrusage 与 wait4
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CpuTimes {
+    pub utime: u64,
+    pub stime: u64,
+}
+
+impl CpuTimes {
+    pub fn accumulate(&mut self, other: &CpuTimes) {
+        self.utime += other.utime;
+        self.stime += other.stime;
+    }
+}
+
+/// 权重为 weight 的任务在 nr 个就绪任务（权重之和为 total_weight）中分得的时间片 (ns)
+pub fn sched_slice(weight: u64, total_weight: u64, nr: usize) -> u64 {
+    let period = SCHED_PERIOD_NS.max(nr as u64 * SCHED_MIN_GRANULARITY_NS);
+    (period * weight / total_weight).max(SCHED_MIN_GRANULARITY_NS)
+}
@@ -1,3 +1,4 @@
+use alloc::collections::VecDeque;
 use core::mem::size_of;
 
 pub const MAX_SIGNUM: u32 = 64;
@@ -5,40 +6,127 @@ pub const MAX_SIGNUM: u32 = 64;
 pub const SIG_DFL: usize = 0;
 pub const SIG_IGN: usize = 1;
 
+pub const SIGHUP: u32 = 1;
 pub const SIGINT: u32 = 2;
+pub const SIGQUIT: u32 = 3;
 pub const SIGILL: u32 = 4;
+pub const SIGTRAP: u32 = 5;
 pub const SIGABRT: u32 = 6;
+pub const SIGBUS: u32 = 7;
 pub const SIGFPE: u32 = 8;
 pub const SIGKILL: u32 = 9;
+pub const SIGUSR1: u32 = 10;
 pub const SIGSEGV: u32 = 11;
+pub const SIGUSR2: u32 = 12;
+pub const SIGPIPE: u32 = 13;
+pub const SIGALRM: u32 = 14;
+pub const SIGTERM: u32 = 15;
+pub const SIGSTKFLT: u32 = 16;
+pub const SIGCHLD: u32 = 17;
+pub const SIGCONT: u32 = 18;
+pub const SIGSTOP: u32 = 19;
+pub const SIGTSTP: u32 = 20;
+pub const SIGTTIN: u32 = 21;
+pub const SIGTTOU: u32 = 22;
+pub const SIGURG: u32 = 23;
+pub const SIGXCPU: u32 = 24;
+pub const SIGXFSZ: u32 = 25;
+pub const SIGVTALRM: u32 = 26;
+pub const SIGPROF: u32 = 27;
+pub const SIGWINCH: u32 = 28;
+pub const SIGIO: u32 = 29;
+pub const SIGPWR: u32 = 30;
+pub const SIGSYS: u32 = 31;
+/// 实时信号
+pub const SIGRTMIN: u32 = 32;
+pub const SIGRTMAX: u32 = MAX_SIGNUM;
 
+pub fn is_rt_signal(signum: u32) -> bool {
+    signum >= SIGRTMIN
+}
+
+/// 信号的默认动作
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SigDefault {
+    /// 终止进程
+    Term,
+    /// 终止进程并转储内存（不生成 core 文件，仅在退出状态中标记）
+    Core,
+    Ign,
+    /// 停止进程的所有线程
+    Stop,
+    /// 继续运行已停止的进程
+    Cont,
+}
+
+pub fn sig_default_action(signum: u32) -> SigDefault {
+    match signum {
+        SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE | SIGSEGV | SIGXCPU | SIGXFSZ
+        | SIGSYS => SigDefault::Core,
+        SIGCHLD | SIGURG | SIGWINCH => SigDefault::Ign,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => SigDefault::Stop,
+        SIGCONT => SigDefault::Cont,
+        // 其余标准信号及实时信号默认终止进程
+        _ => SigDefault::Term,
+    }
+}
+
+pub fn is_stop_signal(signum: u32) -> bool {
+    sig_default_action(signum) == SigDefault::Stop
+}
+
+/// SIGKILL 与 SIGSTOP 不能被捕获、忽略或屏蔽
+pub fn is_signal_catchable(signum: u32) -> bool {
+    signum != SIGKILL && signum != SIGSTOP
+}
+
+/// 转换为 wait 系列系统调用的状态编码
+pub fn exited_status(exit_code: i32) -> i32 {
+    (exit_code & 0xff) << 8
+}
+
+pub fn signaled_status(signum: u32, core_dump: bool) -> i32 {
+    signum as i32 | if core_dump { 0x80 } else { 0 }
+}
+
+pub fn stopped_status(signum: u32) -> i32 {
+    ((signum as i32) << 8) | 0x7f
+}
+
+pub const CONTINUED_STATUS: i32 = 0xffff;
+
+/// 由 wait 状态编码得到 SIGCHLD 与 waitid 的 si_code 与 si_status
+pub fn child_status_code(status: i32) -> (i32, i32) {
+    match status & 0x7f {
+        0 => (CLD_EXITED, (status >> 8) & 0xff),
+        0x7f if status == CONTINUED_STATUS => (CLD_CONTINUED, SIGCONT as i32),
+        0x7f => (CLD_STOPPED, (status >> 8) & 0xff),
+        signum if status & 0x80 != 0 => (CLD_DUMPED, signum),
+        signum => (CLD_KILLED, signum),
+    }
+}
+
+/// 与 Linux riscv64 的 struct sigaction 布局一致（无 sa_restorer）
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct SigAction {
     pub sa_handler: usize,
-    pub sa_sigaction: usize,
-    pub sa_mask: u64,
     pub sa_flags: SAFlags,
-    pub sa_restorer: usize,
+    pub sa_mask: u64,
 }
 
 impl SigAction {
     pub fn new() -> Self {
         Self {
-            sa_handler: 0,
-            sa_sigaction: 0,
-            sa_mask: 0,
+            sa_handler: SIG_DFL,
             sa_flags: SAFlags::empty(),
-            sa_restorer: 0,
+            sa_mask: 0,
         }
     }
-
-    pub fn is_valid(&self) -> bool {
-        self.sa_handler == 0 && self.sa_sigaction == 0 && self.sa_mask == 0
-    }
 }
+
 bitflags! {
-    pub struct SAFlags: u32 {
+    pub struct SAFlags: usize {
         const SA_NOCLDSTOP = 1;		 /* Don't send SIGCHLD when children stop.  */
         const SA_NOCLDWAIT = 2;		 /* Don't create zombie on child death.  */
         const SA_SIGINFO   = 4;  	 /* Invoke signal-catching function with
@@ -51,40 +139,261 @@ bitflags! {
     }
 }
 
+/// 信号编号为 1~MAX_SIGNUM，0 仅用于 kill 检查目标是否存在
 pub fn is_signal_valid(signum: u32) -> bool {
-    signum < MAX_SIGNUM
+    signum <= MAX_SIGNUM
+}
+
+/// 信号 signum 在待处理集合与信号掩码中对应的位，与用户态 sigset_t 一致
+pub fn sig_bit(signum: u32) -> u64 {
+    1 << (signum - 1)
+}
+
+/// SIGKILL 与 SIGSTOP 不能被屏蔽
+pub const UNBLOCKABLE_SIGNALS: u64 = 1 << (SIGKILL - 1) | 1 << (SIGSTOP - 1);
+
+/// si_code：信号的来源
+pub const SI_USER: i32 = 0;
+pub const SI_KERNEL: i32 = 0x80;
+pub const SI_QUEUE: i32 = -1;
+pub const SI_TIMER: i32 = -2;
+pub const SI_TKILL: i32 = -6;
+pub const ILL_ILLOPC: i32 = 1;
+pub const SEGV_MAPERR: i32 = 1;
+pub const SEGV_ACCERR: i32 = 2;
+pub const CLD_EXITED: i32 = 1;
+pub const CLD_KILLED: i32 = 2;
+pub const CLD_DUMPED: i32 = 3;
+pub const CLD_STOPPED: i32 = 5;
+pub const CLD_CONTINUED: i32 = 6;
+
+/// 与 Linux 的 siginfo_t 布局一致，共 128 字节
+/// fields 对应 si_signo 等三个字段之后的联合体
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SigInfo {
+    pub si_signo: i32,
+    pub si_errno: i32,
+    pub si_code: i32,
+    _pad: i32,
+    pub fields: [usize; 14],
+}
+
+impl SigInfo {
+    pub fn new(signum: u32, code: i32) -> Self {
+        Self {
+            si_signo: signum as i32,
+            si_errno: 0,
+            si_code: code,
+            _pad: 0,
+            fields: [0; 14],
+        }
+    }
+
+    /// kill、tkill 等由进程发送的信号，填写 si_pid 与 si_uid
+    pub fn from_sender(signum: u32, code: i32, pid: usize, uid: u32) -> Self {
+        let mut info = Self::new(signum, code);
+        info.fields[0] = (pid as u32 as usize) | (uid as usize) << 32;
+        info
+    }
+
+    /// 访存错误、非法指令等同步异常，si_addr 为出错地址
+    pub fn fault(signum: u32, code: i32, addr: usize) -> Self {
+        let mut info = Self::new(signum, code);
+        info.fields[0] = addr;
+        info
+    }
+
+    /// SIGCHLD，status 为退出码或导致状态变化的信号
+    pub fn child(code: i32, pid: usize, uid: u32, status: i32) -> Self {
+        let mut info = Self::from_sender(SIGCHLD, code, pid, uid);
+        info.fields[1] = status as u32 as usize;
+        info
+    }
+
+    pub fn signum(&self) -> u32 {
+        self.si_signo as u32
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        let size = size_of::<Self>();
+        unsafe { core::slice::from_raw_parts(self as *const _ as *const u8, size) }
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        let size = size_of::<Self>();
+        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as *mut u8, size) }
+    }
 }
 
-pub struct _MContext {
-    __gregs: [usize; 32],
+/// 线程或进程的待处理信号
+/// 标准信号不排队，重复发送时合并，只保留第一次发送时的信息；实时信号按发送顺序排队
+pub struct SigPending {
+    /// 待处理信号集合
+    pub signals: u64,
+    queue: VecDeque<SigInfo>,
 }
 
-pub struct _Signaltstack {
-    ss_sp: usize,
-    ss_flags: u32,
-    ss_size: usize,
+impl SigPending {
+    pub fn new() -> Self {
+        Self {
+            signals: 0,
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub fn add(&mut self, info: SigInfo) {
+        let signum = info.signum();
+        if !is_rt_signal(signum) && self.signals & sig_bit(signum) != 0 {
+            return;
+        }
+        self.signals |= sig_bit(signum);
+        self.queue.push_back(info);
+    }
+
+    /// 取出编号最小的不在 blocked 中的信号
+    pub fn fetch(&mut self, blocked: u64) -> Option<SigInfo> {
+        let deliverable = self.signals & !blocked;
+        if deliverable == 0 {
+            return None;
+        }
+        Some(self.dequeue(deliverable.trailing_zeros() + 1))
+    }
+
+    /// 取出信号 signum 最早的一项，队列中没有其他同号信号时才清除待处理位
+    fn dequeue(&mut self, signum: u32) -> SigInfo {
+        let info = match self.queue.iter().position(|info| info.signum() == signum) {
+            Some(idx) => self.queue.remove(idx).unwrap(),
+            None => SigInfo::new(signum, SI_KERNEL),
+        };
+        if !self.queue.iter().any(|info| info.signum() == signum) {
+            self.signals &= !sig_bit(signum);
+        }
+        info
+    }
+
+    /// 移除 mask 中的所有待处理信号
+    pub fn discard(&mut self, mask: u64) {
+        self.signals &= !mask;
+        self.queue.retain(|info| sig_bit(info.signum()) & mask == 0);
+    }
+
+    /// 排队中的实时信号数
+    pub fn queued_rt(&self) -> usize {
+        self.queue
+            .iter()
+            .filter(|info| is_rt_signal(info.signum()))
+            .count()
+    }
 }
 
+pub const SS_ONSTACK: i32 = 1;
+pub const SS_DISABLE: i32 = 2;
+pub const MINSIGSTKSZ: usize = 2048;
+
+/// 与 Linux 的 stack_t 布局一致
 #[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SignalStack {
+    pub ss_sp: usize,
+    pub ss_flags: i32,
+    pub ss_size: usize,
+}
+
+impl SignalStack {
+    pub fn new() -> Self {
+        Self {
+            ss_sp: 0,
+            ss_flags: SS_DISABLE,
+            ss_size: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.ss_flags & SS_DISABLE == 0
+    }
+
+    pub fn contains(&self, sp: usize) -> bool {
+        sp > self.ss_sp && sp - self.ss_sp <= self.ss_size
+    }
+}
+
+/// 与 Linux riscv64 的 struct sigcontext 布局一致
+/// gregs[0] 为 pc，gregs[1..32] 为 x1~x31；浮点部分按 Q 扩展的大小保留空间
+#[repr(C, align(16))]
+#[derive(Copy, Clone)]
+pub struct MContext {
+    pub gregs: [usize; 32],
+    pub fpregs: [usize; 32],
+    pub fcsr: u32,
+    _reserved: [u32; 67],
+}
+
+impl MContext {
+    pub fn new() -> Self {
+        Self {
+            gregs: [0; 32],
+            fpregs: [0; 32],
+            fcsr: 0,
+            _reserved: [0; 67],
+        }
+    }
+}
+
+/// 与 Linux riscv64 的 struct ucontext 布局一致，uc_mcontext 位于偏移 176 处
+#[repr(C)]
+#[derive(Copy, Clone)]
 pub struct UContext {
-    pub __bits: [usize; 25],
+    pub uc_flags: usize,
+    pub uc_link: usize,
+    pub uc_stack: SignalStack,
+    pub uc_sigmask: u64,
+    _unused: [u8; 120],
+    pub uc_mcontext: MContext,
 }
 
 impl UContext {
     pub fn new() -> Self {
-        Self { __bits: [0; 25] }
+        Self {
+            uc_flags: 0,
+            uc_link: 0,
+            uc_stack: SignalStack::new(),
+            uc_sigmask: 0,
+            _unused: [0; 120],
+            uc_mcontext: MContext::new(),
+        }
     }
+}
 
-    pub fn as_bytes(&self) -> &[u8] {
-        let size = core::mem::size_of::<Self>();
-        unsafe { core::slice::from_raw_parts(self as *const _ as usize as *mut u8, size) }
+/// 执行信号处理函数前压入用户栈的帧，sigreturn 时据此恢复被打断的上下文
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SignalFrame {
+    /// SA_SIGINFO 处理函数的第二个参数
+    pub info: SigInfo,
+    /// SA_SIGINFO 处理函数的第三个参数，处理函数对其中寄存器与信号掩码的修改在 sigreturn 时生效
+    pub ucontext: UContext,
+}
+
+impl SignalFrame {
+    pub fn new(info: SigInfo) -> Self {
+        Self {
+            info,
+            ucontext: UContext::new(),
+        }
     }
 
-    pub fn pc_offset() -> usize {
-        176
+    pub fn ucontext_offset() -> usize {
+        size_of::<SigInfo>()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        let size = size_of::<Self>();
+        unsafe { core::slice::from_raw_parts(self as *const _ as *const u8, size) }
     }
 
-    pub fn mc_pc(&mut self) -> &mut usize {
-        &mut self.__bits[Self::pc_offset() / size_of::<usize>()]
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        let size = size_of::<Self>();
+        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as *mut u8, size) }
     }
 }
@@ -0,0 +1,425 @@
+//! 信号的发送与作业控制
+//! 停止信号使进程的所有线程在返回用户态前阻塞；SIGCONT 在发送时即恢复进程运行，
+//! 父进程通过 SIGCHLD 及 wait4 的 WUNTRACED / WCONTINUED 获知子进程状态变化
+//! kill 发送给进程的信号放入进程的 shared_pending，tkill 发送给线程的信号放入线程的 pending
+
+use super::{
+    block_current_and_run_next, current_process, current_task, is_rt_signal, is_signal_catchable,
+    is_stop_signal, process_group, sig_bit, sig_default_action, unblock_task, JobEvent,
+    ProcessControlBlock, ProcessControlBlockInner, RLIMIT_SIGPENDING,
+    SAFlags, SigAction, SigDefault, SigInfo, SignalFrame, TaskControlBlock, TaskStatus,
+    CLD_CONTINUED, CLD_STOPPED, SIGCHLD, SIGCONT, SIGKILL, SIGSTOP, SIGTSTP, SIGTTIN, SIGTTOU,
+    SIG_DFL, SIG_IGN, SS_ONSTACK, UNBLOCKABLE_SIGNALS,
+};
+use crate::config::SIGRETURN_TRAMPOLINE;
+use crate::mm::{translated_byte_buffer, UserBuffer};
+use crate::syscall::{EAGAIN, ESRCH};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+/// 发送前处理作业控制信号的副作用
+fn prepare_signal(process: &Arc<ProcessControlBlock>, signum: u32) {
+    if signum == SIGCONT {
+        let stop_signals =
+            sig_bit(SIGSTOP) | sig_bit(SIGTSTP) | sig_bit(SIGTTIN) | sig_bit(SIGTTOU);
+        discard_signals(process, stop_signals);
+        if resume_process(process, true) {
+            notify_parent(process, CLD_CONTINUED, SIGCONT as i32);
+        }
+    } else if is_stop_signal(signum) {
+        discard_signals(process, sig_bit(SIGCONT));
+    } else if signum == SIGKILL {
+        // 已停止的进程也要被唤醒以处理 SIGKILL
+        resume_process(process, false);
+    }
+}
+
+/// 被忽略且未被屏蔽的信号直接丢弃
+fn is_signal_ignored(process_inner: &ProcessControlBlockInner, signum: u32, blocked: u64) -> bool {
+    let handler = process_inner.sigactions.lock()[signum as usize].sa_handler;
+    let ignored = handler == SIG_IGN
+        || (handler == SIG_DFL && sig_default_action(signum) == SigDefault::Ign);
+    ignored && is_signal_catchable(signum) && blocked & sig_bit(signum) == 0
+}
+
+/// 实时信号的排队数受 RLIMIT_SIGPENDING 限制，调用者不能持有任何线程的锁
+fn can_queue(process_inner: &ProcessControlBlockInner, signum: u32) -> bool {
+    if !is_rt_signal(signum) {
+        return true;
+    }
+    let queued: usize = process_inner
+        .tasks
+        .iter()
+        .flatten()
+        .map(|task| task.acquire_inner_lock().pending.queued_rt())
+        .sum();
+    queued + process_inner.shared_pending.queued_rt() < process_inner.rlimit(RLIMIT_SIGPENDING)
+}
+
+/// 唤醒阻塞中的任务，使其能及时处理信号
+fn wake_for_signal(task: Arc<TaskControlBlock>) {
+    let blocking = task.acquire_inner_lock().task_status == TaskStatus::Blocking;
+    if blocking {
+        unblock_task(task);
+    }
+}
+
+/// 向线程发送信号，实时信号超出排队上限时返回 -EAGAIN
+pub fn send_signal(task: Arc<TaskControlBlock>, info: SigInfo) -> isize {
+    let signum = info.signum();
+    let process = match task.process.upgrade() {
+        Some(process) => process,
+        None => return 0,
+    };
+    prepare_signal(&process, signum);
+
+    let process_inner = process.acquire_inner_lock();
+    let blocked = task.acquire_inner_lock().sigmask;
+    if is_signal_ignored(&process_inner, signum, blocked) {
+        return 0;
+    }
+    if !can_queue(&process_inner, signum) {
+        return -EAGAIN;
+    }
+    let mut inner = task.acquire_inner_lock();
+    inner.add_signal_info(info);
+    if signum == SIGKILL {
+        inner.killed = true;
+    }
+    drop(inner);
+    drop(process_inner);
+    wake_for_signal(task);
+    0
+}
+
+/// 向进程发送信号，由任一未屏蔽该信号的线程处理；实时信号超出排队上限时返回 -EAGAIN
+pub fn send_signal_process(process: &Arc<ProcessControlBlock>, info: SigInfo) -> isize {
+    let signum = info.signum();
+    prepare_signal(process, signum);
+
+    let mut process_inner = process.acquire_inner_lock();
+    if process_inner.is_zombie {
+        return 0;
+    }
+    let tasks: Vec<_> = process_inner.tasks.iter().flatten().cloned().collect();
+    // 与 Linux 相同，以主线程的信号掩码判断信号是否被忽略
+    let blocked = tasks
+        .first()
+        .map_or(0, |task| task.acquire_inner_lock().sigmask);
+    if is_signal_ignored(&process_inner, signum, blocked) {
+        return 0;
+    }
+    if !can_queue(&process_inner, signum) {
+        return -EAGAIN;
+    }
+    process_inner.shared_pending.add(info);
+    drop(process_inner);
+
+    if signum == SIGKILL {
+        // SIGKILL 终止进程的所有线程
+        for task in tasks {
+            task.acquire_inner_lock().killed = true;
+            wake_for_signal(task);
+        }
+    } else if let Some(task) = tasks
+        .into_iter()
+        .find(|task| task.acquire_inner_lock().sigmask & sig_bit(signum) == 0)
+    {
+        wake_for_signal(task);
+    }
+    0
+}
+
+/// 向进程组中的所有进程发送信号，只要有一个进程接收成功即返回 0
+pub fn send_signal_pgrp(pgid: usize, info: SigInfo) -> isize {
+    let mut ret = -ESRCH;
+    for process in process_group(pgid) {
+        let err = send_signal_process(&process, info);
+        if ret != 0 {
+            ret = err;
+        }
+    }
+    ret
+}
+
+/// 当前线程是否忽略或屏蔽信号 signum，此时终端不会用 SIGTTIN / SIGTTOU 停止它
+pub fn current_ignores_signal(signum: u32) -> bool {
+    let task = current_task().unwrap();
+    let process = current_process();
+    let handler = process.acquire_inner_lock().sigactions.lock()[signum as usize].sa_handler;
+    let blocked = task.acquire_inner_lock().sigmask & sig_bit(signum) != 0;
+    handler == SIG_IGN || blocked
+}
+
+/// 发送同步产生的信号（访存错误、非法指令等）
+/// 这类信号被屏蔽或忽略时重新执行出错指令只会再次出错，因此解除屏蔽并恢复默认动作
+pub fn force_signal(task: &Arc<TaskControlBlock>, info: SigInfo) {
+    let signum = info.signum();
+    let process = task.process.upgrade().unwrap();
+    let process_inner = process.acquire_inner_lock();
+    let mut sigactions = process_inner.sigactions.lock();
+    let mut inner = task.acquire_inner_lock();
+    let action = &mut sigactions[signum as usize];
+    if action.sa_handler == SIG_IGN || inner.sigmask & sig_bit(signum) != 0 {
+        *action = SigAction::new();
+    }
+    inner.sigmask &= !sig_bit(signum);
+    inner.add_signal_info(info);
+}
+
+fn process_tasks(process: &Arc<ProcessControlBlock>) -> Vec<Arc<TaskControlBlock>> {
+    process
+        .acquire_inner_lock()
+        .tasks
+        .iter()
+        .flatten()
+        .cloned()
+        .collect()
+}
+
+/// 从进程及其所有线程的待处理信号中移除 mask 中的信号
+pub fn discard_signals(process: &Arc<ProcessControlBlock>, mask: u64) {
+    let mut process_inner = process.acquire_inner_lock();
+    process_inner.shared_pending.discard(mask);
+    for task in process_inner.tasks.iter().flatten() {
+        task.acquire_inner_lock().pending.discard(mask);
+    }
+}
+
+/// 取出一个不在 blocked 中的待处理信号，线程私有的信号优先于发送给进程的信号
+pub fn dequeue_signal(
+    task: &Arc<TaskControlBlock>,
+    process: &Arc<ProcessControlBlock>,
+    blocked: u64,
+) -> Option<SigInfo> {
+    let mut process_inner = process.acquire_inner_lock();
+    let mut task_inner = task.acquire_inner_lock();
+    task_inner
+        .pending
+        .fetch(blocked)
+        .or_else(|| process_inner.shared_pending.fetch(blocked))
+}
+
+/// 线程是否有不在其信号掩码中的待处理信号
+pub fn has_unmasked_signal(task: &Arc<TaskControlBlock>, process: &Arc<ProcessControlBlock>) -> bool {
+    let process_inner = process.acquire_inner_lock();
+    let task_inner = task.acquire_inner_lock();
+    (task_inner.pending.signals | process_inner.shared_pending.signals) & !task_inner.sigmask != 0
+}
+
+/// 恢复已停止的进程，返回进程此前是否处于停止状态
+fn resume_process(process: &Arc<ProcessControlBlock>, report: bool) -> bool {
+    let mut inner = process.acquire_inner_lock();
+    if !inner.stopped {
+        return false;
+    }
+    inner.stopped = false;
+    if report {
+        inner.job_event = Some(JobEvent::Continued);
+    }
+    drop(inner);
+    // 线程在持有进程锁时设置 job_stopped，此处读到的值不会遗漏正在停止的线程
+    for task in process_tasks(process) {
+        let stopped = task.acquire_inner_lock().job_stopped;
+        if stopped {
+            unblock_task(task);
+        }
+    }
+    true
+}
+
+/// 以 SIGCHLD 通知父进程子进程退出、停止或继续，并唤醒可能在 wait4 中阻塞的父进程
+/// code 为 CLD_* 之一，status 为退出码或导致状态变化的信号
+pub fn notify_parent(child: &Arc<ProcessControlBlock>, code: i32, status: i32) {
+    let parent = child
+        .acquire_inner_lock()
+        .parent
+        .as_ref()
+        .and_then(Weak::upgrade);
+    let parent = match parent {
+        Some(parent) => parent,
+        None => return,
+    };
+    let parent_inner = parent.acquire_inner_lock();
+    let nocldstop = parent_inner.sigactions.lock()[SIGCHLD as usize]
+        .sa_flags
+        .contains(SAFlags::SA_NOCLDSTOP);
+    let ptask = parent_inner.get_task(0);
+    drop(parent_inner);
+    let job_control = code == CLD_STOPPED || code == CLD_CONTINUED;
+    if !job_control || !nocldstop {
+        let info = SigInfo::child(code, child.getpid(), 0, status);
+        send_signal(ptask.clone(), info);
+    }
+    unblock_task(ptask);
+}
+
+/// 执行停止信号的默认动作：停止整个进程并等待 SIGCONT
+pub fn stop_current_process(
+    task: &Arc<TaskControlBlock>,
+    process: &Arc<ProcessControlBlock>,
+    signum: u32,
+) {
+    let mut inner = process.acquire_inner_lock();
+    let newly_stopped = !inner.stopped;
+    if newly_stopped {
+        inner.stopped = true;
+        inner.job_event = Some(JobEvent::Stopped(signum));
+    }
+    drop(inner);
+    if newly_stopped {
+        notify_parent(process, CLD_STOPPED, signum as i32);
+    }
+    wait_while_stopped(task, process);
+}
+
+/// 进程处于停止状态时阻塞当前线程，在返回用户态前调用
+pub fn wait_while_stopped(task: &Arc<TaskControlBlock>, process: &Arc<ProcessControlBlock>) {
+    loop {
+        {
+            let inner = process.acquire_inner_lock();
+            let mut task_inner = task.acquire_inner_lock();
+            // 开始等待，见 `prepare_to_block`
+            task_inner.wakeup_pending = false;
+            if !inner.stopped || task_inner.killed {
+                task_inner.job_stopped = false;
+                return;
+            }
+            task_inner.job_stopped = true;
+        }
+        block_current_and_run_next();
+    }
+}
+
+/// ppoll/pselect 等待期间以 mask 作为信号屏蔽字
+/// 原屏蔽字在返回用户态时恢复；若此时递送信号，则在其处理函数返回时恢复，
+/// 使等待期间解除屏蔽的信号先得到处理
+pub fn set_temporary_sigmask(task: &Arc<TaskControlBlock>, mask: u64) {
+    let mut inner = task.acquire_inner_lock();
+    if inner.saved_sigmask.is_none() {
+        inner.saved_sigmask = Some(inner.sigmask);
+    }
+    inner.sigmask = mask & !UNBLOCKABLE_SIGNALS;
+}
+
+/// 在用户栈（SA_ONSTACK 时为备用信号栈）上压入信号帧，返回用户态时跳转到信号处理函数
+/// 处理函数返回到 sigreturn 跳板，此时 sp 仍指向该帧
+/// 信号帧无法写入时返回 false，调用者应以 SIGSEGV 终止进程
+pub fn setup_signal_frame(
+    task: &Arc<TaskControlBlock>,
+    process: &Arc<ProcessControlBlock>,
+    info: &SigInfo,
+    sigaction: &SigAction,
+) -> bool {
+    extern "C" {
+        fn __sigreturn();
+        fn __alltraps();
+    }
+    let signum = info.signum();
+    let mut frame = SignalFrame::new(*info);
+    let (sp, altstack) = {
+        let task_inner = task.acquire_inner_lock();
+        let trap_cx = task_inner.get_trap_cx();
+        let uc = &mut frame.ucontext;
+        uc.uc_sigmask = task_inner.saved_sigmask.unwrap_or(task_inner.sigmask);
+        uc.uc_mcontext.gregs = trap_cx.x;
+        uc.uc_mcontext.gregs[0] = trap_cx.sepc;
+        uc.uc_mcontext.fpregs = trap_cx.freg;
+        uc.uc_mcontext.fcsr = trap_cx.fcsr as u32;
+        (trap_cx.x[2], task_inner.sigaltstack)
+    };
+    let on_altstack = altstack.is_enabled() && altstack.contains(sp);
+    frame.ucontext.uc_stack = altstack;
+    if on_altstack {
+        frame.ucontext.uc_stack.ss_flags |= SS_ONSTACK;
+    }
+    // 已在备用栈上时（嵌套的信号）继续使用当前栈
+    let stack_top = if sigaction.sa_flags.contains(SAFlags::SA_ONSTACK)
+        && altstack.is_enabled()
+        && !on_altstack
+    {
+        altstack.ss_sp + altstack.ss_size
+    } else {
+        sp
+    };
+    let frame_addr = match stack_top.checked_sub(size_of::<SignalFrame>()) {
+        Some(addr) => addr & !0xf,
+        None => return false,
+    };
+
+    // 不能直接写入 translated_byte_buffer 得到的页面：写时复制的页面会被共享者看到，
+    // 用户给出的 sp 也可能指向 TrapContext 等内核页面
+    let token = {
+        let process_inner = process.acquire_inner_lock();
+        if !process_inner.prepare_user_access(frame_addr, size_of::<SignalFrame>(), true) {
+            return false;
+        }
+        process_inner.get_user_token()
+    };
+    UserBuffer::new(translated_byte_buffer(
+        token,
+        frame_addr as *const u8,
+        size_of::<SignalFrame>(),
+    ))
+    .copy_to_user(frame.as_bytes());
+
+    let mut task_inner = task.acquire_inner_lock();
+    // 处理函数执行期间额外屏蔽 sa_mask，除非设置了 SA_NODEFER，该信号本身也被屏蔽
+    let mut mask = task_inner.sigmask | sigaction.sa_mask;
+    if !sigaction.sa_flags.contains(SAFlags::SA_NODEFER) {
+        mask |= sig_bit(signum);
+    }
+    task_inner.sigmask = mask & !UNBLOCKABLE_SIGNALS;
+    // 临时屏蔽字保存的原值已写入信号帧，由 sigreturn 恢复
+    task_inner.saved_sigmask = None;
+
+    let trap_cx = task_inner.get_trap_cx();
+    trap_cx.x[1] = __sigreturn as usize - __alltraps as usize + SIGRETURN_TRAMPOLINE; // ra
+    trap_cx.x[2] = frame_addr; // sp
+    trap_cx.x[10] = signum as usize; // a0 = signum
+    trap_cx.x[11] = frame_addr; // a1 = &siginfo
+    trap_cx.x[12] = frame_addr + SignalFrame::ucontext_offset(); // a2 = &ucontext
+    trap_cx.sepc = sigaction.sa_handler;
+    drop(task_inner);
+
+    if sigaction.sa_flags.contains(SAFlags::SA_RESETHAND) {
+        process.acquire_inner_lock().sigactions.lock()[signum as usize] = SigAction::new();
+    }
+    true
+}
+
+/// sigreturn：从 sp 指向的信号帧恢复被打断的上下文与信号掩码，返回恢复后的 a0
+/// 信号帧无法读取时返回 None
+pub fn restore_signal_frame(
+    task: &Arc<TaskControlBlock>,
+    process: &Arc<ProcessControlBlock>,
+) -> Option<usize> {
+    let frame_addr = task.acquire_inner_lock().get_trap_cx().x[2];
+    let token = {
+        let process_inner = process.acquire_inner_lock();
+        if !process_inner.prepare_user_access(frame_addr, size_of::<SignalFrame>(), false) {
+            return None;
+        }
+        process_inner.get_user_token()
+    };
+    let mut frame = SignalFrame::new(SigInfo::new(0, 0));
+    UserBuffer::new(translated_byte_buffer(
+        token,
+        frame_addr as *const u8,
+        size_of::<SignalFrame>(),
+    ))
+    .copy_from_user(frame.as_bytes_mut());
+
+    // 处理函数可能修改 ucontext 中的寄存器（如 pthread_cancel 修改 pc），以其为准
+    let mcontext = &frame.ucontext.uc_mcontext;
+    let mut task_inner = task.acquire_inner_lock();
+    let trap_cx = task_inner.get_trap_cx();
+    trap_cx.sepc = mcontext.gregs[0];
+    trap_cx.x = mcontext.gregs;
+    trap_cx.x[0] = 0;
+    trap_cx.freg = mcontext.fpregs;
+    trap_cx.fcsr = mcontext.fcsr as usize;
+    task_inner.sigmask = frame.ucontext.uc_sigmask & !UNBLOCKABLE_SIGNALS;
+    Some(trap_cx.x[10])
+}
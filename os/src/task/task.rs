@@ -1,13 +1,17 @@
-use super::id::TaskUserRes;
-use super::{kstack_alloc, KernelStack, ProcessControlBlock, TaskContext, SAFlags, ITimerSpec, __FA};
+use super::id::{TaskUserRes, TidHandle};
+use super::sched::SchedEntity;
+use super::{
+    kstack_alloc, ITimerSpec, KernelStack, ProcessControlBlock, SigInfo, SigPending,
+    SignalStack, TaskContext, SI_KERNEL, __FA,
+};
 use crate::config::PAGE_SIZE;
 use crate::mm::PhysPageNum;
 use crate::multicore::get_hartid;
+use crate::timer::TimerHandle;
 use crate::trap::TrapContext;
 use alloc::collections::VecDeque;
 use alloc::sync::{Arc, Weak};
 
-use alloc::vec::Vec;
 use spin::{Mutex, MutexGuard};
 
 pub struct TaskControlBlock {
@@ -15,6 +19,8 @@ pub struct TaskControlBlock {
     pub process: Weak<ProcessControlBlock>,
     pub kstack: KernelStack,
     // mutable
+    /// 调度信息单独加锁，便于调度器在不持有 inner 锁时访问
+    pub sched: Mutex<SchedEntity>,
     inner: Arc<Mutex<TaskControlBlockInner>>,
 }
 
@@ -26,7 +32,7 @@ impl TaskControlBlock {
     pub fn get_user_token(&self) -> usize {
         let process = self.process.upgrade().unwrap();
         let inner = process.acquire_inner_lock();
-        inner.memory_set.token()
+        inner.get_user_token()
     }
 }
 
@@ -35,13 +41,25 @@ pub struct TaskControlBlockInner {
     pub trap_cx_ppn: PhysPageNum,
     pub task_cx: TaskContext,
     pub task_status: TaskStatus,
-    pub pending_signals: u64,
+    /// 发送给该线程的待处理信号，发送给进程的信号见进程的 shared_pending
+    pub pending: SigPending,
     pub sigmask: u64,
+    /// ppoll/pselect 临时替换信号屏蔽字时保存的原值，返回用户态时恢复
+    pub saved_sigmask: Option<u64>,
+    /// sigaltstack 设置的备用信号栈
+    pub sigaltstack: SignalStack,
     pub itimer: ITimerSpec,
+    /// ITIMER_REAL 对应的内核定时器
+    pub itimer_handle: Option<TimerHandle>,
+    /// ITIMER_VIRTUAL 与 ITIMER_PROF 的设置，只保存而不计时
+    pub itimer_cpu: [ITimerSpec; 2],
+    /// 本轮等待开始后、任务切换出去之前收到的唤醒，下一次阻塞将立即返回
+    /// 每轮等待开始时由 `prepare_to_block` 清除
+    pub wakeup_pending: bool,
+    /// 因进程停止而阻塞，等待 SIGCONT 唤醒
+    pub job_stopped: bool,
     pub clear_child_tid: Option<ClearChildTid>,
     pub killed: bool,
-    performing_signals: Vec<(u32, SAFlags)>,
-    trap_cx_backup: Option<TrapContext>,
 }
 
 impl TaskControlBlockInner {
@@ -55,28 +73,6 @@ impl TaskControlBlockInner {
         self.task_status
     }
 
-    pub fn pop_trap_cx(&mut self) {
-        *self.get_trap_cx() = self.trap_cx_backup.take().unwrap();
-    }
-
-    pub fn push_trap_cx(&mut self) {
-        self.trap_cx_backup = Some(*self.get_trap_cx());
-    }
-
-    pub fn signal_context_restore(&mut self) -> (u32, SAFlags) {
-        self.pop_trap_cx();
-        self.performing_signals.pop().unwrap()
-    }
-
-    pub fn signal_context_save(&mut self, signum: u32, flag: SAFlags) {
-        self.push_trap_cx();
-        self.performing_signals.push((signum, flag));
-    }
-
-    pub fn is_signaling(&self) -> bool {
-        self.trap_cx_backup.is_some()
-    }
-
     pub fn gettid(&self) -> usize {
         self.res.as_ref().unwrap().tid.0
     }
@@ -86,20 +82,11 @@ impl TaskControlBlockInner {
     }
 
     pub fn add_signal(&mut self, signum: u32) {
-        self.pending_signals |= 1 << signum;
+        self.pending.add(SigInfo::new(signum, SI_KERNEL));
     }
 
-    pub fn remove_signal(&mut self, signum: u32) {
-        self.pending_signals &= !(1 << signum);
-    }
-
-    pub fn fetch_signal(&mut self) -> Option<u32> {
-        if self.pending_signals == 0 {
-            return None;
-        }
-        let signum = self.pending_signals.trailing_zeros();
-        self.remove_signal(signum);
-        Some(signum)
+    pub fn add_signal_info(&mut self, info: SigInfo) {
+        self.pending.add(info);
     }
 
     pub fn __save_info_to_fast_access(&self) {
@@ -114,30 +101,36 @@ impl TaskControlBlockInner {
 }
 
 impl TaskControlBlock {
-    /// pid == -1 means that the main thread is being created.
+    /// 创建主线程时 tid 即进程的 pid
+    /// slot 为 None 时分配新的 trap_cx 与用户栈，见 `TaskUserRes::new`
     pub fn new(
         process: Arc<ProcessControlBlock>,
+        tid: Arc<TidHandle>,
         ustack_base: usize,
-        pid: isize,
-        alloc_user_res: bool,
+        slot: Option<usize>,
     ) -> Self {
-        let res = TaskUserRes::new(Arc::clone(&process), ustack_base, pid, alloc_user_res);
+        let res = TaskUserRes::new(Arc::clone(&process), tid, ustack_base, slot);
         let trap_cx_ppn = res.trap_cx_ppn();
         let kstack = kstack_alloc();
         let kstack_top = kstack.get_top();
         Self {
             process: Arc::downgrade(&process),
             kstack,
+            sched: Mutex::new(SchedEntity::new()),
             inner: Arc::new(Mutex::new(TaskControlBlockInner {
                 res: Some(res),
                 trap_cx_ppn,
                 task_cx: TaskContext::goto_trap_return(kstack_top),
                 task_status: TaskStatus::Ready,
-                pending_signals: 0,
+                pending: SigPending::new(),
                 sigmask: 0,
+                saved_sigmask: None,
+                sigaltstack: SignalStack::new(),
                 itimer: ITimerSpec::new(),
-                performing_signals: Vec::with_capacity(64),
-                trap_cx_backup: None,
+                itimer_handle: None,
+                itimer_cpu: [ITimerSpec::new(); 2],
+                wakeup_pending: false,
+                job_stopped: false,
                 clear_child_tid: None,
                 killed: false
             })),
@@ -150,6 +143,8 @@ pub enum TaskStatus {
     Ready,
     Running,
     Blocking,
+    /// 已退出，切换到调度循环后释放，见 `exit_current_and_run_next`
+    Zombie,
 }
 
 #[derive(Debug)]
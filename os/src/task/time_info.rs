@@ -8,7 +8,7 @@ pub const ITIMER_VIRTUAL:isize = 1; /* Timers run only when the process is execu
 pub const ITIMER_PROF:isize = 2; /* Timers run when the process is executing and when the system is executing on behalf of the process.  */
 
 #[repr(C)]
-#[derive(Copy, Clone,Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct TimeSpec{
     pub tv_sec: usize,
     pub tv_usec: usize,
@@ -39,6 +39,14 @@ impl TimeSpec{
         self.tv_sec == 0 && self.tv_usec == 0
     }
 
+    pub fn from_ns(ns: u64) -> Self{
+        let usec = ns as usize / 1000;
+        Self{
+            tv_sec: usec / USEC_PER_SEC,
+            tv_usec: usec % USEC_PER_SEC,
+        }
+    }
+
 }
 
 impl ITimerSpec{
@@ -1,14 +1,23 @@
-// use core::cmp::Ordering;
-use crate::task::TimeSpec;
+//! 时钟与内核定时器
+//! 每个 hart 维护一个按到期时间排序的定时器队列，SBI 时钟被设置为下一个时钟中断与最早定时器中较早的一个
+
+use crate::board::MAX_CPU_NUM;
 use crate::config::CLOCK_FREQ;
+use crate::fs::tty_poll_expired;
+use crate::multicore::get_hartid;
 use crate::sbi::set_timer;
-use crate::syscall::FUTEX_QUEUE;
-use crate::task::unblock_task;
-//
-// use crate::task::{add_task, TaskControlBlock};
-// use alloc::collections::BinaryHeap;
-// use alloc::sync::Arc;
+#[cfg(feature = "no_hz")]
+use crate::task::has_ready_task;
+use crate::task::{
+    block_current_and_run_next, current_task, unblock_task, ITimerSpec, TaskControlBlock,
+    TaskStatus, TimeSpec, SIGALRM,
+};
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use riscv::register::time;
+use spin::{Lazy, Mutex};
 
 // pub const MSEC_PER_SEC: usize = 1000;
 pub const USEC_PER_SEC: usize = 1000000;
@@ -37,72 +46,182 @@ pub fn get_time_ns() -> usize {
     time::read() * 10000 / (CLOCK_FREQ / 100000)
 }
 
+/// get_time_ns 的逆运算（向上取整），保证按此设置的时钟中断不早于 ns
+fn ns_to_ticks(ns: usize) -> usize {
+    let factor = (CLOCK_FREQ / 100000) as u128;
+    ((ns as u128 * factor + 9999) / 10000) as usize
+}
+
+pub fn timespec_to_ns(sec: usize, nsec: usize) -> usize {
+    sec.saturating_mul(NSEC_PER_SEC).saturating_add(nsec)
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const TRIGGER_INIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+/// 各 hart 已设置的下一次时钟中断时刻 (ticks)
+static NEXT_TRIGGER: [AtomicUsize; MAX_CPU_NUM] = [TRIGGER_INIT; MAX_CPU_NUM];
+
+fn program_timer(ticks: usize) {
+    NEXT_TRIGGER[get_hartid()].store(ticks, Ordering::Relaxed);
+    set_timer(ticks);
+}
+
+/// 设置下一次时钟中断：时间片到期（若需要）与本 hart 最早的定时器中较早者
 pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
-}
-
-pub fn wakeup_futex_waiters() {
-    for (_, fq) in FUTEX_QUEUE.write().iter_mut() {
-        let mut fq_lock = fq.chain.write();
-        let mut i = 0;
-        while i < fq_lock.len() {
-            let w = &fq_lock[i];
-            if w.check_expire() {
-                let task = w.task.clone();
-                fq.waiters_dec();
-                fq_lock.remove(i);
-                unblock_task(task);
-            } else {
-                i += 1;
+    let tick = tick_needed();
+    let mut next = if tick {
+        get_time() + CLOCK_FREQ / TICKS_PER_SEC
+    } else {
+        usize::MAX
+    };
+    if let Some(expire_ns) = next_timer_deadline() {
+        next = next.min(ns_to_ticks(expire_ns));
+    }
+    set_tick_stopped(!tick);
+    program_timer(next);
+}
+
+#[cfg(not(feature = "no_hz"))]
+fn tick_needed() -> bool {
+    true
+}
+
+/// NO_HZ：本 hart 没有其他任务等待运行时，无需时间片时钟
+#[cfg(feature = "no_hz")]
+fn tick_needed() -> bool {
+    has_ready_task(get_hartid())
+}
+
+/// 时间片时钟已停止的 hart 掩码
+static TICK_STOPPED: AtomicUsize = AtomicUsize::new(0);
+
+fn set_tick_stopped(stopped: bool) {
+    let mask = 1 << get_hartid();
+    if stopped {
+        TICK_STOPPED.fetch_or(mask, Ordering::SeqCst);
+    } else {
+        TICK_STOPPED.fetch_and(!mask, Ordering::SeqCst);
+    }
+}
+
+pub fn tick_stopped(hartid: usize) -> bool {
+    TICK_STOPPED.load(Ordering::SeqCst) & (1 << hartid) != 0
+}
+
+/// 定时器到期时执行的动作
+pub enum TimerEvent {
+    /// 唤醒阻塞等待的任务
+    Wakeup(Arc<TaskControlBlock>),
+    /// ITIMER_REAL 到期，向任务发送 SIGALRM 并按间隔重新设置
+    ITimerReal(Weak<TaskControlBlock>),
+    /// 轮询控制台输入，见 `tty_poll_expired`
+    TtyPoll,
+}
+
+/// 定时器句柄，用于取消尚未到期的定时器
+#[derive(Copy, Clone, Debug)]
+pub struct TimerHandle {
+    hartid: usize,
+    key: (usize, u64),
+}
+
+/// 每个 hart 的定时器队列，键为 (到期时间 ns, 序号)
+static TIMER_QUEUES: Lazy<Vec<Mutex<BTreeMap<(usize, u64), TimerEvent>>>> =
+    Lazy::new(|| (0..MAX_CPU_NUM).map(|_| Mutex::new(BTreeMap::new())).collect());
+static TIMER_SEQ: AtomicU64 = AtomicU64::new(0);
+
+fn next_timer_deadline() -> Option<usize> {
+    TIMER_QUEUES[get_hartid()]
+        .lock()
+        .keys()
+        .next()
+        .map(|(expire_ns, _)| *expire_ns)
+}
+
+/// 在当前 hart 上添加一个于 expire_ns 到期的定时器
+pub fn add_timer(expire_ns: usize, event: TimerEvent) -> TimerHandle {
+    let hartid = get_hartid();
+    let key = (expire_ns, TIMER_SEQ.fetch_add(1, Ordering::Relaxed));
+    TIMER_QUEUES[hartid].lock().insert(key, event);
+    // 新定时器早于已设置的时钟中断时提前触发
+    let ticks = ns_to_ticks(expire_ns);
+    if ticks < NEXT_TRIGGER[hartid].load(Ordering::Relaxed) {
+        program_timer(ticks);
+    }
+    TimerHandle { hartid, key }
+}
+
+/// 取消定时器，返回其是否仍未到期
+pub fn cancel_timer(handle: TimerHandle) -> bool {
+    TIMER_QUEUES[handle.hartid]
+        .lock()
+        .remove(&handle.key)
+        .is_some()
+}
+
+/// 在时钟中断中调用：执行本 hart 所有已到期的定时器，并设置下一次时钟中断
+pub fn check_timers() {
+    let now = get_time_ns();
+    let expired: Vec<TimerEvent> = {
+        let mut queue = TIMER_QUEUES[get_hartid()].lock();
+        let keys: Vec<_> = queue.range(..(now + 1, 0)).map(|(key, _)| *key).collect();
+        keys.iter().filter_map(|key| queue.remove(key)).collect()
+    };
+    // 释放队列锁之后再执行，事件中可能添加新的定时器
+    for event in expired {
+        match event {
+            TimerEvent::Wakeup(task) => unblock_task(task),
+            TimerEvent::ITimerReal(task) => {
+                if let Some(task) = task.upgrade() {
+                    itimer_real_expired(&task);
+                }
             }
+            TimerEvent::TtyPoll => tty_poll_expired(),
         }
     }
+    set_next_trigger();
+}
+
+/// 阻塞当前任务直到 expire_ns 或被其他事件提前唤醒，返回是否因超时而醒来
+/// 调用者需先调用 `prepare_to_block` 再检查等待条件
+pub fn sleep_until(expire_ns: usize) -> bool {
+    let handle = add_timer(expire_ns, TimerEvent::Wakeup(current_task().unwrap()));
+    block_current_and_run_next();
+    !cancel_timer(handle)
+}
+
+/// 重新设置任务的 ITIMER_REAL，value 为零时仅取消
+pub fn arm_itimer_real(task: &Arc<TaskControlBlock>, itimer: ITimerSpec) {
+    let mut inner = task.acquire_inner_lock();
+    if let Some(handle) = inner.itimer_handle.take() {
+        cancel_timer(handle);
+    }
+    inner.itimer = itimer;
+    if !itimer.it_value.is_zero() {
+        let expire_ns = itimer.it_value.tv_sec * NSEC_PER_SEC + itimer.it_value.tv_usec * 1000;
+        inner.itimer_handle = Some(add_timer(
+            expire_ns,
+            TimerEvent::ITimerReal(Arc::downgrade(task)),
+        ));
+    }
 }
 
-// pub struct TimerCondVar {
-//     pub expire_ns: usize,
-//     pub task: Arc<TaskControlBlock>,
-// }
-
-// impl PartialEq for TimerCondVar {
-//     fn eq(&self, other: &Self) -> bool {
-//         self.expire_ns == other.expire_ns
-//     }
-// }
-// impl Eq for TimerCondVar {}
-// impl PartialOrd for TimerCondVar {
-//     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-//         let a = -(self.expire_ns as isize);
-//         let b = -(other.expire_ns as isize);
-//         Some(a.cmp(&b))
-//     }
-// }
-
-// impl Ord for TimerCondVar {
-//     fn cmp(&self, other: &Self) -> Ordering {
-//         self.partial_cmp(other).unwrap()
-//     }
-// }
-
-// lazy_static! {
-//     static ref TIMERS: UPSafeCell<BinaryHeap<TimerCondVar>> =
-//         unsafe { UPSafeCell::new(BinaryHeap::<TimerCondVar>::new()) };
-// }
-
-// pub fn add_timer(expire_ns: usize, task: Arc<TaskControlBlock>) {
-//     let mut timers = TIMERS.lock();
-//     timers.push(TimerCondVar { expire_ns, task });
-// }
-
-// pub fn check_timer() {
-//     let current_ns = get_time_ns();
-//     let mut timers = TIMERS.lock();
-//     while let Some(timer) = timers.peek() {
-//         if timer.expire_ns <= current_ns {
-//             add_task(Arc::clone(&timer.task));
-//             timers.pop();
-//         } else {
-//             break;
-//         }
-//     }
-// }
+fn itimer_real_expired(task: &Arc<TaskControlBlock>) {
+    let (mut itimer, blocking) = {
+        let mut inner = task.acquire_inner_lock();
+        inner.itimer_handle = None;
+        inner.add_signal(SIGALRM);
+        (inner.itimer, inner.task_status == TaskStatus::Blocking)
+    };
+    // 周期定时器从本次到期时刻起重新计时
+    if itimer.it_interval.is_zero() {
+        itimer.it_value = TimeSpec::new();
+    } else {
+        itimer.it_value = get_timespec() + itimer.it_interval;
+    }
+    arm_itimer_real(task, itimer);
+    // 阻塞中的任务需要被唤醒以处理信号
+    if blocking {
+        unblock_task(task.clone());
+    }
+}
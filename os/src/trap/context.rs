@@ -11,6 +11,8 @@ pub struct TrapContext {
     pub trap_handler: usize,
     pub core_id: usize,
     pub freg: [usize; 32],
+    /// 浮点控制与状态寄存器
+    pub fcsr: usize,
 }
 
 impl TrapContext {
@@ -37,6 +39,7 @@ impl TrapContext {
             trap_handler,
             core_id,
             freg: [0; 32],
+            fcsr: 0,
         };
         cx.set_sp(sp);
         cx
@@ -1,15 +1,17 @@
 mod context;
 
 use crate::config::TRAMPOLINE;
+use crate::fs::tty_poll;
 use crate::gdb_println;
 use crate::monitor::{QEMU, SYSCALL_ENABLE};
 use crate::multicore::get_hartid;
-use crate::syscall::{SYSCALL_SIGRETURN, SYSCALL_TABLE, SYSCALL_READ, SYSCALL_WRITE, SYSCALL_READDIR};
+use crate::syscall::{ERESTARTSYS, SYSCALL_SIGRETURN, SYSCALL_TABLE, SYSCALL_READ, SYSCALL_WRITE, SYSCALL_READDIR};
+use crate::mm::VirtAddr;
 use crate::task::{
-    current_add_signal, current_process, current_tid, current_trap_cx,
-    current_user_token, perform_signals_of_current, suspend_current_and_run_next, SIGILL, SIGSEGV, current_trap_cx_user_va,
+    check_cpu_rlimit, current_force_signal, current_process, current_task, current_tid, current_trap_cx,
+    current_user_token, handle_ipi, load_balance_tick, need_resched, perform_signals_of_current, suspend_current_and_run_next, SigInfo, ILL_ILLOPC, SEGV_ACCERR, SEGV_MAPERR, SIGILL, SIGSEGV, current_trap_cx_user_va,
 };
-use crate::timer::set_next_trigger;
+use crate::timer::check_timers;
 use core::arch::{asm, global_asm};
 use riscv::register::{
     mtvec::TrapMode,
@@ -45,11 +47,20 @@ pub fn enable_timer_interrupt() {
     }
 }
 
+/// 使能 IPI，用于唤醒空闲的 hart
+pub fn enable_software_interrupt() {
+    unsafe {
+        sie::set_ssoft();
+    }
+}
+
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    current_task().unwrap().sched.lock().leave_user();
     let scause = scause::read();
-    let mut is_sigreturn = false;
+    // 返回 ERESTARTSYS 的系统调用的原始 a0，用于重启
+    let mut restart_a0 = None;
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
             // jump to next instruction anyway
@@ -57,10 +68,8 @@ pub fn trap_handler() -> ! {
             // debug!("syscall sepc = {:#x?}", cx.sepc);
             cx.sepc += 4;
             let syscall_id = cx.x[17];
+            let a0 = cx.x[10];
             // get system call return value
-            if syscall_id == SYSCALL_SIGRETURN {
-                is_sigreturn = true;
-            }
             let result: usize;
             
             if ((syscall_id != SYSCALL_READ && syscall_id != SYSCALL_WRITE) || (cx.x[10] > 2))
@@ -95,6 +104,10 @@ pub fn trap_handler() -> ! {
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
+            // sigreturn 的返回值是恢复出的 a0，不能被当作错误码
+            if syscall_id != SYSCALL_SIGRETURN && result as isize == -ERESTARTSYS {
+                restart_a0 = Some(a0);
+            }
         }
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
@@ -106,7 +119,7 @@ pub fn trap_handler() -> ! {
             // let is_store = scause.cause() == Trap::Exception(Exception::StoreFault) || scause.cause() == Trap::Exception(Exception::StorePageFault);
             let is_load = scause.cause() == Trap::Exception(Exception::LoadFault) || scause.cause() == Trap::Exception(Exception::LoadPageFault);
             let process = current_process();
-            let mut process_inner = process.acquire_inner_lock();
+            let process_inner = process.acquire_inner_lock();
             let ret_lazy = process_inner.check_lazy(stval,is_load);
             // let mut ret_cow:isize = 0;
             // if is_store && ret_lazy==-1 {
@@ -127,7 +140,16 @@ pub fn trap_handler() -> ! {
                 // for (i, v) in cx.x.iter().enumerate() {
                 //     debug!("x[{}] = {:#x?}", i, v);
                 // }
-                current_add_signal(SIGSEGV);
+                // 地址已映射说明是权限错误
+                let mapped = process_inner
+                    .mm
+                    .lock()
+                    .memory_set
+                    .translate(VirtAddr::from(stval).floor())
+                    .map_or(false, |pte| pte.is_valid());
+                let code = if mapped { SEGV_ACCERR } else { SEGV_MAPERR };
+                drop(process_inner);
+                current_force_signal(SigInfo::fault(SIGSEGV, code, stval));
             }
         }
         Trap::Exception(Exception::IllegalInstruction) => {
@@ -143,11 +165,25 @@ pub fn trap_handler() -> ! {
             for (i, v) in cx.x.iter().enumerate() {
                 debug!("x[{}] = {:#x?}", i, v);
             }
-            current_add_signal(SIGILL);
+            current_force_signal(SigInfo::fault(SIGILL, ILL_ILLOPC, cx.sepc));
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
-            suspend_current_and_run_next();
+            check_timers();
+            load_balance_tick();
+            check_cpu_rlimit();
+            // 控制台没有输入中断，在时钟中断中检查 ^C 等控制字符
+            tty_poll();
+            // FIFO 实时任务不会因时间片耗尽而被抢占
+            if need_resched(&current_task().unwrap()) {
+                suspend_current_and_run_next();
+            }
+        }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            // 其他 hart 向本 hart 的队列放入了任务
+            handle_ipi();
+            if need_resched(&current_task().unwrap()) {
+                suspend_current_and_run_next();
+            }
         }
         _ => {
             let stval = stval::read();
@@ -158,10 +194,8 @@ pub fn trap_handler() -> ! {
             );
         }
     }
-    // 处理当前进程的信号
-    if !is_sigreturn {
-        perform_signals_of_current();
-    }
+    // 处理当前进程的信号，sigreturn 恢复信号掩码后被解除屏蔽的信号也在此递送
+    perform_signals_of_current(restart_a0);
     trap_return();
 }
 
@@ -178,6 +212,7 @@ pub fn trap_return() -> ! {
 
     // 设置core_id
     current_trap_cx().core_id = get_hartid();
+    current_task().unwrap().sched.lock().enter_user();
 
     unsafe {
         asm!(
@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    getauxval, AT_BASE, AT_ENTRY, AT_EXECFN, AT_HWCAP, AT_PAGESZ, AT_PLATFORM, AT_RANDOM,
+    AT_SECURE, AT_SYSINFO_EHDR,
+};
+
+/// 以 \0 结尾的用户栈字符串
+fn c_str(addr: usize) -> &'static str {
+    let s = addr as *const u8;
+    let len = (0usize..)
+        .find(|i| unsafe { s.add(*i).read_volatile() == 0 })
+        .unwrap();
+    core::str::from_utf8(unsafe { core::slice::from_raw_parts(s, len) }).unwrap()
+}
+
+#[no_mangle]
+pub fn main(_argc: usize, argv: &[&str]) -> i32 {
+    assert_eq!(getauxval(AT_PAGESZ), Some(4096));
+    assert_eq!(getauxval(AT_SECURE), Some(0));
+    // 静态链接程序没有动态链接器，直接从 _start 开始执行
+    assert_eq!(getauxval(AT_BASE), Some(0));
+    assert_eq!(getauxval(AT_ENTRY), Some(user_lib::_start as usize));
+
+    // rv64gc 至少包含 IMAFDC 扩展
+    let hwcap = getauxval(AT_HWCAP).unwrap();
+    for ext in b"imafdc" {
+        assert!(hwcap & (1 << (ext - b'a')) != 0);
+    }
+
+    let vdso = getauxval(AT_SYSINFO_EHDR).unwrap();
+    assert!(vdso != 0);
+    assert_eq!(unsafe { (vdso as *const [u8; 4]).read_volatile() }, *b"\x7fELF");
+
+    assert_eq!(c_str(getauxval(AT_PLATFORM).unwrap()), "RISC-V64");
+    assert_eq!(c_str(getauxval(AT_EXECFN).unwrap()), argv[0]);
+    let random = getauxval(AT_RANDOM).unwrap();
+    let bytes = unsafe { (random as *const [u8; 16]).read_volatile() };
+    assert!(bytes.iter().any(|&b| b != 0));
+    println!("auxv_test passed!");
+    0
+}
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::null;
+use user_lib::{close, exec, exit, fork, open, read, waitpid, write, OpenFlags};
+
+const MUSL_LDSO: &[u8] = b"/lib/ld-musl-riscv64-sf.so.1";
+const ALT_LDSO: &[u8] = b"/tmp/ld-alt-riscv64-sf.so.1";
+const MISSING_LDSO: &[u8] = b"/lib/ld-missing.so.1";
+const DYNAMIC_EXE: &str = "/entry-dynamic.exe\0";
+
+/// 复制文件，interp 不为空时把第一块中的 PT_INTERP 路径替换为 interp
+fn copy_file(src: &str, dst: &str, interp: Option<&[u8]>) {
+    let src = open(src, OpenFlags::RDONLY);
+    let dst = open(dst, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(src >= 0 && dst >= 0);
+    let mut buf = [0u8; 4096];
+    let mut first = true;
+    loop {
+        let n = read(src as usize, &mut buf);
+        assert!(n >= 0);
+        if n == 0 {
+            break;
+        }
+        let chunk = &mut buf[..n as usize];
+        if let (true, Some(interp)) = (first, interp) {
+            let pos = chunk
+                .windows(MUSL_LDSO.len())
+                .position(|w| w == MUSL_LDSO)
+                .unwrap();
+            let path = &mut chunk[pos..pos + MUSL_LDSO.len()];
+            path.fill(0);
+            path[..interp.len()].copy_from_slice(interp);
+        }
+        first = false;
+        assert_eq!(write(dst as usize, chunk), n);
+    }
+    close(src as usize);
+    close(dst as usize);
+}
+
+fn exists(path: &str) -> bool {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd >= 0 {
+        close(fd as usize);
+    }
+    fd >= 0
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    if !exists(DYNAMIC_EXE) || !exists("/lib/ld-musl-riscv64-sf.so.1\0") {
+        println!("dynamic_interp_test: no dynamic libc-test in rootfs, skipped");
+        return 0;
+    }
+    // 同一程序分别使用 musl 默认解释器、另一份解释器与不存在的解释器
+    copy_file("/lib/ld-musl-riscv64-sf.so.1\0", "/tmp/ld-alt-riscv64-sf.so.1\0", None);
+    copy_file(DYNAMIC_EXE, "/tmp/dyn_alt\0", Some(ALT_LDSO));
+    copy_file(DYNAMIC_EXE, "/tmp/dyn_missing\0", Some(MISSING_LDSO));
+
+    let args = ["entry-dynamic.exe\0".as_ptr(), "argv\0".as_ptr(), null::<u8>()];
+    let paths = [DYNAMIC_EXE, "/tmp/dyn_alt\0", "/tmp/dyn_missing\0"];
+    let mut pids = [0isize; 6];
+    for (i, pid) in pids.iter_mut().enumerate() {
+        let path = paths[i % paths.len()];
+        *pid = fork();
+        if *pid == 0 {
+            let ret = exec(path, &args);
+            // 只有解释器不存在的程序会执行失败
+            exit(if path == "/tmp/dyn_missing\0" && ret < 0 { 0 } else { -1 });
+        }
+    }
+    for pid in pids {
+        let mut status = 0;
+        assert_eq!(waitpid(pid as usize, &mut status), pid);
+        assert_eq!(status, 0);
+    }
+    println!("dynamic_interp_test passed!");
+    0
+}
@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::null;
+use user_lib::{environ, execve, exit, fork, getenv, waitpid};
+
+const E2BIG: isize = 7;
+
+/// 以 \0 结尾的 4KiB 字符串，重复放入 envp 使总大小超过 ARG_MAX
+static mut BIG: [u8; 4096] = [b'A'; 4096];
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 被自身 execve 启动时检查传入的环境变量
+    if let Some(value) = getenv("ENV_TEST_CHILD") {
+        assert_eq!(value, "1");
+        assert_eq!(getenv("FOO"), Some("bar baz"));
+        assert_eq!(environ().len(), 2);
+        return 0;
+    }
+
+    let pid = fork();
+    if pid == 0 {
+        execve(
+            "env_test\0",
+            &[null::<u8>()],
+            &["ENV_TEST_CHILD=1\0".as_ptr(), "FOO=bar baz\0".as_ptr(), null::<u8>()],
+        );
+        exit(-1);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(status, 0);
+    println!("env_test: envp passed through execve ok");
+
+    // 参数过大时 execve 失败，原程序继续运行
+    let big = unsafe {
+        BIG[4095] = 0;
+        BIG.as_ptr()
+    };
+    let mut envp = [big; 41];
+    envp[40] = null::<u8>();
+    assert_eq!(execve("env_test\0", &[null::<u8>()], &envp), -E2BIG);
+    println!("env_test passed!");
+    0
+}
@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, killpg, setpgid, sleep, waitpid, SIGKILL};
+
+const FORK_COUNT: usize = 100_000;
+/// 与内核的 PID_MAX 相同
+const PID_MAX: isize = 32768;
+
+/// 进程组首进程被回收后，组中仍有进程时其 pid 不会分配给新进程
+fn pgid_stays_reserved() {
+    let leader = fork();
+    if leader == 0 {
+        assert_eq!(setpgid(0, 0), 0);
+        // 组中的另一个进程在首进程退出后继续存在
+        if fork() == 0 {
+            sleep(10_000);
+            exit(0);
+        }
+        exit(0);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(leader as usize, &mut status), leader);
+    for _ in 0..100 {
+        let pid = fork();
+        if pid == 0 {
+            exit(0);
+        }
+        assert!(pid > 0 && pid != leader);
+        assert_eq!(waitpid(pid as usize, &mut status), pid);
+    }
+    // 进程组仍然只包含原来的成员
+    assert_eq!(killpg(leader as usize, SIGKILL), 0);
+}
+
+/// 反复创建并回收短生命周期的进程，pid 与内核栈被回收后才不会耗尽
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut max_pid = 0;
+    for i in 0..FORK_COUNT {
+        let pid = fork();
+        if pid == 0 {
+            exit((i & 0xff) as i32);
+        }
+        assert!(pid > 0, "fork failed at iteration {}: {}", i, pid);
+        max_pid = max_pid.max(pid);
+        let mut status = 0;
+        assert_eq!(waitpid(pid as usize, &mut status), pid);
+        assert_eq!((status >> 8) & 0xff, (i & 0xff) as i32);
+        if (i + 1) % 10_000 == 0 {
+            println!("fork_stress_test: {} processes reaped", i + 1);
+        }
+    }
+    assert!(max_pid < PID_MAX);
+    pgid_stays_reserved();
+    println!("fork_stress_test: pgid reserved ok");
+    println!("fork_stress_test passed! max pid = {}", max_pid);
+    0
+}
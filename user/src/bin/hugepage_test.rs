@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, mmap, munmap, waitpid, MAP_ANONYMOUS, MAP_HUGETLB, MAP_PRIVATE, PROT_READ,
+    PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+const HUGE_PAGE_SIZE: usize = 0x20_0000;
+const LEN: usize = HUGE_PAGE_SIZE * 4;
+
+fn fill(base: usize, len: usize, seed: usize) {
+    for off in (0..len).step_by(PAGE_SIZE) {
+        unsafe { ((base + off) as *mut usize).write_volatile(seed ^ off) };
+    }
+}
+
+fn check(base: usize, len: usize, seed: usize) {
+    for off in (0..len).step_by(PAGE_SIZE) {
+        let v = unsafe { ((base + off) as *const usize).read_volatile() };
+        assert_eq!(v, seed ^ off);
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let prot = PROT_READ | PROT_WRITE;
+    let flags = MAP_PRIVATE | MAP_ANONYMOUS;
+
+    // 大块匿名映射应按2MiB对齐
+    let base = mmap(0, LEN, prot, flags, -1, 0);
+    assert!(base > 0);
+    let base = base as usize;
+    assert_eq!(base % HUGE_PAGE_SIZE, 0);
+    fill(base, LEN, 0x5a5a);
+    check(base, LEN, 0x5a5a);
+    println!("hugepage_test: anonymous mapping at {:#x} ok", base);
+
+    // fork 后父子进程互不影响
+    let pid = fork();
+    if pid == 0 {
+        check(base, LEN, 0x5a5a);
+        fill(base, LEN, 0xa5a5);
+        check(base, LEN, 0xa5a5);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    check(base, LEN, 0x5a5a);
+    println!("hugepage_test: fork ok");
+
+    // 解除第二个大页中间的一段，大页被拆分，其余部分保持不变
+    let hole = base + HUGE_PAGE_SIZE + 16 * PAGE_SIZE;
+    assert_eq!(munmap(hole, 32 * PAGE_SIZE), 0);
+    check(base, HUGE_PAGE_SIZE + 16 * PAGE_SIZE, 0x5a5a);
+    let tail = hole + 32 * PAGE_SIZE;
+    for off in (0..base + LEN - tail).step_by(PAGE_SIZE) {
+        let v = unsafe { ((tail + off) as *const usize).read_volatile() };
+        assert_eq!(v, 0x5a5a ^ (tail + off - base));
+    }
+    assert_eq!(munmap(base, LEN), 0);
+    println!("hugepage_test: munmap split ok");
+
+    // MAP_HUGETLB 的长度按大页取整
+    let huge = mmap(0, PAGE_SIZE, prot, flags | MAP_HUGETLB, -1, 0);
+    assert!(huge > 0);
+    let huge = huge as usize;
+    assert_eq!(huge % HUGE_PAGE_SIZE, 0);
+    fill(huge, HUGE_PAGE_SIZE, 0x1234);
+    check(huge, HUGE_PAGE_SIZE, 0x1234);
+    assert_eq!(munmap(huge, HUGE_PAGE_SIZE), 0);
+    println!("hugepage_test passed!");
+    0
+}
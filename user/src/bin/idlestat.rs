@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{idle_stat, sleep, HartIdleStat};
+
+const MAX_HARTS: usize = 8;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 休眠一段时间，让空闲的 hart 有机会执行 wfi
+    sleep(100);
+    let mut stats = [HartIdleStat::default(); MAX_HARTS];
+    let harts = idle_stat(&mut stats);
+    assert!(harts > 0);
+    println!("hart    idle(ms)    wakeups    ipis");
+    for (hartid, stat) in stats[..harts as usize].iter().enumerate() {
+        println!(
+            "{:<8}{:<12}{:<11}{}",
+            hartid,
+            stat.idle_ns / 1_000_000,
+            stat.wakeups,
+            stat.ipis
+        );
+    }
+    0
+}
@@ -3,12 +3,17 @@
 
 extern crate user_lib;
 
-use user_lib::{exec, fork, wait, yield_};
+use user_lib::{execve, fork, wait, yield_};
 
 #[no_mangle]
 fn main() -> i32 {
     if fork() == 0 {
-        exec("user_shell\0", &[core::ptr::null::<u8>()]);
+        // 初始进程没有环境变量，为 shell 提供默认的 PATH
+        execve(
+            "user_shell\0",
+            &[core::ptr::null::<u8>()],
+            &["PATH=/\0".as_ptr(), core::ptr::null::<u8>()],
+        );
     } else {
         loop {
             let mut exit_code: i32 = 0;
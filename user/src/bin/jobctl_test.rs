@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    fork, get_time, kill, waitpid, waitpid_options, SIGCONT, SIGSTOP, SIGTERM, SIGTSTP,
+    WCONTINUED, WUNTRACED,
+};
+
+/// 子进程一直忙循环，直到被信号终止
+fn spin_forever() -> ! {
+    let mut count = 0usize;
+    loop {
+        count = count.wrapping_add(get_time() as usize);
+    }
+}
+
+fn stop_and_continue(pid: usize, stop_signal: i32) {
+    let mut status: i32 = 0;
+    assert_eq!(kill(pid, stop_signal), 0);
+    assert_eq!(waitpid_options(pid, &mut status, WUNTRACED), pid as isize);
+    // WIFSTOPPED && WSTOPSIG
+    assert_eq!(status & 0xff, 0x7f);
+    assert_eq!((status >> 8) & 0xff, stop_signal);
+
+    assert_eq!(kill(pid, SIGCONT), 0);
+    assert_eq!(waitpid_options(pid, &mut status, WCONTINUED), pid as isize);
+    // WIFCONTINUED
+    assert_eq!(status, 0xffff);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        spin_forever();
+    }
+    let pid = pid as usize;
+    stop_and_continue(pid, SIGSTOP);
+    println!("jobctl_test: SIGSTOP/SIGCONT ok");
+    stop_and_continue(pid, SIGTSTP);
+    println!("jobctl_test: SIGTSTP/SIGCONT ok");
+
+    // 默认动作为终止，WIFSIGNALED && WTERMSIG
+    let mut status: i32 = 0;
+    assert_eq!(kill(pid, SIGTERM), 0);
+    assert_eq!(waitpid(pid, &mut status), pid as isize);
+    assert_eq!(status & 0x7f, SIGTERM);
+    println!("jobctl_test passed!");
+    0
+}
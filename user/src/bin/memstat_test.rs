@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, mmap, open, read, waitpid, OpenFlags, MAP_ANONYMOUS, MAP_PRIVATE,
+    PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const PAGES: usize = 16;
+
+/// 读取 path 的全部内容到 buf，返回长度
+fn read_file(path: &str, buf: &mut [u8]) -> usize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut len = 0;
+    loop {
+        let n = read(fd as usize, &mut buf[len..]);
+        assert!(n >= 0);
+        if n == 0 {
+            break;
+        }
+        len += n as usize;
+    }
+    close(fd as usize);
+    len
+}
+
+/// /proc/self/statm 的 size、resident、shared
+fn statm() -> [usize; 3] {
+    let mut buf = [0u8; 128];
+    let len = read_file("/proc/self/statm\0", &mut buf);
+    let text = core::str::from_utf8(&buf[..len]).unwrap();
+    let mut fields = text.split_whitespace().map(|s| s.parse::<usize>().unwrap());
+    [
+        fields.next().unwrap(),
+        fields.next().unwrap(),
+        fields.next().unwrap(),
+    ]
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let [size, resident, _] = statm();
+    let addr = mmap(
+        0,
+        PAGES * PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        -1,
+        0,
+    );
+    assert!(addr > 0);
+    for i in 0..PAGES {
+        unsafe { ((addr as usize + i * PAGE_SIZE) as *mut u8).write_volatile(1) };
+    }
+    let [new_size, new_resident, _] = statm();
+    assert!(new_size >= size + PAGES);
+    assert!(new_resident >= resident + PAGES);
+
+    // fork 后写时复制的页与父进程共享
+    let pid = fork();
+    if pid == 0 {
+        let [_, _, shared] = statm();
+        exit((shared < PAGES) as i32);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(status, 0);
+
+    let mut buf = [0u8; 256];
+    let len = read_file("/proc/self/status\0", &mut buf);
+    let status = core::str::from_utf8(&buf[..len]).unwrap();
+    for field in ["VmSize:", "VmLck:", "VmHWM:", "VmRSS:"] {
+        assert!(status.contains(field));
+    }
+    println!("memstat_test passed!");
+    0
+}
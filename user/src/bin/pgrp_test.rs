@@ -0,0 +1,77 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, getpgid, getpid, getsid, killpg, setpgid, setsid, sleep, waitpgid, waitpid,
+    SIGTERM,
+};
+
+const EPERM: isize = 1;
+const ESRCH: isize = 3;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let me = getpid() as usize;
+    let sid = getsid(0);
+    assert_eq!(getpgid(0), getpgid(me));
+
+    // 新建以自身为首进程的进程组，子进程继承进程组与会话
+    assert_eq!(setpgid(0, 0), 0);
+    assert_eq!(getpgid(0), me as isize);
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(getpgid(0), me as isize);
+        assert_eq!(getsid(0), sid);
+        exit(0);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    // 进程组首进程不能创建新会话
+    assert_eq!(setsid(), -EPERM);
+    println!("pgrp_test: setpgid/getpgid ok");
+
+    // 子进程各自组成新的进程组，向整个进程组发送信号并按进程组等待
+    let first = fork();
+    if first == 0 {
+        assert_eq!(setpgid(0, 0), 0);
+        sleep(1000);
+        exit(1);
+    }
+    let first = first as usize;
+    assert_eq!(setpgid(first, first), 0);
+    let second = fork();
+    if second == 0 {
+        assert_eq!(setpgid(0, first), 0);
+        sleep(1000);
+        exit(1);
+    }
+    assert_eq!(setpgid(second as usize, first), 0);
+    assert_eq!(getpgid(second as usize), first as isize);
+    assert_eq!(killpg(first, SIGTERM), 0);
+    for _ in 0..2 {
+        let pid = waitpgid(first, &mut status, 0);
+        assert!(pid == first as isize || pid == second);
+        assert_eq!(status & 0x7f, SIGTERM);
+    }
+    assert_eq!(killpg(first, SIGTERM), -ESRCH);
+    println!("pgrp_test: kill/wait process group ok");
+
+    // 非首进程的子进程可以创建新会话，并成为新会话与进程组的首进程
+    let pid = fork();
+    if pid == 0 {
+        let pid = getpid();
+        assert_eq!(setsid(), pid);
+        assert_eq!(getsid(0), pid);
+        assert_eq!(getpgid(0), pid);
+        // 不能把其他会话的进程加入本会话的进程组
+        assert_eq!(setpgid(0, me), -EPERM);
+        exit(0);
+    }
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(status, 0);
+    println!("pgrp_test passed!");
+    0
+}
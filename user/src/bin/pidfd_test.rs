@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, fork_pidfd, pidfd_open, pidfd_send_signal, ppoll, sleep, waitid, waitpid,
+    PollFd, SigInfo, CLD_EXITED, PIDFD_NONBLOCK, POLLIN, P_PIDFD, SIGKILL, WEXITED,
+};
+
+/// pidfd 是否可读（进程已退出）
+fn exited(pidfd: usize) -> bool {
+    let mut fds = [PollFd { fd: pidfd as i32, events: POLLIN, revents: 0 }];
+    ppoll(&mut fds, Some(&[0, 0])) == 1 && fds[0].revents & POLLIN != 0
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // CLONE_PIDFD：通过 pidfd 等待子进程退出
+    let mut pidfd = -1;
+    let pid = fork_pidfd(&mut pidfd);
+    if pid == 0 {
+        sleep(50);
+        exit(9);
+    }
+    assert!(pid > 0 && pidfd >= 0);
+    let pidfd = pidfd as usize;
+    assert!(!exited(pidfd));
+    let mut fds = [PollFd { fd: pidfd as i32, events: POLLIN, revents: 0 }];
+    assert_eq!(ppoll(&mut fds, None), 1);
+    let mut info = SigInfo::new(0, 0);
+    assert_eq!(waitid(P_PIDFD, pidfd, &mut info, WEXITED), 0);
+    assert_eq!(info.pid(), pid as i32);
+    assert_eq!(info.code, CLD_EXITED);
+    assert_eq!(info.status(), 9);
+    // 进程已被回收，pidfd 仍然可读，但不能再发送信号或等待
+    assert!(exited(pidfd));
+    assert!(pidfd_send_signal(pidfd, SIGKILL) < 0);
+    assert!(waitid(P_PIDFD, pidfd, &mut info, WEXITED) < 0);
+    close(pidfd);
+    println!("pidfd_test: CLONE_PIDFD ok");
+
+    // pidfd_open 与 pidfd_send_signal
+    let pid = fork();
+    if pid == 0 {
+        sleep(10_000);
+        exit(0);
+    }
+    let pidfd = pidfd_open(pid as usize, PIDFD_NONBLOCK);
+    assert!(pidfd >= 0);
+    let pidfd = pidfd as usize;
+    // 非阻塞的 pidfd 在子进程运行时返回 EAGAIN
+    assert!(waitid(P_PIDFD, pidfd, &mut info, WEXITED) < 0);
+    assert_eq!(pidfd_send_signal(pidfd, SIGKILL), 0);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(status & 0x7f, SIGKILL);
+    assert!(exited(pidfd));
+    close(pidfd);
+
+    // 不存在或已被回收的进程不能打开 pidfd
+    assert!(pidfd_open(0, 0) < 0);
+    assert!(pidfd_open(pid as usize, 0) < 0);
+    println!("pidfd_test passed!");
+    0
+}
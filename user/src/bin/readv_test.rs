@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, pipe, read, readv, setpgid, sigaction, sleep, tcgetpgrp, waitpid, writev,
+    IOVec, SigAction, SIGTTIN, SIG_IGN,
+};
+
+const CHUNK_SIZE: usize = 0x10000;
+static CHUNK: [u8; CHUNK_SIZE] = [b'x'; CHUNK_SIZE];
+
+fn wait_ok(pid: isize) {
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(status, 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // readv 在空管道上阻塞，直到另一端 writev
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(fds[1]);
+        let mut head = [0u8; 5];
+        let mut tail = [0u8; 6];
+        let n = readv(fds[0], &[IOVec::new_mut(&mut head), IOVec::new_mut(&mut tail)]);
+        assert_eq!(n, 11);
+        assert_eq!(&head, b"hello");
+        assert_eq!(&tail, b" world");
+        exit(0);
+    }
+    close(fds[0]);
+    sleep(50);
+    assert_eq!(writev(fds[1], &[IOVec::new(b"hello"), IOVec::new(b" world")]), 11);
+    close(fds[1]);
+    wait_ok(pid);
+    println!("readv_test: readv on empty pipe ok");
+
+    // writev 超出管道缓冲区时阻塞，直到读端取走数据
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(fds[1]);
+        sleep(50);
+        let mut buf = [0u8; 4096];
+        let mut total = 0;
+        loop {
+            let n = read(fds[0], &mut buf);
+            if n <= 0 {
+                break;
+            }
+            assert!(buf[..n as usize].iter().all(|&c| c == b'x'));
+            total += n as usize;
+        }
+        assert_eq!(total, 3 * CHUNK_SIZE);
+        exit(0);
+    }
+    close(fds[0]);
+    let iov = [IOVec::new(&CHUNK), IOVec::new(&CHUNK), IOVec::new(&CHUNK)];
+    assert_eq!(writev(fds[1], &iov), (3 * CHUNK_SIZE) as isize);
+    close(fds[1]);
+    wait_ok(pid);
+    println!("readv_test: writev on full pipe ok");
+
+    // 后台进程组忽略 SIGTTIN 时 readv 终端返回 EIO，而不是阻塞或死锁
+    if tcgetpgrp(0) >= 0 {
+        let pid = fork();
+        if pid == 0 {
+            assert_eq!(setpgid(0, 0), 0);
+            let ignore = SigAction { handler: SIG_IGN, flags: 0, mask: 0 };
+            assert_eq!(sigaction(SIGTTIN, Some(&ignore), None), 0);
+            let mut buf = [0u8; 8];
+            assert!(readv(0, &[IOVec::new_mut(&mut buf)]) < 0);
+            exit(0);
+        }
+        wait_ok(pid);
+        println!("readv_test: readv on stdin ok");
+    }
+    println!("readv_test passed!");
+    0
+}
@@ -0,0 +1,192 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::null;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    brk, dup, exec, exit, fcntl, fork, getpid, getrlimit, kill, mmap, open, pidfd_open, pipe,
+    sendfile, setrlimit, sigaction, waitpid, write, OpenFlags, RLimit, SigAction, F_DUPFD,
+    MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ,
+    PROT_WRITE, RLIMIT_AS, RLIMIT_CORE, RLIMIT_CPU, RLIMIT_DATA, RLIMIT_FSIZE, RLIMIT_NOFILE,
+    RLIMIT_NPROC, RLIMIT_STACK, RLIM_INFINITY, SIGABRT, SIGKILL, SIGXCPU, SIGXFSZ, SIG_IGN,
+};
+
+const E2BIG: isize = 7;
+const EMFILE: isize = 24;
+const ARG_MAX: usize = 128 * 1024;
+
+static XCPU_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn xcpu_count(_signum: i32) {
+    XCPU_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+fn set(resource: usize, cur: usize, max: usize) {
+    assert_eq!(setrlimit(resource, &RLimit { cur, max }), 0);
+}
+
+/// 在子进程中运行 f，返回 wait 状态
+fn run_child(f: fn() -> i32) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        exit(f());
+    }
+    assert!(pid > 0);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    status
+}
+
+fn nofile() -> i32 {
+    set(RLIMIT_NOFILE, 8, RLIM_INFINITY);
+    let mut last = 0;
+    loop {
+        let fd = dup(0);
+        if fd < 0 {
+            break;
+        }
+        last = fd;
+    }
+    assert_eq!(last, 7);
+    // 所有分配描述符的系统调用都受 RLIMIT_NOFILE 限制
+    assert_eq!(dup(0), -EMFILE);
+    assert_eq!(open("/rlimit_test\0", OpenFlags::RDONLY), -EMFILE);
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), -EMFILE);
+    assert_eq!(fcntl(0, F_DUPFD, 0), -EMFILE);
+    assert_eq!(pidfd_open(getpid() as usize, 0), -EMFILE);
+    0
+}
+
+fn fsize() -> i32 {
+    let ignore = SigAction { handler: SIG_IGN, flags: 0, mask: 0 };
+    assert_eq!(sigaction(SIGXFSZ, Some(&ignore), None), 0);
+    let fd = open("/tmp/rlimit_fsize\0", OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    let src = open("/tmp/rlimit_src\0", OpenFlags::CREATE | OpenFlags::RDWR | OpenFlags::TRUNC);
+    assert!(src >= 0);
+    assert_eq!(write(src as usize, b"0123456789"), 10);
+    set(RLIMIT_FSIZE, 4, RLIM_INFINITY);
+    // 超出部分不写入，已达上限时返回 EFBIG
+    assert_eq!(write(fd as usize, b"01"), 2);
+    assert_eq!(sendfile(fd as usize, src as usize, Some(&mut 0), 10), 2);
+    assert!(write(fd as usize, b"x") < 0);
+    assert!(sendfile(fd as usize, src as usize, Some(&mut 0), 10) < 0);
+    // 不再忽略时被 SIGXFSZ 终止
+    assert_eq!(sigaction(SIGXFSZ, Some(&SigAction::default()), None), 0);
+    write(fd as usize, b"x");
+    1
+}
+
+fn memory() -> i32 {
+    const LEN: usize = 2 << 20;
+    set(RLIMIT_DATA, 1 << 20, RLIM_INFINITY);
+    // 堆超出 RLIMIT_DATA 时 brk 返回原来的堆顶
+    let top = brk(0);
+    assert_eq!(brk(top as usize + LEN), top);
+    assert!(mmap(0, LEN, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0) < 0);
+    // 只读映射不计入 RLIMIT_DATA
+    assert!(mmap(0, LEN, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0) > 0);
+    set(RLIMIT_AS, 4096, RLIM_INFINITY);
+    assert!(mmap(0, 4096, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0) < 0);
+    0
+}
+
+fn nproc() -> i32 {
+    set(RLIMIT_NPROC, 1, RLIM_INFINITY);
+    assert!(fork() < 0);
+    0
+}
+
+/// 以此长度的参数 exec 自身时，检查参数后直接退出
+const BIG_ARG_LEN: usize = 64 * 1024;
+static mut ARG: [u8; ARG_MAX] = [0; ARG_MAX];
+
+fn stack() -> i32 {
+    // 参数与环境变量的大小不受 RLIMIT_STACK 限制，总共不超过 ARG_MAX
+    set(RLIMIT_STACK, 16 * 1024, RLIM_INFINITY);
+    let arg = unsafe { &mut *core::ptr::addr_of_mut!(ARG) };
+    arg.fill(b'a');
+    arg[ARG_MAX - 1] = 0;
+    let mut args = [null::<u8>(); 3];
+    args[0] = "rlimit_test\0".as_ptr();
+    args[1] = arg.as_ptr();
+    assert_eq!(exec("rlimit_test\0", &args), -E2BIG);
+    arg[BIG_ARG_LEN] = 0;
+    exec("rlimit_test\0", &args);
+    -1
+}
+
+fn cpu_soft() -> i32 {
+    let action = SigAction { handler: xcpu_count as usize, flags: 0, mask: 0 };
+    assert_eq!(sigaction(SIGXCPU, Some(&action), None), 0);
+    set(RLIMIT_CPU, 1, 10);
+    // 超过软限制后每秒收到一次 SIGXCPU
+    while XCPU_COUNT.load(Ordering::SeqCst) < 2 {
+        core::hint::spin_loop();
+    }
+    let mut limit = RLimit::default();
+    getrlimit(RLIMIT_CPU, &mut limit);
+    (limit.cur != 3) as i32
+}
+
+fn cpu_hard() -> i32 {
+    let ignore = SigAction { handler: SIG_IGN, flags: 0, mask: 0 };
+    assert_eq!(sigaction(SIGXCPU, Some(&ignore), None), 0);
+    // 达到硬限制时被 SIGKILL 终止
+    set(RLIMIT_CPU, 1, 1);
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+fn core_dump() -> i32 {
+    set(RLIMIT_CORE, RLIM_INFINITY, RLIM_INFINITY);
+    kill(getpid() as usize, SIGABRT);
+    0
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 2 {
+        return (argv[1].len() != BIG_ARG_LEN) as i32;
+    }
+    let mut limit = RLimit::default();
+    assert_eq!(getrlimit(RLIMIT_CORE, &mut limit), 0);
+    assert_eq!(limit.cur, 0);
+    assert!(setrlimit(RLIMIT_CORE, &RLimit { cur: 2, max: 1 }) < 0);
+    assert!(getrlimit(16, &mut limit) < 0);
+
+    assert_eq!(run_child(nofile), 0);
+    println!("rlimit_test: RLIMIT_NOFILE ok");
+    assert_eq!(run_child(fsize), SIGXFSZ);
+    println!("rlimit_test: RLIMIT_FSIZE ok");
+    assert_eq!(run_child(memory), 0);
+    println!("rlimit_test: RLIMIT_DATA and RLIMIT_AS ok");
+    assert_eq!(run_child(nproc), 0);
+    println!("rlimit_test: RLIMIT_NPROC ok");
+    assert_eq!(run_child(stack), 0);
+    println!("rlimit_test: RLIMIT_STACK ok");
+    assert_eq!(run_child(cpu_soft), 0);
+    assert_eq!(run_child(cpu_hard), SIGKILL);
+    println!("rlimit_test: RLIMIT_CPU ok");
+    // RLIMIT_CORE 不为零时在状态中标记 core dump（为零时见上面的 SIGXFSZ）
+    assert_eq!(run_child(core_dump), SIGABRT | 0x80);
+    println!("rlimit_test: RLIMIT_CORE ok");
+
+    // 资源限制由子进程继承
+    set(RLIMIT_NOFILE, 64, RLIM_INFINITY);
+    assert_eq!(
+        run_child(|| {
+            let mut limit = RLimit::default();
+            getrlimit(RLIMIT_NOFILE, &mut limit);
+            (limit.cur != 64) as i32
+        }),
+        0
+    );
+    println!("rlimit_test passed!");
+    0
+}
@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    getpid, kill, prlimit, sigmask_of, sigpending, sigprocmask, sigqueue, sigtimedwait, RLimit,
+    SigInfo, RLIMIT_SIGPENDING, SIGRTMIN, SIGUSR1, SIG_BLOCK, SI_QUEUE, SI_USER,
+};
+
+const EAGAIN: isize = 11;
+
+/// 立即返回的超时
+const NO_WAIT: [usize; 2] = [0, 0];
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let me = getpid() as usize;
+    let set = sigmask_of(SIGRTMIN) | sigmask_of(SIGUSR1);
+    assert_eq!(sigprocmask(SIG_BLOCK, Some(&set), None), 0);
+
+    // 实时信号逐个排队，标准信号重复发送时合并
+    for value in 1..=3 {
+        assert_eq!(sigqueue(me, SIGRTMIN, value), 0);
+    }
+    assert_eq!(kill(me, SIGUSR1), 0);
+    assert_eq!(kill(me, SIGUSR1), 0);
+    let mut pending = 0u64;
+    assert_eq!(sigpending(&mut pending), 0);
+    assert_eq!(pending, set);
+
+    // 标准信号先于实时信号取出
+    let mut info = SigInfo::new(0, 0);
+    assert_eq!(sigtimedwait(set, Some(&mut info), Some(&NO_WAIT)), SIGUSR1 as isize);
+    assert_eq!(info.code, SI_USER);
+    assert_eq!(info.pid(), me as i32);
+    for value in 1..=3 {
+        assert_eq!(sigtimedwait(set, Some(&mut info), Some(&NO_WAIT)), SIGRTMIN as isize);
+        assert_eq!(info.code, SI_QUEUE);
+        assert_eq!(info.value(), value);
+    }
+    assert_eq!(sigtimedwait(set, None, Some(&NO_WAIT)), -EAGAIN);
+    assert_eq!(sigpending(&mut pending), 0);
+    assert_eq!(pending, 0);
+    println!("rtsig_test: queueing and sigtimedwait ok");
+
+    // 超出 RLIMIT_SIGPENDING 的实时信号被拒绝
+    let limit = RLimit { cur: 2, max: 2 };
+    assert_eq!(prlimit(RLIMIT_SIGPENDING, Some(&limit), None), 0);
+    assert_eq!(sigqueue(me, SIGRTMIN, 1), 0);
+    assert_eq!(sigqueue(me, SIGRTMIN, 2), 0);
+    assert_eq!(sigqueue(me, SIGRTMIN, 3), -EAGAIN);
+    assert_eq!(sigtimedwait(set, None, Some(&NO_WAIT)), SIGRTMIN as isize);
+    assert_eq!(sigqueue(me, SIGRTMIN, 3), 0);
+    println!("rtsig_test: RLIMIT_SIGPENDING ok");
+
+    println!("rtsig_test passed!");
+    0
+}
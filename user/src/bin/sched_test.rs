@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, get_time, getpriority, sched_getaffinity, sched_getcpu, sched_getscheduler,
+    sched_setaffinity, sched_setscheduler, setpgid, setpriority, waitpid, yield_, PRIO_PGRP,
+    PRIO_PROCESS, PRIO_USER, SCHED_OTHER, SCHED_RR,
+};
+
+const RUN_MS: isize = 300;
+
+/// 在给定时间内忙循环，返回完成的迭代次数
+fn spin(ms: isize) -> usize {
+    let end = get_time() + ms;
+    let mut count = 0;
+    while get_time() < end {
+        count += 1;
+    }
+    count
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // nice 值的设置与读取
+    assert_eq!(getpriority(PRIO_PROCESS, 0), 0);
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 5), 0);
+    assert_eq!(getpriority(PRIO_PROCESS, 0), 5);
+    // 超出范围的 nice 值被截断
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 100), 0);
+    assert_eq!(getpriority(PRIO_PROCESS, 0), 19);
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 0), 0);
+    println!("sched_test: nice ok");
+
+    // 子进程继承 nice 值
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 3), 0);
+    let pid = fork();
+    if pid == 0 {
+        exit(getpriority(PRIO_PROCESS, 0) as i32);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!((exit_code >> 8) & 0xff, 3);
+    assert_eq!(setpriority(PRIO_PROCESS, 0, 0), 0);
+    println!("sched_test: inherit ok");
+
+    // 按进程组与用户设置 nice 值，所有进程都属于 uid 0
+    assert!(setpriority(7, 0, 0) < 0);
+    assert!(getpriority(PRIO_USER, 1) < 0);
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setpgid(0, 0), 0);
+        assert_eq!(setpriority(PRIO_PGRP, 0, 4), 0);
+        exit((getpriority(PRIO_PGRP, 0) == 4 && getpriority(PRIO_PROCESS, 0) == 4) as i32);
+    }
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!((exit_code >> 8) & 0xff, 1);
+    assert_eq!(getpriority(PRIO_PROCESS, 0), 0);
+    println!("sched_test: pgrp and user ok");
+
+    // 调度策略参数检查
+    assert!(sched_setscheduler(0, 7, 0) < 0);
+    assert!(sched_setscheduler(0, SCHED_RR, 0) < 0);
+    assert!(sched_setscheduler(0, SCHED_OTHER, 1) < 0);
+    assert_eq!(sched_setscheduler(0, SCHED_RR, 10), 0);
+    assert_eq!(sched_getscheduler(0), SCHED_RR as isize);
+    assert_eq!(sched_setscheduler(0, SCHED_OTHER, 0), 0);
+    assert_eq!(sched_getscheduler(0), SCHED_OTHER as isize);
+    println!("sched_test: policy ok");
+
+    // 绑定到单个 hart 后只在该 hart 上运行
+    let online = sched_getaffinity(0);
+    assert!(online > 0);
+    assert!(sched_setaffinity(0, 0) < 0);
+    let mut cpu = 0;
+    while online & (1 << cpu) == 0 {
+        cpu += 1;
+    }
+    assert_eq!(sched_setaffinity(0, 1 << cpu), 0);
+    assert_eq!(sched_getaffinity(0), 1 << cpu);
+    for _ in 0..10 {
+        yield_();
+        assert_eq!(sched_getcpu(), cpu);
+    }
+    assert_eq!(sched_setaffinity(0, online as usize), 0);
+    println!("sched_test: affinity ok, online mask {:#x}", online);
+
+    // nice 值较小的进程应获得更多CPU时间
+    let mut pids = [0isize; 2];
+    for (i, nice) in [0isize, 10].iter().enumerate() {
+        let pid = fork();
+        if pid == 0 {
+            setpriority(PRIO_PROCESS, 0, *nice);
+            let count = spin(RUN_MS);
+            println!("sched_test: nice {} ran {} iterations", nice, count);
+            exit(0);
+        }
+        pids[i] = pid;
+    }
+    for pid in pids.iter() {
+        assert_eq!(waitpid(*pid as usize, &mut exit_code), *pid);
+    }
+    println!("sched_test passed!");
+    0
+}
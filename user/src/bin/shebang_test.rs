@@ -0,0 +1,61 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::null;
+use user_lib::{close, exec, exit, fork, open, waitpid, write, OpenFlags};
+
+const ENOEXEC: isize = 8;
+const ELOOP: isize = 40;
+
+fn write_file(path: &str, content: &str) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, content.as_bytes()), content.len() as isize);
+    close(fd as usize);
+}
+
+/// 在子进程中执行 path，返回其退出码
+fn run(path: &str, args: &[*const u8]) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        exec(path, args);
+        exit(-1);
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    (status >> 8) & 0xff
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    // 作为脚本的解释器运行：argv 为解释器、#! 行中的参数、脚本路径与原来的其余参数
+    if argc > 1 && argv[1] == "interp-arg  two" {
+        assert_eq!(argv[0], "/shebang_test");
+        return match &argv[2..] {
+            ["/tmp/shebang_script", "extra"] => 42,
+            ["/tmp/shebang_script", "/tmp/shebang_nested", "extra"] => 43,
+            _ => 1,
+        };
+    }
+
+    write_file("/tmp/shebang_script\0", "#!/shebang_test   interp-arg  two \necho unused\n");
+    let args = ["shebang_script\0".as_ptr(), "extra\0".as_ptr(), null::<u8>()];
+    assert_eq!(run("/tmp/shebang_script\0", &args), 42);
+    println!("shebang_test: interpreter with argument ok");
+
+    // 解释器本身是脚本
+    write_file("/tmp/shebang_nested\0", "#!/tmp/shebang_script\n");
+    assert_eq!(run("/tmp/shebang_nested\0", &args), 43);
+    println!("shebang_test: nested interpreter ok");
+
+    // 解释器嵌套过深与未知格式，execve 失败后原程序继续运行
+    write_file("/tmp/shebang_loop\0", "#!/tmp/shebang_loop\n");
+    assert_eq!(exec("/tmp/shebang_loop\0", &args), -ELOOP);
+    write_file("/tmp/shebang_bad\0", "not an executable\n");
+    assert_eq!(exec("/tmp/shebang_bad\0", &args), -ENOEXEC);
+    println!("shebang_test passed!");
+    0
+}
@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use user_lib::{
+    exit, getpid, kill, sigaction, sigaltstack, SigAction, SigInfo, SignalStack, UContext,
+    SA_ONSTACK, SA_SIGINFO, SEGV_MAPERR, SIGSEGV, SIGUSR1, SI_USER, SS_DISABLE, SS_ONSTACK,
+};
+
+const ALT_STACK_SIZE: usize = 16384;
+const BAD_ADDR: usize = 0x10;
+
+static mut ALT_STACK: [u8; ALT_STACK_SIZE] = [0; ALT_STACK_SIZE];
+
+static SENDER_PID: AtomicI32 = AtomicI32::new(0);
+static SENDER_CODE: AtomicI32 = AtomicI32::new(-1);
+static FAULT_ADDR: AtomicUsize = AtomicUsize::new(0);
+static FAULT_CODE: AtomicI32 = AtomicI32::new(0);
+static HANDLER_SP: AtomicUsize = AtomicUsize::new(0);
+static REPORTED_ONSTACK: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn usr1_info(_signum: i32, info: *const SigInfo, _uc: *mut UContext) {
+    let info = unsafe { &*info };
+    SENDER_PID.store(info.pid(), Ordering::SeqCst);
+    SENDER_CODE.store(info.code, Ordering::SeqCst);
+}
+
+/// 在备用栈上处理访存错误，修改 ucontext 中的 pc 跳过出错的指令
+extern "C" fn segv_handler(_signum: i32, info: *const SigInfo, uc: *mut UContext) {
+    let info = unsafe { &*info };
+    FAULT_ADDR.store(info.addr(), Ordering::SeqCst);
+    FAULT_CODE.store(info.code, Ordering::SeqCst);
+    let local = 0u8;
+    HANDLER_SP.store(&local as *const u8 as usize, Ordering::SeqCst);
+    let mut old = SignalStack::default();
+    assert_eq!(sigaltstack(None, Some(&mut old)), 0);
+    REPORTED_ONSTACK.store(old.flags & SS_ONSTACK != 0, Ordering::SeqCst);
+    unsafe {
+        (*uc).gregs[0] = after_fault as usize;
+    }
+}
+
+extern "C" fn after_fault() -> ! {
+    assert_eq!(FAULT_ADDR.load(Ordering::SeqCst), BAD_ADDR);
+    assert_eq!(FAULT_CODE.load(Ordering::SeqCst), SEGV_MAPERR);
+    let stack_base = unsafe { ALT_STACK.as_ptr() as usize };
+    let sp = HANDLER_SP.load(Ordering::SeqCst);
+    assert!(sp >= stack_base && sp < stack_base + ALT_STACK_SIZE);
+    assert!(REPORTED_ONSTACK.load(Ordering::SeqCst));
+    println!("sigaltstack_test: SIGSEGV on alternate stack ok");
+    println!("sigaltstack_test passed!");
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // SA_SIGINFO 处理函数得到发送者的 pid
+    let action = SigAction {
+        handler: usr1_info as usize,
+        flags: SA_SIGINFO,
+        mask: 0,
+    };
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    kill(getpid() as usize, SIGUSR1);
+    assert_eq!(SENDER_PID.load(Ordering::SeqCst), getpid() as i32);
+    assert_eq!(SENDER_CODE.load(Ordering::SeqCst), SI_USER);
+    println!("sigaltstack_test: siginfo si_pid ok");
+
+    // 未设置时报告 SS_DISABLE，过小的栈被拒绝
+    let mut old = SignalStack::default();
+    assert_eq!(sigaltstack(None, Some(&mut old)), 0);
+    assert_eq!(old.flags, SS_DISABLE);
+    let stack = SignalStack {
+        sp: unsafe { ALT_STACK.as_ptr() as usize },
+        flags: 0,
+        size: 16,
+    };
+    assert!(sigaltstack(Some(&stack), None) < 0);
+    let stack = SignalStack {
+        size: ALT_STACK_SIZE,
+        ..stack
+    };
+    assert_eq!(sigaltstack(Some(&stack), None), 0);
+
+    let action = SigAction {
+        handler: segv_handler as usize,
+        flags: SA_SIGINFO | SA_ONSTACK,
+        mask: 0,
+    };
+    assert_eq!(sigaction(SIGSEGV, Some(&action), None), 0);
+    unsafe {
+        core::ptr::write_volatile(BAD_ADDR as *mut u8, 1);
+    }
+    panic!("sigaltstack_test: returned to the faulting instruction");
+}
@@ -0,0 +1,144 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    close, exit, fork, getpid, kill, pipe, read, sigaction, sigmask_of, sigprocmask, sleep,
+    waitpid, write, SigAction, SA_NODEFER, SA_RESETHAND, SA_RESTART, SIGUSR1, SIGUSR2,
+    SIG_BLOCK, SIG_DFL, SIG_SETMASK,
+};
+
+const EINTR: isize = 4;
+
+static USR1_COUNT: AtomicUsize = AtomicUsize::new(0);
+static USR2_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// SIGUSR1 处理函数运行期间观察到的 SIGUSR2 处理次数
+static USR2_SEEN_IN_USR1: AtomicUsize = AtomicUsize::new(0);
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+fn install(signum: i32, handler: usize, flags: usize, mask: u64) {
+    let action = SigAction {
+        handler,
+        flags,
+        mask,
+    };
+    assert_eq!(sigaction(signum, Some(&action), None), 0);
+}
+
+extern "C" fn usr1_raise_usr2(_signum: i32) {
+    USR1_COUNT.fetch_add(1, Ordering::SeqCst);
+    kill(getpid() as usize, SIGUSR2);
+    USR2_SEEN_IN_USR1.store(USR2_COUNT.load(Ordering::SeqCst), Ordering::SeqCst);
+}
+
+extern "C" fn usr2_count(_signum: i32) {
+    USR2_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+/// 在处理函数中再次发送自身，SA_NODEFER 时可嵌套两层
+extern "C" fn usr1_recursive(_signum: i32) {
+    let depth = DEPTH.fetch_add(1, Ordering::SeqCst) + 1;
+    MAX_DEPTH.fetch_max(depth, Ordering::SeqCst);
+    if depth == 1 {
+        kill(getpid() as usize, SIGUSR1);
+    }
+    DEPTH.fetch_sub(1, Ordering::SeqCst);
+}
+
+fn reset_counters() {
+    USR1_COUNT.store(0, Ordering::SeqCst);
+    USR2_COUNT.store(0, Ordering::SeqCst);
+    USR2_SEEN_IN_USR1.store(0, Ordering::SeqCst);
+    MAX_DEPTH.store(0, Ordering::SeqCst);
+}
+
+/// 子进程稍后向父进程发送 SIGUSR1 再写入管道，返回父进程 read 的结果
+fn read_interrupted_by_signal() -> (isize, u8) {
+    let ppid = getpid() as usize;
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(fds[0]);
+        sleep(20);
+        kill(ppid, SIGUSR1);
+        sleep(20);
+        write(fds[1], &[42u8]);
+        exit(0);
+    }
+    close(fds[1]);
+    let mut buf = [0u8; 1];
+    let ret = read(fds[0], &mut buf);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    close(fds[0]);
+    (ret, buf[0])
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let me = getpid() as usize;
+
+    // sa_mask 中的信号在处理函数返回后才递送
+    install(SIGUSR2, usr2_count as usize, 0, 0);
+    install(SIGUSR1, usr1_raise_usr2 as usize, 0, sigmask_of(SIGUSR2));
+    kill(me, SIGUSR1);
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), 1);
+    assert_eq!(USR2_SEEN_IN_USR1.load(Ordering::SeqCst), 0);
+    assert_eq!(USR2_COUNT.load(Ordering::SeqCst), 1);
+    println!("signal_test: sa_mask ok");
+
+    // 未被屏蔽的信号在处理函数中嵌套递送
+    reset_counters();
+    install(SIGUSR1, usr1_raise_usr2 as usize, 0, 0);
+    kill(me, SIGUSR1);
+    assert_eq!(USR2_SEEN_IN_USR1.load(Ordering::SeqCst), 1);
+    println!("signal_test: nested delivery ok");
+
+    // 默认屏蔽信号自身，SA_NODEFER 时允许递归
+    reset_counters();
+    install(SIGUSR1, usr1_recursive as usize, 0, 0);
+    kill(me, SIGUSR1);
+    assert_eq!(MAX_DEPTH.load(Ordering::SeqCst), 1);
+    reset_counters();
+    install(SIGUSR1, usr1_recursive as usize, SA_NODEFER, 0);
+    kill(me, SIGUSR1);
+    assert_eq!(MAX_DEPTH.load(Ordering::SeqCst), 2);
+    println!("signal_test: SA_NODEFER ok");
+
+    // SA_RESETHAND 递送一次后恢复为默认处理
+    reset_counters();
+    install(SIGUSR2, usr2_count as usize, SA_RESETHAND, 0);
+    kill(me, SIGUSR2);
+    assert_eq!(USR2_COUNT.load(Ordering::SeqCst), 1);
+    let mut old = SigAction::default();
+    assert_eq!(sigaction(SIGUSR2, None, Some(&mut old)), 0);
+    assert_eq!(old.handler, SIG_DFL);
+    println!("signal_test: SA_RESETHAND ok");
+
+    // 处理函数返回后恢复原来的信号掩码
+    let block = sigmask_of(SIGUSR2);
+    let mut old_mask = 0u64;
+    assert_eq!(sigprocmask(SIG_BLOCK, Some(&block), Some(&mut old_mask)), 0);
+    install(SIGUSR1, usr2_count as usize, 0, 0);
+    kill(me, SIGUSR1);
+    let mut mask = 0u64;
+    assert_eq!(sigprocmask(SIG_BLOCK, None, Some(&mut mask)), 0);
+    assert_eq!(mask, old_mask | block);
+    assert_eq!(sigprocmask(SIG_SETMASK, Some(&old_mask), None), 0);
+    println!("signal_test: sigmask restore ok");
+
+    // 阻塞的 read 被打断：无 SA_RESTART 时返回 EINTR，否则自动重启
+    install(SIGUSR1, usr2_count as usize, 0, 0);
+    assert_eq!(read_interrupted_by_signal().0, -EINTR);
+    install(SIGUSR1, usr2_count as usize, SA_RESTART, 0);
+    assert_eq!(read_interrupted_by_signal(), (1, 42));
+    println!("signal_test: EINTR/SA_RESTART ok");
+
+    println!("signal_test passed!");
+    0
+}
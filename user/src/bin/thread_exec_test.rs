@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use core::ptr::null;
+use user_lib::{
+    execve, exit, exit_group, fork, getenv, getpid, gettid, sched_setaffinity, sleep,
+    thread_create, waitpid,
+};
+
+static mut THREAD_STACK: [u8; 16384] = [0; 16384];
+
+/// 在子线程中 fork 与 execve
+extern "C" fn worker(_arg: usize) -> i32 {
+    let pid = getpid();
+    assert_ne!(gettid(), pid);
+
+    // fork 只复制调用线程，它成为子进程的主线程
+    let child = fork();
+    if child == 0 {
+        exit(if getpid() == gettid() { 7 } else { 1 });
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(child as usize, &mut status), child);
+    assert_eq!((status >> 8) & 0xff, 7);
+    println!("thread_exec_test: fork from a thread ok");
+
+    // execve 终止主线程，调用线程以原来的 pid 执行新程序
+    let env = format!("ORIG_PID={}\0", pid);
+    execve(
+        "thread_exec_test\0",
+        &["thread_exec_test\0".as_ptr(), "exec\0".as_ptr(), null::<u8>()],
+        &[env.as_ptr(), null::<u8>()],
+    );
+    1
+}
+
+/// 兄弟线程在另一个 hart 上忙循环时 execve 或 exit_group
+extern "C" fn spin_worker(arg: usize) -> i32 {
+    // 只有一个 hart 时设置失败，两个线程在同一 hart 上轮流运行
+    sched_setaffinity(0, 1 << 1);
+    sleep(50);
+    if arg == 0 {
+        exit_group(9);
+    }
+    execve(
+        "thread_exec_test\0",
+        &["thread_exec_test\0".as_ptr(), "spin\0".as_ptr(), null::<u8>()],
+        &[null::<u8>()],
+    );
+    1
+}
+
+fn run_with_spinning_sibling(arg: usize) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        sched_setaffinity(0, 1 << 0);
+        thread_create(spin_worker, arg, unsafe { &mut THREAD_STACK });
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    status
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 && argv[1] == "spin" {
+        assert_eq!(gettid(), getpid());
+        return 0;
+    }
+    if argc > 1 && argv[1] == "exec" {
+        let pid = getpid();
+        assert_eq!(gettid(), pid);
+        assert_eq!(getenv("ORIG_PID"), Some(format!("{}", pid).as_str()));
+        println!("thread_exec_test: execve from a thread ok");
+        return 0;
+    }
+
+    let pid = fork();
+    if pid == 0 {
+        thread_create(worker, 0, unsafe { &mut THREAD_STACK });
+        // 主线程等待被 execve 终止
+        loop {
+            sleep(1000);
+        }
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(status, 0);
+
+    // 正在其他 hart 上运行的线程退出后才替换地址空间或释放进程
+    assert_eq!(run_with_spinning_sibling(1), 0);
+    println!("thread_exec_test: execve with a spinning thread ok");
+    assert_eq!((run_with_spinning_sibling(0) >> 8) & 0xff, 9);
+    println!("thread_exec_test: exit_group with a spinning thread ok");
+    println!("thread_exec_test passed!");
+    0
+}
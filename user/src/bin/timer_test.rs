@@ -0,0 +1,112 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    clock_nanosleep, close, exit, fork, get_time, getitimer, getpid, kill, pipe, ppoll,
+    ppoll_sigmask, read, setitimer, sigaction, sigmask_of, sigprocmask, sleep, waitpid, write,
+    ITimerVal, PollFd, SigAction, TimeVal, CLOCK_MONOTONIC, ITIMER_PROF, ITIMER_VIRTUAL, POLLIN,
+    SIGUSR1, SIG_BLOCK, TIMER_ABSTIME,
+};
+
+const CHILDREN: usize = 8;
+const SLEEP_MS: usize = 200;
+const EINTR: isize = 4;
+
+static USR1_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn usr1_count(_signum: i32) {
+    USR1_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 相对睡眠不会提前返回
+    let start = get_time();
+    sleep(SLEEP_MS);
+    let elapsed = get_time() - start;
+    assert!(elapsed >= SLEEP_MS as isize);
+    println!("timer_test: nanosleep {}ms took {}ms", SLEEP_MS, elapsed);
+
+    // 绝对时刻睡眠
+    let start = get_time();
+    let deadline_ns = (start as usize + SLEEP_MS) * 1_000_000;
+    assert_eq!(clock_nanosleep(CLOCK_MONOTONIC, TIMER_ABSTIME, deadline_ns), 0);
+    assert!(get_time() - start >= SLEEP_MS as isize - 1);
+    println!("timer_test: clock_nanosleep ok");
+
+    // 睡眠中的进程阻塞而不占用CPU，多个进程同时睡眠的总耗时接近单个进程
+    let start = get_time();
+    let mut pids = [0isize; CHILDREN];
+    for pid in pids.iter_mut() {
+        *pid = fork();
+        if *pid == 0 {
+            sleep(SLEEP_MS);
+            exit(0);
+        }
+    }
+    let mut exit_code = 0;
+    for pid in pids.iter() {
+        assert_eq!(waitpid(*pid as usize, &mut exit_code), *pid);
+    }
+    let elapsed = get_time() - start;
+    println!("timer_test: {} sleepers took {}ms", CHILDREN, elapsed);
+    assert!(elapsed < (SLEEP_MS * CHILDREN) as isize);
+
+    // ITIMER_VIRTUAL 与 ITIMER_PROF 可以设置和读取
+    let value = ITimerVal::new(TimeVal { sec: 5, usec: 0 }, TimeVal { sec: 1, usec: 0 });
+    let zero = ITimerVal::new(TimeVal::new(), TimeVal::new());
+    let mut old = zero;
+    for which in [ITIMER_VIRTUAL, ITIMER_PROF] {
+        assert_eq!(setitimer(which, &value, None), 0);
+        assert_eq!(getitimer(which, &mut old), 0);
+        assert_eq!((old.value.sec, old.interval.sec), (5, 1));
+        assert_eq!(setitimer(which, &zero, Some(&mut old)), 0);
+        assert_eq!(old.value.sec, 5);
+    }
+    assert!(setitimer(3, &value, None) < 0);
+    println!("timer_test: ITIMER_VIRTUAL and ITIMER_PROF ok");
+
+    // ppoll 在管道写入与写端关闭时被唤醒，不必等到超时
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(fds[0]);
+        sleep(SLEEP_MS);
+        write(fds[1], b"x");
+        sleep(SLEEP_MS);
+        exit(0);
+    }
+    close(fds[1]);
+    let mut poll_fds = [PollFd { fd: fds[0] as i32, events: POLLIN, revents: 0 }];
+    let start = get_time();
+    assert_eq!(ppoll(&mut poll_fds, Some(&[10, 0])), 1);
+    assert!(get_time() - start < 10 * SLEEP_MS as isize);
+    let mut buf = [0u8; 1];
+    assert_eq!(read(fds[0], &mut buf), 1);
+    assert_eq!(ppoll(&mut poll_fds, None), 1);
+    assert_eq!(read(fds[0], &mut buf), 0);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    close(fds[0]);
+    println!("timer_test: ppoll wakeup ok");
+
+    // ppoll 等待期间解除屏蔽的信号打断等待并得到处理，返回后恢复原屏蔽字
+    let usr1 = sigmask_of(SIGUSR1);
+    let action = SigAction { handler: usr1_count as usize, flags: 0, mask: 0 };
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    assert_eq!(sigprocmask(SIG_BLOCK, Some(&usr1), None), 0);
+    kill(getpid() as usize, SIGUSR1);
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), 0);
+    assert_eq!(ppoll_sigmask(&mut [], None, Some(&0)), -EINTR);
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), 1);
+    let mut mask = 0;
+    assert_eq!(sigprocmask(SIG_BLOCK, None, Some(&mut mask)), 0);
+    assert_eq!(mask & usr1, usr1);
+    println!("timer_test: ppoll sigmask ok");
+    println!("timer_test passed!");
+    0
+}
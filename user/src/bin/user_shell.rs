@@ -28,9 +28,87 @@ use alloc::vec::Vec;
 use user_lib::console::getchar;
 use user_lib::{
     change_cwd, chdir, close, dup, exec, fork, get_wordlist, longest_common_prefix, open, pipe,
-    preliminary_test, shutdown, toggle_trace, waitpid, OpenFlags, libc_test, busybox_lua_test, lmbench_test, exit,
+    preliminary_test, shutdown, toggle_trace, OpenFlags, libc_test, busybox_lua_test, lmbench_test, exit,
+    getpid, killpg, setpgid, sigaction, tcsetpgrp, waitpgid, SigAction, SIGCONT, SIGINT, SIGQUIT,
+    SIGTSTP, SIGTTIN, SIGTTOU, SIG_DFL, SIG_IGN, WNOHANG, WUNTRACED,
 };
 
+/// 由 shell 启动的一条管道命令，所有进程属于同一个进程组
+struct Job {
+    id: usize,
+    pgid: usize,
+    /// 尚未退出的进程数
+    remaining: usize,
+    command: String,
+    stopped: bool,
+}
+
+/// shell 自身忽略作业控制信号，子进程在 exec 前恢复默认处理
+fn set_job_control_signals(handler: usize) {
+    let action = SigAction {
+        handler,
+        flags: 0,
+        mask: 0,
+    };
+    for signum in [SIGINT, SIGQUIT, SIGTSTP, SIGTTIN, SIGTTOU] {
+        sigaction(signum, Some(&action), None);
+    }
+}
+
+/// 作业被停止时返回 true
+fn wait_job(job: &mut Job, options: isize) -> bool {
+    let mut status: i32 = 0;
+    while job.remaining > 0 {
+        if waitpgid(job.pgid, &mut status, options) <= 0 {
+            break;
+        }
+        // WIFSTOPPED
+        if status & 0xff == 0x7f {
+            job.stopped = true;
+            return true;
+        }
+        job.remaining -= 1;
+    }
+    false
+}
+
+/// 把作业放到前台运行直到它退出或被停止，被停止的作业放回作业表
+fn run_foreground(mut job: Job, jobs: &mut Vec<Job>, shell_pgid: usize) {
+    tcsetpgrp(0, job.pgid);
+    if job.stopped {
+        job.stopped = false;
+        killpg(job.pgid, SIGCONT);
+    }
+    if wait_job(&mut job, WUNTRACED) {
+        println!("\n[{}]+ Stopped\t{}", job.id, job.command);
+        jobs.push(job);
+    }
+    tcsetpgrp(0, shell_pgid);
+}
+
+/// 回收已结束的后台作业
+fn reap_jobs(jobs: &mut Vec<Job>) {
+    for job in jobs.iter_mut() {
+        wait_job(job, WNOHANG | WUNTRACED);
+        if job.remaining == 0 {
+            println!("[{}]+ Done\t{}", job.id, job.command);
+        }
+    }
+    jobs.retain(|job| job.remaining > 0);
+}
+
+/// fg、bg 的参数为作业号，缺省为最近的作业
+fn take_job(jobs: &mut Vec<Job>, arg: Option<&str>) -> Option<Job> {
+    let idx = match arg {
+        Some(arg) => {
+            let id: usize = arg.trim_start_matches('%').parse().ok()?;
+            jobs.iter().position(|job| job.id == id)?
+        }
+        None => jobs.len().checked_sub(1)?,
+    };
+    Some(jobs.remove(idx))
+}
+
 #[derive(Debug)]
 struct ProcessArguments {
     input: String,
@@ -129,6 +207,12 @@ pub fn main() -> i32 {
 
 fn interactive_main() -> i32 {
     println!("Rust user shell");
+    // 成为独立的进程组并占据控制终端的前台
+    set_job_control_signals(SIG_IGN);
+    let shell_pgid = getpid() as usize;
+    setpgid(0, 0);
+    tcsetpgrp(0, shell_pgid);
+    let mut jobs: Vec<Job> = Vec::new();
     let mut line: String = String::new();
     let mut pos: usize = 0;
     let mut cwd = String::from("/");
@@ -168,7 +252,51 @@ fn interactive_main() -> i32 {
                     } else if line == "shutdown" {
                         shutdown();
                     }
-                    let splited: Vec<_> = line.as_str().split('|').collect();
+                    let words: Vec<_> = line.split(' ').filter(|w| !w.is_empty()).collect();
+                    match words.as_slice() {
+                        ["jobs"] => {
+                            for job in jobs.iter() {
+                                let state = if job.stopped { "Stopped" } else { "Running" };
+                                println!("[{}] {}\t{}", job.id, state, job.command);
+                            }
+                            start_new_line(&mut line, &mut pos, cwd.as_str());
+                            continue;
+                        }
+                        ["fg"] | ["fg", _] => {
+                            match take_job(&mut jobs, words.get(1).copied()) {
+                                Some(job) => {
+                                    println!("{}", job.command);
+                                    run_foreground(job, &mut jobs, shell_pgid);
+                                }
+                                None => println!("fg: no such job"),
+                            }
+                            start_new_line(&mut line, &mut pos, cwd.as_str());
+                            continue;
+                        }
+                        ["bg"] | ["bg", _] => {
+                            match take_job(&mut jobs, words.get(1).copied()) {
+                                Some(mut job) => {
+                                    println!("[{}]+ {} &", job.id, job.command);
+                                    job.stopped = false;
+                                    killpg(job.pgid, SIGCONT);
+                                    jobs.push(job);
+                                }
+                                None => println!("bg: no such job"),
+                            }
+                            start_new_line(&mut line, &mut pos, cwd.as_str());
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    // 以 & 结尾的命令在后台运行
+                    let command = line.trim_end();
+                    let background = command.ends_with('&');
+                    let command = command.trim_end_matches('&').trim_end();
+                    if command.is_empty() {
+                        start_new_line(&mut line, &mut pos, cwd.as_str());
+                        continue;
+                    }
+                    let splited: Vec<_> = command.split('|').collect();
                     let process_arguments_list: Vec<_> = splited
                         .iter()
                         .map(|&cmd| ProcessArguments::new(cmd))
@@ -229,10 +357,17 @@ fn interactive_main() -> i32 {
                                 pipes_fd.push(pipe_fd);
                             }
                         }
-                        let mut children: Vec<_> = Vec::new();
+                        // 进程组号为第一个子进程的 pid，父子进程都设置一次以避免竞争
+                        let mut pgid = 0;
                         for (i, process_argument) in process_arguments_list.iter().enumerate() {
                             let pid = fork();
                             if pid == 0 {
+                                let pgid = if pgid == 0 { getpid() as usize } else { pgid };
+                                setpgid(0, pgid);
+                                if !background {
+                                    tcsetpgrp(0, pgid);
+                                }
+                                set_job_control_signals(SIG_DFL);
                                 let input = &process_argument.input;
                                 let output = &process_argument.output;
                                 let args_copy = &process_argument.args_copy;
@@ -288,21 +423,33 @@ fn interactive_main() -> i32 {
                                 }
                                 unreachable!();
                             } else {
-                                children.push(pid);
+                                if pgid == 0 {
+                                    pgid = pid as usize;
+                                }
+                                setpgid(pid as usize, pgid);
                             }
                         }
                         for pipe_fd in pipes_fd.iter() {
                             close(pipe_fd[0]);
                             close(pipe_fd[1]);
                         }
-                        let mut exit_code: i32 = 0;
-                        for pid in children.into_iter() {
-                            let exit_pid = waitpid(pid as usize, &mut exit_code);
-                            assert_eq!(pid, exit_pid);
+                        let job = Job {
+                            id: jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1,
+                            pgid,
+                            remaining: process_arguments_list.len(),
+                            command: command.to_string(),
+                            stopped: false,
+                        };
+                        if background {
+                            println!("[{}] {}", job.id, pgid);
+                            jobs.push(job);
+                        } else {
+                            run_foreground(job, &mut jobs, shell_pgid);
                         }
                         cwd_wl = get_wordlist(cwd.as_str()); // 有可能有更改工作目录下目录项的操作
                     }
                 }
+                reap_jobs(&mut jobs);
                 start_new_line(&mut line, &mut pos, cwd.as_str());
             }
             BS | DL => {
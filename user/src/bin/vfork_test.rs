@@ -0,0 +1,122 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::{addr_of, null, read_volatile};
+use user_lib::{
+    chdir, clone, close, exec, open, sigaction, sleep, waitpid, write, yield_, OpenFlags,
+    SigAction, CLONE_FILES, CLONE_FS, CLONE_SIGHAND, CLONE_VFORK, CLONE_VM, SIGCHLD, SIGUSR1,
+    SIG_DFL, SIG_IGN,
+};
+
+static mut CHILD_STACK: [u8; 16384] = [0; 16384];
+static mut SHARED: usize = 0;
+static mut CHILD_FD: isize = -1;
+
+/// 子进程与父进程共享地址空间，写入的值父进程可见
+extern "C" fn vfork_exec(_arg: usize) -> i32 {
+    unsafe { SHARED = 1 };
+    exec("vfork_test\0", &["vfork_test\0".as_ptr(), "exec\0".as_ptr(), null::<u8>()]);
+    1
+}
+
+extern "C" fn vfork_exit(_arg: usize) -> i32 {
+    unsafe { SHARED = 2 };
+    3
+}
+
+extern "C" fn open_file(_arg: usize) -> i32 {
+    unsafe { CHILD_FD = open("/tmp/vfork_probe\0", OpenFlags::WRONLY) };
+    0
+}
+
+extern "C" fn change_dir(_arg: usize) -> i32 {
+    chdir("/tmp\0") as i32
+}
+
+extern "C" fn ignore_usr1(_arg: usize) -> i32 {
+    let ignore = SigAction { handler: SIG_IGN, flags: 0, mask: 0 };
+    sigaction(SIGUSR1, Some(&ignore), None) as i32
+}
+
+/// 不带 CLONE_VFORK 时父进程继续运行，与子进程并发访问共享的地址空间
+extern "C" fn share_vm(_arg: usize) -> i32 {
+    sleep(20);
+    unsafe { SHARED = 4 };
+    0
+}
+
+fn usr1_handler() -> usize {
+    let mut action = SigAction::default();
+    sigaction(SIGUSR1, None, Some(&mut action));
+    action.handler
+}
+
+/// 以 flags 创建子进程并等待其退出，返回退出码
+fn run(entry: extern "C" fn(usize) -> i32, flags: usize) -> i32 {
+    let pid = clone(entry, 0, unsafe { &mut CHILD_STACK }, flags | SIGCHLD as usize);
+    assert!(pid > 0);
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    (status >> 8) & 0xff
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 && argv[1] == "exec" {
+        return 5;
+    }
+
+    // vfork 返回时子进程已经 execve 或退出
+    assert_eq!(run(vfork_exec, CLONE_VM | CLONE_VFORK), 5);
+    assert_eq!(unsafe { SHARED }, 1);
+    assert_eq!(run(vfork_exit, CLONE_VM | CLONE_VFORK), 3);
+    assert_eq!(unsafe { SHARED }, 2);
+    println!("vfork_test: vfork ok");
+
+    let fd = open("/tmp/vfork_probe\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    close(fd as usize);
+
+    // 共享文件描述符表时，子进程打开的文件父进程可以使用
+    assert_eq!(run(open_file, CLONE_VM | CLONE_FILES | CLONE_VFORK), 0);
+    let fd = unsafe { CHILD_FD };
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"x"), 1);
+    close(fd as usize);
+    // 不共享时子进程的文件描述符表是副本
+    assert_eq!(run(open_file, CLONE_VM | CLONE_VFORK), 0);
+    assert!(write(unsafe { CHILD_FD } as usize, b"x") < 0);
+    println!("vfork_test: CLONE_FILES ok");
+
+    // 共享文件系统信息时，子进程改变的工作目录对父进程生效
+    assert_eq!(run(change_dir, CLONE_VM | CLONE_FS | CLONE_VFORK), 0);
+    let fd = open("vfork_probe\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    close(fd as usize);
+    chdir("/\0");
+    println!("vfork_test: CLONE_FS ok");
+
+    // 共享信号处理函数时，子进程设置的处理方式对父进程生效
+    assert_eq!(run(ignore_usr1, CLONE_VM | CLONE_VFORK), 0);
+    assert_eq!(usr1_handler(), SIG_DFL);
+    assert_eq!(run(ignore_usr1, CLONE_VM | CLONE_SIGHAND | CLONE_VFORK), 0);
+    assert_eq!(usr1_handler(), SIG_IGN);
+    let default = SigAction { handler: SIG_DFL, flags: 0, mask: 0 };
+    sigaction(SIGUSR1, Some(&default), None);
+    println!("vfork_test: CLONE_SIGHAND ok");
+
+    // 只共享地址空间时父进程不被挂起，可以看到子进程随后写入的值
+    let pid = clone(share_vm, 0, unsafe { &mut CHILD_STACK }, CLONE_VM | SIGCHLD as usize);
+    assert!(pid > 0);
+    while unsafe { read_volatile(addr_of!(SHARED)) } != 4 {
+        yield_();
+    }
+    let mut status = 0;
+    assert_eq!(waitpid(pid as usize, &mut status), pid);
+    assert_eq!(status, 0);
+    println!("vfork_test passed!");
+    0
+}
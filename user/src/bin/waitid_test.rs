@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, kill, sleep, waitid, waitpid, waitpid_nb, SigInfo, CLD_EXITED, CLD_KILLED, P_ALL,
+    P_PID, SIGKILL, WEXITED, WNOHANG, WNOWAIT,
+};
+
+/// 子进程睡眠 ms 毫秒后以 code 退出
+fn spawn(ms: usize, code: i32) -> usize {
+    let pid = fork();
+    if pid == 0 {
+        sleep(ms);
+        exit(code);
+    }
+    assert!(pid > 0);
+    pid as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 子进程尚未退出时 WNOHANG 返回 0
+    let pid = spawn(100, 7);
+    let mut status = 0;
+    assert_eq!(waitpid_nb(pid, &mut status), 0);
+    let mut info = SigInfo::new(0, 0);
+    assert_eq!(waitid(P_PID, pid, &mut info, WEXITED | WNOHANG), 0);
+    assert_eq!(info.pid(), 0);
+
+    // WNOWAIT 只报告状态，子进程仍可被再次等待
+    assert_eq!(waitid(P_PID, pid, &mut info, WEXITED | WNOWAIT), 0);
+    assert_eq!(info.pid(), pid as i32);
+    assert_eq!(info.code, CLD_EXITED);
+    assert_eq!(info.status(), 7);
+    assert_eq!(waitpid(pid, &mut status), pid as isize);
+    assert_eq!((status >> 8) & 0xff, 7);
+    println!("waitid_test: WNOHANG and WNOWAIT ok");
+
+    // 被信号杀死的子进程
+    let pid = spawn(10_000, 0);
+    assert_eq!(kill(pid, SIGKILL), 0);
+    assert_eq!(waitid(P_ALL, 0, &mut info, WEXITED), 0);
+    assert_eq!(info.pid(), pid as i32);
+    assert_eq!(info.code, CLD_KILLED);
+    assert_eq!(info.status(), SIGKILL);
+
+    // 没有子进程时返回 ECHILD，未指定等待的状态变化时返回 EINVAL
+    assert!(waitid(P_ALL, 0, &mut info, WEXITED) < 0);
+    assert!(waitid(P_ALL, 0, &mut info, WNOHANG) < 0);
+    println!("waitid_test passed!");
+    0
+}
@@ -25,6 +25,9 @@ static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
 
 const AT_FDCWD: isize = -100;
 
+/// 内核放在初始栈上、以空指针结尾的环境变量数组
+static mut ENVIRON: *const *const u8 = core::ptr::null();
+
 #[global_allocator]
 static HEAP: LockedHeap<32> = LockedHeap::empty();
 
@@ -35,11 +38,18 @@ pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
 
 #[no_mangle]
 #[link_section = ".text.entry"]
-pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
+pub extern "C" fn _start(_rtld_fini: usize, argv: usize, envp: usize) -> ! {
     unsafe {
         HEAP.lock()
             .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
+        ENVIRON = envp as *const *const u8;
     }
+    // execve 按 ABI 把 a0 置 0，argc 位于初始栈上 argv 数组之前；initproc 没有参数
+    let argc = if argv == 0 {
+        0
+    } else {
+        unsafe { ((argv - core::mem::size_of::<usize>()) as *const usize).read_volatile() }
+    };
     let mut v: Vec<&'static str> = Vec::new();
     for i in 0..argc {
         let str_start =
@@ -91,6 +101,7 @@ bitflags! {
     }
 }
 
+#[repr(C)]
 #[derive(Copy, Clone)]
 pub struct TimeVal {
     pub sec: usize,
@@ -113,6 +124,15 @@ impl TimeVal {
     }
 }
 
+/// 单个 hart 的空闲统计
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HartIdleStat {
+    pub idle_ns: u64,
+    pub wakeups: u64,
+    pub ipis: u64,
+}
+
 #[allow(unused)]
 #[repr(packed)]
 pub struct FSDirent {
@@ -133,17 +153,60 @@ pub fn close(fd: usize) -> isize {
     sys_close(fd)
 }
 pub fn pipe(pipe_fd: &mut [usize]) -> isize {
-    sys_pipe2(pipe_fd)
+    // 内核按 int[2] 写回两个描述符
+    let mut fds = [0u32; 2];
+    let ret = sys_pipe2(&mut fds);
+    if ret == 0 {
+        pipe_fd[0] = fds[0] as usize;
+        pipe_fd[1] = fds[1] as usize;
+    }
+    ret
 }
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
 }
+/// offset 不为空时从该偏移读取 in_fd
+pub fn sendfile(out_fd: usize, in_fd: usize, offset: Option<&mut usize>, count: usize) -> isize {
+    sys_sendfile(out_fd, in_fd, offset, count)
+}
+pub const F_DUPFD: usize = 0;
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_fcntl(fd, cmd, arg)
+}
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
+
+#[repr(C)]
+pub struct IOVec {
+    pub base: *const u8,
+    pub len: usize,
+}
+
+impl IOVec {
+    /// 供 writev 使用
+    pub fn new(buf: &[u8]) -> Self {
+        Self { base: buf.as_ptr(), len: buf.len() }
+    }
+    /// 供 readv 使用
+    pub fn new_mut(buf: &mut [u8]) -> Self {
+        Self { base: buf.as_mut_ptr(), len: buf.len() }
+    }
+}
+
+pub fn readv(fd: usize, iov: &[IOVec]) -> isize {
+    sys_readv(fd, iov)
+}
+pub fn writev(fd: usize, iov: &[IOVec]) -> isize {
+    sys_writev(fd, iov)
+}
 pub fn exit(exit_code: i32) -> ! {
     sys_exit(exit_code);
 }
+/// 终止进程的所有线程
+pub fn exit_group(exit_code: i32) -> ! {
+    sys_exit_group(exit_code);
+}
 pub fn yield_() -> isize {
     sys_yield()
 }
@@ -158,11 +221,120 @@ pub fn getpid() -> isize {
 pub fn fork() -> isize {
     sys_clone()
 }
+pub const CLONE_VM: usize = 0x100;
+pub const CLONE_FS: usize = 0x200;
+pub const CLONE_FILES: usize = 0x400;
+pub const CLONE_SIGHAND: usize = 0x800;
+pub const CLONE_PIDFD: usize = 0x1000;
+pub const CLONE_VFORK: usize = 0x4000;
+pub const CLONE_THREAD: usize = 0x10000;
+
+/// 在 stack 上执行 entry(arg)，entry 返回后以其返回值退出，flags 决定与调用者共享的资源，
+/// 返回新进程的 pid 或新线程的 tid
+pub fn clone(entry: extern "C" fn(usize) -> i32, arg: usize, stack: &'static mut [u8], flags: usize) -> isize {
+    // 栈向下增长，栈顶按 16 字节对齐
+    let stack_top = (stack.as_ptr() as usize + stack.len()) & !0xf;
+    sys_clone_thread(flags, stack_top, entry, arg)
+}
+
+/// 与 fork 相同，父进程中 pidfd 为指向子进程的文件描述符
+pub fn fork_pidfd(pidfd: &mut i32) -> isize {
+    sys_clone_pidfd(CLONE_PIDFD | SIGCHLD as usize, pidfd as *mut _)
+}
+
+/// 创建在 stack 上执行 entry(arg) 的线程，entry 返回后线程以其返回值退出，返回新线程的 tid
+pub fn thread_create(entry: extern "C" fn(usize) -> i32, arg: usize, stack: &'static mut [u8]) -> isize {
+    clone(entry, arg, stack, CLONE_VM | CLONE_FS | CLONE_FILES | CLONE_SIGHAND | CLONE_THREAD)
+}
+/// 以当前进程的环境变量执行新程序
 pub fn exec(path: &str, args: &[*const u8]) -> isize {
-    sys_exec(path, args)
+    sys_exec(path, args, unsafe { ENVIRON })
 }
 
-const WNOHANG: isize = 1;
+/// envp 需以空指针结尾
+pub fn execve(path: &str, args: &[*const u8], envp: &[*const u8]) -> isize {
+    sys_exec(path, args, envp.as_ptr())
+}
+
+/// 当前进程的环境变量，不含结尾的 \0
+pub fn environ() -> Vec<&'static str> {
+    let mut env = Vec::new();
+    let mut p = unsafe { ENVIRON };
+    if p.is_null() {
+        return env;
+    }
+    loop {
+        let s = unsafe { p.read_volatile() };
+        if s.is_null() {
+            break;
+        }
+        let len = (0usize..)
+            .find(|i| unsafe { s.add(*i).read_volatile() == 0 })
+            .unwrap();
+        env.push(core::str::from_utf8(unsafe { core::slice::from_raw_parts(s, len) }).unwrap());
+        p = unsafe { p.add(1) };
+    }
+    env
+}
+
+/// 环境变量 name 的值
+pub fn getenv(name: &str) -> Option<&'static str> {
+    environ()
+        .into_iter()
+        .find_map(|var| var.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')))
+}
+
+pub const AT_NULL: usize = 0;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_BASE: usize = 7;
+pub const AT_ENTRY: usize = 9;
+pub const AT_PLATFORM: usize = 15;
+pub const AT_HWCAP: usize = 16;
+pub const AT_SECURE: usize = 23;
+pub const AT_RANDOM: usize = 25;
+pub const AT_EXECFN: usize = 31;
+pub const AT_SYSINFO_EHDR: usize = 33;
+
+/// 辅助向量中 aux_type 项的值，辅助向量紧跟在环境变量数组的空指针之后
+pub fn getauxval(aux_type: usize) -> Option<usize> {
+    let mut p = unsafe { ENVIRON };
+    if p.is_null() {
+        return None;
+    }
+    while !unsafe { p.read_volatile() }.is_null() {
+        p = unsafe { p.add(1) };
+    }
+    let mut auxv = unsafe { p.add(1) } as *const usize;
+    loop {
+        let (ty, value) = unsafe { (auxv.read_volatile(), auxv.add(1).read_volatile()) };
+        if ty == AT_NULL {
+            return None;
+        }
+        if ty == aux_type {
+            return Some(value);
+        }
+        auxv = unsafe { auxv.add(2) };
+    }
+}
+
+pub const WNOHANG: isize = 1;
+pub const WUNTRACED: isize = 2;
+pub const WSTOPPED: isize = WUNTRACED;
+pub const WEXITED: isize = 4;
+pub const WCONTINUED: isize = 8;
+pub const WNOWAIT: isize = 0x0100_0000;
+
+pub const P_ALL: usize = 0;
+pub const P_PID: usize = 1;
+pub const P_PGID: usize = 2;
+pub const P_PIDFD: usize = 3;
+
+/// waitid 中子进程状态变化的 si_code
+pub const CLD_EXITED: i32 = 1;
+pub const CLD_KILLED: i32 = 2;
+pub const CLD_DUMPED: i32 = 3;
+pub const CLD_STOPPED: i32 = 5;
+pub const CLD_CONTINUED: i32 = 6;
 
 pub fn wait(wstatus: &mut i32) -> isize {
     sys_waitpid(-1, wstatus as *mut _, 0)
@@ -176,6 +348,15 @@ pub fn waitpid_nb(pid: usize, exit_code: &mut i32) -> isize {
     sys_waitpid(pid as isize, exit_code as *mut _, WNOHANG)
 }
 
+pub fn waitpid_options(pid: usize, wstatus: &mut i32, options: isize) -> isize {
+    sys_waitpid(pid as isize, wstatus as *mut _, options)
+}
+
+/// 成功返回 0，info 中为子进程的 pid、状态变化类型与退出码或信号
+pub fn waitid(idtype: usize, id: usize, info: &mut SigInfo, options: isize) -> isize {
+    sys_waitid(idtype, id, info as *mut _, options)
+}
+
 bitflags! {
     pub struct SignalFlags: i32 {
         const SIGINT    = 1 << 2;
@@ -186,13 +367,332 @@ bitflags! {
     }
 }
 
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+pub const SIGQUIT: i32 = 3;
+pub const SIGABRT: i32 = 6;
+pub const SIGKILL: i32 = 9;
+pub const SIGUSR1: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+pub const SIGUSR2: i32 = 12;
+pub const SIGALRM: i32 = 14;
+pub const SIGTERM: i32 = 15;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+pub const SIGSTOP: i32 = 19;
+pub const SIGTSTP: i32 = 20;
+pub const SIGTTIN: i32 = 21;
+pub const SIGTTOU: i32 = 22;
+pub const SIGXCPU: i32 = 24;
+pub const SIGXFSZ: i32 = 25;
+pub const SIGRTMIN: i32 = 32;
+pub const SIGRTMAX: i32 = 64;
+
+pub const PIDFD_NONBLOCK: usize = 0x800;
+
+/// 返回指向进程 pid 的文件描述符，进程退出后可读
+pub fn pidfd_open(pid: usize, flags: usize) -> isize {
+    sys_pidfd_open(pid, flags)
+}
+
+/// 向 pidfd 指向的进程发送信号，进程已退出时失败
+pub fn pidfd_send_signal(pidfd: usize, signal: i32) -> isize {
+    sys_pidfd_send_signal(pidfd, signal, core::ptr::null(), 0)
+}
+
 pub fn kill(pid: usize, signal: i32) -> isize {
     sys_kill(pid, signal)
 }
 
-pub fn sleep(_sleep_ms: usize) {
-    panic!("outdated function");
-    // sys_sleep(sleep_ms);
+/// 向进程组 pgid 发送信号，pgid 为 0 时发送给调用者所在的进程组
+pub fn killpg(pgid: usize, signal: i32) -> isize {
+    sys_kill((pgid as isize).wrapping_neg() as usize, signal)
+}
+
+/// 等待进程组 pgid 中的任意子进程
+pub fn waitpgid(pgid: usize, wstatus: &mut i32, options: isize) -> isize {
+    sys_waitpid(-(pgid as isize), wstatus as *mut _, options)
+}
+
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    sys_setpgid(pid, pgid)
+}
+
+pub fn getpgid(pid: usize) -> isize {
+    sys_getpgid(pid)
+}
+
+pub fn setsid() -> isize {
+    sys_setsid()
+}
+
+pub fn getsid(pid: usize) -> isize {
+    sys_getsid(pid)
+}
+
+pub const TIOCSCTTY: usize = 0x540E;
+pub const TIOCGPGRP: usize = 0x540F;
+pub const TIOCSPGRP: usize = 0x5410;
+
+pub fn ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    sys_ioctl(fd, request, arg)
+}
+
+/// 终端 fd 的前台进程组
+pub fn tcgetpgrp(fd: usize) -> isize {
+    let mut pgid: i32 = 0;
+    match ioctl(fd, TIOCGPGRP, &mut pgid as *mut i32 as usize) {
+        0 => pgid as isize,
+        err => err,
+    }
+}
+
+pub fn tcsetpgrp(fd: usize, pgid: usize) -> isize {
+    let pgid = pgid as i32;
+    ioctl(fd, TIOCSPGRP, &pgid as *const i32 as usize)
+}
+
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+pub const SA_SIGINFO: usize = 4;
+pub const SA_ONSTACK: usize = 0x08000000;
+pub const SA_RESTART: usize = 0x10000000;
+pub const SA_NODEFER: usize = 0x40000000;
+pub const SA_RESETHAND: usize = 0x80000000;
+
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// 内核的 struct sigaction，信号处理函数返回时由内核提供的跳板执行 sigreturn
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SigAction {
+    pub handler: usize,
+    pub flags: usize,
+    pub mask: u64,
+}
+
+/// 信号集合中 signum 对应的位
+pub fn sigmask_of(signum: i32) -> u64 {
+    1 << (signum - 1)
+}
+
+pub fn sigaction(signum: i32, action: Option<&SigAction>, old_action: Option<&mut SigAction>) -> isize {
+    sys_sigaction(
+        signum,
+        action.map_or(core::ptr::null(), |a| a as *const _),
+        old_action.map_or(core::ptr::null_mut(), |a| a as *mut _),
+    )
+}
+
+pub fn sigprocmask(how: usize, set: Option<&u64>, old_set: Option<&mut u64>) -> isize {
+    sys_sigprocmask(
+        how,
+        set.map_or(core::ptr::null(), |s| s as *const _),
+        old_set.map_or(core::ptr::null_mut(), |s| s as *mut _),
+    )
+}
+
+pub const SS_ONSTACK: i32 = 1;
+pub const SS_DISABLE: i32 = 2;
+pub const MINSIGSTKSZ: usize = 2048;
+
+pub const SI_USER: i32 = 0;
+pub const SI_QUEUE: i32 = -1;
+pub const SEGV_MAPERR: i32 = 1;
+
+/// stack_t
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SignalStack {
+    pub sp: usize,
+    pub flags: i32,
+    pub size: usize,
+}
+
+pub fn sigaltstack(ss: Option<&SignalStack>, old_ss: Option<&mut SignalStack>) -> isize {
+    sys_sigaltstack(
+        ss.map_or(core::ptr::null(), |s| s as *const _),
+        old_ss.map_or(core::ptr::null_mut(), |s| s as *mut _),
+    )
+}
+
+/// siginfo_t 的前几个字段，si_pid 与 si_addr 共用联合体的起始位置
+#[repr(C)]
+pub struct SigInfo {
+    pub signo: i32,
+    pub errno: i32,
+    pub code: i32,
+    _pad: i32,
+    pub fields: [usize; 14],
+}
+
+impl SigInfo {
+    pub fn pid(&self) -> i32 {
+        self.fields[0] as i32
+    }
+
+    pub fn addr(&self) -> usize {
+        self.fields[0]
+    }
+}
+
+impl SigInfo {
+    pub fn new(signo: i32, code: i32) -> Self {
+        Self {
+            signo,
+            errno: 0,
+            code,
+            _pad: 0,
+            fields: [0; 14],
+        }
+    }
+
+    /// sigqueue 附带的 si_value
+    pub fn value(&self) -> usize {
+        self.fields[1]
+    }
+
+    /// SIGCHLD 与 waitid 的 si_status
+    pub fn status(&self) -> i32 {
+        self.fields[1] as i32
+    }
+}
+
+/// 向进程发送排队的信号，value 通过 si_value 传给接收者
+pub fn sigqueue(pid: usize, signum: i32, value: usize) -> isize {
+    let mut info = SigInfo::new(signum, SI_QUEUE);
+    info.fields[0] = getpid() as u32 as usize;
+    info.fields[1] = value;
+    sys_rt_sigqueueinfo(pid, signum, &info)
+}
+
+/// 等待 set 中的信号，timeout 为 None 时无限等待，返回信号编号
+pub fn sigtimedwait(set: u64, info: Option<&mut SigInfo>, timeout: Option<&[usize; 2]>) -> isize {
+    sys_rt_sigtimedwait(
+        &set,
+        info.map_or(core::ptr::null_mut(), |i| i as *mut _),
+        timeout.map_or(core::ptr::null(), |t| t as *const _),
+    )
+}
+
+#[repr(C)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+pub const POLLIN: i16 = 0x001;
+pub const POLLOUT: i16 = 0x004;
+
+/// 等待 fds 中的文件就绪，timeout 为 None 时无限等待，返回就绪的文件个数
+pub fn ppoll(fds: &mut [PollFd], timeout: Option<&[usize; 2]>) -> isize {
+    ppoll_sigmask(fds, timeout, None)
+}
+
+/// 与 ppoll 相同，sigmask 不为 None 时等待期间以其作为信号屏蔽字
+pub fn ppoll_sigmask(fds: &mut [PollFd], timeout: Option<&[usize; 2]>, sigmask: Option<&u64>) -> isize {
+    sys_ppoll(
+        fds.as_mut_ptr(),
+        fds.len(),
+        timeout.map_or(core::ptr::null(), |t| t as *const _),
+        sigmask.map_or(core::ptr::null(), |s| s as *const _),
+    )
+}
+
+pub fn sigpending(set: &mut u64) -> isize {
+    sys_rt_sigpending(set)
+}
+
+pub const RLIMIT_CPU: usize = 0;
+pub const RLIMIT_FSIZE: usize = 1;
+pub const RLIMIT_DATA: usize = 2;
+pub const RLIMIT_STACK: usize = 3;
+pub const RLIMIT_CORE: usize = 4;
+pub const RLIMIT_NPROC: usize = 6;
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIMIT_AS: usize = 9;
+pub const RLIMIT_SIGPENDING: usize = 11;
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RLimit {
+    pub cur: usize,
+    pub max: usize,
+}
+
+/// 修改或查询当前进程的资源限制
+pub fn prlimit(resource: usize, new: Option<&RLimit>, old: Option<&mut RLimit>) -> isize {
+    sys_prlimit(
+        0,
+        resource,
+        new.map_or(core::ptr::null(), |r| r as *const _),
+        old.map_or(core::ptr::null_mut(), |r| r as *mut _),
+    )
+}
+
+pub fn getrlimit(resource: usize, rlimit: &mut RLimit) -> isize {
+    sys_getrlimit(resource, rlimit)
+}
+
+pub fn setrlimit(resource: usize, rlimit: &RLimit) -> isize {
+    sys_setrlimit(resource, rlimit)
+}
+
+/// riscv64 ucontext_t 中 uc_mcontext 之前的部分与通用寄存器，uc_mcontext 位于偏移 176
+#[repr(C)]
+pub struct UContext {
+    pub flags: usize,
+    pub link: usize,
+    pub stack: SignalStack,
+    pub sigmask: u64,
+    _unused: [u8; 128],
+    /// pc, x1~x31
+    pub gregs: [usize; 32],
+}
+
+pub fn sleep(sleep_ms: usize) {
+    let req = [sleep_ms / 1000, sleep_ms % 1000 * 1_000_000];
+    let mut rem = [0; 2];
+    sys_nanosleep(&req, &mut rem);
+}
+
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const TIMER_ABSTIME: usize = 1;
+
+/// 以 ns 为单位的 clock_nanosleep
+pub fn clock_nanosleep(clock_id: usize, flags: usize, ns: usize) -> isize {
+    let req = [ns / 1_000_000_000, ns % 1_000_000_000];
+    sys_clock_nanosleep(clock_id, flags, &req)
+}
+
+pub const ITIMER_REAL: usize = 0;
+pub const ITIMER_VIRTUAL: usize = 1;
+pub const ITIMER_PROF: usize = 2;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ITimerVal {
+    pub interval: TimeVal,
+    pub value: TimeVal,
+}
+
+impl ITimerVal {
+    pub fn new(value: TimeVal, interval: TimeVal) -> Self {
+        Self { interval, value }
+    }
+}
+
+pub fn getitimer(which: usize, curr: &mut ITimerVal) -> isize {
+    sys_getitimer(which, curr)
+}
+
+pub fn setitimer(which: usize, new: &ITimerVal, old: Option<&mut ITimerVal>) -> isize {
+    sys_setitimer(which, new, old)
 }
 
 pub fn gettid() -> isize {
@@ -214,6 +714,79 @@ pub fn brk(addr: usize) -> isize {
     sys_brk(addr)
 }
 
+pub const PROT_READ: usize = 1 << 0;
+pub const PROT_WRITE: usize = 1 << 1;
+pub const MAP_PRIVATE: usize = 1 << 1;
+pub const MAP_FIXED: usize = 1 << 4;
+pub const MAP_ANONYMOUS: usize = 1 << 5;
+pub const MAP_HUGETLB: usize = 1 << 18;
+
+pub fn mmap(start: usize, len: usize, prot: usize, flags: usize, fd: isize, offset: usize) -> isize {
+    sys_mmap(start, len, prot, flags, fd, offset)
+}
+
+pub fn munmap(start: usize, len: usize) -> isize {
+    sys_munmap(start, len)
+}
+
+pub const PRIO_PROCESS: usize = 0;
+pub const PRIO_PGRP: usize = 1;
+pub const PRIO_USER: usize = 2;
+pub const SCHED_OTHER: usize = 0;
+pub const SCHED_FIFO: usize = 1;
+pub const SCHED_RR: usize = 2;
+
+pub fn setpriority(which: usize, who: usize, prio: isize) -> isize {
+    sys_setpriority(which, who, prio)
+}
+
+/// 返回 nice 值
+pub fn getpriority(which: usize, who: usize) -> isize {
+    let ret = sys_getpriority(which, who);
+    if ret < 0 {
+        ret
+    } else {
+        20 - ret
+    }
+}
+
+pub fn sched_setscheduler(pid: usize, policy: usize, prio: i32) -> isize {
+    sys_sched_setscheduler(pid, policy, &prio)
+}
+
+pub fn sched_getscheduler(pid: usize) -> isize {
+    sys_sched_getscheduler(pid)
+}
+
+pub fn sched_setaffinity(pid: usize, mask: usize) -> isize {
+    sys_sched_setaffinity(pid, &mask)
+}
+
+pub fn sched_getaffinity(pid: usize) -> isize {
+    let mut mask = 0;
+    let ret = sys_sched_getaffinity(pid, &mut mask);
+    if ret < 0 {
+        ret
+    } else {
+        mask as isize
+    }
+}
+
+pub fn sched_getcpu() -> isize {
+    let mut cpu = 0;
+    let ret = sys_getcpu(&mut cpu);
+    if ret < 0 {
+        ret
+    } else {
+        cpu as isize
+    }
+}
+
+/// 返回写入的 hart 数
+pub fn idle_stat(buf: &mut [HartIdleStat]) -> isize {
+    sys_idle_stat(buf)
+}
+
 pub fn shutdown() -> ! {
     sys_shutdown()
 }
@@ -405,7 +978,16 @@ pub fn busybox_lua_test() {
     for app_name in apps {
         let pid = fork();
         if pid == 0 {
-            exec(app_name, &[app_name.as_ptr(), core::ptr::null::<u8>()]);
+            // 测试脚本没有 #! 行，直接交给 busybox sh 执行
+            exec(
+                "/busybox\0",
+                &[
+                    "busybox\0".as_ptr(),
+                    "sh\0".as_ptr(),
+                    app_name.as_ptr(),
+                    core::ptr::null::<u8>(),
+                ],
+            );
         } else {
             let mut exit_code = 0;
             waitpid(pid as usize, &mut exit_code);
@@ -2,7 +2,7 @@
 
 use core::arch::asm;
 
-use crate::TimeVal;
+use crate::{HartIdleStat, IOVec, ITimerVal, PollFd, RLimit, SigAction, SigInfo, SignalStack, TimeVal};
 
 const SYSCALL_GETCWD: usize = 17;
 const SYSCALL_DUP: usize = 23;
@@ -23,9 +23,11 @@ const SYSCALL_GETDENTS64: usize = 61;
 const SYSCALL_LSEEK: usize = 62;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_READV: usize = 65;
 const SYSCALL_WRITEV: usize = 66;
 const SYSCALL_SENDFILE: usize = 71;
 const SYSCALL_PSELECT6: usize = 72;
+const SYSCALL_PPOLL: usize = 73;
 const SYSCALL_READLINKAT: usize = 78;
 const SYSCALL_NEW_FSTATAT: usize = 79;
 const SYSCALL_FSTAT:usize = 80;
@@ -33,18 +35,38 @@ const SYSCALL_FSYNC:usize = 82;
 const SYSCALL_UTIMENSAT:usize = 88;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_EXIT_GRUOP: usize = 94;
+const SYSCALL_WAITID: usize = 95;
 const SYSCALL_SET_TID_ADDRESS: usize = 96;
 const SYSCALL_NANOSLEEP: usize = 101;
 const SYSCALL_GETITIMER: usize = 102;
 const SYSCALL_SETITIMER: usize = 103;
 const SYSCALL_CLOCK_GETTIME: usize = 113;
+const SYSCALL_CLOCK_NANOSLEEP: usize = 115;
+const SYSCALL_SCHED_SETSCHEDULER: usize = 119;
+const SYSCALL_SCHED_GETSCHEDULER: usize = 120;
+const SYSCALL_SCHED_SETAFFINITY: usize = 122;
+const SYSCALL_SCHED_GETAFFINITY: usize = 123;
 const SYSCALL_SCHED_YIELD: usize = 124;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGALTSTACK: usize = 132;
 const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_RT_SIGPENDING: usize = 136;
+const SYSCALL_RT_SIGTIMEDWAIT: usize = 137;
+const SYSCALL_RT_SIGQUEUEINFO: usize = 138;
 const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_SETPRIORITY: usize = 140;
+const SYSCALL_GETPRIORITY: usize = 141;
 const SYSCALL_TIMES: usize = 153;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_GETSID: usize = 156;
+const SYSCALL_SETSID: usize = 157;
 const SYSCALL_UNAME: usize = 160;
+const SYSCALL_GETRLIMIT: usize = 163;
+const SYSCALL_SETRLIMIT: usize = 164;
 const SYSCALL_GETRUSAGE: usize = 165;
+const SYSCALL_GETCPU: usize = 168;
 const SYSCALL_GETTIMEOFDAY: usize = 169;
 const SYSCALL_GETPID: usize = 172;
 const SYSCALL_GETPPID: usize = 173;
@@ -63,9 +85,12 @@ const SYSCALL_MPROTECT: usize = 226;
 const SYSCALL_WAIT4: usize = 260;
 const SYSCALL_PRLIMIT: usize = 261;
 const SYSCALL_RENAMEAT2: usize = 276;
+const SYSCALL_PIDFD_SEND_SIGNAL: usize = 424;
+const SYSCALL_PIDFD_OPEN: usize = 434;
 
 const SYSCALL_TOGGLE_TRACE: usize = 0xf000;
 const SYSCALL_READDIR: usize = 0xf001;
+const SYSCALL_IDLE_STAT: usize = 0xf002;
 const SYSCALL_SHUTDOWN: usize = 0xffff;
 
 fn syscall(id: usize, args: [usize; 6]) -> isize {
@@ -97,7 +122,7 @@ pub fn sys_close(fd: usize) -> isize {
     syscall(SYSCALL_CLOSE, [fd, 0, 0, 0, 0, 0])
 }
 
-pub fn sys_pipe2(pipe: &mut [usize]) -> isize {
+pub fn sys_pipe2(pipe: &mut [u32; 2]) -> isize {
     syscall(SYSCALL_PIPE2, [pipe.as_mut_ptr() as usize, 0, 0, 0, 0, 0])
 }
 
@@ -112,23 +137,167 @@ pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len(), 0, 0, 0])
 }
 
+pub fn sys_readv(fd: usize, iov: &[IOVec]) -> isize {
+    syscall(SYSCALL_READV, [fd, iov.as_ptr() as usize, iov.len(), 0, 0, 0])
+}
+
+pub fn sys_writev(fd: usize, iov: &[IOVec]) -> isize {
+    syscall(SYSCALL_WRITEV, [fd, iov.as_ptr() as usize, iov.len(), 0, 0, 0])
+}
+
+pub fn sys_sendfile(out_fd: usize, in_fd: usize, offset: Option<&mut usize>, count: usize) -> isize {
+    let offset = offset.map_or(0, |offset| offset as *mut usize as usize);
+    syscall(SYSCALL_SENDFILE, [out_fd, in_fd, offset, count, 0, 0])
+}
+
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_FCNTL, [fd, cmd, arg, 0, 0, 0])
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     syscall(SYSCALL_EXIT, [exit_code as usize, 0, 0, 0, 0, 0]);
     panic!("sys_exit never returns!");
 }
 
-pub fn sys_sleep(sleep_ms: usize) -> isize {
-    syscall(SYSCALL_NANOSLEEP, [sleep_ms, 0, 0, 0, 0, 0])
+pub fn sys_exit_group(exit_code: i32) -> ! {
+    syscall(SYSCALL_EXIT_GRUOP, [exit_code as usize, 0, 0, 0, 0, 0]);
+    panic!("sys_exit_group never returns!");
+}
+
+pub fn sys_nanosleep(req: &[usize; 2], rem: &mut [usize; 2]) -> isize {
+    syscall(SYSCALL_NANOSLEEP, [req.as_ptr() as usize, rem.as_mut_ptr() as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_getitimer(which: usize, curr: &mut ITimerVal) -> isize {
+    syscall(SYSCALL_GETITIMER, [which, curr as *mut _ as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_setitimer(which: usize, new: &ITimerVal, old: Option<&mut ITimerVal>) -> isize {
+    let old = old.map_or(0, |old| old as *mut _ as usize);
+    syscall(SYSCALL_SETITIMER, [which, new as *const _ as usize, old, 0, 0, 0])
+}
+
+pub fn sys_clock_nanosleep(clock_id: usize, flags: usize, req: &[usize; 2]) -> isize {
+    syscall(SYSCALL_CLOCK_NANOSLEEP, [clock_id, flags, req.as_ptr() as usize, 0, 0, 0])
 }
 
 pub fn sys_yield() -> isize {
     syscall(SYSCALL_SCHED_YIELD, [0, 0, 0, 0, 0, 0])
 }
 
+pub fn sys_setpriority(which: usize, who: usize, prio: isize) -> isize {
+    syscall(SYSCALL_SETPRIORITY, [which, who, prio as usize, 0, 0, 0])
+}
+
+pub fn sys_getpriority(which: usize, who: usize) -> isize {
+    syscall(SYSCALL_GETPRIORITY, [which, who, 0, 0, 0, 0])
+}
+
+pub fn sys_sched_setscheduler(pid: usize, policy: usize, prio: &i32) -> isize {
+    syscall(SYSCALL_SCHED_SETSCHEDULER, [pid, policy, prio as *const i32 as usize, 0, 0, 0])
+}
+
+pub fn sys_sched_getscheduler(pid: usize) -> isize {
+    syscall(SYSCALL_SCHED_GETSCHEDULER, [pid, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_sched_setaffinity(pid: usize, mask: &usize) -> isize {
+    syscall(
+        SYSCALL_SCHED_SETAFFINITY,
+        [pid, core::mem::size_of::<usize>(), mask as *const usize as usize, 0, 0, 0],
+    )
+}
+
+pub fn sys_sched_getaffinity(pid: usize, mask: &mut usize) -> isize {
+    syscall(
+        SYSCALL_SCHED_GETAFFINITY,
+        [pid, core::mem::size_of::<usize>(), mask as *mut usize as usize, 0, 0, 0],
+    )
+}
+
+pub fn sys_getcpu(cpu: &mut u32) -> isize {
+    syscall(SYSCALL_GETCPU, [cpu as *mut u32 as usize, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_idle_stat(buf: &mut [HartIdleStat]) -> isize {
+    syscall(SYSCALL_IDLE_STAT, [buf.as_mut_ptr() as usize, buf.len(), 0, 0, 0, 0])
+}
+
 pub fn sys_kill(pid: usize, signal: i32) -> isize {
     syscall(SYSCALL_KILL, [pid, signal as usize, 0, 0, 0, 0])
 }
 
+pub fn sys_sigaction(signum: i32, action: *const SigAction, old_action: *mut SigAction) -> isize {
+    syscall(
+        SYSCALL_SIGACTION,
+        [signum as usize, action as usize, old_action as usize, 0, 0, 0],
+    )
+}
+
+pub fn sys_sigprocmask(how: usize, set: *const u64, old_set: *mut u64) -> isize {
+    syscall(
+        SYSCALL_SIGPROCMASK,
+        [how, set as usize, old_set as usize, 8, 0, 0],
+    )
+}
+
+pub fn sys_sigaltstack(ss: *const SignalStack, old_ss: *mut SignalStack) -> isize {
+    syscall(SYSCALL_SIGALTSTACK, [ss as usize, old_ss as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_rt_sigpending(set: *mut u64) -> isize {
+    syscall(SYSCALL_RT_SIGPENDING, [set as usize, 8, 0, 0, 0, 0])
+}
+
+pub fn sys_rt_sigtimedwait(set: *const u64, info: *mut SigInfo, timeout: *const [usize; 2]) -> isize {
+    syscall(
+        SYSCALL_RT_SIGTIMEDWAIT,
+        [set as usize, info as usize, timeout as usize, 8, 0, 0],
+    )
+}
+
+pub fn sys_rt_sigqueueinfo(pid: usize, signum: i32, info: *const SigInfo) -> isize {
+    syscall(
+        SYSCALL_RT_SIGQUEUEINFO,
+        [pid, signum as usize, info as usize, 0, 0, 0],
+    )
+}
+
+pub fn sys_prlimit(pid: usize, resource: usize, new: *const RLimit, old: *mut RLimit) -> isize {
+    syscall(
+        SYSCALL_PRLIMIT,
+        [pid, resource, new as usize, old as usize, 0, 0],
+    )
+}
+
+pub fn sys_getrlimit(resource: usize, rlimit: *mut RLimit) -> isize {
+    syscall(SYSCALL_GETRLIMIT, [resource, rlimit as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_setrlimit(resource: usize, rlimit: *const RLimit) -> isize {
+    syscall(SYSCALL_SETRLIMIT, [resource, rlimit as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(SYSCALL_SETPGID, [pid, pgid, 0, 0, 0, 0])
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    syscall(SYSCALL_GETPGID, [pid, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_getsid(pid: usize) -> isize {
+    syscall(SYSCALL_GETSID, [pid, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_setsid() -> isize {
+    syscall(SYSCALL_SETSID, [0, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, request, arg, 0, 0, 0])
+}
+
 pub fn sys_get_time(time:&mut TimeVal) -> isize {
     unsafe{
         syscall(SYSCALL_GETTIMEOFDAY, [time as *mut TimeVal as usize, 0, 0, 0, 0, 0])
@@ -143,10 +312,40 @@ pub fn sys_clone() -> isize {
     syscall(SYSCALL_CLONE, [0, 0, 0, 0, 0, 0])
 }
 
-pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
+/// 与 fork 相同，并通过 pidfd 返回指向子进程的 pidfd
+pub fn sys_clone_pidfd(flags: usize, pidfd: *mut i32) -> isize {
+    syscall(SYSCALL_CLONE, [flags, 0, pidfd as usize, 0, 0, 0])
+}
+
+/// 新线程从 clone 返回后已在 stack 上运行，不能再回到 Rust 代码，直接调用 entry(arg) 并以返回值退出
+pub fn sys_clone_thread(flags: usize, stack: usize, entry: extern "C" fn(usize) -> i32, arg: usize) -> isize {
+    let mut ret: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            "bnez a0, 1f",
+            "mv a0, {arg}",
+            "jalr {entry}",
+            "li a7, 93",
+            "ecall",
+            "1:",
+            entry = in(reg) entry,
+            arg = in(reg) arg,
+            inlateout("x10") flags => ret,
+            in("x11") stack,
+            in("x12") 0,
+            in("x13") 0,
+            in("x14") 0,
+            in("x17") SYSCALL_CLONE
+        );
+    }
+    ret
+}
+
+pub fn sys_exec(path: &str, args: &[*const u8], envp: *const *const u8) -> isize {
     syscall(
         SYSCALL_EXECVE,
-        [path.as_ptr() as usize, args.as_ptr() as usize, 0, 0, 0, 0],
+        [path.as_ptr() as usize, args.as_ptr() as usize, envp as usize, 0, 0, 0],
     )
 }
 
@@ -154,6 +353,27 @@ pub fn sys_waitpid(pid: isize, wstatus: *mut i32, options: isize) -> isize {
     syscall(SYSCALL_WAIT4, [pid as usize, wstatus as usize, options as usize, 0, 0, 0])
 }
 
+pub fn sys_ppoll(
+    fds: *mut PollFd,
+    nfds: usize,
+    timeout: *const [usize; 2],
+    sigmask: *const u64,
+) -> isize {
+    syscall(SYSCALL_PPOLL, [fds as usize, nfds, timeout as usize, sigmask as usize, 8, 0])
+}
+
+pub fn sys_waitid(idtype: usize, id: usize, info: *mut SigInfo, options: isize) -> isize {
+    syscall(SYSCALL_WAITID, [idtype, id, info as usize, options as usize, 0, 0])
+}
+
+pub fn sys_pidfd_open(pid: usize, flags: usize) -> isize {
+    syscall(SYSCALL_PIDFD_OPEN, [pid, flags, 0, 0, 0, 0])
+}
+
+pub fn sys_pidfd_send_signal(pidfd: usize, signum: i32, info: *const SigInfo, flags: usize) -> isize {
+    syscall(SYSCALL_PIDFD_SEND_SIGNAL, [pidfd, signum as usize, info as usize, flags, 0, 0])
+}
+
 pub fn sys_gettid() -> isize {
     syscall(SYSCALL_GETTID, [0; 6])
 }
@@ -162,6 +382,14 @@ pub fn sys_brk(addr: usize) -> isize {
     syscall(SYSCALL_BRK, [addr, 0, 0, 0, 0, 0])
 }
 
+pub fn sys_mmap(start: usize, len: usize, prot: usize, flags: usize, fd: isize, offset: usize) -> isize {
+    syscall(SYSCALL_MMAP, [start, len, prot, flags, fd as usize, offset])
+}
+
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [start, len, 0, 0, 0, 0])
+}
+
 pub fn sys_shutdown() -> ! {
     syscall(SYSCALL_SHUTDOWN, [0, 0, 0, 0, 0, 0]);
     panic!("Shutdown");